@@ -0,0 +1,123 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use ips::Patch;
+use std::fs::{self, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+// The tool is a single binary, so pull in the modules being measured directly.
+// Their tests are left out of a bench, which leaves the tests' imports unused.
+#[allow(dead_code, unused_imports)]
+#[path = "../src/rom.rs"]
+mod rom;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/utils.rs"]
+mod utils;
+
+// Same size as an expanded Super Metroid ROM
+const ROM_SIZE: usize = 4 * 1024 * 1024;
+const HUNKS: usize = 5000;
+
+fn base_rom() -> Vec<u8> {
+    (0..ROM_SIZE as u32).map(|i| (i * 7) as u8).collect()
+}
+
+// Plain and RLE hunks spread over the whole ROM, like a large hack
+fn ips_patch() -> Vec<u8> {
+    let mut patch = b"PATCH".to_vec();
+    for i in 0..HUNKS {
+        let offset = (i * (ROM_SIZE / HUNKS)) as u32;
+        patch.extend_from_slice(&offset.to_be_bytes()[1..]);
+        if i % 4 == 0 {
+            patch.extend_from_slice(&[0, 0, 0x01, 0x00, 0xea]);
+        } else {
+            patch.extend_from_slice(&64u16.to_be_bytes());
+            patch.extend((0..64).map(|b| b as u8 ^ i as u8));
+        }
+    }
+    patch.extend_from_slice(b"EOF");
+    patch
+}
+
+fn scratch_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("metconst-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let patch = ips_patch();
+    c.bench_function("ips parse", |b| {
+        b.iter(|| Patch::parse(black_box(&patch)).unwrap())
+    });
+}
+
+fn bench_apply(c: &mut Criterion) {
+    let rom = base_rom();
+    let patch_contents = ips_patch();
+    let patch = Patch::parse(&patch_contents).unwrap();
+    let dir = scratch_dir();
+    let base_file = dir.join("base.sfc");
+    fs::write(&base_file, &rom).unwrap();
+    let rom_file = dir.join("patched.sfc");
+
+    let mut group = c.benchmark_group("apply hunks");
+    // What the patch mode does: copy the base ROM, then seek and write
+    group.bench_function("copy and patch file", |b| {
+        b.iter(|| {
+            fs::copy(&base_file, &rom_file).unwrap();
+            let mut out = OpenOptions::new().write(true).open(&rom_file).unwrap();
+            for hunk in patch.hunks() {
+                out.seek(SeekFrom::Start(hunk.offset() as u64)).unwrap();
+                out.write_all(hunk.payload()).unwrap();
+            }
+        })
+    });
+    group.bench_function("patch in memory and write", |b| {
+        b.iter(|| {
+            let mut contents = fs::read(&base_file).unwrap();
+            for hunk in patch.hunks() {
+                let end = hunk.offset() + hunk.payload().len();
+                if end > contents.len() {
+                    contents.resize(end, 0);
+                }
+                contents[hunk.offset()..end].copy_from_slice(hunk.payload());
+            }
+            fs::write(&rom_file, contents).unwrap();
+        })
+    });
+    group.bench_function("patch in memory only", |b| {
+        b.iter(|| {
+            let mut contents = rom.clone();
+            for hunk in patch.hunks() {
+                let end = hunk.offset() + hunk.payload().len();
+                contents[hunk.offset()..end].copy_from_slice(hunk.payload());
+            }
+            black_box(contents)
+        })
+    });
+    group.finish();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+fn bench_hash(c: &mut Criterion) {
+    let mut rom = base_rom();
+    let dir = scratch_dir();
+    let rom_file = dir.join("hash.sfc");
+    fs::write(&rom_file, &rom).unwrap();
+
+    let mut group = c.benchmark_group("hashing");
+    group.bench_function("sha256 file", |b| {
+        b.iter(|| utils::sha256_file(&rom_file).unwrap())
+    });
+    group.bench_function("unheadered crc32", |b| {
+        b.iter(|| rom::unheadered_crc32(&rom_file).unwrap())
+    });
+    group.bench_function("fix checksum", |b| {
+        b.iter(|| rom::fix_checksum(black_box(&mut rom)))
+    });
+    group.finish();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+criterion_group!(benches, bench_parse, bench_apply, bench_hash);
+criterion_main!(benches);
@@ -0,0 +1,21 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::path::{Component, Path};
+
+#[allow(dead_code)]
+#[path = "../../src/utils.rs"]
+mod utils;
+
+// Whatever name an archive entry has, an accepted path must stay inside
+// the extraction directory
+fuzz_target!(|name: &str| {
+    let dest = Path::new("staging/archive");
+    if let Ok(path) = utils::archive_entry_path(dest, name, &utils::NameRules::default()) {
+        let rest = path.strip_prefix(dest).expect("escaped the extraction directory");
+        assert!(rest
+            .components()
+            .all(|c| matches!(c, Component::Normal(_))));
+        assert!(rest.components().count() > 0);
+    }
+});
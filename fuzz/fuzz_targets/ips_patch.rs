@@ -0,0 +1,34 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::fs;
+use std::time::Duration;
+
+#[allow(dead_code)]
+#[path = "../../src/patch.rs"]
+mod patch;
+#[allow(dead_code)]
+#[path = "../../src/rom.rs"]
+mod rom;
+#[allow(dead_code)]
+#[path = "../../src/utils.rs"]
+mod utils;
+
+// Patches come straight from the internet, so applying one the way the
+// patch mode does must never panic whatever the file contains, and must
+// leave either a ROM no bigger than a ROM can be or none at all
+fuzz_target!(|data: &[u8]| {
+    let dir = std::env::temp_dir().join(format!("metconst-fuzz-ips-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (patch_file, rom_file) = (dir.join("hack.ips"), dir.join("hack.sfc"));
+    fs::write(&patch_file, data).unwrap();
+    fs::write(&rom_file, vec![0u8; 0x8000]).unwrap();
+    let deadline = utils::Deadline::after(Duration::from_secs(10), &utils::Cancel::default());
+    match patch::apply_ips(&patch_file, &rom_file, deadline, &mut std::io::sink()) {
+        Ok(()) => {
+            let len = fs::metadata(&rom_file).unwrap().len();
+            assert!(len as usize <= rom::MAX_ROM_SIZE + rom::COPIER_HEADER_SIZE);
+        }
+        Err(_) => assert!(!rom_file.exists()),
+    }
+});
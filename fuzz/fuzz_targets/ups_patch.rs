@@ -0,0 +1,37 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::time::Duration;
+
+#[allow(dead_code)]
+#[path = "../../src/patch.rs"]
+mod patch;
+#[allow(dead_code)]
+#[path = "../../src/rom.rs"]
+mod rom;
+#[allow(dead_code)]
+#[path = "../../src/utils.rs"]
+mod utils;
+
+// UPS patches come from the same places IPS ones do, so applying one must
+// fail cleanly instead of panicking or making more than a ROM's worth
+fuzz_target!(|data: &[u8]| {
+    let mut patch = b"UPS1".to_vec();
+    patch.extend_from_slice(data);
+    // A base ROM of the size the patch asks for, with the CRC32s filled in
+    // so the fuzzer gets past them to the hunks
+    let body = patch.len();
+    patch.extend([0; 12]);
+    let source = match patch::source_size(&patch) {
+        Ok(Some(size)) if size <= 0x10000 => vec![0u8; size],
+        _ => return,
+    };
+    patch.truncate(body);
+    patch.extend(crc32fast::hash(&source).to_le_bytes());
+    patch.extend([0; 4]);
+    patch.extend(crc32fast::hash(&patch).to_le_bytes());
+    let deadline = utils::Deadline::after(Duration::from_secs(10), &utils::Cancel::default());
+    if let Ok(rom) = patch::apply(&patch, &source, &deadline) {
+        assert!(rom.len() <= rom::MAX_ROM_SIZE + rom::COPIER_HEADER_SIZE);
+    }
+});
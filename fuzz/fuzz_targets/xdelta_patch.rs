@@ -0,0 +1,28 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::time::Duration;
+
+#[allow(dead_code)]
+#[path = "../../src/patch.rs"]
+mod patch;
+#[allow(dead_code)]
+#[path = "../../src/rom.rs"]
+mod rom;
+#[allow(dead_code)]
+#[path = "../../src/utils.rs"]
+mod utils;
+
+// xdelta patches come from the same places IPS ones do, so applying one
+// must fail cleanly instead of panicking or making more than a ROM's worth
+fuzz_target!(|data: &[u8]| {
+    let mut patch = vec![0xd6, 0xc3, 0xc4, 0x00];
+    patch.extend_from_slice(data);
+    // Nothing in the patch says how big the base ROM is, and the checksums
+    // are over the output, so any base ROM gets as far as the windows
+    let source: Vec<u8> = (0..0x1000).map(|i| i as u8).collect();
+    let deadline = utils::Deadline::after(Duration::from_secs(10), &utils::Cancel::default());
+    if let Ok(rom) = patch::apply(&patch, &source, &deadline) {
+        assert!(rom.len() <= rom::MAX_ROM_SIZE + rom::COPIER_HEADER_SIZE);
+    }
+});
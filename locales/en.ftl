@@ -0,0 +1,276 @@
+# Console messages and the HTML catalog in English, which the other
+# languages fall back to for any message they don't have yet
+
+## The table printed when a run ends
+
+summary-title = { $mode } summary
+summary-processed = processed
+summary-skipped = skipped
+summary-failed = failed
+summary-bytes = bytes
+summary-elapsed = elapsed
+skip-already-present = already present
+skip-up-to-date = up to date
+skip-blocklisted = blocklisted
+skip-filtered-out = filtered out
+skip-quarantined = quarantined
+skip-locked = locked
+
+## The export mode
+
+export-hashing = Hashing { $count } files...
+export-chunking = Chunking { $count } files...
+export-links = Made { $count } links under { $path }
+export-html =
+    { $count ->
+        [one] Wrote one hack
+       *[other] Wrote { $count } hacks
+    } to { $path }
+
+## The HTML catalog
+
+html-title = Metroid Construction mirror
+html-hack = Hack { $id }
+html-column-title = Title
+html-author = Author
+html-released = Released
+html-genre = Genre
+html-difficulty = Difficulty
+html-rating = Rating
+html-video = Video
+html-videos = Videos
+html-files = Files
+html-file = { $name } ({ $size } bytes)
+html-on-site = On metroidconstruction.com
+html-all-hacks = All hacks
+
+## Said by more than one mode
+
+wrote = Wrote { $path }
+fetching-hack-list = Fetching list of hacks...
+nothing-downloaded = Nothing has been downloaded yet, run `download` first (see `howto`)
+downloading = Downloading { $name }...
+stopping = Stopping, press Ctrl-C again to quit right away
+up-running = Running { $mode }...
+http-retried =
+    { $count ->
+        [one] One URL needed retries:
+       *[other] { $count } URLs needed retries:
+    }
+http-attempts = { $url }: { $count } attempts, { $last }
+heartbeat-still-working = Still working on { $path } after { $secs }s
+heartbeat-failed =
+    { $count ->
+        [one] One item failed:
+       *[other] { $count } items failed:
+    }
+heartbeat-slow =
+    { $count ->
+        [one] One item took
+       *[other] { $count } items took
+    } longer than { $secs }s:
+sign-signed = Signed it as { $path }
+
+## The init mode
+
+init-intro = Setting up { $path } in { $dir }, press enter to keep the value in brackets.
+init-relative = Relative paths are from that directory.
+init-base-rom = Path to an unmodified Super Metroid ROM
+init-no-base-rom = Skipping the base ROM, the patch mode will need one on the command line.
+init-downloads = Directory for downloaded hacks
+init-patched = Directory for patched ROMs
+init-page-delay = Seconds to wait between page requests
+init-file-delay = Seconds to wait between file downloads
+init-retries = Retries for failed requests
+
+## The howto mode
+
+howto-intro = Mirroring and patching every hack takes three modes, in this order:
+howto-init = Sets up the config file and where your unmodified ROM is.
+howto-download = Mirrors every hack's files into { $path }/.
+howto-download-resume = Takes hours the first time; stop it any time and run it again to resume.
+howto-unzip-into = Unpacks the archives in there into { $path }/.
+howto-unzip = Unpacks the archives in there, each next to itself.
+howto-patch = Applies every patch to { $path }
+howto-patch-any = Applies every patch to the ROM passed on the command line
+howto-patch-output = and writes the patched ROMs to { $path }/.
+howto-again =
+    Run the three again now and then, or `{ $name } up` for all of them, to pick up
+    new and updated hacks.
+howto-side = On the side, any time:
+howto-metadata = scrape titles, authors and ratings into metadata.csv
+howto-status = see what has been mirrored so far
+howto-stats = rank the authors in the catalog
+howto-resolve = get one hack patched without mirroring the rest
+howto-patch-url = try a linked hack that isn't on the site
+howto-doctor = check the setup when something goes wrong
+
+## The download, metadata and news modes
+
+download-total = There are a total of { $count } hacks available. This process may take several hours.
+download-resuming = Resuming at hack { $at } of { $count }
+download-locked = Skipping hack { $id }, locked by { $holder }
+remote-fetching = Fetching the patches outside archives from { $url }...
+manifest-rebuilding = No manifest found, rebuilding it from { $path }...
+metadata-read = Read { $count } hacks off the list
+metadata-total = There are a total of { $count } hacks available.
+metadata-audit =
+    { $count ->
+        [one] One hack has
+       *[other] { $count } hacks have
+    } missing or suspect fields:
+metadata-changelog = Wrote { $path }: { $added } new, { $updated } updated, { $removed } removed
+news-fetching = Fetching the front page...
+news-found = Found { $count } post(s) mentioning hacks, { $new } new announcement(s)
+
+## The patch and file-types modes
+
+patch-unmet = { $title } (id { $id }) { $why }, its patched ROM may not work
+patch-not-unpacked =
+    No archive has been unpacked yet, so only loose patches get applied. Run `unzip` first to patch every hack (see `howto`).
+patch-base-size = The base ROMs aren't the same size, so every patch is redone
+patch-changed =
+    { $count ->
+        [one] One patched ROM changed
+       *[other] { $count } patched ROMs changed
+    } since the last run:
+patch-updated = the patch file was updated
+patch-output-changed = same patch, different output (patch engine or base ROM changed)
+patch-randomizers =
+    { $count ->
+        [one] Left out one randomizer base patch
+       *[other] Left out { $count } randomizer base patches
+    }, pass --randomizers to patch them too:
+filetypes-extensions = extensions: { $list }
+normalize-failed =
+    { $count ->
+        [one] One ROM image couldn't be normalized:
+       *[other] { $count } ROM images couldn't be normalized:
+    }
+normalize-wrote = { $image } { $how }, wrote { $path }
+cache-downloading = Downloading hack { $id }...
+cache-patching = Unpacking and patching hack { $id }...
+
+## The doctor mode
+
+doctor-ok = ok:
+doctor-problem = PROBLEM:
+doctor-fix = fix:
+doctor-no-base-rom = no base ROM configured
+doctor-no-base-rom-fix = run `init`, or pass the base ROM to the patch mode each time
+doctor-unreadable-base-rom = cannot read base ROM { $path }: { $error }
+doctor-unreadable-base-rom-fix = fix the base_rom path in { $path }
+doctor-bad-base-rom-fix = use a clean dump, hacks are made against the unmodified JU ROM
+doctor-headered = base ROM { $path } has a 512 byte copier header
+doctor-headered-fix = most patches expect an unheadered ROM, strip the first 512 bytes
+doctor-base-rom-ok = base ROM { $path } is an unheadered Super Metroid ROM
+doctor-not-created = { $path } doesn't exist yet and will be created
+doctor-writable = { $path } is writable
+doctor-not-writable = cannot write to { $path }: { $error }
+doctor-not-writable-fix = fix the directory permissions or point the config at another directory
+doctor-unreadable-manifest = cannot read { $path }: { $error }
+doctor-unreadable-manifest-fix = delete { $path } and it will be rebuilt from disk
+doctor-wrong-size = { $path } has a different size than the manifest records
+doctor-download-again-fix = delete it and run `download` again
+doctor-missing = { $path } is in the manifest but missing on disk
+doctor-missing-fix = run `download` again to fetch it
+doctor-all-present = all { $count } files in the manifest are present
+doctor-untracked = { $path } isn't in the manifest, possibly a partial download
+doctor-no-untracked = no untracked files in the downloads directory
+doctor-leftover = { $path } was left behind by an interrupted run
+doctor-leftover-fix = delete it
+doctor-no-leftovers = no leftover temporary files
+doctor-all-good = Everything looks good.
+doctor-found =
+    { $count ->
+        [one] Found one problem.
+       *[other] Found { $count } problems.
+    }
+
+## The fsck mode
+
+fsck-checking = Checking { $count } files...
+fsck-agree = The manifest and the downloads agree
+fsck-plan = Repair plan:
+fsck-repair-hint = Run again with --repair to carry it out
+fsck-downloading =
+    { $count ->
+        [one] Downloading one file again...
+       *[other] Downloading { $count } files again...
+    }
+fsck-re-download = re-download
+fsck-re-hash = re-hash
+fsck-adopt = adopt
+fsck-delete = delete
+fsck-missing = missing
+fsck-wrong-size = { $size } bytes instead of { $expected }
+fsck-changed = contents changed
+fsck-unfinished = an unfinished download
+fsck-empty = empty and untracked
+fsck-untracked = untracked
+
+## The status and prune modes
+
+status-mirror = { $count } hacks, { $files } files, { $size } downloaded
+status-last-run = Last { $mode } ({ $finished }): { $processed } processed, { $skipped } skipped, { $failed } failed
+status-skipped = skipped: { $reasons }
+status-disk = Disk usage:
+status-archives = archives
+status-extracted = extracted
+status-patched = patched
+status-screenshots = screenshots
+status-cache = cache
+status-total = total
+status-largest = Largest { $count } hacks:
+prune-nothing = Nothing to prune
+prune-would-remove = Would remove { $path } ({ $size }): { $why }
+prune-removing = Removing { $path } ({ $size }): { $why }
+prune-would-free = Would free { $size }
+prune-freed = Freed { $size }
+prune-old = extracted more than { $count } days ago
+prune-superseded = superseded by { $name }
+prune-low-rated = rated { $rating }, below { $below }
+
+## The smaller modes
+
+smoke-failed = { $path } failed: { $why }
+smoke-result = { $passed } patched ROM(s) passed, { $failed } failed
+deploy-done = Deployed { $count } ROMs to { $path }, copied { $copied }
+codes-applied = Applied { $applied } of { $count } codes to { $path }
+diff-wrote = Wrote { $path } ({ $size } bytes)
+hashdb-wrote = Wrote { $count } hashes to { $path }
+hashdb-mismatch = Hack { $id } ({ $patch }) patched to { $ours } here but { $theirs } in { $path }
+hashdb-result = { $matched } matching, { $different } different, { $unknown } not patched here
+hashdb-hint = Differences usually mean a different base ROM or a header mismatch, try `doctor`.
+curation-wrote = Wrote { $count } curated hacks to { $path }
+curation-tagged = Tagged { $count } hacks from { $path }, as { $from }/...
+snapshot-recorded = Recorded { $count } patched ROMs as { $name }
+snapshot-changed = { $count } patched ROMs changed
+state-wrote = Wrote { $count } state files to { $path }
+state-restored = Restored { $count } state files from { $path }
+state-present = { $present } of { $count } mirrored files are in place
+state-hint = Sync the downloads directory or run `download` for the rest
+stats-wrote = Wrote { $count } rows to { $path }
+
+## The gui mode
+
+gui-updating = Updating the list of hacks
+gui-downloading = Downloading hack { $id }
+gui-patching = Patching hack { $id }
+gui-starting = Starting hack { $id }
+gui-playing = Playing { $path }
+gui-up-to-date = The list of hacks is up to date
+gui-downloaded = Downloaded hack { $id } to { $path }
+gui-patched = Patched hack { $id }: { $roms }
+gui-failed = { $job } failed: { $error }
+gui-unreadable = Can't read the catalog: { $error }
+gui-status-patched = patched
+gui-status-downloaded = downloaded
+gui-search = Search:
+gui-update-list = Update list
+gui-download = Download
+gui-patch = Patch
+gui-play = Play
+gui-read = { $job }, { $count } read...
+gui-empty = No hacks yet, press "Update list" to read them from the site.
+gui-row = { $title }  by { $author }  ·  { $genre }  ·  { $rating }  { $status }
@@ -0,0 +1,275 @@
+# Console messages and the HTML catalog in Spanish
+
+## The table printed when a run ends
+
+summary-title = resumen de { $mode }
+summary-processed = procesados
+summary-skipped = omitidos
+summary-failed = fallidos
+summary-bytes = bytes
+summary-elapsed = tiempo
+skip-already-present = ya presente
+skip-up-to-date = al día
+skip-blocklisted = en la lista de bloqueo
+skip-filtered-out = filtrado
+skip-quarantined = en cuarentena
+skip-locked = en uso
+
+## The export mode
+
+export-hashing = Calculando el hash de { $count } archivos...
+export-chunking = Dividiendo { $count } archivos en bloques...
+export-links = Se crearon { $count } enlaces en { $path }
+export-html =
+    { $count ->
+        [one] Se escribió un hack
+       *[other] Se escribieron { $count } hacks
+    } en { $path }
+
+## The HTML catalog
+
+html-title = Espejo de Metroid Construction
+html-hack = Hack { $id }
+html-column-title = Título
+html-author = Autor
+html-released = Publicado
+html-genre = Género
+html-difficulty = Dificultad
+html-rating = Valoración
+html-video = Vídeo
+html-videos = Vídeos
+html-files = Archivos
+html-file = { $name } ({ $size } bytes)
+html-on-site = En metroidconstruction.com
+html-all-hacks = Todos los hacks
+
+## Said by more than one mode
+
+wrote = Se escribió { $path }
+fetching-hack-list = Obteniendo la lista de hacks...
+nothing-downloaded = Todavía no se ha descargado nada, ejecuta `download` primero (ver `howto`)
+downloading = Descargando { $name }...
+stopping = Deteniendo, pulsa Ctrl-C otra vez para salir de inmediato
+up-running = Ejecutando { $mode }...
+http-retried =
+    { $count ->
+        [one] Una URL necesitó reintentos:
+       *[other] { $count } URLs necesitaron reintentos:
+    }
+http-attempts = { $url }: { $count } intentos, { $last }
+heartbeat-still-working = Todavía trabajando en { $path } tras { $secs } s
+heartbeat-failed =
+    { $count ->
+        [one] Falló un elemento:
+       *[other] Fallaron { $count } elementos:
+    }
+heartbeat-slow =
+    { $count ->
+        [one] Un elemento tardó
+       *[other] { $count } elementos tardaron
+    } más de { $secs } s:
+sign-signed = Firmado como { $path }
+
+## The init mode
+
+init-intro = Configurando { $path } en { $dir }, pulsa Intro para mantener el valor entre corchetes.
+init-relative = Las rutas relativas parten de ese directorio.
+init-base-rom = Ruta a una ROM de Super Metroid sin modificar
+init-no-base-rom = Se omite la ROM base, el modo patch necesitará una en la línea de comandos.
+init-downloads = Directorio para los hacks descargados
+init-patched = Directorio para las ROM parcheadas
+init-page-delay = Segundos de espera entre peticiones de páginas
+init-file-delay = Segundos de espera entre descargas de archivos
+init-retries = Reintentos para las peticiones fallidas
+
+## The howto mode
+
+howto-intro = Replicar y parchear todos los hacks requiere tres modos, en este orden:
+howto-init = Crea el archivo de configuración e indica dónde está tu ROM sin modificar.
+howto-download = Replica los archivos de cada hack en { $path }/.
+howto-download-resume = La primera vez tarda horas; detenlo cuando quieras y ejecútalo de nuevo para continuar.
+howto-unzip-into = Desempaqueta ahí los archivos comprimidos en { $path }/.
+howto-unzip = Desempaqueta ahí los archivos comprimidos, cada uno junto a sí mismo.
+howto-patch = Aplica cada parche a { $path }
+howto-patch-any = Aplica cada parche a la ROM pasada en la línea de comandos
+howto-patch-output = y escribe las ROM parcheadas en { $path }/.
+howto-again =
+    Vuelve a ejecutar los tres de vez en cuando, o `{ $name } up` para todos, para obtener
+    los hacks nuevos y actualizados.
+howto-side = Aparte, en cualquier momento:
+howto-metadata = extrae títulos, autores y valoraciones a metadata.csv
+howto-status = muestra lo que se ha replicado hasta ahora
+howto-stats = clasifica a los autores del catálogo
+howto-resolve = parchea un solo hack sin replicar el resto
+howto-patch-url = prueba un hack enlazado que no está en el sitio
+howto-doctor = revisa la configuración cuando algo falla
+
+## The download, metadata and news modes
+
+download-total = Hay un total de { $count } hacks disponibles. Este proceso puede tardar varias horas.
+download-resuming = Continuando en el hack { $at } de { $count }
+download-locked = Se omite el hack { $id }, bloqueado por { $holder }
+remote-fetching = Obteniendo los parches fuera de archivos comprimidos desde { $url }...
+manifest-rebuilding = No se encontró el manifiesto, reconstruyéndolo desde { $path }...
+metadata-read = Se leyeron { $count } hacks de la lista
+metadata-total = Hay un total de { $count } hacks disponibles.
+metadata-audit =
+    { $count ->
+        [one] Un hack tiene
+       *[other] { $count } hacks tienen
+    } campos ausentes o dudosos:
+metadata-changelog = Se escribió { $path }: { $added } nuevos, { $updated } actualizados, { $removed } eliminados
+news-fetching = Obteniendo la portada...
+news-found = Se encontraron { $count } publicación(es) que mencionan hacks, { $new } anuncio(s) nuevo(s)
+
+## The patch and file-types modes
+
+patch-unmet = { $title } (id { $id }) { $why }, puede que su ROM parcheada no funcione
+patch-not-unpacked =
+    Todavía no se ha desempaquetado ningún archivo comprimido, así que solo se aplican los parches sueltos. Ejecuta `unzip` primero para parchear todos los hacks (ver `howto`).
+patch-base-size = Las ROM base no tienen el mismo tamaño, así que se rehacen todos los parches
+patch-changed =
+    { $count ->
+        [one] Una ROM parcheada cambió
+       *[other] { $count } ROM parcheadas cambiaron
+    } desde la última ejecución:
+patch-updated = el archivo del parche se actualizó
+patch-output-changed = mismo parche, resultado distinto (cambió el motor de parcheo o la ROM base)
+patch-randomizers =
+    { $count ->
+        [one] Se dejó fuera un parche base de randomizer
+       *[other] Se dejaron fuera { $count } parches base de randomizer
+    }, pasa --randomizers para parchearlos también:
+filetypes-extensions = extensiones: { $list }
+normalize-failed =
+    { $count ->
+        [one] No se pudo normalizar una imagen de ROM:
+       *[other] No se pudieron normalizar { $count } imágenes de ROM:
+    }
+normalize-wrote = { $image } { $how }, se escribió { $path }
+cache-downloading = Descargando el hack { $id }...
+cache-patching = Desempaquetando y parcheando el hack { $id }...
+
+## The doctor mode
+
+doctor-ok = bien:
+doctor-problem = PROBLEMA:
+doctor-fix = solución:
+doctor-no-base-rom = no hay ninguna ROM base configurada
+doctor-no-base-rom-fix = ejecuta `init`, o pasa la ROM base al modo patch cada vez
+doctor-unreadable-base-rom = no se puede leer la ROM base { $path }: { $error }
+doctor-unreadable-base-rom-fix = corrige la ruta base_rom en { $path }
+doctor-bad-base-rom-fix = usa un volcado limpio, los hacks se hacen sobre la ROM JU sin modificar
+doctor-headered = la ROM base { $path } tiene una cabecera de copiador de 512 bytes
+doctor-headered-fix = la mayoría de los parches esperan una ROM sin cabecera, quita los primeros 512 bytes
+doctor-base-rom-ok = la ROM base { $path } es una ROM de Super Metroid sin cabecera
+doctor-not-created = { $path } todavía no existe y se creará
+doctor-writable = se puede escribir en { $path }
+doctor-not-writable = no se puede escribir en { $path }: { $error }
+doctor-not-writable-fix = corrige los permisos del directorio o apunta la configuración a otro directorio
+doctor-unreadable-manifest = no se puede leer { $path }: { $error }
+doctor-unreadable-manifest-fix = borra { $path } y se reconstruirá desde el disco
+doctor-wrong-size = { $path } tiene un tamaño distinto del que registra el manifiesto
+doctor-download-again-fix = bórralo y ejecuta `download` de nuevo
+doctor-missing = { $path } está en el manifiesto pero falta en el disco
+doctor-missing-fix = ejecuta `download` de nuevo para obtenerlo
+doctor-all-present = los { $count } archivos del manifiesto están presentes
+doctor-untracked = { $path } no está en el manifiesto, posiblemente una descarga parcial
+doctor-no-untracked = no hay archivos sin registrar en el directorio de descargas
+doctor-leftover = { $path } quedó de una ejecución interrumpida
+doctor-leftover-fix = bórralo
+doctor-no-leftovers = no quedan archivos temporales
+doctor-all-good = Todo parece correcto.
+doctor-found =
+    { $count ->
+        [one] Se encontró un problema.
+       *[other] Se encontraron { $count } problemas.
+    }
+
+## The fsck mode
+
+fsck-checking = Comprobando { $count } archivos...
+fsck-agree = El manifiesto y las descargas coinciden
+fsck-plan = Plan de reparación:
+fsck-repair-hint = Ejecuta de nuevo con --repair para llevarlo a cabo
+fsck-downloading =
+    { $count ->
+        [one] Descargando un archivo de nuevo...
+       *[other] Descargando { $count } archivos de nuevo...
+    }
+fsck-re-download = redescargar
+fsck-re-hash = recalcular
+fsck-adopt = adoptar
+fsck-delete = borrar
+fsck-missing = falta
+fsck-wrong-size = { $size } bytes en vez de { $expected }
+fsck-changed = el contenido cambió
+fsck-unfinished = una descarga sin terminar
+fsck-empty = vacío y sin registrar
+fsck-untracked = sin registrar
+
+## The status and prune modes
+
+status-mirror = { $count } hacks, { $files } archivos, { $size } descargados
+status-last-run = Último { $mode } ({ $finished }): { $processed } procesados, { $skipped } omitidos, { $failed } fallidos
+status-skipped = omitidos: { $reasons }
+status-disk = Uso del disco:
+status-archives = comprimidos
+status-extracted = extraídos
+status-patched = parcheados
+status-screenshots = capturas
+status-cache = caché
+status-total = total
+status-largest = Los { $count } hacks más grandes:
+prune-nothing = No hay nada que podar
+prune-would-remove = Se eliminaría { $path } ({ $size }): { $why }
+prune-removing = Eliminando { $path } ({ $size }): { $why }
+prune-would-free = Se liberarían { $size }
+prune-freed = Se liberaron { $size }
+prune-old = extraído hace más de { $count } días
+prune-superseded = reemplazado por { $name }
+prune-low-rated = valorado { $rating }, por debajo de { $below }
+
+## The smaller modes
+
+smoke-failed = { $path } falló: { $why }
+smoke-result = { $passed } ROM parcheada(s) pasaron, { $failed } fallaron
+deploy-done = Se desplegaron { $count } ROM en { $path }, se copiaron { $copied }
+codes-applied = Se aplicaron { $applied } de { $count } códigos a { $path }
+diff-wrote = Se escribió { $path } ({ $size } bytes)
+hashdb-wrote = Se escribieron { $count } hashes en { $path }
+hashdb-mismatch = El hack { $id } ({ $patch }) da { $ours } aquí pero { $theirs } en { $path }
+hashdb-result = { $matched } coinciden, { $different } distintos, { $unknown } sin parchear aquí
+hashdb-hint = Las diferencias suelen deberse a otra ROM base o a una cabecera distinta, prueba `doctor`.
+curation-wrote = Se escribieron { $count } hacks seleccionados en { $path }
+curation-tagged = Se etiquetaron { $count } hacks de { $path }, como { $from }/...
+snapshot-recorded = Se registraron { $count } ROM parcheadas como { $name }
+snapshot-changed = Cambiaron { $count } ROM parcheadas
+state-wrote = Se escribieron { $count } archivos de estado en { $path }
+state-restored = Se restauraron { $count } archivos de estado desde { $path }
+state-present = { $present } de { $count } archivos replicados están en su sitio
+state-hint = Sincroniza el directorio de descargas o ejecuta `download` para el resto
+stats-wrote = Se escribieron { $count } filas en { $path }
+
+## The gui mode
+
+gui-updating = Actualizando la lista de hacks
+gui-downloading = Descargando el hack { $id }
+gui-patching = Parcheando el hack { $id }
+gui-starting = Iniciando el hack { $id }
+gui-playing = Jugando { $path }
+gui-up-to-date = La lista de hacks está al día
+gui-downloaded = Se descargó el hack { $id } en { $path }
+gui-patched = Se parcheó el hack { $id }: { $roms }
+gui-failed = { $job } falló: { $error }
+gui-unreadable = No se puede leer el catálogo: { $error }
+gui-status-patched = parcheado
+gui-status-downloaded = descargado
+gui-search = Buscar:
+gui-update-list = Actualizar lista
+gui-download = Descargar
+gui-patch = Parchear
+gui-play = Jugar
+gui-read = { $job }, { $count } leídos...
+gui-empty = Todavía no hay hacks, pulsa "Actualizar lista" para leerlos del sitio.
+gui-row = { $title }  de { $author }  ·  { $genre }  ·  { $rating }  { $status }
@@ -0,0 +1,275 @@
+# Console messages and the HTML catalog in Portuguese
+
+## The table printed when a run ends
+
+summary-title = resumo de { $mode }
+summary-processed = processados
+summary-skipped = pulados
+summary-failed = com falha
+summary-bytes = bytes
+summary-elapsed = tempo
+skip-already-present = já presente
+skip-up-to-date = em dia
+skip-blocklisted = na lista de bloqueio
+skip-filtered-out = filtrado
+skip-quarantined = em quarentena
+skip-locked = em uso
+
+## The export mode
+
+export-hashing = Calculando o hash de { $count } arquivos...
+export-chunking = Dividindo { $count } arquivos em blocos...
+export-links = { $count } links criados em { $path }
+export-html =
+    { $count ->
+        [one] Um hack gravado
+       *[other] { $count } hacks gravados
+    } em { $path }
+
+## The HTML catalog
+
+html-title = Espelho do Metroid Construction
+html-hack = Hack { $id }
+html-column-title = Título
+html-author = Autor
+html-released = Lançamento
+html-genre = Gênero
+html-difficulty = Dificuldade
+html-rating = Nota
+html-video = Vídeo
+html-videos = Vídeos
+html-files = Arquivos
+html-file = { $name } ({ $size } bytes)
+html-on-site = No metroidconstruction.com
+html-all-hacks = Todos os hacks
+
+## Said by more than one mode
+
+wrote = { $path } gravado
+fetching-hack-list = Buscando a lista de hacks...
+nothing-downloaded = Nada foi baixado ainda, rode `download` primeiro (veja `howto`)
+downloading = Baixando { $name }...
+stopping = Parando, aperte Ctrl-C de novo para sair na hora
+up-running = Rodando { $mode }...
+http-retried =
+    { $count ->
+        [one] Uma URL precisou de novas tentativas:
+       *[other] { $count } URLs precisaram de novas tentativas:
+    }
+http-attempts = { $url }: { $count } tentativas, { $last }
+heartbeat-still-working = Ainda trabalhando em { $path } após { $secs } s
+heartbeat-failed =
+    { $count ->
+        [one] Um item falhou:
+       *[other] { $count } itens falharam:
+    }
+heartbeat-slow =
+    { $count ->
+        [one] Um item levou
+       *[other] { $count } itens levaram
+    } mais de { $secs } s:
+sign-signed = Assinado como { $path }
+
+## The init mode
+
+init-intro = Configurando { $path } em { $dir }, aperte Enter para manter o valor entre colchetes.
+init-relative = Caminhos relativos partem desse diretório.
+init-base-rom = Caminho para uma ROM de Super Metroid sem modificações
+init-no-base-rom = Pulando a ROM base, o modo patch vai precisar de uma na linha de comando.
+init-downloads = Diretório para os hacks baixados
+init-patched = Diretório para as ROMs patcheadas
+init-page-delay = Segundos de espera entre requisições de páginas
+init-file-delay = Segundos de espera entre downloads de arquivos
+init-retries = Novas tentativas para requisições que falharam
+
+## The howto mode
+
+howto-intro = Espelhar e patchear todos os hacks leva três modos, nesta ordem:
+howto-init = Cria o arquivo de configuração e diz onde está sua ROM sem modificações.
+howto-download = Espelha os arquivos de cada hack em { $path }/.
+howto-download-resume = Leva horas na primeira vez; pare quando quiser e rode de novo para continuar.
+howto-unzip-into = Descompacta os arquivos compactados de lá em { $path }/.
+howto-unzip = Descompacta os arquivos compactados de lá, cada um ao lado de si mesmo.
+howto-patch = Aplica cada patch em { $path }
+howto-patch-any = Aplica cada patch na ROM passada na linha de comando
+howto-patch-output = e grava as ROMs patcheadas em { $path }/.
+howto-again =
+    Rode os três de novo de vez em quando, ou `{ $name } up` para todos, para pegar
+    hacks novos e atualizados.
+howto-side = À parte, a qualquer momento:
+howto-metadata = extrai títulos, autores e notas para metadata.csv
+howto-status = mostra o que já foi espelhado
+howto-stats = classifica os autores do catálogo
+howto-resolve = patcheia um só hack sem espelhar o resto
+howto-patch-url = testa um hack de um link que não está no site
+howto-doctor = verifica a configuração quando algo dá errado
+
+## The download, metadata and news modes
+
+download-total = Há um total de { $count } hacks disponíveis. Este processo pode levar várias horas.
+download-resuming = Continuando no hack { $at } de { $count }
+download-locked = Pulando o hack { $id }, bloqueado por { $holder }
+remote-fetching = Buscando os patches fora de arquivos compactados em { $url }...
+manifest-rebuilding = Manifesto não encontrado, reconstruindo a partir de { $path }...
+metadata-read = { $count } hacks lidos da lista
+metadata-total = Há um total de { $count } hacks disponíveis.
+metadata-audit =
+    { $count ->
+        [one] Um hack tem
+       *[other] { $count } hacks têm
+    } campos ausentes ou suspeitos:
+metadata-changelog = { $path } gravado: { $added } novos, { $updated } atualizados, { $removed } removidos
+news-fetching = Buscando a página inicial...
+news-found = { $count } post(s) mencionando hacks, { $new } anúncio(s) novo(s)
+
+## The patch and file-types modes
+
+patch-unmet = { $title } (id { $id }) { $why }, a ROM patcheada pode não funcionar
+patch-not-unpacked =
+    Nenhum arquivo compactado foi descompactado ainda, então só os patches soltos são aplicados. Rode `unzip` primeiro para patchear todos os hacks (veja `howto`).
+patch-base-size = As ROMs base não têm o mesmo tamanho, então todos os patches são refeitos
+patch-changed =
+    { $count ->
+        [one] Uma ROM patcheada mudou
+       *[other] { $count } ROMs patcheadas mudaram
+    } desde a última execução:
+patch-updated = o arquivo do patch foi atualizado
+patch-output-changed = mesmo patch, resultado diferente (mudou o motor de patch ou a ROM base)
+patch-randomizers =
+    { $count ->
+        [one] Um patch base de randomizer ficou de fora
+       *[other] { $count } patches base de randomizer ficaram de fora
+    }, passe --randomizers para patcheá-los também:
+filetypes-extensions = extensões: { $list }
+normalize-failed =
+    { $count ->
+        [one] Uma imagem de ROM não pôde ser normalizada:
+       *[other] { $count } imagens de ROM não puderam ser normalizadas:
+    }
+normalize-wrote = { $image } { $how }, { $path } gravado
+cache-downloading = Baixando o hack { $id }...
+cache-patching = Descompactando e patcheando o hack { $id }...
+
+## The doctor mode
+
+doctor-ok = ok:
+doctor-problem = PROBLEMA:
+doctor-fix = solução:
+doctor-no-base-rom = nenhuma ROM base configurada
+doctor-no-base-rom-fix = rode `init`, ou passe a ROM base para o modo patch toda vez
+doctor-unreadable-base-rom = não foi possível ler a ROM base { $path }: { $error }
+doctor-unreadable-base-rom-fix = corrija o caminho base_rom em { $path }
+doctor-bad-base-rom-fix = use um dump limpo, os hacks são feitos sobre a ROM JU sem modificações
+doctor-headered = a ROM base { $path } tem um cabeçalho de copiadora de 512 bytes
+doctor-headered-fix = a maioria dos patches espera uma ROM sem cabeçalho, remova os primeiros 512 bytes
+doctor-base-rom-ok = a ROM base { $path } é uma ROM de Super Metroid sem cabeçalho
+doctor-not-created = { $path } ainda não existe e será criado
+doctor-writable = { $path } pode ser gravado
+doctor-not-writable = não foi possível gravar em { $path }: { $error }
+doctor-not-writable-fix = corrija as permissões do diretório ou aponte a configuração para outro diretório
+doctor-unreadable-manifest = não foi possível ler { $path }: { $error }
+doctor-unreadable-manifest-fix = apague { $path } e ele será reconstruído a partir do disco
+doctor-wrong-size = { $path } tem um tamanho diferente do que o manifesto registra
+doctor-download-again-fix = apague-o e rode `download` de novo
+doctor-missing = { $path } está no manifesto mas falta no disco
+doctor-missing-fix = rode `download` de novo para buscá-lo
+doctor-all-present = todos os { $count } arquivos do manifesto estão presentes
+doctor-untracked = { $path } não está no manifesto, talvez um download parcial
+doctor-no-untracked = nenhum arquivo não registrado no diretório de downloads
+doctor-leftover = { $path } sobrou de uma execução interrompida
+doctor-leftover-fix = apague-o
+doctor-no-leftovers = nenhum arquivo temporário sobrando
+doctor-all-good = Tudo parece certo.
+doctor-found =
+    { $count ->
+        [one] Um problema encontrado.
+       *[other] { $count } problemas encontrados.
+    }
+
+## The fsck mode
+
+fsck-checking = Verificando { $count } arquivos...
+fsck-agree = O manifesto e os downloads conferem
+fsck-plan = Plano de reparo:
+fsck-repair-hint = Rode de novo com --repair para executá-lo
+fsck-downloading =
+    { $count ->
+        [one] Baixando um arquivo de novo...
+       *[other] Baixando { $count } arquivos de novo...
+    }
+fsck-re-download = rebaixar
+fsck-re-hash = recalcular
+fsck-adopt = adotar
+fsck-delete = apagar
+fsck-missing = faltando
+fsck-wrong-size = { $size } bytes em vez de { $expected }
+fsck-changed = o conteúdo mudou
+fsck-unfinished = um download inacabado
+fsck-empty = vazio e não registrado
+fsck-untracked = não registrado
+
+## The status and prune modes
+
+status-mirror = { $count } hacks, { $files } arquivos, { $size } baixados
+status-last-run = Último { $mode } ({ $finished }): { $processed } processados, { $skipped } pulados, { $failed } falharam
+status-skipped = pulados: { $reasons }
+status-disk = Uso do disco:
+status-archives = compactados
+status-extracted = extraídos
+status-patched = patcheados
+status-screenshots = capturas
+status-cache = cache
+status-total = total
+status-largest = Os { $count } maiores hacks:
+prune-nothing = Nada para podar
+prune-would-remove = Removeria { $path } ({ $size }): { $why }
+prune-removing = Removendo { $path } ({ $size }): { $why }
+prune-would-free = Liberaria { $size }
+prune-freed = { $size } liberados
+prune-old = extraído há mais de { $count } dias
+prune-superseded = substituído por { $name }
+prune-low-rated = nota { $rating }, abaixo de { $below }
+
+## The smaller modes
+
+smoke-failed = { $path } falhou: { $why }
+smoke-result = { $passed } ROM(s) patcheada(s) passaram, { $failed } falharam
+deploy-done = { $count } ROMs implantadas em { $path }, { $copied } copiadas
+codes-applied = { $applied } de { $count } códigos aplicados em { $path }
+diff-wrote = { $path } gravado ({ $size } bytes)
+hashdb-wrote = { $count } hashes gravados em { $path }
+hashdb-mismatch = O hack { $id } ({ $patch }) dá { $ours } aqui mas { $theirs } em { $path }
+hashdb-result = { $matched } iguais, { $different } diferentes, { $unknown } não patcheados aqui
+hashdb-hint = Diferenças costumam indicar outra ROM base ou um cabeçalho diferente, tente `doctor`.
+curation-wrote = { $count } hacks selecionados gravados em { $path }
+curation-tagged = { $count } hacks de { $path } marcados, como { $from }/...
+snapshot-recorded = { $count } ROMs patcheadas registradas como { $name }
+snapshot-changed = { $count } ROMs patcheadas mudaram
+state-wrote = { $count } arquivos de estado gravados em { $path }
+state-restored = { $count } arquivos de estado restaurados de { $path }
+state-present = { $present } de { $count } arquivos espelhados estão no lugar
+state-hint = Sincronize o diretório de downloads ou rode `download` para o resto
+stats-wrote = { $count } linhas gravadas em { $path }
+
+## The gui mode
+
+gui-updating = Atualizando a lista de hacks
+gui-downloading = Baixando o hack { $id }
+gui-patching = Patcheando o hack { $id }
+gui-starting = Iniciando o hack { $id }
+gui-playing = Jogando { $path }
+gui-up-to-date = A lista de hacks está em dia
+gui-downloaded = Hack { $id } baixado em { $path }
+gui-patched = Hack { $id } patcheado: { $roms }
+gui-failed = { $job } falhou: { $error }
+gui-unreadable = Não foi possível ler o catálogo: { $error }
+gui-status-patched = patcheado
+gui-status-downloaded = baixado
+gui-search = Buscar:
+gui-update-list = Atualizar lista
+gui-download = Baixar
+gui-patch = Patchear
+gui-play = Jogar
+gui-read = { $job }, { $count } lidos...
+gui-empty = Nenhum hack ainda, aperte "Atualizar lista" para lê-los do site.
+gui-row = { $title }  por { $author }  ·  { $genre }  ·  { $rating }  { $status }
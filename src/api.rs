@@ -0,0 +1,229 @@
+use crate::config::Config;
+use crate::pipeline;
+use crate::records::HackListing;
+use crate::scrape;
+use crate::utils::*;
+use reqwest::header::{ACCEPT, RETRY_AFTER};
+use reqwest::StatusCode;
+use reqwest_middleware::ClientWithMiddleware;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::io::Write;
+use std::time::Duration;
+
+// The site's JSON list of hacks, which runs ask for before scraping the
+// hack list. Until the site has one the request fails and the run scrapes
+// as before; the report records which of the two each run used.
+
+// The longest a Retry-After is waited out, past which the list is scraped
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Api,
+    Scraped,
+}
+
+impl Source {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Source::Api => "api",
+            Source::Scraped => "scraped",
+        }
+    }
+}
+
+// The hack list as the run got it
+#[derive(Debug, Clone, PartialEq)]
+pub enum HackList {
+    Api(Vec<HackListing>),
+    Pages(Vec<String>),
+}
+
+impl HackList {
+    pub fn source(&self) -> Source {
+        match self {
+            HackList::Api(_) => Source::Api,
+            HackList::Pages(_) => Source::Scraped,
+        }
+    }
+
+    // The ids in list order, each once
+    pub fn ids(&self) -> ResultErr<Vec<String>> {
+        match self {
+            HackList::Api(hacks) => {
+                let mut seen = HashSet::new();
+                Ok(hacks
+                    .iter()
+                    .filter(|h| seen.insert(h.id))
+                    .map(|h| h.id.to_string())
+                    .collect())
+            }
+            HackList::Pages(pages) => pipeline::listed_ids(pages),
+        }
+    }
+
+    pub fn listings(&self) -> ResultErr<Vec<HackListing>> {
+        match self {
+            HackList::Api(hacks) => Ok(hacks.clone()),
+            HackList::Pages(pages) => {
+                let mut rows = Vec::new();
+                for page in pages {
+                    rows.extend(scrape::parse_hack_list(page)?);
+                }
+                Ok(rows)
+            }
+        }
+    }
+}
+
+// None when the config turns the API off
+pub fn api_url(config: &Config) -> Option<String> {
+    match &config.api_url {
+        Some(url) if url.is_empty() => None,
+        Some(url) => Some(url.clone()),
+        None => Some(format!("{}api/hacks.json", config.site_url)),
+    }
+}
+
+// Reads `{"hacks": [...]}` or a bare array, of objects with at least an id.
+// Ratings may be numbers or strings.
+pub fn parse(body: &str) -> ResultErr<Vec<HackListing>> {
+    let value: Value = serde_json::from_str(body)?;
+    let hacks = match &value {
+        Value::Array(hacks) => hacks,
+        _ => value
+            .get("hacks")
+            .and_then(|h| h.as_array())
+            .ok_or("no list of hacks in the API's answer")?,
+    };
+    let text = |hack: &Value, key: &str| match hack.get(key) {
+        Some(Value::String(s)) => scrape::clean_text(s),
+        Some(Value::Number(n)) => n.to_string(),
+        _ => String::new(),
+    };
+    let mut listings = Vec::new();
+    for hack in hacks {
+        let id = hack
+            .get("id")
+            .and_then(|id| id.as_u64().or_else(|| id.as_str()?.parse().ok()))
+            .ok_or("a hack without an id in the API's answer")?;
+        listings.push(HackListing {
+            id: id.try_into()?,
+            title: text(hack, "title"),
+            author: text(hack, "author"),
+            date: text(hack, "date"),
+            rating: text(hack, "rating"),
+        });
+    }
+    Ok(listings)
+}
+
+// Ask the API for the list, waiting once for as long as it says to when it
+// turns the request away for coming too often. Requests are already spaced
+// by the page delay like every other.
+async fn fetch(client: &ClientWithMiddleware, url: &str) -> ResultErr<Vec<HackListing>> {
+    let mut waited = false;
+    loop {
+        let response = client
+            .get(url)
+            .header(ACCEPT, "application/json")
+            .send()
+            .await?;
+        if response.status() == StatusCode::TOO_MANY_REQUESTS && !waited {
+            let wait = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|v| v.to_str().ok()?.trim().parse().ok())
+                .map(Duration::from_secs)
+                .ok_or("rate limited without a Retry-After")?;
+            if wait > MAX_RETRY_AFTER {
+                return Err(format!("rate limited for {}s", wait.as_secs()).into());
+            }
+            tokio::time::sleep(wait).await;
+            waited = true;
+            continue;
+        }
+        let body = response.error_for_status()?.text().await?;
+        return parse(&body);
+    }
+}
+
+// The hack list from the API if it answers with one, scraped otherwise
+pub async fn hack_list(
+    config: &Config,
+    client: &ClientWithMiddleware,
+    log: &mut dyn Write,
+) -> ResultErr<HackList> {
+    if let Some(url) = api_url(config) {
+        match fetch(client, &url).await {
+            Ok(hacks) if !hacks.is_empty() => return Ok(HackList::Api(hacks)),
+            Ok(_) => writeln!(log, "{} lists no hacks, scraping the list instead", url)?,
+            Err(e) => writeln!(log, "No hack list from {}, scraping it instead: {}", url, e)?,
+        }
+    }
+    Ok(HackList::Pages(
+        pipeline::fetch_hack_list(config, client).await?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http;
+    use crate::http::tests::{path_of, respond, serve};
+    use crate::summary::Summary;
+
+    #[tokio::test]
+    async fn the_hack_list_comes_from_the_api_when_the_site_has_one() {
+        let page =
+            r#"<table><tr><td><a href="hack.php?id=756">Hyper Metroid</a></td></tr></table>"#;
+        let feed = r#"{"hacks": [{"id": 756, "title": "Hyper Metroid", "rating": 4.5},
+                                 {"id": "212", "author": "Kejardon"}]}"#;
+        // No API at first, so the list is scraped; then there is one
+        let mut answers = [("404 Not Found", ""), ("200 OK", page), ("200 OK", feed)].into_iter();
+        let (site, requests) = serve(move |_| {
+            let (status, body) = answers.next().unwrap();
+            respond(status, body)
+        })
+        .await;
+
+        let config = Config {
+            site_url: site,
+            max_retries: 0,
+            page_delay_secs: 0,
+            ..Config::default()
+        };
+        let client = http::client(&config, &http::RetryLog::default()).unwrap();
+        let mut log = Vec::new();
+        let scraped = hack_list(&config, &client, &mut log).await.unwrap();
+        let listed = hack_list(&config, &client, &mut log).await.unwrap();
+        let paths: Vec<_> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| path_of(r))
+            .collect();
+        assert_eq!(paths[0], "/api/hacks.json");
+        assert!(paths[1].starts_with("/hacks.php?"));
+        assert_eq!(scraped.source(), Source::Scraped);
+        assert_eq!(scraped.ids().unwrap(), ["756"]);
+        assert_eq!(listed.source(), Source::Api);
+        assert_eq!(listed.ids().unwrap(), ["756", "212"]);
+        let listings = listed.listings().unwrap();
+        assert_eq!(
+            (listings[0].rating.as_str(), listings[1].author.as_str()),
+            ("4.5", "Kejardon")
+        );
+
+        let summary = Summary::default();
+        summary.listed_from(listed.source().as_str());
+        let document = summary.document("download", None, std::time::Duration::ZERO);
+        assert_eq!(document["hack_list"], "api");
+        let off = Config {
+            api_url: Some(String::new()),
+            ..Config::default()
+        };
+        assert_eq!(api_url(&off), None);
+    }
+}
@@ -0,0 +1,249 @@
+use crate::api;
+use crate::config::Config;
+use crate::extract::{self, ExtractSettings};
+use crate::http;
+use crate::i18n;
+use crate::manifest::{Manifest, MANIFEST_FILE};
+use crate::outputs::{Outputs, OUTPUTS_FILE};
+use crate::overrides::Overrides;
+use crate::pins::{Pins, PINS_FILE};
+use crate::pipeline::{self, PatchSettings};
+use crate::reporter::Reporter;
+use crate::shared::Locks;
+use crate::utils::*;
+use reqwest_middleware::ClientWithMiddleware;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// The mirror as a lazy cache for launchers and frontends: `resolve` asks for
+// one hack at a stage, and whichever earlier stages are missing are run for
+// that hack alone before its paths are handed back.
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    /// The hack's directory of downloaded files
+    Downloaded,
+    /// The directories its archives were unpacked into
+    Extracted,
+    /// Its patched ROMs
+    Patched,
+}
+
+// The hack's position on the list, from the directory an earlier run
+// stored it in
+pub fn list_position(hack_dir: &str) -> Option<usize> {
+    hack_dir.split('-').next()?.parse().ok()
+}
+
+// The hack's patched ROMs that are still on disk
+pub fn patched_roms(outputs: &Outputs, id: u32) -> Vec<PathBuf> {
+    outputs
+        .entries
+        .values()
+        .filter(|e| e.hack_id == Some(id))
+        .map(|e| PathBuf::from(&e.output))
+        .filter(|rom| rom.is_file())
+        .collect()
+}
+
+// Download one hack's files into its directory the way the download mode
+// does, without touching the progress of a run through the whole list.
+// Returns the directory's name.
+pub async fn download_hack(
+    config: &Config,
+    client: &ClientWithMiddleware,
+    manifest: &mut Manifest,
+    locks: &Locks,
+    idx: usize,
+    id: u32,
+    log: &mut dyn Write,
+) -> ResultErr<String> {
+    let downloads = Path::new(&config.downloads_dir);
+    let found = pipeline::resolve_hack_files(config, client, id).await?;
+    let title = found.title;
+    let hack_dir = pipeline::hack_dir_name(config, idx, id, title.as_deref());
+    let pins = Pins::load(PINS_FILE)?;
+    for url in found.files {
+        let Some((_, file_name)) = url.rsplit_once('/') else {
+            continue;
+        };
+        let path = downloads
+            .join(&hack_dir)
+            .join(config.names.apply(file_name));
+        if path.exists() {
+            writeln!(log, "skipping {}, already downloaded", url)?;
+        } else {
+            writeln!(log, "url: {}", url)?;
+            fs::create_dir_all(downloads.join(&hack_dir))?;
+            let part = http::part_path(&path);
+            http::download_resuming(client, &url, &part).await?;
+            if let Err(e) = pins.check(id, &config.names.apply(file_name), &fs::read(&part)?) {
+                fs::remove_file(&part)?;
+                return Err(e);
+            }
+            fs::rename(&part, &path)?;
+            tokio::time::sleep(tokio::time::Duration::from_secs(config.file_delay_secs)).await;
+        }
+        manifest.record_file(downloads, id, title.as_deref(), &hack_dir, &path)?;
+        let links = found.links.get(&url).map(Vec::as_slice).unwrap_or_default();
+        manifest.record_links(downloads, id, &path, links)?;
+    }
+    manifest.save_hack(MANIFEST_FILE, id, locks)?;
+    Ok(hack_dir)
+}
+
+// Unpack the archives of the hack in `hack_dir` that aren't yet, returning
+// where they all were unpacked to. A hack without archives is its own
+// download directory.
+pub fn extract_hack(
+    settings: &ExtractSettings,
+    overrides: &Overrides,
+    hack_dir: &str,
+    log: &mut dyn Write,
+) -> ResultErr<Vec<PathBuf>> {
+    let downloads = &settings.downloads_dir;
+    let mut unpacked = Vec::new();
+    for archive in matching_files(downloads.join(hack_dir), any_dir, is_archive_file, log)? {
+        let dir = settings.unpack_dir(archive.path())?;
+        if !dir.exists() {
+            let ov = overrides.for_path(downloads, archive.path());
+            extract::unarchive_in_dir(settings, ov, &archive, log)?;
+        }
+        // Still missing if the overrides disable it
+        if dir.exists() {
+            unpacked.push(dir);
+        }
+    }
+    if unpacked.is_empty() {
+        unpacked.push(downloads.join(hack_dir));
+    }
+    Ok(unpacked)
+}
+
+// The paths to hack `id`'s files at `stage`, after running whichever stages
+// are missing for this hack alone. What it's doing goes to `report`.
+pub async fn ensure(
+    id: u32,
+    stage: Stage,
+    config: Config,
+    overrides: Overrides,
+    mut log: Reporter,
+    report: &dyn Fn(&str),
+) -> ResultErr<Vec<PathBuf>> {
+    if overrides.skips(id) {
+        return Err(format!("hack {} is disabled in overrides", id).into());
+    }
+    let locks = Locks::new(&config);
+    let _lock = locks
+        .hack(id)?
+        .map_err(|holder| format!("hack {} is locked by {}", id, holder))?;
+    if stage == Stage::Patched {
+        let roms = patched_roms(&Outputs::load(OUTPUTS_FILE)?, id);
+        if !roms.is_empty() {
+            return Ok(roms);
+        }
+    }
+
+    let downloads = PathBuf::from(&config.downloads_dir);
+    let mut manifest = Manifest::load(MANIFEST_FILE)?;
+    let hack_dir = if manifest.has_all_files(&downloads, id) {
+        manifest.hacks[&id].dir.clone()
+    } else {
+        if config.work_dir.is_some() {
+            return Err("the downloads directory is read-only while work_dir is set".into());
+        }
+        let retries = http::RetryLog::default();
+        let client = http::client(&config, &retries)?;
+        // A hack with some of its files has its directory already
+        let known = manifest.hacks.get(&id);
+        let idx = match known.and_then(|h| list_position(&h.dir)) {
+            Some(idx) => idx,
+            None => {
+                report(&i18n::tr("fetching-hack-list", &[]));
+                api::hack_list(&config, &client, &mut log)
+                    .await?
+                    .ids()?
+                    .iter()
+                    .position(|h| *h == id.to_string())
+                    .ok_or(format!("hack {} is not on the list of hacks", id))?
+            }
+        };
+        report(&i18n::tr("cache-downloading", &[("id", id.into())]));
+        let dir = download_hack(&config, &client, &mut manifest, &locks, idx, id, &mut log).await?;
+        retries.report(&mut log)?;
+        dir
+    };
+    if stage == Stage::Downloaded {
+        return Ok(vec![downloads.join(hack_dir)]);
+    }
+
+    report(&i18n::tr("cache-patching", &[("id", id.into())]));
+    crate::run_blocking(move |cancel| {
+        let settings = extract::ExtractSettings::from_config(&config, cancel);
+        let unpacked = extract_hack(&settings, &overrides, &hack_dir, &mut log)?;
+        if stage == Stage::Extracted {
+            return Ok(unpacked);
+        }
+        let settings = PatchSettings::from_config(&config, None, false, cancel)?;
+        pipeline::patch_hack(&settings, &overrides, &hack_dir, &mut log)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract;
+    use crate::pipeline::patch_in_dir;
+    use crate::pipeline::tests::test_patch_settings;
+    use crate::utils::tests::TestDir;
+
+    #[test]
+    fn resolve_only_hands_back_what_is_on_disk() {
+        let dir = TestDir::new("resolve");
+        let hack_dir = dir.join("downloads").join("0042-5-Test");
+        fs::create_dir_all(&hack_dir).unwrap();
+        let base_rom = dir.join("base.sfc");
+        fs::write(&base_rom, vec![0u8; 0x8000]).unwrap();
+        fs::write(hack_dir.join("a.ips"), b"PATCH\x00\x00\x10\x00\x01AEOF").unwrap();
+        fs::write(hack_dir.join("b.ips"), b"PATCH\x00\x00\x20\x00\x01BEOF").unwrap();
+        let settings = test_patch_settings(&dir);
+        let mut outputs = Outputs::default();
+        let mut roms = Vec::new();
+        for name in ["a.ips", "b.ips"] {
+            let entry = walkdir::WalkDir::new(hack_dir.join(name))
+                .into_iter()
+                .next()
+                .unwrap()
+                .unwrap();
+            let patched = patch_in_dir(&settings, None, &entry, &mut std::io::sink())
+                .unwrap()
+                .unwrap();
+            outputs
+                .record(&settings.downloads_dir, entry.path(), &patched)
+                .unwrap();
+            roms.push(patched.rom);
+        }
+        fs::remove_file(&roms[1]).unwrap();
+        let found = patched_roms(&outputs, 5);
+        let other = patched_roms(&outputs, 6);
+        let extract = extract::ExtractSettings {
+            downloads_dir: dir.join("downloads"),
+            ..extract::ExtractSettings::from_config(&Config::default(), &Cancel::default())
+        };
+        let unpacked = extract_hack(
+            &extract,
+            &Overrides::default(),
+            "0042-5-Test",
+            &mut std::io::sink(),
+        );
+
+        assert_eq!(found, &roms[..1]);
+        assert!(other.is_empty());
+        // Nothing to unpack, the patches are loose in the hack's directory
+        assert_eq!(unpacked.unwrap(), [hack_dir]);
+        assert_eq!(list_position("0042-5-Test"), Some(42));
+        assert_eq!(list_position("Not a hack"), None);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::schema::{self, Migration};
+use crate::utils::*;
+use reqwest::{Request, Response, ResponseBuilderExt};
+use reqwest_middleware::{Middleware, Next};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use task_local_extensions::Extensions;
+
+const INDEX_FILE: &str = "index.json";
+
+const MIGRATIONS: &[Migration] = &[schema::unchanged];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    // Save every response into the cassette
+    Record,
+    // Answer every request from the cassette, never touching the network
+    Replay,
+}
+
+// A directory of recorded HTTP responses: an index from request to status
+// and headers, and the bodies next to it, one file each
+#[derive(Debug, Clone)]
+pub struct Cassette {
+    dir: PathBuf,
+    mode: Mode,
+    index: Arc<Mutex<serde_json::Map<String, Value>>>,
+}
+
+fn key(req: &Request) -> String {
+    format!("{} {}", req.method(), req.url())
+}
+
+fn other_error(message: String) -> reqwest_middleware::Error {
+    reqwest_middleware::Error::middleware(std::io::Error::other(message))
+}
+
+impl Cassette {
+    pub fn open(dir: &str, mode: Mode) -> ResultErr<Cassette> {
+        let dir = PathBuf::from(dir);
+        let index_file = dir.join(INDEX_FILE);
+        let index = if index_file.exists() {
+            let mut value = read_json(&index_file)?;
+            schema::upgrade(&mut value, MIGRATIONS, INDEX_FILE)?;
+            value
+                .get("requests")
+                .and_then(|r| r.as_object())
+                .cloned()
+                .unwrap_or_default()
+        } else if mode == Mode::Replay {
+            return Err(format!("nothing recorded in {:?}", dir).into());
+        } else {
+            serde_json::Map::new()
+        };
+        fs::create_dir_all(&dir)?;
+        Ok(Cassette {
+            dir,
+            mode,
+            index: Arc::new(Mutex::new(index)),
+        })
+    }
+
+    fn replay(&self, req: &Request) -> ResultErr<Response> {
+        let key = key(req);
+        let index = self.index.lock().unwrap();
+        let entry = index
+            .get(&key)
+            .ok_or_else(|| format!("{} was not recorded", key))?;
+        let mut response = http::Response::builder()
+            .status(json_u64(entry, "status").unwrap_or(200) as u16)
+            .url(req.url().clone());
+        for header in entry
+            .get("headers")
+            .and_then(|h| h.as_array())
+            .into_iter()
+            .flatten()
+        {
+            if let (Some(name), Some(value)) = (header[0].as_str(), header[1].as_str()) {
+                response = response.header(name, value);
+            }
+        }
+        let body = fs::read(self.dir.join(json_str(entry, "body").unwrap_or_default()))?;
+        Ok(Response::from(response.body(body)?))
+    }
+
+    async fn record(&self, key: String, response: Response) -> ResultErr<Response> {
+        let (response, body) = crate::http::buffered(response).await?;
+        let body_file = format!("{}.body", &to_hex(&Sha256::digest(key.as_bytes()))[..16]);
+        fs::write(self.dir.join(&body_file), &body)?;
+
+        let recorded_headers: Vec<_> = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some(json!([name.as_str(), value.to_str().ok()?])))
+            .collect();
+        let mut index = self.index.lock().unwrap();
+        index.insert(
+            key,
+            json!({ "status": response.status().as_u16(), "headers": recorded_headers, "body": body_file }),
+        );
+        let mut value = json!({ "requests": *index });
+        schema::stamp(&mut value, MIGRATIONS);
+        write_json(self.dir.join(INDEX_FILE), &value)?;
+        Ok(response)
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for Cassette {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        match self.mode {
+            Mode::Replay => self.replay(&req).map_err(|e| other_error(e.to_string())),
+            Mode::Record => {
+                let key = key(&req);
+                let response = next.run(req, extensions).await?;
+                self.record(key, response)
+                    .await
+                    .map_err(|e| other_error(e.to_string()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::http;
+    use crate::http::tests::serve;
+    use crate::utils::tests::TestDir;
+
+    #[tokio::test]
+    async fn replayed_runs_never_touch_the_network() {
+        let dir = TestDir::new("cassette");
+        let cassette_dir = dir.to_string_lossy().to_string();
+        let (site, requests) = serve(|_| {
+            let body = "<h1>Hacks</h1>";
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\n\
+                 Connection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        })
+        .await;
+        let url = format!("{}index.php?action=hacks", site);
+
+        let retries = http::RetryLog::default();
+        let mut config = Config {
+            max_retries: 0,
+            cassette: Some(Cassette::open(&cassette_dir, Mode::Record).unwrap()),
+            ..Config::default()
+        };
+        let client = http::client(&config, &retries).unwrap();
+        assert_eq!(
+            http::fetch_page(&client, &url).await.unwrap(),
+            "<h1>Hacks</h1>"
+        );
+
+        config.cassette = Some(Cassette::open(&cassette_dir, Mode::Replay).unwrap());
+        let client = http::client(&config, &retries).unwrap();
+        let page = http::fetch_page(&client, &url).await;
+        let missing = http::fetch_page(&client, &format!("{}&page=2", url)).await;
+        assert_eq!(page.unwrap(), "<h1>Hacks</h1>");
+        assert!(missing.is_err());
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,67 @@
+use crate::config::Config;
+use crate::records::HackMetadata;
+use crate::schema::{self, Migration};
+use crate::utils::*;
+use crate::{collate, played, tags, ListArgs};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+pub const CATALOG_FILE: &str = "catalog.json";
+
+const MIGRATIONS: &[Migration] = &[schema::unchanged];
+
+// Snapshot of the whole site from the last metadata run, used to tell what
+// changed since
+#[derive(Debug, Default)]
+pub struct Catalog {
+    pub hacks: BTreeMap<u32, HackMetadata>,
+}
+
+impl Catalog {
+    pub fn load<P: AsRef<Path>>(fname: P) -> ResultErr<Catalog> {
+        if !fname.as_ref().exists() {
+            return Ok(Catalog::default());
+        }
+        let mut value = read_json(fname)?;
+        schema::upgrade(&mut value, MIGRATIONS, CATALOG_FILE)?;
+        let hacks = value.get("hacks").cloned().unwrap_or_else(|| json!({}));
+        Ok(Catalog {
+            hacks: serde_json::from_value(hacks)?,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, fname: P) -> ResultErr<()> {
+        let mut value = json!({ "hacks": self.hacks });
+        schema::stamp(&mut value, MIGRATIONS);
+        write_json(fname, &value)
+    }
+}
+
+// The catalog's hacks in title order, one per line
+pub fn list(args: &ListArgs, config: &Config) -> ResultErr<()> {
+    let catalog = Catalog::load(CATALOG_FILE)?;
+    if catalog.hacks.is_empty() {
+        return Err("the catalog is empty, run `metadata` first".into());
+    }
+    let played = played::Played::load(played::PLAYED_FILE)?;
+    let tagged = match args.tag.is_empty() {
+        true => None,
+        false => Some(tags::Tags::load(tags::TAGS_FILE)?.select(&args.tag)?),
+    };
+    let mut hacks: Vec<_> = catalog
+        .hacks
+        .iter()
+        .filter(|(id, _)| !args.unplayed || !played.hacks.contains_key(id))
+        .filter(|(id, _)| tagged.as_ref().is_none_or(|t| t.contains(id)))
+        .collect();
+    hacks.sort_by(|a, b| {
+        collate::compare_titles(&a.1.title, &b.1.title, config.sort_strip_articles)
+    });
+    let mut out = std::io::stdout().lock();
+    for (id, hack) in hacks {
+        writeln!(out, "{:>5}  {}  by {}", id, hack.title, hack.author)?;
+    }
+    Ok(())
+}
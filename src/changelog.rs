@@ -0,0 +1,82 @@
+use crate::catalog::Catalog;
+use crate::collate::compare_titles;
+use crate::news::News;
+use crate::utils::*;
+use std::io::Write;
+
+const HACK_URL: &str = "https://metroidconstruction.com/hack.php?id=";
+
+// Quote the latest front page post about a hack under its changelog line
+fn write_announcement(news: &News, id: u32, out: &mut dyn Write) -> ResultErr<()> {
+    if let Some(post) = news.latest(id) {
+        writeln!(out, "  > {}", post.text)?;
+    }
+    Ok(())
+}
+
+// Write a markdown summary of the differences between two catalog snapshots,
+// returning how many hacks were new, updated and removed
+pub fn write_changelog(
+    old: &Catalog,
+    new: &Catalog,
+    news: &News,
+    date: &str,
+    strip_articles: bool,
+    out: &mut dyn Write,
+) -> ResultErr<(usize, usize, usize)> {
+    let mut added: Vec<_> = new
+        .hacks
+        .iter()
+        .filter(|(id, _)| !old.hacks.contains_key(id))
+        .collect();
+    let mut updated: Vec<_> = new
+        .hacks
+        .iter()
+        .filter_map(|(id, hack)| {
+            let changes = hack.changes_from(old.hacks.get(id)?);
+            (!changes.is_empty()).then_some((id, hack, changes))
+        })
+        .collect();
+    let mut removed: Vec<_> = old
+        .hacks
+        .iter()
+        .filter(|(id, _)| !new.hacks.contains_key(id))
+        .collect();
+    added.sort_by(|a, b| compare_titles(&a.1.title, &b.1.title, strip_articles));
+    updated.sort_by(|a, b| compare_titles(&a.1.title, &b.1.title, strip_articles));
+    removed.sort_by(|a, b| compare_titles(&a.1.title, &b.1.title, strip_articles));
+
+    writeln!(out, "# Metroid Construction changes, {}", date)?;
+    writeln!(out)?;
+    writeln!(out, "## New hacks ({})", added.len())?;
+    writeln!(out)?;
+    for (id, hack) in &added {
+        writeln!(
+            out,
+            "- [{}]({}{}) by {} ({}, {})",
+            hack.title, HACK_URL, id, hack.author, hack.genre, hack.difficulty
+        )?;
+        write_announcement(news, **id, out)?;
+    }
+    writeln!(out)?;
+    writeln!(out, "## Updated hacks ({})", updated.len())?;
+    writeln!(out)?;
+    for (id, hack, changes) in &updated {
+        writeln!(
+            out,
+            "- [{}]({}{}): {}",
+            hack.title,
+            HACK_URL,
+            id,
+            changes.join(", ")
+        )?;
+        write_announcement(news, **id, out)?;
+    }
+    writeln!(out)?;
+    writeln!(out, "## Removed hacks ({})", removed.len())?;
+    writeln!(out)?;
+    for (id, hack) in &removed {
+        writeln!(out, "- {} (id {})", hack.title, id)?;
+    }
+    Ok((added.len(), updated.len(), removed.len()))
+}
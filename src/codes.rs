@@ -0,0 +1,147 @@
+use crate::rom;
+use crate::utils::*;
+use std::io::Write;
+
+// Game Genie codes use their own letters for the hex digits
+const GENIE_DIGITS: &str = "DF4709156BC8A23E";
+
+// A cheat code turned into the ROM byte it changes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Code {
+    pub text: String,
+    pub address: u32,
+    pub value: u8,
+}
+
+// `DDAA-AAAA`, with the address bits shuffled around
+fn decode_game_genie(code: &str) -> Option<(u32, u8)> {
+    let (left, right) = code.split_once('-')?;
+    if left.len() != 4 || right.len() != 4 {
+        return None;
+    }
+    let mut raw = 0u32;
+    for c in left.chars().chain(right.chars()) {
+        let digit = GENIE_DIGITS.find(c.to_ascii_uppercase())?;
+        raw = raw << 4 | digit as u32;
+    }
+    let value = (raw >> 24) as u8;
+    let n = raw & 0xffffff;
+    let address = ((n & 0x003c00) << 10)
+        | ((n & 0x00003c) << 14)
+        | ((n & 0xf00000) >> 8)
+        | ((n & 0x000003) << 10)
+        | ((n & 0x00c000) >> 6)
+        | ((n & 0x0f0000) >> 12)
+        | ((n & 0x0003c0) >> 6);
+    Some((address, value))
+}
+
+// `AAAAAADD`, a plain address and value
+fn decode_par(code: &str) -> Option<(u32, u8)> {
+    if code.len() != 8 || !code.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let raw = u32::from_str_radix(code, 16).ok()?;
+    Some((raw >> 8, raw as u8))
+}
+
+pub fn decode(code: &str) -> Option<Code> {
+    let (address, value) = decode_game_genie(code).or_else(|| decode_par(code))?;
+    Some(Code {
+        text: code.to_uppercase(),
+        address,
+        value,
+    })
+}
+
+// Code lists put one code at the start of each line, usually followed by
+// what it does. A text file with any other line in it is something else,
+// like a readme that happens to have a line start with a RAM address, and
+// has no codes.
+pub fn parse_code_list(text: &str) -> Vec<Code> {
+    text.lines()
+        .filter_map(|l| l.split_whitespace().next())
+        .map(decode)
+        .collect::<Option<_>>()
+        .unwrap_or_default()
+}
+
+// Where a LoROM bus address (Super Metroid's mapping) lives in the ROM file.
+// Codes for work RAM or anything else that isn't ROM have no such place.
+pub fn lorom_offset(address: u32) -> Option<usize> {
+    let bank = address >> 16;
+    let addr = address & 0xffff;
+    if addr < 0x8000 || bank == 0x7e || bank == 0x7f {
+        return None;
+    }
+    Some(((bank & 0x7f) * 0x8000 + (addr & 0x7fff)) as usize)
+}
+
+// Apply codes to a ROM image, returning how many could be
+pub fn apply_codes(contents: &mut [u8], codes: &[Code], log: &mut dyn Write) -> ResultErr<usize> {
+    let header = if rom::has_copier_header(contents.len()) {
+        rom::COPIER_HEADER_SIZE
+    } else {
+        0
+    };
+    let mut applied = 0;
+    for code in codes {
+        let offset = lorom_offset(code.address).map(|o| o + header);
+        match offset.filter(|o| *o < contents.len()) {
+            Some(offset) => {
+                writeln!(
+                    log,
+                    "{}: writing {:#04x} at {:#x}",
+                    code.text, code.value, offset
+                )?;
+                contents[offset] = code.value;
+                applied += 1;
+            }
+            None => writeln!(
+                log,
+                "{}: {:06x} isn't in the ROM, RAM codes can't be applied",
+                code.text, code.address
+            )?,
+        }
+    }
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cheat_codes_map_to_rom_offsets() {
+        let list =
+            parse_code_list("80D5A3EA infinite missiles\nC2DD-DDDD\n\n7E09C2FF max energy\n");
+        assert_eq!(list.len(), 3);
+        assert_eq!((list[0].address, list[0].value), (0x80d5a3, 0xea));
+        assert_eq!(lorom_offset(list[0].address), Some(0x55a3));
+        assert_eq!((list[1].address, list[1].value), (0, 0xad));
+        assert_eq!(lorom_offset(list[2].address), None);
+
+        let mut rom = vec![0; 0x10200];
+        let applied = apply_codes(&mut rom, &list, &mut std::io::sink()).unwrap();
+        assert_eq!(applied, 1);
+        assert_eq!(rom[0x200 + 0x55a3], 0xea);
+    }
+
+    #[test]
+    fn readmes_are_not_code_lists() {
+        let readme = "\
+Hyper Metroid v1.0 by RealRed
+
+Patch a headerless Super Metroid (JU) ROM with the included IPS.
+CRC32: D63ED5F8
+
+7E0A1234 in RAM holds the item percentage, if you want to cheat.
+ABCD-1234 is the password for the bonus room.
+";
+        assert_eq!(parse_code_list(readme), []);
+        assert_eq!(
+            parse_code_list("CRC32: 12345678\n80D5A3EA infinite missiles"),
+            []
+        );
+    }
+}
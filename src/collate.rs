@@ -0,0 +1,105 @@
+use std::cmp::Ordering;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+// Leading words a title is filed without, `The Chozo Trials` goes under C
+const ARTICLES: &[&str] = &["the ", "a ", "an "];
+
+// Numbers sort before letters and by value, so `Hack 2` comes before `Hack 10`
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Chunk {
+    Number(usize, String),
+    Text(String),
+}
+
+fn without_article(title: &str) -> &str {
+    let title = title.trim_start();
+    for article in ARTICLES {
+        if let Some(start) = title.get(..article.len()) {
+            if start.eq_ignore_ascii_case(article) && title.len() > article.len() {
+                return title[article.len()..].trim_start();
+            }
+        }
+    }
+    title
+}
+
+// What two titles are compared on first: the letters without their accents
+// or case, and the numbers in them
+fn primary_key(title: &str) -> Vec<Chunk> {
+    let base: String = title
+        .nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(|c| c.to_lowercase())
+        .collect();
+    let mut chunks = Vec::new();
+    let mut rest = base.as_str();
+    while let Some(first) = rest.chars().next() {
+        let is_digit = first.is_ascii_digit();
+        let end = rest
+            .find(|c: char| c.is_ascii_digit() != is_digit)
+            .unwrap_or(rest.len());
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(if is_digit {
+            let digits = chunk.trim_start_matches('0');
+            Chunk::Number(digits.len(), digits.to_owned())
+        } else {
+            Chunk::Text(chunk.to_owned())
+        });
+        rest = tail;
+    }
+    chunks
+}
+
+// Compare titles the way a reader expects them listed. Accents and then case
+// only decide between titles that are otherwise the same.
+pub fn compare_titles(a: &str, b: &str, strip_articles: bool) -> Ordering {
+    let (a, b) = if strip_articles {
+        (without_article(a), without_article(b))
+    } else {
+        (a, b)
+    };
+    primary_key(a)
+        .cmp(&primary_key(b))
+        .then_with(|| {
+            let accents = |s: &str| s.nfkd().flat_map(|c| c.to_lowercase()).collect::<String>();
+            accents(a).cmp(&accents(b))
+        })
+        // Lowercase first, like most dictionaries
+        .then_with(|| b.cmp(a))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn titles_sort_like_a_reader_expects() {
+        let mut titles = vec![
+            "The Chozo Trials",
+            "Zebes Redux",
+            "hack 10",
+            "Éclipse",
+            "Hack 2",
+            "eclipse",
+            "A Bit Harder",
+            "Ascent",
+        ];
+        titles.sort_by(|a, b| compare_titles(a, b, false));
+        assert_eq!(
+            titles,
+            [
+                "A Bit Harder",
+                "Ascent",
+                "eclipse",
+                "Éclipse",
+                "Hack 2",
+                "hack 10",
+                "The Chozo Trials",
+                "Zebes Redux"
+            ]
+        );
+        titles.sort_by(|a, b| compare_titles(a, b, true));
+        assert_eq!(&titles[..3], ["Ascent", "A Bit Harder", "The Chozo Trials"]);
+    }
+}
@@ -0,0 +1,398 @@
+use crate::cassette::Cassette;
+use crate::events::EventStream;
+use crate::i18n;
+use crate::rom;
+use crate::schema::{self, Migration};
+use crate::utils::*;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+pub const CONFIG_FILE: &str = "metconst.json";
+
+const MIGRATIONS: &[Migration] = &[schema::unchanged];
+
+// Settings missing from the file keep their defaults
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub base_rom: Option<String>,
+    pub downloads_dir: String,
+    pub patched_dir: String,
+    // Screenshots and their thumbnails, one directory per hack
+    pub screenshots_dir: String,
+    // When set, the downloads directory is treated as read-only (e.g. a
+    // mounted mirror) and extracted files are written here instead
+    pub work_dir: Option<String>,
+    // When set, archives are unpacked under here, one directory per hack,
+    // instead of next to the archives
+    pub extract_dir: Option<String>,
+    // Scratch space archives are extracted into before being moved into place
+    pub staging_dir: String,
+    // Most bytes a single archive may expand to
+    pub max_extract_bytes: u64,
+    // How often to say which archive is still being extracted
+    pub heartbeat_secs: u64,
+    // Archives taking longer than this are listed at the end of the run
+    pub slow_item_secs: u64,
+    // Extracting or patching a single item is abandoned after this long
+    pub item_timeout_secs: u64,
+    // Largest decompression dictionary, in bytes, a 7z archive may use
+    pub max_7z_memory: u64,
+    // Pause after every page fetched from the site
+    pub page_delay_secs: u64,
+    // Pause after every archive downloaded
+    pub file_delay_secs: u64,
+    pub max_retries: u32,
+    // Where the site is scraped from, always ending in '/'. Point it at a
+    // local snapshot or a staging copy to try out changes.
+    #[serde(deserialize_with = "site_url")]
+    pub site_url: String,
+    // Idle connections kept open to the site between requests, and for how
+    // long
+    pub http_pool_max_idle: usize,
+    pub http_pool_idle_secs: u64,
+    // `host:ip` pairs that skip DNS, like curl's `--resolve`
+    pub resolve: Vec<String>,
+    // Base URLs that stand in for a host, e.g. an internal caching proxy
+    pub host_overrides: BTreeMap<String, String>,
+    // Every page and file fetched is also archived into this WARC file
+    pub warc_file: Option<String>,
+    // Recorded HTTP responses to save to or answer from, set from the
+    // command line and never saved with the rest
+    #[serde(skip)]
+    pub cassette: Option<Cassette>,
+    // Where progress events are streamed to, likewise only from the
+    // command line
+    #[serde(skip)]
+    pub events: Option<EventStream>,
+    // Questions take their default answer, from --yes
+    #[serde(skip)]
+    pub assume_yes: bool,
+    // The download mode notes how far down the hack list it got after this
+    // many hacks, so an interrupted run resumes from there
+    pub download_checkpoint_every: u64,
+    // The asar assembler, for hacks that ship .asm patches
+    pub asar: Option<String>,
+    // Emulator the smoke-test mode runs each patched ROM in, `{rom}` is
+    // replaced with the ROM's path
+    pub smoke_test_command: Vec<String>,
+    // A ROM passes if the emulator is still running after this long, or
+    // exited cleanly before
+    pub smoke_test_secs: u64,
+    // Emulator the `gui` mode plays hacks in, `{rom}` is replaced with the
+    // ROM's path. The smoke-test emulator when empty.
+    pub play_command: Vec<String>,
+    // Restrict the names of patched ROMs and exports to what FAT32 allows
+    pub fat32_safe: bool,
+    // How downloaded, extracted and patched files are named, saved as the
+    // `name_*` settings
+    #[serde(flatten)]
+    pub names: NameRules,
+    // Sort titles in listings without a leading "The", "A" or "An"
+    pub sort_strip_articles: bool,
+    // What console messages and the HTML catalog are written in, e.g. "pt"
+    // or "es". The system's language when unset, English when there's no
+    // translation for it.
+    pub language: Option<String>,
+    // Hack ids or title regexes the download mode never fetches
+    pub blocklist: Vec<String>,
+    // When not empty, the download mode only fetches these ids or titles
+    pub allowlist: Vec<String>,
+    // `prune` removes extracted trees that are older than this many days
+    pub prune_extracted_days: Option<u64>,
+    // `prune` keeps only the newest archive in each hack directory
+    pub prune_old_archives: bool,
+    // `prune` drops the screenshots of hacks rated below this
+    pub prune_screenshots_below: Option<f64>,
+    // For mirrors shared by several users: the umask files are created with,
+    // as an octal string like "002", and the group that owns the mirror's
+    // directories
+    pub umask: Option<String>,
+    pub group: Option<String>,
+    // A hack locked by another run for longer than this is assumed to be
+    // left over from a run that died
+    pub lock_stale_secs: u64,
+    // Groups of authors to flag hacks by, each with the names its members
+    // post under. Every group is a `by_<group>` column in metadata.csv.
+    // An empty object turns off even the default group.
+    pub author_groups: BTreeMap<String, Vec<String>>,
+    // Exports and changelogs are signed with these when set: the path of a
+    // minisign secret key, and the id of a gpg key
+    pub minisign_key: Option<String>,
+    pub gpg_key: Option<String>,
+    // A downloads mirror on another machine, over http(s) (e.g. WebDAV) or
+    // sftp, which the unzip and patch modes fetch from instead of reading
+    // downloads_dir
+    pub remote_downloads: Option<String>,
+    // The site's JSON list of hacks, when it isn't at api/hacks.json on the
+    // site. Empty to always scrape the hack list.
+    pub api_url: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            base_rom: None,
+            downloads_dir: "downloads".to_owned(),
+            patched_dir: "patched".to_owned(),
+            screenshots_dir: "screenshots".to_owned(),
+            work_dir: None,
+            extract_dir: None,
+            staging_dir: "staging".to_owned(),
+            max_extract_bytes: 4 * 1024 * 1024 * 1024,
+            heartbeat_secs: 30,
+            slow_item_secs: 120,
+            item_timeout_secs: 30 * 60,
+            max_7z_memory: 512 * 1024 * 1024,
+            page_delay_secs: 1,
+            file_delay_secs: 5,
+            max_retries: 10,
+            site_url: crate::scrape::SITE_URL.to_owned(),
+            http_pool_max_idle: 4,
+            http_pool_idle_secs: 90,
+            resolve: Vec::new(),
+            host_overrides: BTreeMap::new(),
+            warc_file: None,
+            cassette: None,
+            events: None,
+            assume_yes: false,
+            download_checkpoint_every: 10,
+            asar: None,
+            smoke_test_command: Vec::new(),
+            smoke_test_secs: 5,
+            play_command: Vec::new(),
+            fat32_safe: false,
+            names: NameRules::default(),
+            sort_strip_articles: false,
+            language: None,
+            blocklist: Vec::new(),
+            allowlist: Vec::new(),
+            prune_extracted_days: None,
+            prune_old_archives: false,
+            prune_screenshots_below: None,
+            umask: None,
+            group: None,
+            lock_stale_secs: 6 * 60 * 60,
+            author_groups: BTreeMap::from([(
+                "pedro".to_owned(),
+                [
+                    "crimsonsunbird",
+                    "Juan Dennys",
+                    "pedro123",
+                    "jailsonmendes",
+                    "FaiskaBr",
+                ]
+                .map(String::from)
+                .to_vec(),
+            )]),
+            minisign_key: None,
+            gpg_key: None,
+            remote_downloads: None,
+            api_url: None,
+        }
+    }
+}
+
+impl Config {
+    // A missing config file just means the defaults
+    pub fn load<P: AsRef<Path>>(fname: P) -> ResultErr<Config> {
+        if !fname.as_ref().exists() {
+            return Ok(Config::default());
+        }
+        let mut value = read_json(fname)?;
+        schema::upgrade(&mut value, MIGRATIONS, CONFIG_FILE)?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    // Everything a portable setup writes has to stay under its directory,
+    // so it still finds it after being moved. Tools like the base ROM and
+    // asar may live anywhere.
+    pub fn check_portable(&self) -> ResultErr<()> {
+        let mut dirs = vec![
+            ("downloads_dir", Some(&self.downloads_dir)),
+            ("patched_dir", Some(&self.patched_dir)),
+            ("screenshots_dir", Some(&self.screenshots_dir)),
+            ("staging_dir", Some(&self.staging_dir)),
+            ("work_dir", self.work_dir.as_ref()),
+            ("extract_dir", self.extract_dir.as_ref()),
+            ("warc_file", self.warc_file.as_ref()),
+        ];
+        dirs.retain(|(_, dir)| dir.is_some_and(|d| Path::new(d).has_root()));
+        if dirs.is_empty() {
+            return Ok(());
+        }
+        let names: Vec<_> = dirs.iter().map(|(name, _)| *name).collect();
+        Err(format!(
+            "--portable needs relative paths, but {} has {} set to an absolute one",
+            CONFIG_FILE,
+            names.join(", ")
+        )
+        .into())
+    }
+
+    // The `by_<group>` flags of a hack by `author`, one per author group.
+    // Names match regardless of case.
+    pub fn author_flags(&self, author: &str) -> BTreeMap<String, bool> {
+        let author = author.trim().to_lowercase();
+        self.author_groups
+            .iter()
+            .map(|(group, names)| {
+                let member = names.iter().any(|n| n.to_lowercase() == author);
+                (format!("by_{}", group), member)
+            })
+            .collect()
+    }
+
+    // Where archives get unpacked, if not next to the archives themselves
+    pub fn extract_root(&self) -> Option<&str> {
+        self.extract_dir.as_deref().or(self.work_dir.as_deref())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, fname: P) -> ResultErr<()> {
+        let mut value = serde_json::to_value(self)?;
+        schema::stamp(&mut value, MIGRATIONS);
+        write_json(fname, &value)
+    }
+}
+
+// Stored with a trailing '/' so paths can be appended to it
+fn site_url<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let url = String::deserialize(deserializer)?;
+    Ok(format!("{}/", url.trim_end_matches('/')))
+}
+
+// With --yes the default is taken without reading anything
+fn prompt(question: &str, default: &str, yes: bool) -> ResultErr<String> {
+    print!("{} [{}]: ", question, default);
+    if yes {
+        println!("{}", default);
+        return Ok(default.to_owned());
+    }
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    let answer = answer.trim();
+    Ok(if answer.is_empty() {
+        default.to_owned()
+    } else {
+        answer.to_owned()
+    })
+}
+
+pub fn init(mut config: Config) -> ResultErr<()> {
+    let yes = config.assume_yes;
+    let dir = std::env::current_dir()?.to_string_lossy().into_owned();
+    let args = [("path", CONFIG_FILE.into()), ("dir", dir.into())];
+    println!("{}", i18n::tr("init-intro", &args));
+    println!("{}", i18n::tr("init-relative", &[]));
+    loop {
+        let current = config.base_rom.clone().unwrap_or_default();
+        let base_rom = prompt(&i18n::tr("init-base-rom", &[]), &current, yes)?;
+        if base_rom.is_empty() {
+            println!("{}", i18n::tr("init-no-base-rom", &[]));
+            break;
+        }
+        match rom::check_base_rom(&base_rom) {
+            Ok(()) => {
+                config.base_rom = Some(base_rom);
+                break;
+            }
+            // Asking again would give the same answer
+            Err(e) if yes => return Err(e),
+            Err(e) => println!("{}", e),
+        }
+    }
+    config.downloads_dir = prompt(&i18n::tr("init-downloads", &[]), &config.downloads_dir, yes)?;
+    config.patched_dir = prompt(&i18n::tr("init-patched", &[]), &config.patched_dir, yes)?;
+    config.page_delay_secs = prompt(
+        &i18n::tr("init-page-delay", &[]),
+        &config.page_delay_secs.to_string(),
+        yes,
+    )?
+    .parse()?;
+    config.file_delay_secs = prompt(
+        &i18n::tr("init-file-delay", &[]),
+        &config.file_delay_secs.to_string(),
+        yes,
+    )?
+    .parse()?;
+    config.max_retries = prompt(
+        &i18n::tr("init-retries", &[]),
+        &config.max_retries.to_string(),
+        yes,
+    )?
+    .parse()?;
+    config.save(CONFIG_FILE)?;
+    println!("{}", i18n::tr("wrote", &[("path", CONFIG_FILE.into())]));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::TestDir;
+    use std::fs;
+
+    #[test]
+    fn settings_missing_from_the_file_keep_their_defaults() {
+        let dir = TestDir::new("config");
+        let file = dir.join(CONFIG_FILE);
+        fs::write(
+            &file,
+            r#"{"site_url": "http://localhost:8000", "name_lowercase": true, "author_groups": {}}"#,
+        )
+        .unwrap();
+        let loaded = Config::load(&file).unwrap();
+        let mut saved = loaded.clone();
+        saved.names.max_len = Some(32);
+        saved.save(&file).unwrap();
+        let value = read_json(&file).unwrap();
+        let reloaded = Config::load(&file).unwrap();
+
+        assert_eq!(loaded.site_url, "http://localhost:8000/");
+        assert!(loaded.names.lowercase);
+        assert!(loaded.author_groups.is_empty());
+        assert_eq!(loaded.downloads_dir, "downloads");
+        assert_eq!(value["name_max_len"], 32);
+        assert_eq!(schema::version(&value), schema::current_version(MIGRATIONS));
+        assert!(value.get("assume_yes").is_none());
+        assert_eq!(reloaded.names, saved.names);
+    }
+
+    #[test]
+    fn portable_setups_refuse_absolute_state_paths() {
+        let mut config = Config {
+            base_rom: Some("/roms/sm.sfc".to_owned()),
+            ..Config::default()
+        };
+        config.check_portable().unwrap();
+
+        config.patched_dir = "/mnt/patched".to_owned();
+        let err = config.check_portable().unwrap_err().to_string();
+        assert!(err.contains("patched_dir"), "{}", err);
+        assert!(!err.contains("downloads_dir"), "{}", err);
+    }
+
+    #[test]
+    fn author_groups_flag_every_alias_regardless_of_case() {
+        let config = Config {
+            author_groups: [
+                (
+                    "team".to_owned(),
+                    vec!["Oi27".to_owned(), "Digital Mantra".to_owned()],
+                ),
+                ("solo".to_owned(), vec!["P.JBoy".to_owned()]),
+            ]
+            .into(),
+            ..Config::default()
+        };
+        let flags = config.author_flags(" digital mantra");
+        assert_eq!(
+            flags.into_iter().collect::<Vec<_>>(),
+            [("by_solo".to_owned(), false), ("by_team".to_owned(), true)]
+        );
+        assert!(Config::default().author_flags("CrimsonSunbird")["by_pedro"]);
+    }
+}
@@ -0,0 +1,200 @@
+use crate::cache;
+use crate::config::Config;
+use crate::manifest::{Manifest, MANIFEST_FILE};
+use crate::outputs::{Outputs, OUTPUTS_FILE};
+use crate::overrides::Overrides;
+use crate::reporter::Reporter;
+use crate::utils::*;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// `--control stdio` keeps one process around for GUIs and scripts: they
+// send commands on stdin as one JSON object per line, e.g.
+//
+//   {"id": 1, "command": "download", "hack": 756}
+//   {"id": 2, "command": "patch", "hack": 756}
+//   {"id": 3, "command": "status"}
+//
+// and read JSON events back on stdout, one per line, each carrying the id
+// of the command it's about: "started", any number of "progress", then
+// "done" or "error". Commands run one after the other until stdin closes.
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    /// Commands on stdin, events on stdout
+    Stdio,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    // Make sure the hack is downloaded, or patched, fetching what's missing
+    Download(u32),
+    Patch(u32),
+    // The whole mirror, or one hack
+    Status(Option<u32>),
+}
+
+impl Command {
+    // The command on one line of input, and the id to answer it with
+    pub fn parse(line: &str) -> (Value, ResultErr<Command>) {
+        let value: Value = match serde_json::from_str(line) {
+            Ok(value) => value,
+            Err(e) => return (Value::Null, Err(format!("not JSON: {}", e).into())),
+        };
+        let id = value.get("id").cloned().unwrap_or(Value::Null);
+        let hack = json_u64(&value, "hack").map(|h| h as u32);
+        let command = match (json_str(&value, "command").as_deref(), hack) {
+            (Some("download"), Some(hack)) => Ok(Command::Download(hack)),
+            (Some("patch"), Some(hack)) => Ok(Command::Patch(hack)),
+            (Some("download" | "patch"), None) => Err("missing the hack id".into()),
+            (Some("status"), hack) => Ok(Command::Status(hack)),
+            (Some(other), _) => Err(format!("unknown command {:?}", other).into()),
+            (None, _) => Err("missing the command".into()),
+        };
+        (id, command)
+    }
+}
+
+// One event about the command with `id`, with `fields` added to it
+pub fn event(id: &Value, event: &str, fields: Value) -> Value {
+    let mut value = json!({ "id": id, "event": event });
+    if let (Some(value), Value::Object(fields)) = (value.as_object_mut(), fields) {
+        value.extend(fields);
+    }
+    value
+}
+
+// Events are flushed one by one so they arrive as they happen
+pub fn send(id: &Value, name: &str, fields: Value) -> ResultErr<()> {
+    let mut out = std::io::stdout().lock();
+    writeln!(out, "{}", event(id, name, fields))?;
+    out.flush()?;
+    Ok(())
+}
+
+pub fn paths_json(paths: &[PathBuf]) -> Value {
+    json!(paths
+        .iter()
+        .map(|p| p.to_string_lossy())
+        .collect::<Vec<_>>())
+}
+
+// What's been mirrored and patched, of everything or of one hack
+pub fn status(
+    manifest: &Manifest,
+    outputs: &Outputs,
+    downloads: &Path,
+    hack: Option<u32>,
+) -> Value {
+    let Some(id) = hack else {
+        let files: Vec<_> = manifest.files().collect();
+        return json!({
+            "hacks": manifest.hacks.len(),
+            "files": files.len(),
+            "bytes": files.iter().map(|f| f.size).sum::<u64>(),
+            "patched": outputs.entries.len(),
+        });
+    };
+    let entry = manifest.hacks.get(&id);
+    json!({
+        "hack": id,
+        "title": entry.and_then(|h| h.title.clone()),
+        "downloaded": manifest.has_all_files(downloads, id),
+        "files": entry.map(|h| h.files.iter().map(|f| f.path.clone()).collect::<Vec<_>>()),
+        "patched": paths_json(&cache::patched_roms(outputs, id)),
+    })
+}
+
+// The `--control` loop: one command per line of input, each run to the end
+// before the next is read, with everything said about it sent as events
+pub async fn control(channel: Channel, config: Config, overrides: Overrides) -> ResultErr<()> {
+    use tokio::io::AsyncBufReadExt;
+    let Channel::Stdio = channel;
+    let log = Reporter::open("control.txt")?;
+    let null = Value::Null;
+    send(&null, "ready", json!({}))?;
+    let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (id, command) = Command::parse(&line);
+        let command = match command {
+            Ok(command) => command,
+            Err(e) => {
+                send(&id, "error", json!({ "message": e.to_string() }))?;
+                continue;
+            }
+        };
+        send(&id, "started", json!({}))?;
+        let (hack, stage) = match command {
+            Command::Download(hack) => (hack, cache::Stage::Downloaded),
+            Command::Patch(hack) => (hack, cache::Stage::Patched),
+            Command::Status(hack) => {
+                let status = status(
+                    &Manifest::load(MANIFEST_FILE)?,
+                    &Outputs::load(OUTPUTS_FILE)?,
+                    Path::new(&config.downloads_dir),
+                    hack,
+                );
+                send(&id, "done", json!({ "status": status }))?;
+                continue;
+            }
+        };
+        let report = |message: &str| {
+            let fields = json!({ "message": message });
+            let _ = send(&id, "progress", fields);
+        };
+        let ensured = cache::ensure(
+            hack,
+            stage,
+            config.clone(),
+            overrides.clone(),
+            log.clone(),
+            &report,
+        )
+        .await;
+        let (name, fields) = match ensured {
+            Ok(paths) => ("done", json!({ "paths": paths_json(&paths) })),
+            Err(e) => ("error", json!({ "message": e.to_string() })),
+        };
+        send(&id, name, fields)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_commands_are_answered_with_their_id() {
+        let parse = |line| {
+            let (id, command) = Command::parse(line);
+            (id, command.map_err(|e| e.to_string()))
+        };
+        assert_eq!(
+            parse(r#"{"id": 1, "command": "patch", "hack": 756}"#),
+            (serde_json::json!(1), Ok(Command::Patch(756)))
+        );
+        assert_eq!(
+            parse(r#"{"id": "a", "command": "status"}"#),
+            (serde_json::json!("a"), Ok(Command::Status(None)))
+        );
+        let (id, missing) = parse(r#"{"id": 2, "command": "download"}"#);
+        assert_eq!((id, missing.is_err()), (serde_json::json!(2), true));
+        let (id, garbage) = parse("download 756");
+        assert_eq!((id, garbage.is_err()), (serde_json::Value::Null, true));
+
+        let event = event(
+            &serde_json::json!(1),
+            "progress",
+            serde_json::json!({ "message": "Downloading hack 756..." }),
+        );
+        assert_eq!(
+            event.to_string(),
+            r#"{"event":"progress","id":1,"message":"Downloading hack 756..."}"#
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use crate::schema::{self, Migration};
+use crate::utils::*;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const COUNTS_FILE: &str = "downloads.json";
+
+const MIGRATIONS: &[Migration] = &[schema::unchanged];
+
+// One download counter reading per hack per metadata run, oldest first
+#[derive(Debug, Default)]
+pub struct DownloadCounts {
+    pub hacks: BTreeMap<u32, Vec<(String, u64)>>,
+}
+
+impl DownloadCounts {
+    pub fn load<P: AsRef<Path>>(fname: P) -> ResultErr<DownloadCounts> {
+        let mut counts = DownloadCounts::default();
+        if !fname.as_ref().exists() {
+            return Ok(counts);
+        }
+        let mut value = read_json(fname)?;
+        schema::upgrade(&mut value, MIGRATIONS, COUNTS_FILE)?;
+        if let Some(hacks) = value.get("hacks").and_then(|h| h.as_object()) {
+            for (id, samples) in hacks {
+                let samples = samples
+                    .as_array()
+                    .map(|a| {
+                        a.iter()
+                            .filter_map(|s| Some((json_str(s, "date")?, json_u64(s, "downloads")?)))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                counts.hacks.insert(id.parse()?, samples);
+            }
+        }
+        Ok(counts)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, fname: P) -> ResultErr<()> {
+        let hacks: serde_json::Map<_, _> = self
+            .hacks
+            .iter()
+            .map(|(id, samples)| {
+                let samples: Vec<Value> = samples
+                    .iter()
+                    .map(|(date, downloads)| json!({ "date": date, "downloads": downloads }))
+                    .collect();
+                (id.to_string(), json!(samples))
+            })
+            .collect();
+        let mut value = json!({ "hacks": hacks });
+        schema::stamp(&mut value, MIGRATIONS);
+        write_json(fname, &value)
+    }
+
+    // Running metadata twice on the same day replaces that day's reading
+    pub fn record(&mut self, id: u32, date: &str, downloads: u64) {
+        let samples = self.hacks.entry(id).or_default();
+        match samples.last_mut() {
+            Some((last, count)) if last == date => *count = downloads,
+            _ => samples.push((date.to_owned(), downloads)),
+        }
+    }
+}
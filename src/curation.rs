@@ -0,0 +1,175 @@
+use crate::catalog::Catalog;
+use crate::played::{format_minutes, Play, Played};
+use crate::records;
+use crate::schema::{self, Migration};
+use crate::tags::Tags;
+use crate::utils::*;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::path::Path;
+
+// The user's tags and plays on their own, small enough to pass around a
+// group without the mirror. JSON files can be imported by the others, CSV
+// is for spreadsheets.
+
+const MIGRATIONS: &[Migration] = &[schema::unchanged];
+
+// One hack of a CSV export. Fields are in column order.
+#[derive(Debug, Default, Serialize)]
+struct CurationRow {
+    id: u32,
+    title: String,
+    tags: String,
+    notes: String,
+    finished: String,
+    rating: String,
+    time: String,
+}
+
+fn curated_ids(tags: &Tags, played: &Played) -> BTreeSet<u32> {
+    tags.hacks
+        .keys()
+        .chain(played.hacks.keys())
+        .copied()
+        .collect()
+}
+
+// Writes the export as CSV when the file name says so, JSON otherwise.
+// Returns how many hacks it covers.
+pub fn export(tags: &Tags, played: &Played, catalog: &Catalog, fname: &Path) -> ResultErr<usize> {
+    let ids = curated_ids(tags, played);
+    let title = |id| {
+        catalog
+            .hacks
+            .get(&id)
+            .map(|h| h.title.clone())
+            .unwrap_or_default()
+    };
+    if fname
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("csv"))
+    {
+        let mut out = create_output(fname)?;
+        writeln!(out, "{}", records::csv_header::<CurationRow>()?)?;
+        for id in &ids {
+            let hack_tags = tags.hacks.get(id);
+            let play = played.hacks.get(id).cloned().unwrap_or_default();
+            let row = CurationRow {
+                id: *id,
+                title: title(*id),
+                tags: hack_tags
+                    .map(|t| t.keys().cloned().collect::<Vec<_>>().join("; "))
+                    .unwrap_or_default(),
+                notes: hack_tags
+                    .map(|t| {
+                        t.iter()
+                            .filter(|(_, note)| !note.is_empty())
+                            .map(|(tag, note)| format!("{}: {}", tag, note))
+                            .collect::<Vec<_>>()
+                            .join("; ")
+                    })
+                    .unwrap_or_default(),
+                finished: play.finished,
+                rating: play.rating.map(|r| r.to_string()).unwrap_or_default(),
+                time: play.minutes.map(format_minutes).unwrap_or_default(),
+            };
+            writeln!(out, "{}", records::csv_row(&row)?)?;
+        }
+        out.flush()?;
+    } else {
+        let hacks: serde_json::Map<_, _> = ids
+            .iter()
+            .map(|id| {
+                let hack = json!({
+                    "title": title(*id),
+                    "tags": tags.hacks.get(id),
+                    "played": played.hacks.get(id),
+                });
+                (id.to_string(), hack)
+            })
+            .collect();
+        let mut value = json!({ "hacks": hacks });
+        schema::stamp(&mut value, MIGRATIONS);
+        write_json(fname, &value)?;
+    }
+    Ok(ids.len())
+}
+
+// Merge someone else's export into the local tags, as `<from>/<tag>` so
+// theirs stay apart from ours, and their plays as a `<from>/played` tag
+// noting their rating and time. Returns how many hacks got tags.
+pub fn import(tags: &mut Tags, fname: &Path, from: &str) -> ResultErr<usize> {
+    let mut value = read_json(fname)?;
+    schema::upgrade(&mut value, MIGRATIONS, &fname.to_string_lossy())?;
+    let Some(hacks) = value.get("hacks").and_then(|h| h.as_object()) else {
+        return Err(format!("{:?} isn't a curation export", fname).into());
+    };
+    let mut count = 0;
+    for (id, hack) in hacks {
+        let id: u32 = id.parse()?;
+        let mut tagged = false;
+        if let Some(theirs) = hack.get("tags").and_then(|t| t.as_object()) {
+            for (tag, note) in theirs {
+                let note = note.as_str().unwrap_or_default();
+                tags.add(id, &format!("{}/{}", from, tag), note)?;
+                tagged = true;
+            }
+        }
+        if let Some(play) = hack.get("played").filter(|p| !p.is_null()) {
+            let play: Play = serde_json::from_value(play.clone())?;
+            tags.add(id, &format!("{}/played", from), &describe_play(&play))?;
+            tagged = true;
+        }
+        if tagged {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+fn describe_play(play: &Play) -> String {
+    let mut parts = vec![format!("finished {}", play.finished)];
+    parts.extend(play.rating.map(|r| format!("rated {}", r)));
+    parts.extend(play.minutes.map(|m| format!("took {}", format_minutes(m))));
+    parts.join(", ")
+}
+
+// Names imports after the file they came from, e.g. alice.json as "alice"
+pub fn default_source(fname: &Path) -> String {
+    fname
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "shared".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::TestDir;
+    use crate::{played, tags};
+
+    #[test]
+    fn curation_exports_import_as_prefixed_tags() {
+        let dir = TestDir::new("curation");
+        let mut theirs = tags::Tags::default();
+        theirs.add(756, "favorite", "great physics").unwrap();
+        let mut played = played::Played::default();
+        played.record(12, "2026-10-01", Some(4.5), Some(95));
+        let file = dir.join("alice.json");
+        let count = export(&theirs, &played, &Catalog::default(), &file).unwrap();
+        assert_eq!(count, 2);
+
+        let mut ours = tags::Tags::default();
+        ours.add(756, "favorite", "").unwrap();
+        let from = default_source(&file);
+        assert_eq!(import(&mut ours, &file, &from).unwrap(), 2);
+        assert_eq!(ours.hacks[&756]["favorite"], "");
+        assert_eq!(ours.hacks[&756]["alice/favorite"], "great physics");
+        assert_eq!(
+            ours.hacks[&12]["alice/played"],
+            "finished 2026-10-01, rated 4.5, took 1h35m"
+        );
+    }
+}
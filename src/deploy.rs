@@ -0,0 +1,181 @@
+use crate::catalog::Catalog;
+use crate::collate::compare_titles;
+use crate::i18n;
+use crate::outputs::{OutputEntry, Outputs};
+use crate::utils::*;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+// Where a flashcart expects ROMs and what names its FAT32 menu copes with
+pub struct Profile {
+    pub folder: &'static str,
+    // Longest file name stem, in characters
+    pub max_stem: usize,
+    // 8.3 names, uppercase letters and digits only
+    pub short_names: bool,
+}
+
+// The FXPAK/SD2SNES menu reads long names but cuts them off on screen
+pub const SD2SNES: Profile = Profile {
+    folder: "Metroid Construction",
+    max_stem: 60,
+    short_names: false,
+};
+
+// Older Everdrive menus only handle 8.3 names reliably
+pub const EVERDRIVE: Profile = Profile {
+    folder: "METCONST",
+    max_stem: 8,
+    short_names: true,
+};
+
+fn clean_stem(profile: &Profile, name: &str) -> String {
+    let cleaned: String = if profile.short_names {
+        name.chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .map(|c| c.to_ascii_uppercase())
+            .collect()
+    } else {
+        fat32_chars(name)
+    };
+    let cleaned: String = cleaned.chars().take(profile.max_stem).collect();
+    // FAT32 drops trailing dots and spaces, which can make two names clash
+    let cleaned = cleaned.trim_end_matches(['.', ' ']).to_owned();
+    if cleaned.is_empty() {
+        "ROM".to_owned()
+    } else {
+        cleaned
+    }
+}
+
+// A name for `title` that fits the profile and isn't in `taken` yet, which
+// is compared case insensitively like FAT32 does
+pub fn deploy_name(profile: &Profile, title: &str, ext: &str, taken: &HashSet<String>) -> String {
+    let stem = clean_stem(profile, title);
+    let ext = clean_stem(
+        &Profile {
+            max_stem: 3,
+            ..*profile
+        },
+        ext,
+    );
+    let mut name = format!("{}.{}", stem, ext);
+    let mut n = 1;
+    while taken.contains(&name.to_lowercase()) {
+        n += 1;
+        let suffix = format!("~{}", n);
+        let keep = profile.max_stem.saturating_sub(suffix.len());
+        let base: String = stem.chars().take(keep).collect();
+        name = format!("{}{}.{}", base, suffix, ext);
+    }
+    name
+}
+
+fn title_of(entry: &OutputEntry, catalog: &Catalog) -> String {
+    let stem = Path::new(&entry.output)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match entry.hack_id.and_then(|id| catalog.hacks.get(&id)) {
+        Some(info) if !info.title.is_empty() => info.title.clone(),
+        _ => stem,
+    }
+}
+
+// Copy the patched ROMs of `hacks` (all of them when empty) onto a flashcart
+// SD card mounted at `target`, and list what went where in an index file
+pub fn deploy(
+    outputs: &Outputs,
+    catalog: &Catalog,
+    hacks: &[u32],
+    target: &Path,
+    profile: &Profile,
+    strip_articles: bool,
+    log: &mut dyn Write,
+) -> ResultErr<()> {
+    let mut selected: Vec<_> = outputs
+        .entries
+        .values()
+        .filter(|e| hacks.is_empty() || e.hack_id.is_some_and(|id| hacks.contains(&id)))
+        .filter(|e| Path::new(&e.output).exists())
+        .collect();
+    if selected.is_empty() {
+        return Err("no patched ROMs to deploy, run the patch mode first".into());
+    }
+    // The index is listed in title order
+    selected.sort_by(|a, b| {
+        compare_titles(&title_of(a, catalog), &title_of(b, catalog), strip_articles)
+            .then(a.output.cmp(&b.output))
+    });
+    let mut per_hack: BTreeMap<Option<u32>, usize> = BTreeMap::new();
+    for entry in &selected {
+        *per_hack.entry(entry.hack_id).or_default() += 1;
+    }
+
+    let dir = target.join(profile.folder);
+    fs::create_dir_all(&dir)?;
+    let mut taken = HashSet::new();
+    let mut index = Vec::new();
+    let mut copied = 0;
+    for entry in selected {
+        let rom = Path::new(&entry.output);
+        let mut title = title_of(entry, catalog);
+        // Tell apart the variants of hacks that ship more than one patch
+        if per_hack[&entry.hack_id] > 1 {
+            let stem = rom.file_stem().unwrap_or_default().to_string_lossy();
+            title = format!("{} - {}", title, stem);
+        }
+        let ext = rom.extension().unwrap_or_default().to_string_lossy();
+        let name = deploy_name(profile, &title, &ext, &taken);
+        taken.insert(name.to_lowercase());
+        let dest = dir.join(&name);
+
+        // Writing to SD cards is slow, leave ROMs that are already there alone
+        if dest.exists() && sha256_file(&dest)? == entry.sha256 {
+            writeln!(log, "{:?} is up to date", dest)?;
+        } else {
+            writeln!(log, "Copying {} to {:?}", entry.output, dest)?;
+            fs::copy(rom, &dest)?;
+            copied += 1;
+        }
+        let id = entry.hack_id.map(|id| id.to_string()).unwrap_or_default();
+        index.push(format!("{}\t{}\t{}", name, id, title));
+    }
+
+    let mut out = open_log(&dir.join("index.txt").to_string_lossy())?;
+    writeln!(out, "file\thack id\ttitle")?;
+    for line in &index {
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()?;
+    let args = [
+        ("count", index.len().into()),
+        ("path", format!("{:?}", dir).into()),
+        ("copied", copied.into()),
+    ];
+    println!("{}", i18n::tr("deploy-done", &args));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deploy_names_fit_the_profile() {
+        let mut taken = std::collections::HashSet::new();
+        let title = "Super Metroid: Redesign";
+        let first = deploy_name(&EVERDRIVE, title, "sfc", &taken);
+        taken.insert(first.to_lowercase());
+        let second = deploy_name(&EVERDRIVE, title, "sfc", &taken);
+        assert_eq!(
+            (first.as_str(), second.as_str()),
+            ("SUPERMET.SFC", "SUPERM~2.SFC")
+        );
+
+        let long = deploy_name(&SD2SNES, "Ωmega: Fusion?. ", "smc", &taken);
+        assert_eq!(long, "_mega_ Fusion_.smc");
+    }
+}
@@ -0,0 +1,217 @@
+use crate::config::{Config, CONFIG_FILE};
+use crate::i18n;
+use crate::manifest::{Manifest, MANIFEST_FILE};
+use crate::output;
+use crate::rom;
+use crate::utils::*;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+struct Doctor<'a> {
+    log: &'a mut dyn Write,
+    problems: usize,
+}
+
+impl Doctor<'_> {
+    fn ok(&mut self, msg: &str) -> ResultErr<()> {
+        let ok = i18n::tr("doctor-ok", &[]);
+        println!("{} {}", output::success(&ok), msg);
+        writeln!(self.log, "{} {}", ok, msg)?;
+        Ok(())
+    }
+
+    fn problem(&mut self, msg: &str, fix: &str) -> ResultErr<()> {
+        self.problems += 1;
+        let (problem, label) = (i18n::tr("doctor-problem", &[]), i18n::tr("doctor-fix", &[]));
+        println!("{} {}\n    {} {}", output::error(&problem), msg, label, fix);
+        writeln!(self.log, "{} {}\n    {} {}", problem, msg, label, fix)?;
+        Ok(())
+    }
+
+    fn check_base_rom(&mut self, config: &Config) -> ResultErr<()> {
+        let base_rom = match &config.base_rom {
+            Some(base_rom) => base_rom,
+            None => {
+                return self.problem(
+                    &i18n::tr("doctor-no-base-rom", &[]),
+                    &i18n::tr("doctor-no-base-rom-fix", &[]),
+                )
+            }
+        };
+        let len = match fs::metadata(base_rom) {
+            Ok(m) => m.len() as usize,
+            Err(e) => {
+                let args = [
+                    ("path", base_rom.as_str().into()),
+                    ("error", e.to_string().into()),
+                ];
+                return self.problem(
+                    &i18n::tr("doctor-unreadable-base-rom", &args),
+                    &i18n::tr(
+                        "doctor-unreadable-base-rom-fix",
+                        &[("path", CONFIG_FILE.into())],
+                    ),
+                );
+            }
+        };
+        if let Err(e) = rom::check_base_rom(base_rom) {
+            return self.problem(&e.to_string(), &i18n::tr("doctor-bad-base-rom-fix", &[]));
+        }
+        if rom::has_copier_header(len) {
+            self.problem(
+                &i18n::tr("doctor-headered", &[("path", base_rom.as_str().into())]),
+                &i18n::tr("doctor-headered-fix", &[]),
+            )
+        } else {
+            self.ok(&i18n::tr(
+                "doctor-base-rom-ok",
+                &[("path", base_rom.as_str().into())],
+            ))
+        }
+    }
+
+    fn check_writable(&mut self, dir: &str) -> ResultErr<()> {
+        let path = Path::new(dir);
+        if !path.exists() {
+            return self.ok(&i18n::tr("doctor-not-created", &[("path", dir.into())]));
+        }
+        let probe = path.join(".metconst-doctor");
+        let result = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&probe);
+        match result {
+            Ok(_) => {
+                fs::remove_file(&probe)?;
+                self.ok(&i18n::tr("doctor-writable", &[("path", dir.into())]))
+            }
+            Err(e) => self.problem(
+                &i18n::tr(
+                    "doctor-not-writable",
+                    &[("path", dir.into()), ("error", e.to_string().into())],
+                ),
+                &i18n::tr("doctor-not-writable-fix", &[]),
+            ),
+        }
+    }
+
+    fn check_manifest(&mut self, config: &Config) -> ResultErr<()> {
+        let manifest = match Manifest::load(MANIFEST_FILE) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                let args = [
+                    ("path", MANIFEST_FILE.into()),
+                    ("error", e.to_string().into()),
+                ];
+                return self.problem(
+                    &i18n::tr("doctor-unreadable-manifest", &args),
+                    &i18n::tr("doctor-unreadable-manifest-fix", &args),
+                );
+            }
+        };
+        let downloads = Path::new(&config.downloads_dir);
+        let mut missing = 0;
+        for file in manifest.files() {
+            let path = downloads.join(&file.path);
+            let args = [("path", format!("{:?}", path).into())];
+            match fs::metadata(&path) {
+                Ok(m) if m.len() == file.size => (),
+                Ok(_) => {
+                    missing += 1;
+                    self.problem(
+                        &i18n::tr("doctor-wrong-size", &args),
+                        &i18n::tr("doctor-download-again-fix", &[]),
+                    )?;
+                }
+                Err(_) => {
+                    missing += 1;
+                    self.problem(
+                        &i18n::tr("doctor-missing", &args),
+                        &i18n::tr("doctor-missing-fix", &[]),
+                    )?;
+                }
+            }
+        }
+        if missing == 0 {
+            let count = manifest.files().count();
+            self.ok(&i18n::tr("doctor-all-present", &[("count", count.into())]))?;
+        }
+
+        // Downloads are only recorded once they were written completely, so
+        // anything the manifest doesn't know about is most likely left over
+        // from an interrupted download
+        if manifest.hacks.is_empty() || !downloads.is_dir() {
+            return Ok(());
+        }
+        let mut partial = 0;
+        for hack_dir in fs::read_dir(downloads)?.filter_map(|d| d.ok()) {
+            if !hack_dir.path().is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(hack_dir.path())?.filter_map(|f| f.ok()) {
+                let path = file.path();
+                if path.is_file() && !manifest.contains_path(downloads, &path) {
+                    partial += 1;
+                    self.problem(
+                        &i18n::tr(
+                            "doctor-untracked",
+                            &[("path", format!("{:?}", path).into())],
+                        ),
+                        &i18n::tr("doctor-download-again-fix", &[]),
+                    )?;
+                }
+            }
+        }
+        if partial == 0 {
+            self.ok(&i18n::tr("doctor-no-untracked", &[]))?;
+        }
+        Ok(())
+    }
+
+    fn check_leftovers(&mut self) -> ResultErr<()> {
+        let mut found = false;
+        for fname in [MANIFEST_FILE, CONFIG_FILE] {
+            let tmp = format!("{}.tmp", fname);
+            if Path::new(&tmp).exists() {
+                found = true;
+                self.problem(
+                    &i18n::tr("doctor-leftover", &[("path", tmp.into())]),
+                    &i18n::tr("doctor-leftover-fix", &[]),
+                )?;
+            }
+        }
+        if !found {
+            self.ok(&i18n::tr("doctor-no-leftovers", &[]))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn doctor(config: &Config, log: &mut dyn Write) -> ResultErr<()> {
+    let mut doctor = Doctor { log, problems: 0 };
+    doctor.check_base_rom(config)?;
+    let home = std::env::current_dir()?;
+    doctor.check_writable(&home.to_string_lossy())?;
+    if let Some(logs) = log_dir() {
+        doctor.check_writable(&logs.to_string_lossy())?;
+    }
+    match &config.work_dir {
+        Some(work_dir) => doctor.check_writable(work_dir)?,
+        None => doctor.check_writable(&config.downloads_dir)?,
+    }
+    if let Some(extract_dir) = &config.extract_dir {
+        doctor.check_writable(extract_dir)?;
+    }
+    doctor.check_writable(&config.patched_dir)?;
+    doctor.check_manifest(config)?;
+    doctor.check_leftovers()?;
+    if doctor.problems == 0 {
+        println!("{}", output::success(i18n::tr("doctor-all-good", &[])));
+    } else {
+        let found = i18n::tr("doctor-found", &[("count", doctor.problems.into())]);
+        println!("{}", output::error(found));
+    }
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use crate::outputs::{OutputEntry, Outputs};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Share of changed banks two patched ROMs must have in common to count as
+// the same hack, e.g. a reupload with a small fix or a renamed version
+const NEAR_IDENTICAL: f64 = 0.9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Likeness {
+    // Byte for byte the same patched ROM
+    Identical,
+    // The share of changed banks they have in common
+    Near(f64),
+}
+
+// Hacks that came out the same, or nearly, when patched
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cluster {
+    pub hacks: Vec<u32>,
+    // How alike the least alike two of them are
+    pub likeness: Likeness,
+}
+
+fn likeness(a: &OutputEntry, b: &OutputEntry) -> Option<Likeness> {
+    if a.sha256 == b.sha256 {
+        return Some(Likeness::Identical);
+    }
+    let banks = |e: &OutputEntry| -> Option<BTreeSet<String>> {
+        let fingerprint = e.fingerprint.as_deref()?;
+        Some(
+            fingerprint
+                .split_whitespace()
+                .map(|b| b.to_owned())
+                .collect(),
+        )
+    };
+    let (a, b) = (banks(a)?, banks(b)?);
+    let union = a.union(&b).count();
+    if union == 0 {
+        return None;
+    }
+    let share = a.intersection(&b).count() as f64 / union as f64;
+    (share >= NEAR_IDENTICAL).then_some(Likeness::Near(share))
+}
+
+fn root(parent: &mut BTreeMap<u32, u32>, id: u32) -> u32 {
+    let up = *parent.entry(id).or_insert(id);
+    if up == id {
+        return id;
+    }
+    let top = root(parent, up);
+    parent.insert(id, top);
+    top
+}
+
+// Every group of two or more hacks whose patched ROMs match, biggest first
+pub fn clusters(outputs: &Outputs) -> Vec<Cluster> {
+    let entries: Vec<_> = outputs
+        .entries
+        .values()
+        .filter_map(|e| Some((e.hack_id?, e)))
+        .collect();
+    let mut parent = BTreeMap::new();
+    let mut links = Vec::new();
+    for (i, (a_id, a)) in entries.iter().enumerate() {
+        for (b_id, b) in &entries[i + 1..] {
+            if a_id == b_id {
+                continue;
+            }
+            if let Some(likeness) = likeness(a, b) {
+                let (ra, rb) = (root(&mut parent, *a_id), root(&mut parent, *b_id));
+                if ra != rb {
+                    parent.insert(ra, rb);
+                }
+                links.push((*a_id, *b_id, likeness));
+            }
+        }
+    }
+
+    let mut groups: BTreeMap<u32, Cluster> = BTreeMap::new();
+    for (a, b, likeness) in links {
+        let cluster = groups.entry(root(&mut parent, a)).or_insert(Cluster {
+            hacks: Vec::new(),
+            likeness: Likeness::Identical,
+        });
+        for id in [a, b] {
+            if !cluster.hacks.contains(&id) {
+                cluster.hacks.push(id);
+            }
+        }
+        cluster.likeness = match (cluster.likeness, likeness) {
+            (Likeness::Near(x), Likeness::Near(y)) => Likeness::Near(x.min(y)),
+            (Likeness::Identical, other) | (other, Likeness::Identical) => other,
+        };
+    }
+    let mut clusters: Vec<_> = groups.into_values().collect();
+    for cluster in &mut clusters {
+        cluster.hacks.sort();
+    }
+    clusters.sort_by(|a, b| {
+        b.hacks
+            .len()
+            .cmp(&a.hacks.len())
+            .then(a.hacks.cmp(&b.hacks))
+    });
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outputs::tests::test_output_entry;
+    use crate::rom;
+
+    #[test]
+    fn duplicate_hacks_cluster_by_hash_and_fingerprint() {
+        let base = vec![0u8; 32 * 0x8000];
+        let patch = |banks: &[(usize, u8)]| {
+            let mut rom = base.clone();
+            for (bank, value) in banks {
+                rom[bank * 0x8000..(bank + 1) * 0x8000].fill(*value);
+            }
+            rom
+        };
+        let full: Vec<_> = (0..32).map(|b| (b, 1)).collect();
+        // A reupload that fixed something in the last bank
+        let mut fixed = full.clone();
+        fixed[31].1 = 2;
+        let mut outputs = Outputs::default();
+        for (id, rom) in [
+            (1, patch(&full)),
+            (2, patch(&full)),
+            (3, patch(&fixed)),
+            (4, patch(&[(0, 9)])),
+        ] {
+            let output = format!("{}.sfc", id);
+            let entry = OutputEntry {
+                hack_id: Some(id),
+                sha256: format!("{:08x}", crc32fast::hash(&rom)),
+                bytes_changed: Some(rom::bytes_changed(&base, &rom)),
+                fingerprint: Some(rom::fingerprint(&base, &rom)),
+                ..test_output_entry(&output)
+            };
+            outputs.entries.insert(output, entry);
+        }
+        assert_eq!(
+            clusters(&outputs),
+            [Cluster {
+                hacks: vec![1, 2, 3],
+                likeness: Likeness::Near(31.0 / 33.0),
+            }]
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use crate::config::Config;
+use crate::utils::*;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Live progress for dashboards and GUIs, so they needn't parse the logs.
+// `--events PATH` creates a Unix socket there, and every client that
+// connects gets each event from then on as one JSON object per line, e.g.
+//
+//   {"at":"2026-10-16T12:00:00Z","event":"hack_started","hack":756,...}
+//
+// A client that can't keep up is dropped rather than slowing the run down.
+
+#[derive(Debug, Clone)]
+pub struct EventStream {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    path: PathBuf,
+    #[cfg(unix)]
+    clients: std::sync::Mutex<Vec<std::os::unix::net::UnixStream>>,
+}
+
+// The socket goes away with the last handle on it
+impl Drop for Inner {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl EventStream {
+    #[cfg(unix)]
+    pub fn listen(path: &Path) -> ResultErr<EventStream> {
+        use std::os::unix::fs::FileTypeExt;
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        // Left behind by a run that died, unless it's still going
+        if std::fs::metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+            if UnixStream::connect(path).is_ok() {
+                return Err(format!("another run is streaming events to {:?}", path).into());
+            }
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        let inner = Arc::new(Inner {
+            path: path.to_path_buf(),
+            clients: std::sync::Mutex::new(Vec::new()),
+        });
+        // Only holds on weakly, so the socket is removed once the run is done
+        let weak = Arc::downgrade(&inner);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().filter_map(|s| s.ok()) {
+                let Some(inner) = weak.upgrade() else {
+                    break;
+                };
+                let _ = stream.set_write_timeout(Some(std::time::Duration::from_secs(1)));
+                inner.clients.lock().unwrap().push(stream);
+            }
+        });
+        Ok(EventStream { inner })
+    }
+
+    #[cfg(not(unix))]
+    pub fn listen(_path: &Path) -> ResultErr<EventStream> {
+        Err("--events needs Unix sockets, which this platform doesn't have".into())
+    }
+
+    #[cfg(unix)]
+    pub fn emit(&self, name: &str, fields: Value) {
+        use std::io::Write;
+        let line = format!("{}\n", event(name, fields));
+        let mut clients = self.inner.clients.lock().unwrap();
+        clients.retain_mut(|c| c.write_all(line.as_bytes()).is_ok());
+    }
+
+    #[cfg(not(unix))]
+    pub fn emit(&self, _name: &str, _fields: Value) {}
+}
+
+pub fn event(name: &str, fields: Value) -> Value {
+    let at = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let mut value = json!({ "at": at, "event": name });
+    if let (Some(value), Value::Object(fields)) = (value.as_object_mut(), fields) {
+        value.extend(fields);
+    }
+    value
+}
+
+// Sends the event if the run was asked to stream them
+pub fn emit(config: &Config, name: &str, fields: Value) {
+    if let Some(events) = &config.events {
+        events.emit(name, fields);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::TestDir;
+
+    #[cfg(unix)]
+    #[test]
+    fn progress_events_reach_connected_clients() {
+        use std::io::BufRead;
+        let dir = TestDir::new("events");
+        let socket = dir.join("events.sock");
+        let events = EventStream::listen(&socket).unwrap();
+        let second = EventStream::listen(&socket);
+        let client = std::os::unix::net::UnixStream::connect(&socket).unwrap();
+        // The listener picks clients up on its own thread
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        events.emit("hack_started", serde_json::json!({ "hack": 756 }));
+        let mut line = String::new();
+        std::io::BufReader::new(client)
+            .read_line(&mut line)
+            .unwrap();
+        drop(events);
+
+        assert!(second.is_err());
+        let event: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(event["event"], "hack_started");
+        assert_eq!(event["hack"], 756);
+        assert!(event["at"].as_str().unwrap().ends_with('Z'));
+        assert!(!socket.exists());
+    }
+}
@@ -0,0 +1,129 @@
+use crate::catalog::{Catalog, CATALOG_FILE};
+use crate::config::Config;
+use crate::manifest::{Manifest, MANIFEST_FILE};
+use crate::outputs::{Outputs, OUTPUTS_FILE};
+use crate::utils::*;
+use crate::{feed, html, i18n, ipfs, links, sign, tags, torrent};
+use crate::{ExportArgs, ExportFormat};
+use std::io::Write;
+use std::path::Path;
+
+// The `export` mode: the mirror written out in forms other tools read, the
+// files among them signed when the config has a key
+
+pub fn export(args: &ExportArgs, config: &Config, log: &mut dyn Write) -> ResultErr<()> {
+    let downloads = Path::new(&config.downloads_dir);
+    match args.format {
+        ExportFormat::Torrent => {
+            let manifest = Manifest::load_or_rebuild(downloads, log)?;
+            let output = args.output.as_deref().unwrap_or("metconst-mirror.torrent");
+            println!(
+                "{}",
+                i18n::tr(
+                    "export-hashing",
+                    &[("count", manifest.files().count().into())]
+                )
+            );
+            let magnet = torrent::write_torrent(
+                &manifest,
+                downloads,
+                Path::new(output),
+                &args.tracker,
+                log,
+            )?;
+            println!("{}", i18n::tr("wrote", &[("path", output.into())]));
+            println!("{}", magnet);
+            sign::sign_export(config, output, log)?;
+        }
+        ExportFormat::Ipfs => {
+            let mut manifest = Manifest::load_or_rebuild(downloads, log)?;
+            let output = args.output.as_deref().unwrap_or("ipfs-pins.txt");
+            ipfs::write_pin_list(&mut manifest, downloads, Path::new(output), log)?;
+            manifest.save(MANIFEST_FILE)?;
+            println!("{}", i18n::tr("wrote", &[("path", output.into())]));
+            sign::sign_export(config, output, log)?;
+        }
+        ExportFormat::Car => {
+            let mut manifest = Manifest::load_or_rebuild(downloads, log)?;
+            let output = args.output.as_deref().unwrap_or("metconst-mirror.car");
+            println!(
+                "{}",
+                i18n::tr(
+                    "export-chunking",
+                    &[("count", manifest.files().count().into())]
+                )
+            );
+            ipfs::write_car(&mut manifest, downloads, Path::new(output), log)?;
+            manifest.save(MANIFEST_FILE)?;
+            println!("{}", i18n::tr("wrote", &[("path", output.into())]));
+            sign::sign_export(config, output, log)?;
+        }
+        ExportFormat::Rss => {
+            let manifest = Manifest::load_or_rebuild(downloads, log)?;
+            let catalog = Catalog::load(CATALOG_FILE)?;
+            let output = args.output.as_deref().unwrap_or("feed.xml");
+            let mut out = create_output(output)?;
+            feed::write_feed(&manifest, &catalog, &mut out)?;
+            out.flush()?;
+            println!("{}", i18n::tr("wrote", &[("path", output.into())]));
+            sign::sign_export(config, output, log)?;
+        }
+        ExportFormat::Html => {
+            let manifest = Manifest::load_or_rebuild(downloads, log)?;
+            let catalog = Catalog::load(CATALOG_FILE)?;
+            let output = args.output.as_deref().unwrap_or("html");
+            let count = html::write_catalog(
+                &manifest,
+                &catalog,
+                Path::new(&config.screenshots_dir),
+                Path::new(output),
+                config.sort_strip_articles,
+                log,
+            )?;
+            let index = Path::new(output).join("index.html");
+            let index = index.to_string_lossy();
+            println!(
+                "{}",
+                i18n::tr(
+                    "export-html",
+                    &[("count", count.into()), ("path", index.into())]
+                )
+            );
+        }
+        ExportFormat::Links => {
+            let mut outputs = Outputs::load(OUTPUTS_FILE)?;
+            if !args.tag.is_empty() {
+                let tagged = tags::Tags::load(tags::TAGS_FILE)?.select(&args.tag)?;
+                outputs
+                    .entries
+                    .retain(|_, e| e.hack_id.is_some_and(|id| tagged.contains(&id)));
+            }
+            let catalog = Catalog::load(CATALOG_FILE)?;
+            let output = args.output.as_deref().unwrap_or("links");
+            let count = links::write_links(
+                &outputs,
+                &catalog,
+                Path::new(output),
+                config.fat32_safe,
+                log,
+            )?;
+            println!(
+                "{}",
+                i18n::tr(
+                    "export-links",
+                    &[("count", count.into()), ("path", output.into())]
+                )
+            );
+        }
+        ExportFormat::Sums => {
+            let manifest = Manifest::load_or_rebuild(downloads, log)?;
+            let output = args.output.as_deref().unwrap_or("SHA256SUMS");
+            let mut out = create_output(output)?;
+            manifest.write_sums(&mut out)?;
+            out.flush()?;
+            println!("{}", i18n::tr("wrote", &[("path", output.into())]));
+            sign::sign_export(config, output, log)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,550 @@
+use crate::config::Config;
+use crate::heartbeat;
+use crate::howto;
+use crate::http;
+use crate::i18n;
+use crate::overrides::Override;
+use crate::overrides::Overrides;
+use crate::records;
+use crate::remote;
+use crate::shared;
+use crate::summary::{Skip, Summary};
+use crate::utils::*;
+use indicatif::ProgressBar;
+use std::fs::{self, create_dir_all, File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use walkdir::DirEntry;
+
+pub struct ExtractSettings {
+    // Archives are unpacked here and only moved next to the archive once
+    // they extracted completely
+    pub staging_dir: PathBuf,
+    // Most bytes a single archive may expand to
+    pub max_bytes: u64,
+    pub downloads_dir: PathBuf,
+    // Extract into a copy of the downloads layout here instead of next to
+    // the archives
+    pub extract_dir: Option<PathBuf>,
+    // Give up on an archive that takes longer than this
+    pub timeout: Duration,
+    // Largest decompression dictionary a 7z archive may ask for
+    pub max_7z_memory: u64,
+    pub names: NameRules,
+    pub cancel: Cancel,
+}
+
+impl ExtractSettings {
+    pub fn from_config(config: &Config, cancel: &Cancel) -> ExtractSettings {
+        ExtractSettings {
+            staging_dir: PathBuf::from(&config.staging_dir),
+            max_bytes: config.max_extract_bytes,
+            downloads_dir: PathBuf::from(&config.downloads_dir),
+            extract_dir: config.extract_root().map(PathBuf::from),
+            timeout: Duration::from_secs(config.item_timeout_secs),
+            max_7z_memory: config.max_7z_memory,
+            names: config.names.clone(),
+            cancel: cancel.clone(),
+        }
+    }
+
+    // Where `archive` gets unpacked to
+    pub fn unpack_dir(&self, archive: &Path) -> ResultErr<PathBuf> {
+        let parent = archive.parent().ok_or("bad path")?;
+        let archive_name = self
+            .names
+            .apply(&archive.file_stem().ok_or("bad path")?.to_string_lossy());
+        match &self.extract_dir {
+            Some(extract_dir) => {
+                let relative = parent.strip_prefix(&self.downloads_dir)?;
+                Ok(extract_dir.join(relative).join(archive_name))
+            }
+            None => Ok(parent.join(archive_name)),
+        }
+    }
+}
+
+// Running total of the bytes an archive has expanded to so far, and how
+// long it may keep going
+struct Budget {
+    used: u64,
+    max: u64,
+    deadline: Deadline,
+}
+
+impl Budget {
+    fn take(&mut self, bytes: u64) -> ResultErr<()> {
+        self.deadline.check()?;
+        self.used = self.used.saturating_add(bytes);
+        if self.used > self.max {
+            return Err(format!("archive expands to more than {} bytes", self.max).into());
+        }
+        Ok(())
+    }
+}
+
+// Fails reads once the deadline passes, so a single huge entry can't run
+// on forever
+struct DeadlineReader<R> {
+    inner: R,
+    deadline: Deadline,
+}
+
+impl<R: Read> Read for DeadlineReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if let Err(e) = self.deadline.check() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("extraction {}", e),
+            ));
+        }
+        self.inner.read(buf)
+    }
+}
+
+pub fn unarchive_in_dir(
+    settings: &ExtractSettings,
+    ov: Option<&Override>,
+    entry: &DirEntry,
+    log: &mut dyn Write,
+) -> ResultErr<()> {
+    settings.cancel.check()?;
+    if ov.map(|o| o.skip).unwrap_or(false) {
+        writeln!(log, "skipping {:?}, disabled in overrides", entry.path())?;
+        return Ok(());
+    }
+    let password = ov.and_then(|o| o.password.as_deref());
+    let archive_name = entry.path().file_stem().ok_or("bad path")?;
+    let unpack_dir = settings.unpack_dir(entry.path())?;
+
+    let mut staging_name = archive_name.to_os_string();
+    staging_name.push(format!(".{}", std::process::id()));
+    let staging = settings.staging_dir.join(staging_name);
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    create_dir_all(&staging)?;
+
+    let mut budget = Budget {
+        used: 0,
+        max: settings.max_bytes,
+        deadline: Deadline::after(settings.timeout, &settings.cancel),
+    };
+    let result = if is_zip_file(entry) {
+        unzip_to(
+            entry.path(),
+            &staging,
+            password,
+            &settings.names,
+            &mut budget,
+            log,
+        )
+    } else if is_rar_file(entry) {
+        unrar_to(
+            entry.path(),
+            &staging,
+            password,
+            &settings.names,
+            &mut budget,
+            log,
+        )
+    } else if is_7z_file(entry) {
+        un7z_to(
+            entry.path(),
+            &staging,
+            password,
+            settings.max_7z_memory,
+            &settings.names,
+            &mut budget,
+            log,
+        )
+    } else {
+        Ok(())
+    };
+    if let Err(e) = result {
+        writeln!(log, "Removing partial extraction {:?}", staging)?;
+        fs::remove_dir_all(&staging)?;
+        return Err(e);
+    }
+
+    // Swap the finished directory into place, so a failed or interrupted
+    // run never leaves a half extracted directory next to the archive
+    if unpack_dir.exists() {
+        fs::remove_dir_all(&unpack_dir)?;
+    }
+    create_dir_all(unpack_dir.parent().ok_or("bad path")?)?;
+    writeln!(log, "Moving {:?} to {:?}", staging, unpack_dir)?;
+    fs::rename(&staging, &unpack_dir)?;
+    Ok(())
+}
+
+// The LZMA decoders allocate their whole dictionary up front, which is
+// most of the memory a 7z extraction needs
+fn dictionary_size(method: &[u8], properties: &[u8]) -> Option<u64> {
+    if method == sevenz_rust::SevenZMethod::ID_LZMA {
+        let props = properties.get(1..5)?;
+        Some(u32::from_le_bytes(props.try_into().ok()?) as u64)
+    } else if method == sevenz_rust::SevenZMethod::ID_LZMA2 {
+        let bits = *properties.first()? as u64;
+        if bits >= 40 {
+            return Some(0xFFFF_FFFF);
+        }
+        Some((2 | (bits & 1)) << (bits / 2 + 11))
+    } else {
+        None
+    }
+}
+
+fn check_7z_memory(archive: &Path, max_memory: u64, log: &mut dyn Write) -> ResultErr<()> {
+    let mut file = File::open(archive)?;
+    let len = file.metadata()?.len();
+    let header = sevenz_rust::Archive::read(&mut file, len, &[])?;
+    let needed = header
+        .folders
+        .iter()
+        .flat_map(|f| f.coders.iter())
+        .filter_map(|c| dictionary_size(c.decompression_method_id(), &c.properties))
+        .max()
+        .unwrap_or(0);
+    writeln!(log, "Needs a {} byte dictionary", needed)?;
+    if needed > max_memory {
+        return Err(format!(
+            "archive needs a {} byte dictionary, more than the {} byte limit",
+            needed, max_memory
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn un7z_to(
+    archive: &Path,
+    dest: &Path,
+    password: Option<&str>,
+    max_memory: u64,
+    names: &NameRules,
+    budget: &mut Budget,
+    log: &mut dyn Write,
+) -> ResultErr<()> {
+    writeln!(log, "7z file: {:?}", archive).expect("cannot write to log");
+    // sevenz-rust only decrypts archives with its aes256 feature enabled
+    if password.is_some() {
+        return Err("password protected 7z archives are not supported".into());
+    }
+    check_7z_memory(archive, max_memory, log)?;
+    // Entries are streamed straight to disk rather than decompressed into
+    // memory first
+    sevenz_rust::decompress_file_with_extract_fn(archive, dest, |entry, reader, _| {
+        if let Err(e) = budget.take(entry.size()) {
+            return Err(sevenz_rust::Error::other(e.to_string()));
+        }
+        let path = match archive_entry_path(dest, entry.name(), names) {
+            Ok(path) => path,
+            Err(e) => return Err(sevenz_rust::Error::other(e.to_string())),
+        };
+        writeln!(log, "Creating: {:?}", path).expect("failed to write to log");
+        let mut reader = DeadlineReader {
+            inner: reader,
+            deadline: budget.deadline.clone(),
+        };
+        sevenz_rust::default_entry_extract_fn(entry, &mut reader, &path)
+    })?;
+    Ok(())
+}
+
+// RAR archives are read with the unrar tool, since unrar's licence keeps
+// its decoder out of the crates this builds with
+fn unrar_command(command: &str, archive: &Path, password: Option<&str>) -> Command {
+    let mut unrar = Command::new("unrar");
+    unrar.arg(command).arg("-idq").arg(match password {
+        Some(pw) => format!("-p{}", pw),
+        // Fail instead of asking
+        None => "-p-".to_owned(),
+    });
+    unrar.arg("--").arg(archive);
+    unrar
+}
+
+// The files in `unrar lt` output, with their unpacked sizes
+pub fn parse_rar_listing(listing: &str) -> Vec<(String, u64)> {
+    let mut files = Vec::new();
+    let mut entry: Option<(String, bool, u64)> = None;
+    for line in listing.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix("Name: ") {
+            files.extend(entry.take().filter(|e| e.1).map(|e| (e.0, e.2)));
+            entry = Some((name.to_owned(), false, 0));
+        } else if let Some(entry) = &mut entry {
+            if let Some(kind) = line.strip_prefix("Type: ") {
+                entry.1 = kind == "File";
+            } else if let Some(size) = line.strip_prefix("Size: ") {
+                entry.2 = size.parse().unwrap_or(0);
+            }
+        }
+    }
+    files.extend(entry.filter(|e| e.1).map(|e| (e.0, e.2)));
+    files
+}
+
+fn unrar_to(
+    archive: &Path,
+    dest: &Path,
+    password: Option<&str>,
+    names: &NameRules,
+    budget: &mut Budget,
+    log: &mut dyn Write,
+) -> ResultErr<()> {
+    writeln!(log, "Rar file: {:?}", archive).expect("cannot write to log");
+    let listed = unrar_command("lt", archive, password)
+        .output()
+        .map_err(|e| format!("cannot run unrar, which unpacks .rar archives: {}", e))?;
+    if !listed.status.success() {
+        return Err(format!(
+            "unrar couldn't read {:?}: {}",
+            archive,
+            String::from_utf8_lossy(&listed.stderr).trim()
+        )
+        .into());
+    }
+    for (name, size) in parse_rar_listing(&String::from_utf8_lossy(&listed.stdout)) {
+        budget.take(size)?;
+        let full_file_name = archive_entry_path(dest, &name, names)?;
+        create_dir_all(full_file_name.parent().unwrap())?;
+        writeln!(log, "Creating: {:?}", full_file_name).expect("failed to write to log");
+
+        // Each file is printed to a pipe, so it can be cut off like a zip
+        // entry when it runs past its size or the deadline
+        let mut unrar = unrar_command("p", archive, password)
+            .arg(&name)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let mut reader = DeadlineReader {
+            inner: unrar
+                .stdout
+                .take()
+                .ok_or("no output from unrar")?
+                .take(size + 1),
+            deadline: budget.deadline.clone(),
+        };
+        let mut output = BufWriter::new(File::create(&full_file_name)?);
+        let copied = std::io::copy(&mut reader, &mut output);
+        if copied.is_err() || copied.as_ref().is_ok_and(|c| *c > size) {
+            let _ = unrar.kill();
+        }
+        let status = unrar.wait()?;
+        match copied? {
+            copied if copied > size => {
+                return Err(format!("{} in {:?} is bigger than listed", name, archive).into())
+            }
+            _ if !status.success() => {
+                return Err(format!("unrar couldn't unpack {} from {:?}", name, archive).into())
+            }
+            _ => output.flush()?,
+        }
+    }
+    Ok(())
+}
+
+fn unzip_to(
+    archive: &Path,
+    dest: &Path,
+    password: Option<&str>,
+    names: &NameRules,
+    budget: &mut Budget,
+    log: &mut dyn Write,
+) -> ResultErr<()> {
+    writeln!(log, "Zip file: {:?}", archive).expect("cannot write to log");
+    let zip_file = File::open(archive)?;
+    let zip_reader = BufReader::new(&zip_file);
+
+    let mut zip = zip::ZipArchive::new(zip_reader)?;
+
+    for i in 0..zip.len() {
+        let file = match password {
+            Some(pw) => zip.by_index_decrypt(i, pw.as_bytes())??,
+            None => zip.by_index(i)?,
+        };
+        if file.name().ends_with('/') {
+            continue;
+        }
+        budget.take(file.size())?;
+        let full_file_name = archive_entry_path(dest, file.name(), names)?;
+
+        create_dir_all(full_file_name.parent().unwrap())?;
+
+        writeln!(log, "Creating: {:?}", full_file_name).expect("failed to write to log");
+        let output = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(full_file_name)?;
+        let mut output_writer = BufWriter::new(&output);
+        // Don't trust the size in the header, stop once the budget runs out
+        let declared = file.size();
+        let mut reader = DeadlineReader {
+            inner: file.take(budget.max - budget.used + declared + 1),
+            deadline: budget.deadline.clone(),
+        };
+        let copied = std::io::copy(&mut reader, &mut output_writer)?;
+        if copied > declared {
+            budget.take(copied - declared)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn unzip(
+    config: &Config,
+    overrides: &Overrides,
+    summary: &Summary,
+    cancel: &Cancel,
+    log: &mut dyn Write,
+) -> ResultErr<()> {
+    if let Some(url) = &config.remote_downloads {
+        return unzip_remote(config, url, overrides, summary, cancel, log);
+    }
+    let downloads = Path::new(&config.downloads_dir);
+    if howto::nothing_downloaded(config) {
+        println!("{}", i18n::tr("nothing-downloaded", &[]));
+        return Ok(());
+    }
+    let settings = ExtractSettings::from_config(config, cancel);
+    let mut heartbeat = heartbeat::Heartbeat::start(
+        std::time::Duration::from_secs(config.heartbeat_secs),
+        std::time::Duration::from_secs(config.slow_item_secs),
+    );
+    let locks = shared::Locks::new(config);
+    let walked = process_directory(
+        |f, l| {
+            let _lock = match locks.path(downloads, f.path())? {
+                Ok(lock) => lock,
+                Err(holder) => {
+                    writeln!(l, "skipping {:?}, locked by {}", f.path(), holder)?;
+                    summary.skipped(f.path().display(), Skip::Locked);
+                    return Ok(());
+                }
+            };
+            let ov = overrides.for_path(downloads, f.path());
+            let unzipped = heartbeat.track(f.path(), l, |l| unarchive_in_dir(&settings, ov, f, l));
+            match &unzipped {
+                Ok(()) => summary.processed(f.metadata().map(|m| m.len()).unwrap_or(0)),
+                Err(e) if !e.is::<Cancelled>() => summary.failed(),
+                Err(_) => (),
+            }
+            unzipped
+        },
+        &config.downloads_dir,
+        any_dir,
+        is_archive_file,
+        log,
+    );
+    heartbeat.report(log)?;
+    walked
+}
+
+// Unzip a mirror on another machine a hack at a time, removing each hack's
+// archives once they're unpacked
+fn unzip_remote(
+    config: &Config,
+    url: &str,
+    overrides: &Overrides,
+    summary: &Summary,
+    cancel: &Cancel,
+    log: &mut dyn Write,
+) -> ResultErr<()> {
+    if config.extract_root().is_none() {
+        return Err("unzipping from remote_downloads needs extract_dir set, \
+                    for the unpacked files to outlive the fetched archives"
+            .into());
+    }
+    let remote = remote::Remote::parse(url)?;
+    let retries = http::RetryLog::default();
+    let client = http::client(config, &retries)?;
+    let runtime = tokio::runtime::Handle::current();
+    let root = remote::staged_root(config);
+    let settings = ExtractSettings {
+        downloads_dir: root.clone(),
+        ..ExtractSettings::from_config(config, cancel)
+    };
+    let manifest = remote::manifest()?;
+    let pb = ProgressBar::new(manifest.hacks.len() as u64);
+    for hack in manifest.hacks.values() {
+        pb.inc(1);
+        if cancel.is_cancelled() {
+            break;
+        }
+        // Only fetch what hasn't been unpacked on an earlier run
+        let pending = |f: &records::DownloadRecord| {
+            remote::is_archive(f)
+                && settings
+                    .unpack_dir(&root.join(&f.path))
+                    .is_ok_and(|dir| !dir.exists())
+        };
+        let archives = match runtime.block_on(remote::stage_hack(
+            &remote, &client, hack, &root, pending, log,
+        )) {
+            Ok(archives) => archives,
+            Err(e) => {
+                writeln!(log, "{}", e)?;
+                summary.failed();
+                continue;
+            }
+        };
+        if archives.is_empty() {
+            continue;
+        }
+        for archive in matching_files(root.join(&hack.dir), any_dir, is_archive_file, log)? {
+            let ov = overrides.for_path(&root, archive.path());
+            match unarchive_in_dir(&settings, ov, &archive, log) {
+                Ok(()) => summary.processed(archive.metadata().map(|m| m.len()).unwrap_or(0)),
+                Err(e) if e.is::<Cancelled>() => return Err(e),
+                Err(e) => {
+                    writeln!(log, "{:?}: {}", archive.path(), e)?;
+                    summary.failed();
+                }
+            }
+        }
+        for archive in archives {
+            fs::remove_file(archive)?;
+        }
+    }
+    pb.finish_and_clear();
+    retries.report(log)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rar_listings_give_the_files_and_their_sizes() {
+        let listing = "
+Archive: hack.rar
+Details: RAR 5
+
+        Name: docs
+        Type: Directory
+
+        Name: docs/readme.txt
+        Type: File
+        Size: 12
+ Packed size: 20
+       Ratio: 60%
+
+        Name: hack.ips
+        Type: File
+        Size: 4096
+ Packed size: 1024
+";
+        assert_eq!(
+            parse_rar_listing(listing),
+            [
+                ("docs/readme.txt".to_owned(), 12),
+                ("hack.ips".to_owned(), 4096)
+            ]
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use crate::catalog::Catalog;
+use crate::manifest::Manifest;
+use crate::utils::*;
+use std::io::Write;
+
+pub const HACK_URL: &str = "https://metroidconstruction.com/hack.php?id=";
+// Keeps the feed a reasonable size for readers that poll it
+const MAX_ENTRIES: usize = 50;
+
+pub fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Atom feed of the hacks most recently added to or updated in the mirror.
+// `catalog` is optional enrichment, the manifest alone is enough.
+pub fn write_feed(manifest: &Manifest, catalog: &Catalog, out: &mut dyn Write) -> ResultErr<()> {
+    let mut entries: Vec<_> = manifest
+        .hacks
+        .iter()
+        .filter_map(|(id, hack)| {
+            let times: Vec<_> = hack
+                .files
+                .iter()
+                .filter_map(|f| f.downloaded.as_deref())
+                .collect();
+            // RFC 3339 timestamps in UTC sort chronologically as strings
+            let published = times.iter().min()?.to_string();
+            let updated = times.iter().max()?.to_string();
+            Some((id, hack, published, updated))
+        })
+        .collect();
+    entries.sort_by(|a, b| b.3.cmp(&a.3).then(a.0.cmp(b.0)));
+    entries.truncate(MAX_ENTRIES);
+
+    let feed_updated = entries
+        .first()
+        .map(|e| e.3.clone())
+        .unwrap_or_else(|| "1970-01-01T00:00:00Z".to_owned());
+    writeln!(out, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+    writeln!(out, r#"<feed xmlns="http://www.w3.org/2005/Atom">"#)?;
+    writeln!(out, "  <title>Metroid Construction mirror</title>")?;
+    writeln!(out, "  <id>urn:metconst-tool:mirror</id>")?;
+    writeln!(out, "  <updated>{}</updated>", feed_updated)?;
+    writeln!(out, "  <author><name>metconst-tool</name></author>")?;
+    for (id, hack, published, updated) in entries {
+        let info = catalog.hacks.get(id);
+        let title = hack
+            .title
+            .clone()
+            .or(info.map(|i| i.title.clone()))
+            .unwrap_or_else(|| format!("Hack {}", id));
+        let files: Vec<_> = hack
+            .files
+            .iter()
+            .map(|f| f.path.rsplit('/').next().unwrap_or(&f.path))
+            .collect();
+        let mut summary = if published == updated {
+            format!("Added {}", files.join(", "))
+        } else {
+            format!("Updated, now has {}", files.join(", "))
+        };
+        if let Some(info) = info {
+            summary.push_str(&format!(
+                ". {} by {}, rated {}",
+                info.genre, info.author, info.rating
+            ));
+        }
+        writeln!(out, "  <entry>")?;
+        writeln!(out, "    <title>{}</title>", xml_escape(&title))?;
+        writeln!(out, "    <id>{}{}</id>", HACK_URL, id)?;
+        writeln!(out, r#"    <link href="{}{}"/>"#, HACK_URL, id)?;
+        for video in info.map(|i| i.videos.as_slice()).unwrap_or_default() {
+            writeln!(
+                out,
+                r#"    <link rel="related" type="text/html" title="Video" href="{}"/>"#,
+                xml_escape(video)
+            )?;
+        }
+        writeln!(out, "    <published>{}</published>", published)?;
+        writeln!(out, "    <updated>{}</updated>", updated)?;
+        if let Some(author) = info.map(|i| &i.author).filter(|a| !a.is_empty()) {
+            writeln!(
+                out,
+                "    <author><name>{}</name></author>",
+                xml_escape(author)
+            )?;
+        }
+        writeln!(out, "    <summary>{}</summary>", xml_escape(&summary))?;
+        writeln!(out, "  </entry>")?;
+    }
+    writeln!(out, "</feed>")?;
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use crate::config::Config;
+use crate::scrape::{self, HackPage};
+use crate::utils::*;
+use chrono::NaiveDate;
+use regex::Regex;
+
+// Which hacks the download mode may fetch. Config list entries that are
+// numbers are hack ids, anything else is a regex matched against the title.
+#[derive(Debug, Default)]
+pub struct HackFilter {
+    blocked_ids: Vec<u32>,
+    blocked_titles: Vec<Regex>,
+    allowed_ids: Vec<u32>,
+    allowed_titles: Vec<Regex>,
+    selection: Selection,
+}
+
+// The hacks a download run was asked for on the command line, on top of the
+// config's lists. Authors and genres match anywhere in the field, ignoring
+// case; a hack whose date can't be read is left out by a date range.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Selection {
+    pub ids: Vec<u32>,
+    pub authors: Vec<String>,
+    pub genres: Vec<String>,
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+}
+
+impl Selection {
+    pub fn is_empty(&self) -> bool {
+        *self == Selection::default()
+    }
+
+    // Whether deciding takes what the hack's page says
+    pub fn needs_page(&self) -> bool {
+        !self.authors.is_empty()
+            || !self.genres.is_empty()
+            || self.since.is_some()
+            || self.until.is_some()
+    }
+
+    fn selects_id(&self, id: u32) -> bool {
+        self.ids.is_empty() || self.ids.contains(&id)
+    }
+
+    pub fn selects(&self, page: &HackPage) -> bool {
+        let any_in = |wanted: &[String], field: &str| {
+            let field = field.to_lowercase();
+            wanted.is_empty() || wanted.iter().any(|w| field.contains(&w.to_lowercase()))
+        };
+        let dated = match (self.since, self.until) {
+            (None, None) => true,
+            (since, until) => scrape::parse_date(page.date.trim()).is_some_and(|date| {
+                since.is_none_or(|since| date >= since) && until.is_none_or(|until| date <= until)
+            }),
+        };
+        any_in(&self.authors, &page.author) && any_in(&self.genres, &page.genre) && dated
+    }
+}
+
+fn parse_entries(entries: &[String]) -> ResultErr<(Vec<u32>, Vec<Regex>)> {
+    let mut ids = Vec::new();
+    let mut titles = Vec::new();
+    for entry in entries {
+        match entry.parse() {
+            Ok(id) => ids.push(id),
+            Err(_) => titles.push(Regex::new(entry)?),
+        }
+    }
+    Ok((ids, titles))
+}
+
+impl HackFilter {
+    pub fn from_config(config: &Config) -> ResultErr<HackFilter> {
+        let (blocked_ids, blocked_titles) = parse_entries(&config.blocklist)?;
+        let (allowed_ids, allowed_titles) = parse_entries(&config.allowlist)?;
+        Ok(HackFilter {
+            blocked_ids,
+            blocked_titles,
+            allowed_ids,
+            allowed_titles,
+            selection: Selection::default(),
+        })
+    }
+
+    pub fn with_selection(self, selection: Selection) -> HackFilter {
+        HackFilter { selection, ..self }
+    }
+
+    pub fn selection(&self) -> &Selection {
+        &self.selection
+    }
+
+    // An empty allowlist allows everything that isn't blocked
+    fn allowlist_mode(&self) -> bool {
+        !self.allowed_ids.is_empty() || !self.allowed_titles.is_empty()
+    }
+
+    // Checked before fetching the hack page, so only rejects hacks that no
+    // title could bring back in
+    pub fn may_allow(&self, id: u32) -> bool {
+        if self.blocks(id, None) || !self.selection.selects_id(id) {
+            return false;
+        }
+        !self.allowlist_mode() || self.allowed_ids.contains(&id) || !self.allowed_titles.is_empty()
+    }
+
+    // Whether the blocklist rather than the allowlist keeps a hack out
+    pub fn blocks(&self, id: u32, title: Option<&str>) -> bool {
+        self.blocked_ids.contains(&id)
+            || title.is_some_and(|t| self.blocked_titles.iter().any(|re| re.is_match(t)))
+    }
+
+    pub fn allows(&self, id: u32, title: Option<&str>) -> bool {
+        if self.blocks(id, title) || !self.selection.selects_id(id) {
+            return false;
+        }
+        if !self.allowlist_mode() || self.allowed_ids.contains(&id) {
+            return true;
+        }
+        title
+            .map(|t| self.allowed_titles.iter().any(|re| re.is_match(t)))
+            .unwrap_or(false)
+    }
+}
@@ -0,0 +1,317 @@
+use crate::config::Config;
+use crate::http;
+use crate::i18n;
+use crate::manifest::MANIFEST_FILE;
+use crate::manifest::{hack_id_of, parse_hack_dir, Manifest};
+use crate::output;
+use crate::overrides::Overrides;
+use crate::reporter::Reporter;
+use crate::summary::Summary;
+use crate::utils::*;
+use crate::{DownloadArgs, FsckArgs};
+use indicatif::ProgressBar;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repair {
+    // Fetched again by the download mode
+    ReDownload,
+    // Recorded again as it is now
+    ReHash,
+    // Recorded for the first time
+    Adopt,
+    Delete,
+}
+
+impl Repair {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Repair::ReDownload => "re-download",
+            Repair::ReHash => "re-hash",
+            Repair::Adopt => "adopt",
+            Repair::Delete => "delete",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Finding {
+    pub repair: Repair,
+    pub path: PathBuf,
+    pub why: String,
+}
+
+// Names download tools give files they haven't finished writing. The
+// download mode's own `.part` files aren't among them, it picks those up
+// where they stopped, maybe while this runs on another machine.
+const PARTIAL_EXTENSIONS: &[&str] = &["partial", "tmp", "crdownload"];
+const RESUMABLE_EXTENSION: &str = "part";
+
+// Cross-checks the manifest against the downloads directory both ways. A
+// recorded file that no longer matches is only re-hashed when it was
+// modified after it was downloaded, i.e. replaced on purpose; otherwise it
+// went bad in place and is fetched again. `quick` compares sizes only.
+pub fn check(
+    manifest: &Manifest,
+    downloads: &Path,
+    quick: bool,
+    summary: &Summary,
+    log: &mut dyn Write,
+) -> ResultErr<Vec<Finding>> {
+    let mut findings = Vec::new();
+    let pb = ProgressBar::new(manifest.files().count() as u64);
+    for file in manifest.files() {
+        pb.inc(1);
+        let path = downloads.join(&file.path);
+        let Ok(metadata) = fs::metadata(&path) else {
+            findings.push(Finding {
+                repair: Repair::ReDownload,
+                path,
+                why: i18n::tr("fsck-missing", &[]),
+            });
+            continue;
+        };
+        summary.processed(metadata.len());
+        let why = if metadata.len() != file.size {
+            let args = [
+                ("size", metadata.len().into()),
+                ("expected", file.size.into()),
+            ];
+            i18n::tr("fsck-wrong-size", &args)
+        } else if !quick && sha256_file(&path)? != file.sha256 {
+            i18n::tr("fsck-changed", &[])
+        } else {
+            writeln!(log, "{:?} matches the manifest", path)?;
+            continue;
+        };
+        let downloaded = file
+            .downloaded
+            .as_deref()
+            .and_then(|d| chrono::DateTime::parse_from_rfc3339(d).ok())
+            .map(SystemTime::from);
+        let replaced = match downloaded {
+            Some(downloaded) => metadata.modified()? > downloaded,
+            None => false,
+        };
+        let repair = if replaced {
+            Repair::ReHash
+        } else {
+            Repair::ReDownload
+        };
+        findings.push(Finding { repair, path, why });
+    }
+    pb.finish_and_clear();
+
+    // Only the files directly inside each hack directory are downloads,
+    // anything deeper was unpacked from them
+    if !downloads.is_dir() {
+        return Ok(findings);
+    }
+    let mut dirs: Vec<_> = fs::read_dir(downloads)?
+        .filter_map(|d| d.ok())
+        .filter(|d| d.path().is_dir())
+        .collect();
+    dirs.sort_by_key(|d| d.file_name());
+    for dir in dirs {
+        if parse_hack_dir(&dir.file_name().to_string_lossy()).is_none() {
+            writeln!(log, "Not a hack directory, leaving alone: {:?}", dir.path())?;
+            continue;
+        }
+        let mut files: Vec<_> = fs::read_dir(dir.path())?
+            .filter_map(|f| f.ok())
+            .map(|f| f.path())
+            .filter(|f| f.is_file() && !manifest.contains_path(downloads, f))
+            .filter(|f| f.extension().is_none_or(|e| e != RESUMABLE_EXTENSION))
+            .collect();
+        files.sort();
+        for path in files {
+            let partial = path
+                .extension()
+                .is_some_and(|e| PARTIAL_EXTENSIONS.contains(&&*e.to_string_lossy()));
+            let finding = if partial {
+                (Repair::Delete, "fsck-unfinished")
+            } else if fs::metadata(&path)?.len() == 0 {
+                (Repair::Delete, "fsck-empty")
+            } else {
+                (Repair::Adopt, "fsck-untracked")
+            };
+            findings.push(Finding {
+                repair: finding.0,
+                path,
+                why: i18n::tr(finding.1, &[]),
+            });
+        }
+    }
+    Ok(findings)
+}
+
+// Carries out the plan, except for the downloads, which are left for the
+// download mode: files to fetch again are removed so it sees them missing.
+// Returns how many files that is.
+pub fn repair(
+    findings: &[Finding],
+    manifest: &mut Manifest,
+    downloads: &Path,
+    log: &mut dyn Write,
+) -> ResultErr<usize> {
+    let mut to_download = 0;
+    for finding in findings {
+        let path = &finding.path;
+        match finding.repair {
+            Repair::ReDownload => {
+                if path.exists() {
+                    fs::remove_file(path)?;
+                    writeln!(log, "removed {:?} to download it again", path)?;
+                }
+                to_download += 1;
+            }
+            Repair::ReHash | Repair::Adopt => {
+                let id = hack_id_of(downloads, path).ok_or("not in a hack directory")?;
+                let dir = path
+                    .strip_prefix(downloads)?
+                    .components()
+                    .next()
+                    .ok_or("not in a hack directory")?
+                    .as_os_str()
+                    .to_string_lossy()
+                    .into_owned();
+                let title = match manifest.hacks.get(&id) {
+                    Some(hack) => hack.title.clone(),
+                    None => parse_hack_dir(&dir).and_then(|(_, t)| t.map(|t| t.to_owned())),
+                };
+                manifest.record_file(downloads, id, title.as_deref(), &dir, path)?;
+                writeln!(log, "recorded {:?}", path)?;
+            }
+            Repair::Delete => {
+                fs::remove_file(path)?;
+                writeln!(log, "deleted {:?}", path)?;
+            }
+        }
+    }
+    Ok(to_download)
+}
+
+pub async fn fsck(
+    args: &FsckArgs,
+    config: &Config,
+    overrides: &Overrides,
+    summary: &Summary,
+    log: &mut Reporter,
+) -> ResultErr<()> {
+    let downloads = Path::new(&config.downloads_dir);
+    let mut manifest = Manifest::load(MANIFEST_FILE)?;
+    let count = manifest.files().count();
+    println!("{}", i18n::tr("fsck-checking", &[("count", count.into())]));
+    let findings = check(&manifest, downloads, args.quick, summary, log)?;
+    if findings.is_empty() {
+        println!("{}", output::success(i18n::tr("fsck-agree", &[])));
+        return Ok(());
+    }
+    let plan = i18n::tr("fsck-plan", &[]);
+    println!("{}", plan);
+    writeln!(log, "{}", plan)?;
+    for finding in &findings {
+        let line = format!(
+            "  {:<12} {} ({})",
+            i18n::tr(&format!("fsck-{}", finding.repair.name()), &[]),
+            finding.path.to_string_lossy(),
+            finding.why
+        );
+        println!("{}", output::error(&line));
+        writeln!(log, "{}", line)?;
+    }
+    if !args.repair {
+        println!("{}", i18n::tr("fsck-repair-hint", &[]));
+        return Ok(());
+    }
+    if config.work_dir.is_some() {
+        return Err("the downloads directory is read-only while work_dir is set".into());
+    }
+    let to_download = repair(&findings, &mut manifest, downloads, log)?;
+    manifest.save(MANIFEST_FILE)?;
+    if to_download > 0 {
+        let args = [("count", to_download.into())];
+        println!("{}", i18n::tr("fsck-downloading", &args));
+        let da = DownloadArgs {
+            recheck: false,
+            jobs: 1,
+            id: Vec::new(),
+            author: Vec::new(),
+            genre: Vec::new(),
+            since: None,
+            until: None,
+        };
+        http::download(&da, config, overrides, summary, log).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::TestDir;
+
+    #[test]
+    fn fsck_plans_a_repair_for_each_kind_of_mismatch() {
+        let dir = TestDir::new("fsck");
+        let downloads = dir.join("downloads");
+        let hack_dir = downloads.join("0001-5-Test");
+        fs::create_dir_all(&hack_dir).unwrap();
+        let log = &mut std::io::sink();
+        let mut manifest = Manifest::default();
+        for name in ["ok.zip", "gone.zip", "rotted.zip", "replaced.zip"] {
+            fs::write(hack_dir.join(name), name).unwrap();
+            manifest
+                .record_file(&downloads, 5, None, "0001-5-Test", &hack_dir.join(name))
+                .unwrap();
+        }
+        fs::remove_file(hack_dir.join("gone.zip")).unwrap();
+        for (name, downloaded) in [
+            ("rotted.zip", "2999-01-01T00:00:00Z"),
+            ("replaced.zip", "2000-01-01T00:00:00Z"),
+        ] {
+            fs::write(hack_dir.join(name), name.to_uppercase()).unwrap();
+            let hack = manifest.hacks.get_mut(&5).unwrap();
+            let file = hack.files.iter_mut().find(|f| f.path.ends_with(name));
+            file.unwrap().downloaded = Some(downloaded.to_owned());
+        }
+        fs::write(hack_dir.join("extra.zip"), "extra").unwrap();
+        fs::write(hack_dir.join("half.zip.crdownload"), "ha").unwrap();
+        fs::write(hack_dir.join("resumed.zip.part"), "re").unwrap();
+
+        let findings = check(&manifest, &downloads, false, &Summary::default(), log);
+        let planned: Vec<_> = findings
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|f| {
+                (
+                    f.repair,
+                    f.path.file_name().unwrap().to_string_lossy().into_owned(),
+                )
+            })
+            .collect();
+        let pending = repair(&findings.unwrap(), &mut manifest, &downloads, log).unwrap();
+        let rechecked = check(&manifest, &downloads, false, &Summary::default(), log);
+
+        use Repair::*;
+        assert_eq!(
+            planned,
+            [
+                (ReDownload, "gone.zip".to_owned()),
+                (ReDownload, "rotted.zip".to_owned()),
+                (ReHash, "replaced.zip".to_owned()),
+                (Adopt, "extra.zip".to_owned()),
+                (Delete, "half.zip.crdownload".to_owned()),
+            ]
+        );
+        assert_eq!(pending, 2);
+        // Only the downloads are left once the rest is repaired
+        let left: Vec<_> = rechecked.unwrap().iter().map(|f| f.repair).collect();
+        assert_eq!(left, [ReDownload, ReDownload]);
+        assert!(hack_dir.join("resumed.zip.part").exists());
+    }
+}
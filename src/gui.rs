@@ -0,0 +1,477 @@
+use crate::cache::{self, Stage};
+use crate::catalog::{Catalog, CATALOG_FILE};
+use crate::collate;
+use crate::config::Config;
+use crate::i18n;
+use crate::manifest::{Manifest, MANIFEST_FILE};
+use crate::metadata;
+use crate::outputs::{Outputs, OUTPUTS_FILE};
+use crate::overrides::Overrides;
+use crate::reporter::Reporter;
+use crate::smoke;
+use crate::summary::Summary;
+use crate::utils::*;
+use crate::MetadataArgs;
+use eframe::egui;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+// The `gui` mode, for players who'd rather not use a terminal: a window
+// listing the catalog's hacks, which downloads, patches and plays the one
+// picked the way `resolve` does, saying what it's doing as it goes. Built
+// only with the `gui` feature.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Job {
+    // Read the list of hacks into the catalog, as `metadata --fast` does
+    Catalog,
+    Download(u32),
+    Patch(u32),
+    // Patch the hack if it isn't yet, then start the emulator on it
+    Play(u32),
+}
+
+impl Job {
+    fn describe(self) -> String {
+        match self {
+            Job::Catalog => i18n::tr("gui-updating", &[]),
+            Job::Download(id) => i18n::tr("gui-downloading", &[("id", id.into())]),
+            Job::Patch(id) => i18n::tr("gui-patching", &[("id", id.into())]),
+            Job::Play(id) => i18n::tr("gui-starting", &[("id", id.into())]),
+        }
+    }
+}
+
+enum Update {
+    Progress(String),
+    // Errors don't cross threads, only their messages do
+    Done(Result<String, String>),
+}
+
+// One line of the list
+struct Row {
+    id: u32,
+    title: String,
+    author: String,
+    genre: String,
+    rating: String,
+}
+
+struct App {
+    config: Config,
+    // Where the catalog and the rest of the state files are
+    home: PathBuf,
+    overrides: Overrides,
+    runtime: tokio::runtime::Handle,
+    log: Reporter,
+    rows: Vec<Row>,
+    downloaded: HashSet<u32>,
+    patched: HashSet<u32>,
+    filter: String,
+    selected: Option<u32>,
+    running: Option<(Job, Summary)>,
+    messages: Vec<String>,
+    sender: Sender<Update>,
+    receiver: Receiver<Update>,
+}
+
+// Start the emulator on the hack's first patched ROM, and leave it running
+fn play(config: &Config, roms: &[PathBuf]) -> ResultErr<String> {
+    let command = match config.play_command.is_empty() {
+        true => &config.smoke_test_command,
+        false => &config.play_command,
+    };
+    if command.is_empty() {
+        return Err("no emulator configured, set play_command in the config".into());
+    }
+    let rom = roms.first().ok_or("the hack has no patched ROM")?;
+    let args = smoke::command_args(command, &rom.to_string_lossy());
+    let mut child = Command::new(&args[0])
+        .args(&args[1..])
+        .stdin(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("cannot run {}: {}", args[0], e))?;
+    // Reaped when it exits, rather than left until the window closes
+    std::thread::spawn(move || child.wait());
+    Ok(i18n::tr(
+        "gui-playing",
+        &[("path", rom.to_string_lossy().into())],
+    ))
+}
+
+impl App {
+    fn new(config: Config, overrides: Overrides) -> ResultErr<App> {
+        let home = std::env::current_dir()?;
+        App::open(config, overrides, home, Reporter::open("gui.txt")?)
+    }
+
+    fn open(config: Config, overrides: Overrides, home: PathBuf, log: Reporter) -> ResultErr<App> {
+        let (sender, receiver) = mpsc::channel();
+        let mut app = App {
+            config,
+            home,
+            overrides,
+            runtime: tokio::runtime::Handle::current(),
+            log,
+            rows: Vec::new(),
+            downloaded: HashSet::new(),
+            patched: HashSet::new(),
+            filter: String::new(),
+            selected: None,
+            running: None,
+            messages: Vec::new(),
+            sender,
+            receiver,
+        };
+        app.reload()?;
+        Ok(app)
+    }
+
+    // Read the catalog and what's on disk again, after a job changed them
+    fn reload(&mut self) -> ResultErr<()> {
+        let catalog = Catalog::load(self.home.join(CATALOG_FILE))?;
+        self.rows = catalog
+            .hacks
+            .into_iter()
+            .map(|(id, hack)| Row {
+                id,
+                title: hack.title,
+                author: hack.author,
+                genre: hack.genre,
+                rating: hack.rating,
+            })
+            .collect();
+        let strip = self.config.sort_strip_articles;
+        self.rows
+            .sort_by(|a, b| collate::compare_titles(&a.title, &b.title, strip));
+        let manifest = Manifest::load(self.home.join(MANIFEST_FILE))?;
+        let downloads = Path::new(&self.config.downloads_dir);
+        self.downloaded = manifest
+            .hacks
+            .keys()
+            .filter(|id| manifest.has_all_files(downloads, **id))
+            .copied()
+            .collect();
+        let outputs = Outputs::load(self.home.join(OUTPUTS_FILE))?;
+        self.patched = self
+            .rows
+            .iter()
+            .map(|r| r.id)
+            .filter(|id| !cache::patched_roms(&outputs, *id).is_empty())
+            .collect();
+        Ok(())
+    }
+
+    // Run `job` on a thread of its own, so the window keeps drawing
+    fn start(&mut self, job: Job, ctx: &egui::Context) {
+        let summary = Summary::default();
+        self.running = Some((job, summary.clone()));
+        self.messages.push(format!("{}...", job.describe()));
+        let config = self.config.clone();
+        let overrides = self.overrides.clone();
+        let runtime = self.runtime.clone();
+        let log = self.log.clone();
+        let sender = self.sender.clone();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let report = |message: &str| {
+                let _ = sender.send(Update::Progress(message.to_owned()));
+                ctx.request_repaint();
+            };
+            let ensure = |id, stage| {
+                let ensured = cache::ensure(
+                    id,
+                    stage,
+                    config.clone(),
+                    overrides.clone(),
+                    log.clone(),
+                    &report,
+                );
+                runtime.block_on(ensured)
+            };
+            let result = match job {
+                Job::Catalog => {
+                    let args = MetadataArgs {
+                        audit: false,
+                        forum: false,
+                        screenshots: false,
+                        fast: true,
+                    };
+                    let mut log = log.clone();
+                    let read = metadata::metadata(&args, &config, &summary, &mut log);
+                    runtime
+                        .block_on(read)
+                        .map(|()| i18n::tr("gui-up-to-date", &[]))
+                }
+                Job::Download(id) => ensure(id, Stage::Downloaded).map(|dirs| {
+                    let path = dirs[0].to_string_lossy().into_owned();
+                    i18n::tr(
+                        "gui-downloaded",
+                        &[("id", id.into()), ("path", path.into())],
+                    )
+                }),
+                Job::Patch(id) => ensure(id, Stage::Patched).map(|roms| {
+                    let roms: Vec<_> = roms.iter().map(|r| r.display().to_string()).collect();
+                    let args = [("id", id.into()), ("roms", roms.join(", ").into())];
+                    i18n::tr("gui-patched", &args)
+                }),
+                Job::Play(id) => ensure(id, Stage::Patched).and_then(|roms| play(&config, &roms)),
+            };
+            let _ = sender.send(Update::Done(result.map_err(|e| e.to_string())));
+            ctx.request_repaint();
+        });
+    }
+
+    fn receive(&mut self) {
+        while let Ok(update) = self.receiver.try_recv() {
+            match update {
+                Update::Progress(message) => self.messages.push(message),
+                Update::Done(result) => {
+                    if let Some((job, _)) = self.running.take() {
+                        match result {
+                            Ok(message) => self.messages.push(message),
+                            Err(e) => {
+                                let args = [("job", job.describe().into()), ("error", e.into())];
+                                self.messages.push(i18n::tr("gui-failed", &args))
+                            }
+                        }
+                    }
+                    if let Err(e) = self.reload() {
+                        let args = [("error", e.to_string().into())];
+                        self.messages.push(i18n::tr("gui-unreadable", &args));
+                    }
+                }
+            }
+        }
+    }
+
+    fn status(&self, id: u32) -> String {
+        if self.patched.contains(&id) {
+            i18n::tr("gui-status-patched", &[])
+        } else if self.downloaded.contains(&id) {
+            i18n::tr("gui-status-downloaded", &[])
+        } else {
+            String::new()
+        }
+    }
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.receive();
+        let idle = self.running.is_none();
+
+        egui::TopBottomPanel::top("actions").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(i18n::tr("gui-search", &[]));
+                ui.text_edit_singleline(&mut self.filter);
+                if ui
+                    .add_enabled(idle, egui::Button::new(i18n::tr("gui-update-list", &[])))
+                    .clicked()
+                {
+                    self.start(Job::Catalog, ctx);
+                }
+                ui.separator();
+                let picked = self.selected.filter(|_| idle);
+                for (label, job) in [
+                    ("gui-download", Job::Download as fn(u32) -> Job),
+                    ("gui-patch", Job::Patch),
+                    ("gui-play", Job::Play),
+                ] {
+                    let button = egui::Button::new(i18n::tr(label, &[]));
+                    if ui.add_enabled(picked.is_some(), button).clicked() {
+                        self.start(job(picked.unwrap()), ctx);
+                    }
+                }
+            });
+        });
+
+        egui::TopBottomPanel::bottom("progress")
+            .resizable(true)
+            .show(ctx, |ui| {
+                if let Some((job, summary)) = &self.running {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        let read = summary.tally().processed;
+                        match job {
+                            Job::Catalog if read > 0 => {
+                                let args = [("job", job.describe().into()), ("count", read.into())];
+                                ui.label(i18n::tr("gui-read", &args))
+                            }
+                            _ => ui.label(format!("{}...", job.describe())),
+                        }
+                    });
+                }
+                egui::ScrollArea::vertical()
+                    .max_height(120.0)
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for message in &self.messages {
+                            ui.label(message);
+                        }
+                    });
+            });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if self.rows.is_empty() {
+                ui.label(i18n::tr("gui-empty", &[]));
+                return;
+            }
+            let filter = self.filter.to_lowercase();
+            let rows: Vec<_> = self
+                .rows
+                .iter()
+                .filter(|r| {
+                    filter.is_empty()
+                        || r.title.to_lowercase().contains(&filter)
+                        || r.author.to_lowercase().contains(&filter)
+                })
+                .map(|r| {
+                    let args = [
+                        ("title", r.title.as_str().into()),
+                        ("author", r.author.as_str().into()),
+                        ("genre", r.genre.as_str().into()),
+                        ("rating", r.rating.as_str().into()),
+                        ("status", self.status(r.id).into()),
+                    ];
+                    let text = i18n::tr("gui-row", &args);
+                    (r.id, text)
+                })
+                .collect();
+            egui::ScrollArea::vertical().show_rows(
+                ui,
+                ui.text_style_height(&egui::TextStyle::Body),
+                rows.len(),
+                |ui, range| {
+                    for (id, text) in &rows[range] {
+                        let selected = self.selected == Some(*id);
+                        if ui.selectable_label(selected, text).clicked() {
+                            self.selected = Some(*id);
+                        }
+                    }
+                },
+            );
+        });
+    }
+}
+
+pub fn gui(config: Config, overrides: Overrides) -> ResultErr<()> {
+    let app = App::new(config, overrides)?;
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_title("metconst-tool")
+            .with_inner_size([900.0, 600.0]),
+        ..Default::default()
+    };
+    eframe::run_native("metconst-tool", options, Box::new(|_| Box::new(app)))
+        .map_err(|e| format!("can't open a window: {}", e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outputs::tests::test_output_entry;
+    use crate::records::HackMetadata;
+    use crate::utils::tests::TestDir;
+    use std::fs;
+
+    #[tokio::test]
+    async fn the_list_is_sorted_and_shows_what_is_on_disk() {
+        let dir = TestDir::new("gui-list");
+        let mut catalog = Catalog::default();
+        for (id, title) in [
+            (5, "The Zebes Chronicles"),
+            (756, "Hyper Metroid"),
+            (9, "Axeil"),
+        ] {
+            let hack = HackMetadata {
+                title: title.to_owned(),
+                author: "RealRed".to_owned(),
+                ..HackMetadata::default()
+            };
+            catalog.hacks.insert(id, hack);
+        }
+        catalog.save(dir.join(CATALOG_FILE)).unwrap();
+        let downloads = dir.join("downloads");
+        let hack_dir = downloads.join("0001-756-Hyper Metroid");
+        fs::create_dir_all(&hack_dir).unwrap();
+        fs::write(hack_dir.join("hyper.ips"), "PATCHEOF").unwrap();
+        let mut manifest = Manifest::default();
+        let dir_name = "0001-756-Hyper Metroid";
+        manifest
+            .record_file(&downloads, 756, None, dir_name, &hack_dir.join("hyper.ips"))
+            .unwrap();
+        manifest.save(dir.join(MANIFEST_FILE)).unwrap();
+        let rom = dir.join("Zebes.sfc");
+        fs::write(&rom, "rom").unwrap();
+        let mut outputs = Outputs::default();
+        let mut entry = test_output_entry(&rom.to_string_lossy());
+        entry.hack_id = Some(5);
+        outputs.entries.insert(entry.output.clone(), entry);
+        outputs.save(dir.join(OUTPUTS_FILE)).unwrap();
+
+        let config = Config {
+            downloads_dir: downloads.to_string_lossy().into_owned(),
+            sort_strip_articles: true,
+            ..Config::default()
+        };
+        let log = Reporter::new(std::io::sink());
+        let mut app = App::open(config, Overrides::default(), dir.to_path_buf(), log).unwrap();
+        let titles: Vec<_> = app.rows.iter().map(|r| r.title.as_str()).collect();
+        assert_eq!(titles, ["Axeil", "Hyper Metroid", "The Zebes Chronicles"]);
+        assert_eq!(
+            [app.status(756), app.status(5), app.status(9)],
+            ["downloaded", "patched", ""]
+        );
+
+        // A job that patched the hack is seen on the next reload
+        fs::remove_file(&rom).unwrap();
+        app.reload().unwrap();
+        assert_eq!(app.status(5), "");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn play_starts_the_emulator_on_the_first_rom() {
+        let dir = TestDir::new("gui-play");
+        let played = dir.join("played.txt");
+        let roms = [dir.join("hack.sfc"), dir.join("hack (v2).sfc")];
+        let mut config = Config {
+            smoke_test_command: vec![
+                "sh".to_owned(),
+                "-c".to_owned(),
+                format!("echo \"$0\" > '{}'", played.display()),
+                "{rom}".to_owned(),
+            ],
+            ..Config::default()
+        };
+        assert!(play(&config, &[])
+            .unwrap_err()
+            .to_string()
+            .contains("no patched ROM"));
+        // The smoke test's emulator unless there's one just for playing
+        let message = play(&config, &roms).unwrap();
+        assert_eq!(message, format!("Playing {}", roms[0].display()));
+        // It's left running, so it's waited for here
+        let mut rom = String::new();
+        for _ in 0..50 {
+            rom = fs::read_to_string(&played).unwrap_or_default();
+            if rom.ends_with('\n') {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        assert_eq!(rom.trim_end(), roms[0].to_string_lossy());
+
+        config.smoke_test_command.clear();
+        let error = play(&config, &roms).unwrap_err();
+        assert!(
+            error.to_string().contains("no emulator configured"),
+            "{}",
+            error
+        );
+    }
+}
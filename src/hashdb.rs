@@ -0,0 +1,111 @@
+use crate::i18n;
+use crate::outputs::Outputs;
+use crate::utils::*;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+// The shared format identifies a hack version by the hash of the patch file,
+// since the site doesn't version its uploads
+struct HashEntry {
+    id: u32,
+    version: String,
+    patch: String,
+    sha256: String,
+}
+
+fn local_entries(outputs: &Outputs) -> Vec<HashEntry> {
+    outputs
+        .entries
+        .values()
+        .filter_map(|e| {
+            Some(HashEntry {
+                id: e.hack_id?,
+                version: e.patch_sha256.clone(),
+                patch: e.patch.rsplit('/').next().unwrap_or(&e.patch).to_owned(),
+                sha256: e.sha256.clone(),
+            })
+        })
+        .collect()
+}
+
+pub fn export(outputs: &Outputs, fname: &Path) -> ResultErr<usize> {
+    let entries: Vec<_> = local_entries(outputs)
+        .iter()
+        .map(|e| json!({ "id": e.id, "version": e.version, "patch": e.patch, "sha256": e.sha256 }))
+        .collect();
+    write_json(fname, &json!({ "hashes": entries }))?;
+    Ok(entries.len())
+}
+
+// Compare someone else's hashes against our patched ROMs, reporting every
+// hack version both sides have patched
+pub fn import(outputs: &Outputs, fname: &Path, log: &mut dyn Write) -> ResultErr<()> {
+    let value = read_json(fname)?;
+    let mut local: BTreeMap<(u32, String), Vec<HashEntry>> = BTreeMap::new();
+    for entry in local_entries(outputs) {
+        local
+            .entry((entry.id, entry.version.clone()))
+            .or_default()
+            .push(entry);
+    }
+    let (mut matched, mut mismatched, mut unknown) = (0, 0, 0);
+    let empty = Vec::new();
+    for theirs in value
+        .get("hashes")
+        .and_then(|h| h.as_array())
+        .unwrap_or(&empty)
+    {
+        let (id, version, sha256) = match (
+            json_u64(theirs, "id"),
+            json_str(theirs, "version"),
+            json_str(theirs, "sha256"),
+        ) {
+            (Some(id), Some(version), Some(sha256)) => (id as u32, version, sha256),
+            _ => {
+                writeln!(log, "Ignoring malformed entry: {}", theirs)?;
+                continue;
+            }
+        };
+        match local.get(&(id, version)) {
+            None => unknown += 1,
+            Some(ours) if ours.iter().any(|o| o.sha256 == sha256) => matched += 1,
+            Some(ours) => {
+                mismatched += 1;
+                let msg = format!(
+                    "Hack {} ({}) patched to {} here but {} in {}",
+                    id,
+                    ours[0].patch,
+                    ours[0].sha256,
+                    sha256,
+                    fname.to_string_lossy()
+                );
+                let args = [
+                    ("id", id.into()),
+                    ("patch", ours[0].patch.as_str().into()),
+                    ("ours", ours[0].sha256.as_str().into()),
+                    ("theirs", sha256.into()),
+                    ("path", fname.to_string_lossy().into()),
+                ];
+                println!("{}", i18n::tr("hashdb-mismatch", &args));
+                writeln!(log, "{}", msg)?;
+            }
+        }
+    }
+    let args = [
+        ("matched", matched.into()),
+        ("different", mismatched.into()),
+        ("unknown", unknown.into()),
+    ];
+    println!("{}", i18n::tr("hashdb-result", &args));
+    if mismatched > 0 {
+        println!("{}", i18n::tr("hashdb-hint", &[]));
+    }
+    writeln!(
+        log,
+        "{} matching, {} different, {} not patched here",
+        matched, mismatched, unknown
+    )?;
+    Ok(())
+}
@@ -0,0 +1,203 @@
+use crate::i18n;
+use crate::output;
+use crate::utils::*;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+type Current = Arc<Mutex<Option<(PathBuf, Instant)>>>;
+
+// How much of a failed item's own log is shown with its failure
+const TAIL_LINES: usize = 10;
+// An item's log is only kept this far back while it's worked on
+const ITEM_LOG_BYTES: usize = 64 * 1024;
+
+// Passes an item's log lines on to the run's log, keeping the latest of them
+// so they can be shown with the item's failure
+struct ItemLog<'a> {
+    log: &'a mut dyn Write,
+    kept: Vec<u8>,
+}
+
+impl Write for ItemLog<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.log.write(buf)?;
+        self.kept.extend_from_slice(&buf[..written]);
+        if self.kept.len() > ITEM_LOG_BYTES {
+            self.kept.drain(..self.kept.len() - ITEM_LOG_BYTES / 2);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.log.flush()
+    }
+}
+
+impl ItemLog<'_> {
+    fn tail(&self) -> Vec<String> {
+        let text = String::from_utf8_lossy(&self.kept);
+        let lines: Vec<_> = text.lines().map(|l| l.to_owned()).collect();
+        lines[lines.len().saturating_sub(TAIL_LINES)..].to_vec()
+    }
+}
+
+// An item that failed, and the last lines it logged
+struct Failure {
+    item: PathBuf,
+    error: String,
+    tail: Vec<String>,
+}
+
+// Prints a line every so often while a single item is taking a long time,
+// and remembers the items that went over the slow threshold or failed
+pub struct Heartbeat {
+    current: Current,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    slow_after: Duration,
+    slow: Vec<(PathBuf, Duration)>,
+    failed: Vec<Failure>,
+}
+
+impl Heartbeat {
+    pub fn start(interval: Duration, slow_after: Duration) -> Heartbeat {
+        let current: Current = Arc::new(Mutex::new(None));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let current = current.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut last_beat = Instant::now();
+                while !stop.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(250));
+                    if last_beat.elapsed() < interval {
+                        continue;
+                    }
+                    last_beat = Instant::now();
+                    if let Some((item, started)) = &*current.lock().unwrap() {
+                        if started.elapsed() >= interval {
+                            let args = [
+                                ("path", item.to_string_lossy().into()),
+                                ("secs", started.elapsed().as_secs().into()),
+                            ];
+                            eprintln!("{}", i18n::tr("heartbeat-still-working", &args));
+                        }
+                    }
+                }
+            })
+        };
+        Heartbeat {
+            current,
+            stop,
+            thread: Some(thread),
+            slow_after,
+            slow: Vec::new(),
+            failed: Vec::new(),
+        }
+    }
+
+    pub fn track<T>(
+        &mut self,
+        item: &Path,
+        log: &mut dyn Write,
+        action: impl FnOnce(&mut dyn Write) -> ResultErr<T>,
+    ) -> ResultErr<T> {
+        let started = Instant::now();
+        *self.current.lock().unwrap() = Some((item.to_path_buf(), started));
+        let mut item_log = ItemLog {
+            log,
+            kept: Vec::new(),
+        };
+        let result = action(&mut item_log);
+        let tail = item_log.tail();
+        *self.current.lock().unwrap() = None;
+        let elapsed = started.elapsed();
+        if elapsed >= self.slow_after {
+            writeln!(log, "{:?} took {}s", item, elapsed.as_secs())?;
+            self.slow.push((item.to_path_buf(), elapsed));
+        }
+        // Items never started because the run was cancelled didn't fail
+        match &result {
+            Err(e) if !e.is::<Cancelled>() => self.failed.push(Failure {
+                item: item.to_path_buf(),
+                error: e.to_string(),
+                tail,
+            }),
+            _ => (),
+        }
+        result
+    }
+
+    pub fn report(mut self, log: &mut dyn Write) -> ResultErr<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().map_err(|_| "heartbeat thread panicked")?;
+        }
+        if !self.failed.is_empty() {
+            let header = i18n::tr("heartbeat-failed", &[("count", self.failed.len().into())]);
+            println!("{}", output::error(header));
+            writeln!(log, "Failed items:")?;
+            for failure in &self.failed {
+                println!(
+                    "  {}: {}",
+                    failure.item.to_string_lossy(),
+                    output::error(&failure.error)
+                );
+                writeln!(log, "  {:?}: {}", failure.item, failure.error)?;
+                for line in &failure.tail {
+                    println!("    {}", output::skip(format!("| {}", line)));
+                    writeln!(log, "    | {}", line)?;
+                }
+            }
+        }
+        if self.slow.is_empty() {
+            return Ok(());
+        }
+        let args = [
+            ("count", self.slow.len().into()),
+            ("secs", self.slow_after.as_secs().into()),
+        ];
+        println!("{}", i18n::tr("heartbeat-slow", &args));
+        writeln!(log, "Slow items:")?;
+        for (item, elapsed) in &self.slow {
+            println!("  {}: {}s", item.to_string_lossy(), elapsed.as_secs());
+            writeln!(log, "  {:?}: {}s", item, elapsed.as_secs())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_come_with_the_end_of_their_own_log() {
+        let secs = std::time::Duration::from_secs(60);
+        let mut heartbeat = Heartbeat::start(secs, secs);
+        let mut log = Vec::new();
+        let _ = heartbeat.track(Path::new("0412-80-Hack/a.zip"), &mut log, |l| {
+            for i in 1..=15 {
+                writeln!(l, "line {}", i)?;
+            }
+            Err::<(), _>("broken archive".into())
+        });
+        heartbeat
+            .track(Path::new("0413-81-Other/b.zip"), &mut log, |l| {
+                writeln!(l, "fine")?;
+                Ok(())
+            })
+            .unwrap();
+        let mut report = Vec::new();
+        heartbeat.report(&mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(log.starts_with(b"line 1\n"));
+        assert!(report.contains("a.zip\": broken archive\n    | line 6\n"));
+        assert!(report.ends_with("    | line 15\n"));
+        assert!(!report.contains("line 5\n") && !report.contains("fine"));
+    }
+}
@@ -0,0 +1,165 @@
+use crate::catalog::Catalog;
+use crate::records::HackMetadata;
+use crate::schema::{self, Migration};
+use crate::utils::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+pub const HISTORY_FILE: &str = "history.json";
+
+const MIGRATIONS: &[Migration] = &[schema::unchanged];
+
+// What a hack looked like over a stretch of metadata runs that all scraped
+// the same thing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub first_seen: String,
+    pub last_seen: String,
+    pub hack: HackMetadata,
+}
+
+// Every metadata run merged into one timeline per hack, oldest first. A
+// run that finds a hack unchanged only moves its last snapshot's end date,
+// so the file grows with changes rather than with runs.
+#[derive(Debug, Default)]
+pub struct History {
+    pub hacks: BTreeMap<u32, Vec<Snapshot>>,
+}
+
+impl History {
+    pub fn load<P: AsRef<Path>>(fname: P) -> ResultErr<History> {
+        if !fname.as_ref().exists() {
+            return Ok(History::default());
+        }
+        let mut value = read_json(fname)?;
+        schema::upgrade(&mut value, MIGRATIONS, HISTORY_FILE)?;
+        let hacks = value.get("hacks").cloned().unwrap_or_else(|| json!({}));
+        Ok(History {
+            hacks: serde_json::from_value(hacks)?,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, fname: P) -> ResultErr<()> {
+        let mut value = json!({ "hacks": self.hacks });
+        schema::stamp(&mut value, MIGRATIONS);
+        write_json(fname, &value)
+    }
+
+    pub fn record(&mut self, id: u32, date: &str, hack: &HackMetadata) {
+        let snapshots = self.hacks.entry(id).or_default();
+        match snapshots.last_mut() {
+            Some(last) if last.hack.changes_from(hack).is_empty() => {
+                last.last_seen = date.to_owned();
+            }
+            // Running metadata twice on the same day keeps the later scrape
+            Some(last) if last.first_seen == date => last.hack = hack.clone(),
+            _ => snapshots.push(Snapshot {
+                first_seen: date.to_owned(),
+                last_seen: date.to_owned(),
+                hack: hack.clone(),
+            }),
+        }
+    }
+
+    pub fn record_catalog(&mut self, date: &str, catalog: &Catalog) {
+        for (id, hack) in &catalog.hacks {
+            self.record(*id, date, hack);
+        }
+    }
+}
+
+// How the hack changed from run to run
+pub fn write_history(history: &History, id: u32, out: &mut dyn Write) -> ResultErr<()> {
+    let Some(snapshots) = history.hacks.get(&id).filter(|s| !s.is_empty()) else {
+        return Err(format!("no metadata recorded for hack {}, run `metadata` first", id).into());
+    };
+    let latest = &snapshots[snapshots.len() - 1].hack;
+    writeln!(out, "{} (id {}) by {}", latest.title, id, latest.author)?;
+    let mut previous: Option<&HackMetadata> = None;
+    for snapshot in snapshots {
+        let span = if snapshot.first_seen == snapshot.last_seen {
+            snapshot.first_seen.clone()
+        } else {
+            format!("{} to {}", snapshot.first_seen, snapshot.last_seen)
+        };
+        let hack = &snapshot.hack;
+        let what = match previous {
+            Some(previous) => hack.changes_from(previous).join(", "),
+            None => format!(
+                "first seen: released {}, {}, {}, rated {}",
+                or_unknown(&hack.date),
+                or_unknown(&hack.genre),
+                or_unknown(&hack.difficulty),
+                or_unknown(&hack.rating)
+            ),
+        };
+        writeln!(out, "  {:<24}  {}", span, what)?;
+        previous = Some(hack);
+    }
+    Ok(())
+}
+
+fn or_unknown(value: &str) -> &str {
+    if value.is_empty() {
+        "unknown"
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_merges_unchanged_runs_into_one_snapshot() {
+        let hack = |rating: &str| HackMetadata {
+            title: "Redesign".to_owned(),
+            author: "Drewseph".to_owned(),
+            rating: rating.to_owned(),
+            ..HackMetadata::default()
+        };
+        let mut history = History::default();
+        history.record(5, "2026-01-01", &hack("4.5"));
+        history.record(5, "2026-02-01", &hack("4.5"));
+        history.record(5, "2026-03-01", &hack("4.4"));
+        // A second run the same day replaces that day's scrape
+        history.record(5, "2026-03-01", &hack("4.6"));
+        let spans: Vec<_> = history.hacks[&5]
+            .iter()
+            .map(|s| {
+                (
+                    s.first_seen.as_str(),
+                    s.last_seen.as_str(),
+                    s.hack.rating.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                ("2026-01-01", "2026-02-01", "4.5"),
+                ("2026-03-01", "2026-03-01", "4.6")
+            ]
+        );
+
+        let mut out = Vec::new();
+        write_history(&history, 5, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(
+            text.starts_with("Redesign (id 5) by Drewseph\n"),
+            "{}",
+            text
+        );
+        assert!(text.contains("2026-01-01 to 2026-02-01"), "{}", text);
+        assert!(
+            text.ends_with("2026-03-01                rating: 4.5 → 4.6\n"),
+            "{}",
+            text
+        );
+        assert!(write_history(&history, 6, &mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use crate::config::{Config, CONFIG_FILE};
+use crate::extract::ExtractSettings;
+use crate::i18n;
+use crate::utils::*;
+use std::fmt::Write;
+use std::io;
+use std::path::Path;
+
+// The order the modes build on each other in, spelled out with the
+// directories this setup actually uses
+pub fn workflow(config: &Config) -> String {
+    let tool = env!("CARGO_PKG_NAME");
+    let mut out = String::new();
+    let mut step = 0;
+    let mut add = |out: &mut String, command: &str, lines: &[String]| {
+        step += 1;
+        let _ = writeln!(out, "{}. {} {}", step, tool, command);
+        for line in lines {
+            let _ = writeln!(out, "   {}", line);
+        }
+        let _ = writeln!(out);
+    };
+
+    let _ = writeln!(out, "{}\n", i18n::tr("howto-intro", &[]));
+    if !Path::new(CONFIG_FILE).exists() || config.base_rom.is_none() {
+        add(&mut out, "init", &[i18n::tr("howto-init", &[])]);
+    }
+    add(
+        &mut out,
+        "download",
+        &[
+            i18n::tr(
+                "howto-download",
+                &[("path", config.downloads_dir.as_str().into())],
+            ),
+            i18n::tr("howto-download-resume", &[]),
+        ],
+    );
+    let unpacked = match config.extract_root() {
+        Some(dir) => i18n::tr("howto-unzip-into", &[("path", dir.into())]),
+        None => i18n::tr("howto-unzip", &[]),
+    };
+    add(&mut out, "unzip", &[unpacked]);
+    let base_rom = match &config.base_rom {
+        Some(rom) => i18n::tr("howto-patch", &[("path", rom.as_str().into())]),
+        None => i18n::tr("howto-patch-any", &[]),
+    };
+    add(
+        &mut out,
+        "patch",
+        &[
+            base_rom,
+            i18n::tr(
+                "howto-patch-output",
+                &[("path", config.patched_dir.as_str().into())],
+            ),
+        ],
+    );
+
+    let _ = writeln!(out, "{}", i18n::tr("howto-again", &[("name", tool.into())]));
+    let _ = writeln!(out, "{}", i18n::tr("howto-side", &[]));
+    for (command, what) in [
+        ("metadata", "howto-metadata"),
+        ("status", "howto-status"),
+        ("stats --authors", "howto-stats"),
+        ("resolve --id ID", "howto-resolve"),
+        ("patch-url URL", "howto-patch-url"),
+        ("doctor", "howto-doctor"),
+    ] {
+        let _ = writeln!(out, "  {} {:<16} {}", tool, command, i18n::tr(what, &[]));
+    }
+    out
+}
+
+// Whether the downloads directory has nothing in it yet, for modes that
+// work on what `download` mirrored
+pub fn nothing_downloaded(config: &Config) -> bool {
+    !std::fs::read_dir(&config.downloads_dir).is_ok_and(|mut d| d.next().is_some())
+}
+
+// Whether there are archives but `unzip` hasn't unpacked any of them, so
+// `patch` would miss most hacks
+pub fn nothing_unzipped(settings: &ExtractSettings, log: &mut dyn io::Write) -> ResultErr<bool> {
+    let archives = matching_files(&settings.downloads_dir, any_dir, is_archive_file, log)?;
+    for archive in &archives {
+        if settings.unpack_dir(archive.path())?.exists() {
+            return Ok(false);
+        }
+    }
+    Ok(!archives.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn howto_lists_the_modes_in_order_with_the_configured_paths() {
+        let config = Config {
+            downloads_dir: "mirror".to_owned(),
+            extract_dir: Some("unpacked".to_owned()),
+            base_rom: Some("sm.sfc".to_owned()),
+            ..Config::default()
+        };
+        let text = workflow(&config);
+        let at = |command: &str| {
+            text.find(&format!(". metconst-tool {}\n", command))
+                .unwrap()
+        };
+        assert!(at("download") < at("unzip") && at("unzip") < at("patch"));
+        for path in [
+            "mirror/",
+            "unpacked/",
+            "sm.sfc",
+            &format!("{}/", config.patched_dir),
+        ] {
+            assert!(text.contains(path), "{}", text);
+        }
+    }
+}
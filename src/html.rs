@@ -0,0 +1,289 @@
+use crate::catalog::Catalog;
+use crate::collate;
+use crate::feed::{xml_escape, HACK_URL};
+use crate::i18n;
+use crate::manifest::{HackEntry, Manifest};
+use crate::records::HackMetadata;
+use crate::screenshots;
+use crate::utils::*;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+// A static catalog of the mirror, for browsing it without the site:
+// `index.html` lists every mirrored hack, and each has a page of its own
+// under `hacks/` with what the catalog knows about it. Only the thumbnails
+// of the screenshots are copied in, under `thumbs/`, to keep it light.
+
+fn write_head(out: &mut dyn Write, title: &str) -> ResultErr<()> {
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, r#"<html lang="{}">"#, i18n::language())?;
+    writeln!(
+        out,
+        r#"<head><meta charset="utf-8"><title>{}</title></head>"#,
+        title
+    )?;
+    writeln!(out, "<body>")?;
+    writeln!(out, "<h1>{}</h1>", title)?;
+    Ok(())
+}
+
+fn write_foot(out: &mut dyn Write) -> ResultErr<()> {
+    writeln!(out, "</body>")?;
+    writeln!(out, "</html>")?;
+    Ok(())
+}
+
+fn title_of(id: u32, hack: &HackEntry, info: Option<&HackMetadata>) -> String {
+    hack.title
+        .clone()
+        .or(info.map(|i| i.title.clone()))
+        .unwrap_or_else(|| i18n::tr("html-hack", &[("id", id.into())]))
+}
+
+fn write_hack_page(
+    out: &mut dyn Write,
+    id: u32,
+    hack: &HackEntry,
+    info: Option<&HackMetadata>,
+    thumbs: &[String],
+) -> ResultErr<()> {
+    write_head(out, &xml_escape(&title_of(id, hack, info)))?;
+    if !thumbs.is_empty() {
+        writeln!(out, "<p>")?;
+        for thumb in thumbs {
+            writeln!(out, r#"<img src="../{}" alt="">"#, thumb)?;
+        }
+        writeln!(out, "</p>")?;
+    }
+    if let Some(info) = info {
+        writeln!(out, "<dl>")?;
+        let fields = [
+            ("html-author", &info.author),
+            ("html-released", &info.date),
+            ("html-genre", &info.genre),
+            ("html-difficulty", &info.difficulty),
+            ("html-rating", &info.rating),
+        ];
+        for (name, value) in fields.iter().filter(|(_, v)| !v.is_empty()) {
+            let name = i18n::tr(name, &[]);
+            writeln!(out, "<dt>{}</dt><dd>{}</dd>", name, xml_escape(value))?;
+        }
+        writeln!(out, "</dl>")?;
+        if !info.videos.is_empty() {
+            writeln!(out, "<h2>{}</h2>", i18n::tr("html-videos", &[]))?;
+            writeln!(out, "<ul>")?;
+            for video in &info.videos {
+                let video = xml_escape(video);
+                writeln!(out, r#"<li><a href="{0}">{0}</a></li>"#, video)?;
+            }
+            writeln!(out, "</ul>")?;
+        }
+    }
+    writeln!(out, "<h2>{}</h2>", i18n::tr("html-files", &[]))?;
+    writeln!(out, "<ul>")?;
+    for file in &hack.files {
+        let name = file.path.rsplit('/').next().unwrap_or(&file.path);
+        let args = [("name", name.into()), ("size", file.size.into())];
+        writeln!(
+            out,
+            "<li>{}</li>",
+            xml_escape(&i18n::tr("html-file", &args))
+        )?;
+    }
+    writeln!(out, "</ul>")?;
+    writeln!(
+        out,
+        r#"<p><a href="{}{}">{}</a> · <a href="../index.html">{}</a></p>"#,
+        HACK_URL,
+        id,
+        i18n::tr("html-on-site", &[]),
+        i18n::tr("html-all-hacks", &[])
+    )?;
+    write_foot(out)
+}
+
+// Copy the thumbnails of the hack's screenshots under `screenshots_dir`
+// into `dest`, in the order the site shows them. Returns their paths
+// relative to `dest`.
+fn copy_thumbnails(
+    screenshots_dir: &Path,
+    id: u32,
+    info: Option<&HackMetadata>,
+    dest: &Path,
+) -> ResultErr<Vec<String>> {
+    let mut thumbs = Vec::new();
+    for url in info.map(|i| i.screenshots.as_slice()).unwrap_or_default() {
+        let thumb =
+            screenshots::thumbnail_path(&screenshots::screenshot_path(screenshots_dir, id, url))?;
+        if !thumb.exists() {
+            continue;
+        }
+        let name = thumb.file_name().ok_or("bad path")?.to_string_lossy();
+        let path = format!("thumbs/{}/{}", id, name);
+        fs::create_dir_all(dest.join("thumbs").join(id.to_string()))?;
+        fs::copy(&thumb, dest.join(&path))?;
+        thumbs.push(path);
+    }
+    Ok(thumbs)
+}
+
+// Write the catalog under `dest`, the mirror's hacks in title order.
+// Returns how many hacks it lists.
+pub fn write_catalog(
+    manifest: &Manifest,
+    catalog: &Catalog,
+    screenshots_dir: &Path,
+    dest: &Path,
+    strip_articles: bool,
+    log: &mut dyn Write,
+) -> ResultErr<usize> {
+    // Start over, so hacks gone from the mirror don't keep their pages
+    let pages = dest.join("hacks");
+    for dir in [&pages, &dest.join("thumbs")] {
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+    }
+    fs::create_dir_all(&pages)?;
+
+    let mut hacks: Vec<_> = manifest
+        .hacks
+        .iter()
+        .map(|(&id, hack)| {
+            let info = catalog.hacks.get(&id);
+            (id, hack, info, title_of(id, hack, info))
+        })
+        .collect();
+    hacks.sort_by(|a, b| collate::compare_titles(&a.3, &b.3, strip_articles));
+
+    let mut index = create_output(dest.join("index.html"))?;
+    write_head(&mut index, &xml_escape(&i18n::tr("html-title", &[])))?;
+    writeln!(index, "<table>")?;
+    let columns = [
+        "html-column-title",
+        "html-author",
+        "html-genre",
+        "html-difficulty",
+        "html-rating",
+        "html-video",
+    ];
+    write!(index, "<tr><th></th>")?;
+    for column in columns {
+        write!(index, "<th>{}</th>", i18n::tr(column, &[]))?;
+    }
+    writeln!(index, "</tr>")?;
+    for (id, hack, info, title) in &hacks {
+        let page = pages.join(format!("{}.html", id));
+        writeln!(log, "Writing {:?}", page)?;
+        let thumbs = copy_thumbnails(screenshots_dir, *id, *info, dest)?;
+        let mut out = create_output(&page)?;
+        write_hack_page(&mut out, *id, hack, *info, &thumbs)?;
+        out.flush()?;
+
+        let field =
+            |f: fn(&HackMetadata) -> &String| info.map(|i| xml_escape(f(i))).unwrap_or_default();
+        // The first video is the trailer, the rest are on the hack's page
+        let video = match info.and_then(|i| i.videos.first()) {
+            Some(video) => format!(
+                r#"<a href="{}">{}</a>"#,
+                xml_escape(video),
+                i18n::tr("html-video", &[])
+            ),
+            None => String::new(),
+        };
+        let thumb = match thumbs.first() {
+            Some(thumb) => format!(r#"<img src="{}" alt="">"#, thumb),
+            None => String::new(),
+        };
+        writeln!(
+            index,
+            r#"<tr><td>{}</td><td><a href="hacks/{}.html">{}</a></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            thumb,
+            id,
+            xml_escape(title),
+            field(|i| &i.author),
+            field(|i| &i.genre),
+            field(|i| &i.difficulty),
+            field(|i| &i.rating),
+            video
+        )?;
+    }
+    writeln!(index, "</table>")?;
+    write_foot(&mut index)?;
+    index.flush()?;
+    Ok(hacks.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::records::DownloadRecord;
+    use crate::utils::tests::TestDir;
+
+    #[test]
+    fn the_index_links_each_hack_page_its_trailer_and_thumbnails() {
+        let dir = TestDir::new("html");
+        let mut manifest = Manifest::default();
+        manifest.hacks.insert(
+            756,
+            HackEntry {
+                title: Some("Hyper Metroid".to_owned()),
+                dir: "0001-756-Hyper Metroid".to_owned(),
+                files: vec![DownloadRecord {
+                    path: "0001-756-Hyper Metroid/Hyper Metroid.ips".to_owned(),
+                    size: 42,
+                    sha256: String::new(),
+                    cid: None,
+                    downloaded: None,
+                    links: Vec::new(),
+                }],
+                randomizer: false,
+            },
+        );
+        let mut catalog = Catalog::default();
+        catalog.hacks.insert(
+            756,
+            HackMetadata {
+                author: "RealRed & friends".to_owned(),
+                videos: vec!["https://www.youtube.com/watch?v=abc".to_owned()],
+                screenshots: vec![
+                    "https://metroidconstruction.com/screenshots/title.png".to_owned(),
+                    "https://metroidconstruction.com/screenshots/missing.png".to_owned(),
+                ],
+                ..HackMetadata::default()
+            },
+        );
+        let screenshots_dir = dir.join("screenshots");
+        let shot = screenshots::screenshot_path(&screenshots_dir, 756, "title.png");
+        fs::create_dir_all(shot.parent().unwrap()).unwrap();
+        image::RgbImage::new(256, 224).save(&shot).unwrap();
+        screenshots::make_thumbnail(&shot, &mut std::io::sink()).unwrap();
+
+        let html = dir.join("html");
+        let count = write_catalog(
+            &manifest,
+            &catalog,
+            &screenshots_dir,
+            &html,
+            true,
+            &mut std::io::sink(),
+        )
+        .unwrap();
+        let index = fs::read_to_string(html.join("index.html")).unwrap();
+        let page = fs::read_to_string(html.join("hacks").join("756.html")).unwrap();
+        let thumb = image::image_dimensions(html.join("thumbs/756/title.png")).unwrap();
+
+        assert_eq!(count, 1);
+        assert!(index.contains(r#"<a href="hacks/756.html">Hyper Metroid</a>"#));
+        assert!(index.contains("RealRed &amp; friends"));
+        assert!(index.contains(r#"<a href="https://www.youtube.com/watch?v=abc">Video</a>"#));
+        assert!(page.contains("<li>Hyper Metroid.ips (42 bytes)</li>"));
+        assert!(page.contains("https://www.youtube.com/watch?v=abc"));
+        // Only the screenshot that was downloaded has a thumbnail
+        assert!(index.contains(r#"<td><img src="thumbs/756/title.png" alt=""></td>"#));
+        assert!(page.contains(r#"<img src="../thumbs/756/title.png" alt="">"#));
+        assert!(!page.contains("missing"));
+        assert_eq!(thumb, (160, 140));
+    }
+}
@@ -0,0 +1,540 @@
+use crate::api;
+use crate::config::Config;
+use crate::filter;
+use crate::i18n;
+use crate::manifest::{Manifest, MANIFEST_FILE};
+use crate::overrides::Overrides;
+use crate::pipeline;
+use crate::progress::{self, Progress, PROGRESS_FILE};
+use crate::reporter::Reporter;
+use crate::schema::{self, Migration};
+use crate::shared;
+use crate::summary::Summary;
+use crate::utils::*;
+use crate::warc::WarcWriter;
+use crate::DownloadArgs;
+use flate2::read::GzDecoder;
+use reqwest::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_RANGE, RANGE};
+use reqwest::{Request, Response, ResponseBuilderExt, StatusCode, Url};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware, Next};
+use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use task_local_extensions::Extensions;
+
+pub const RETRIES_FILE: &str = "retries.json";
+
+const MIGRATIONS: &[Migration] = &[schema::unchanged];
+
+// How one URL fared during a run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Attempts {
+    count: u32,
+    // Status code of the last attempt, or the error it failed with
+    last: String,
+}
+
+// Sits inside the retry middleware, so it sees every attempt the retries
+// make rather than just the final result
+#[derive(Debug, Clone, Default)]
+pub struct RetryLog {
+    urls: Arc<Mutex<BTreeMap<String, Attempts>>>,
+}
+
+#[async_trait::async_trait]
+impl Middleware for RetryLog {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let url = req.url().to_string();
+        let result = next.run(req, extensions).await;
+        let last = match &result {
+            Ok(response) => response.status().to_string(),
+            Err(e) => e.to_string(),
+        };
+        let mut urls = self.urls.lock().unwrap();
+        let attempts = urls.entry(url).or_default();
+        attempts.count += 1;
+        attempts.last = last;
+        result
+    }
+}
+
+impl RetryLog {
+    // List the URLs that needed more than one attempt, and add them to the
+    // running tally in the retries file so the ones that are always flaky
+    // stand out
+    pub fn report(&self, log: &mut dyn Write) -> ResultErr<()> {
+        let urls = self.urls.lock().unwrap();
+        let retried: Vec<_> = urls.iter().filter(|(_, a)| a.count > 1).collect();
+        writeln!(log, "{} request(s), {} retried", urls.len(), retried.len())?;
+        if retried.is_empty() {
+            return Ok(());
+        }
+        println!(
+            "{}",
+            i18n::tr("http-retried", &[("count", retried.len().into())])
+        );
+        let mut tally = load_tally(RETRIES_FILE)?;
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+        for (url, attempts) in &retried {
+            let args = [
+                ("url", url.as_str().into()),
+                ("count", attempts.count.into()),
+                ("last", attempts.last.as_str().into()),
+            ];
+            println!("  {}", i18n::tr("http-attempts", &args));
+            writeln!(
+                log,
+                "{}: {} attempts, last {}",
+                url, attempts.count, attempts.last
+            )?;
+            let entry = tally.entry(url.to_string()).or_insert_with(|| json!({}));
+            let runs = json_u64(entry, "runs").unwrap_or(0) + 1;
+            let retries = json_u64(entry, "retries").unwrap_or(0) + attempts.count as u64 - 1;
+            *entry = json!({
+                "runs": runs,
+                "retries": retries,
+                "last_status": attempts.last,
+                "last_seen": today,
+            });
+        }
+        let mut value = json!({ "urls": tally });
+        schema::stamp(&mut value, MIGRATIONS);
+        write_json(RETRIES_FILE, &value)
+    }
+}
+
+fn load_tally<P: AsRef<Path>>(fname: P) -> ResultErr<serde_json::Map<String, Value>> {
+    if !fname.as_ref().exists() {
+        return Ok(serde_json::Map::new());
+    }
+    let mut value = read_json(fname)?;
+    schema::upgrade(&mut value, MIGRATIONS, RETRIES_FILE)?;
+    Ok(value
+        .get("urls")
+        .and_then(|u| u.as_object())
+        .cloned()
+        .unwrap_or_default())
+}
+
+// `host:ip` like curl's `--resolve`, which also has a port in the middle.
+// The port is dropped: requests keep going to the one in their URL.
+pub fn parse_resolve(spec: &str) -> ResultErr<(String, IpAddr)> {
+    let bad = || format!("expected host:ip, got {:?}", spec);
+    let (host, rest) = spec.split_once(':').ok_or_else(bad)?;
+    let ip = |s: &str| s.trim_matches(['[', ']']).parse::<IpAddr>().ok();
+    let addr = match ip(rest) {
+        Some(addr) => addr,
+        None => {
+            let (_, addr) = rest.split_once(':').ok_or_else(bad)?;
+            ip(addr).ok_or_else(bad)?
+        }
+    };
+    if host.is_empty() {
+        return Err(bad().into());
+    }
+    Ok((host.to_owned(), addr))
+}
+
+// Sends requests for some hosts to another base URL instead, like a caching
+// proxy in front of the site. The path and query are kept.
+#[derive(Debug, Clone)]
+struct BaseUrls(Vec<(String, Url)>);
+
+impl BaseUrls {
+    fn rewrite(&self, url: &Url) -> Option<Url> {
+        let host = url.host_str()?;
+        let (_, base) = self.0.iter().find(|(h, _)| h.eq_ignore_ascii_case(host))?;
+        let mut rewritten = base.clone();
+        let path = format!("{}{}", base.path().trim_end_matches('/'), url.path());
+        rewritten.set_path(&path);
+        rewritten.set_query(url.query());
+        Some(rewritten)
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for BaseUrls {
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        if let Some(url) = self.rewrite(req.url()) {
+            *req.url_mut() = url;
+        }
+        next.run(req, extensions).await
+    }
+}
+
+// One client per run, so its connections are kept alive and reused between
+// requests. HTTP/2 is used where the server offers it.
+pub fn client(config: &Config, retries: &RetryLog) -> ResultErr<ClientWithMiddleware> {
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(config.max_retries);
+    let mut client = reqwest::ClientBuilder::new()
+        .user_agent("Foo")
+        .pool_max_idle_per_host(config.http_pool_max_idle)
+        .pool_idle_timeout(Duration::from_secs(config.http_pool_idle_secs))
+        .tcp_keepalive(Duration::from_secs(60))
+        .http2_adaptive_window(true);
+    for spec in &config.resolve {
+        let (host, addr) = parse_resolve(spec)?;
+        client = client.resolve(&host, SocketAddr::new(addr, 0));
+    }
+    let mut base_urls = Vec::new();
+    for (host, base) in &config.host_overrides {
+        let base = Url::parse(base).map_err(|e| format!("bad base URL for {}: {}", host, e))?;
+        base_urls.push((host.clone(), base));
+    }
+    let mut builder = ClientBuilder::new(client.build()?);
+    if let Some(fname) = &config.warc_file {
+        builder = builder.with(WarcWriter::open(fname)?);
+    }
+    if let Some(cassette) = &config.cassette {
+        builder = builder.with(cassette.clone());
+    }
+    Ok(builder
+        .with(BaseUrls(base_urls))
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .with(retries.clone())
+        .with(Pacer::new(Duration::from_secs(config.page_delay_secs)))
+        .build())
+}
+
+// Spaces out the requests of everything sharing a client, retries included,
+// so downloading several hacks at once asks no more of the site than one
+// at a time does
+#[derive(Debug, Clone)]
+struct Pacer {
+    interval: Duration,
+    // When the next request may go out
+    next: Arc<Mutex<tokio::time::Instant>>,
+}
+
+impl Pacer {
+    fn new(interval: Duration) -> Pacer {
+        Pacer {
+            interval,
+            next: Arc::new(Mutex::new(tokio::time::Instant::now())),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for Pacer {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let start = {
+            let mut slot = self.next.lock().unwrap();
+            let start = (*slot).max(tokio::time::Instant::now());
+            *slot = start + self.interval;
+            start
+        };
+        tokio::time::sleep_until(start).await;
+        next.run(req, extensions).await
+    }
+}
+
+// Read a response's body, handing back a response that can still be read
+// from along with it
+pub async fn buffered(response: Response) -> reqwest::Result<(Response, Vec<u8>)> {
+    let status = response.status();
+    let version = response.version();
+    let url = response.url().clone();
+    let headers = response.headers().clone();
+    let body = response.bytes().await?;
+    let mut rebuilt = http::Response::builder()
+        .status(status)
+        .version(version)
+        .url(url);
+    for (name, value) in &headers {
+        rebuilt = rebuilt.header(name, value);
+    }
+    let rebuilt = rebuilt
+        .body(body.to_vec())
+        .expect("status and headers came from a response");
+    Ok((Response::from(rebuilt), body.to_vec()))
+}
+
+// Fetch an HTML page, gzipped on the way if the server agrees. Archives and
+// images are already compressed, so they are fetched as they are.
+pub async fn fetch_page(client: &ClientWithMiddleware, url: &str) -> ResultErr<String> {
+    let response = client
+        .get(url)
+        .header(ACCEPT_ENCODING, "gzip")
+        .send()
+        .await?;
+    let gzipped = response
+        .headers()
+        .get(CONTENT_ENCODING)
+        .is_some_and(|e| e.as_bytes().eq_ignore_ascii_case(b"gzip"));
+    let body = response.bytes().await?;
+    if !gzipped {
+        return Ok(String::from_utf8_lossy(&body).into_owned());
+    }
+    let mut page = Vec::new();
+    GzDecoder::new(&body[..]).read_to_end(&mut page)?;
+    Ok(String::from_utf8_lossy(&page).into_owned())
+}
+
+// Where a file is downloaded to until it's whole
+pub fn part_path(path: &Path) -> std::path::PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".part");
+    name.into()
+}
+
+// Download `url` into `part`, carrying on from whatever an interrupted run
+// left there with a Range request. A server that ignores the range sends
+// the whole file, which then starts the part over.
+pub async fn download_resuming(
+    client: &ClientWithMiddleware,
+    url: &str,
+    part: &Path,
+) -> ResultErr<()> {
+    let have = fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+    let mut request = client.get(url);
+    if have > 0 {
+        request = request.header(RANGE, format!("bytes={}-", have));
+    }
+    let mut response = request.send().await?;
+    let mut file = match response.status() {
+        StatusCode::PARTIAL_CONTENT if have > 0 => OpenOptions::new().append(true).open(part)?,
+        // Nothing past what's there, which is whole if the server's total
+        // agrees
+        StatusCode::RANGE_NOT_SATISFIABLE if have > 0 => {
+            let total = response
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|r| r.to_str().ok())
+                .and_then(|r| r.strip_prefix("bytes */"))
+                .and_then(|total| total.parse::<u64>().ok());
+            if total == Some(have) {
+                return Ok(());
+            }
+            fs::remove_file(part)?;
+            return Err(format!(
+                "{} doesn't match its partial download, which was removed",
+                url
+            )
+            .into());
+        }
+        status if status.is_success() => File::create(part)?,
+        status => return Err(format!("{} answered {}", url, status).into()),
+    };
+    let expected = response.content_length();
+    let mut received = 0;
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk)?;
+        received += chunk.len() as u64;
+    }
+    if expected.is_some_and(|len| received < len) {
+        return Err(format!("{} stopped short, run again to resume it", url).into());
+    }
+    Ok(())
+}
+
+pub async fn download(
+    args: &DownloadArgs,
+    config: &Config,
+    overrides: &Overrides,
+    summary: &Summary,
+    log: &Reporter,
+) -> ResultErr<()> {
+    if config.work_dir.is_some() {
+        return Err("the downloads directory is read-only while work_dir is set".into());
+    }
+    let mut store_log = log.clone();
+    let retries = RetryLog::default();
+    let client = client(config, &retries)?;
+
+    println!("{}", i18n::tr("fetching-hack-list", &[]));
+    let list = api::hack_list(config, &client, &mut store_log).await?;
+    summary.listed_from(list.source().as_str());
+    let hack_id = list.ids()?;
+    let total = [("count", hack_id.len().into())];
+    println!("{}", i18n::tr("download-total", &total));
+
+    let pb = log.bar(hack_id.len() as u64);
+    let mut manifest = Manifest::load(MANIFEST_FILE)?;
+    let selection = args.selection();
+    let filter = filter::HackFilter::from_config(config)?.with_selection(selection.clone());
+    let mut ids: Vec<&str> = hack_id.iter().map(|id| id.as_str()).collect();
+    // A run over some of the hacks doesn't resume one over all of them, or
+    // one over others
+    let selected = format!("{:?}", selection);
+    if !selection.is_empty() {
+        ids.push(&selected);
+    }
+    let mut progress = Progress::load(PROGRESS_FILE, &progress::list_hash(&ids))?;
+    if progress.next > 0 {
+        let at = [
+            ("at", (progress.next + 1).into()),
+            ("count", hack_id.len().into()),
+        ];
+        log.println(i18n::tr("download-resuming", &at));
+        writeln!(store_log, "resuming at hack {}", progress.next + 1)?;
+        pb.inc(progress.next as u64);
+    }
+    let listed = pipeline::plan(
+        &hack_id,
+        args.recheck,
+        config,
+        overrides,
+        &filter,
+        &manifest,
+        &progress,
+    )?;
+
+    let locks = std::sync::Arc::new(shared::Locks::new(config));
+    let fetcher = pipeline::Fetcher {
+        config: config.clone(),
+        client: client.clone(),
+        filter: std::sync::Arc::new(filter),
+        locks: locks.clone(),
+        reporter: log.clone(),
+    };
+    let (fetched_tx, fetched_rx) = tokio::sync::mpsc::channel(1);
+    tokio::try_join!(
+        pipeline::fetch(fetcher, args.jobs as usize, listed, fetched_tx),
+        pipeline::store(
+            config,
+            &mut manifest,
+            &mut progress,
+            &pb,
+            summary,
+            &locks,
+            fetched_rx,
+            &mut store_log
+        ),
+    )?;
+    pb.finish_with_message("done");
+    // A finished run starts over from the top next time
+    if Path::new(PROGRESS_FILE).exists() {
+        fs::remove_file(PROGRESS_FILE)?;
+    }
+    retries.report(&mut store_log)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::utils::tests::TestDir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // A local server standing in for the site, answering each request with
+    // the raw response `routes` makes for it. Gives the server's root URL and
+    // the requests it has been sent so far.
+    pub async fn serve<F>(mut routes: F) -> (String, Arc<Mutex<Vec<String>>>)
+    where
+        F: FnMut(&str) -> String + Send + 'static,
+    {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let site = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let sent = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0; 2048];
+                let len = socket.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..len]).to_string();
+                let response = routes(&request);
+                sent.lock().unwrap().push(request);
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (site, requests)
+    }
+
+    // A whole response, after which the connection is closed
+    pub fn respond(status: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+    }
+
+    // The path and query a request asked for
+    pub fn path_of(request: &str) -> String {
+        request.split(' ').nth(1).unwrap_or_default().to_owned()
+    }
+
+    #[test]
+    fn resolve_specs_take_curl_syntax_too() {
+        let v4 = "203.0.113.7".parse().unwrap();
+        let v6 = "2001:db8::7".parse().unwrap();
+        let site = "metroidconstruction.com".to_owned();
+        assert_eq!(
+            parse_resolve("metroidconstruction.com:203.0.113.7").unwrap(),
+            (site.clone(), v4)
+        );
+        assert_eq!(
+            parse_resolve("metroidconstruction.com:443:203.0.113.7").unwrap(),
+            (site.clone(), v4)
+        );
+        assert_eq!(
+            parse_resolve("metroidconstruction.com:443:[2001:db8::7]").unwrap(),
+            (site, v6)
+        );
+        assert!(parse_resolve("metroidconstruction.com").is_err());
+        assert!(parse_resolve("metroidconstruction.com:443:proxy").is_err());
+    }
+
+    #[tokio::test]
+    async fn cut_off_downloads_resume_with_a_range_request() {
+        let dir = TestDir::new("range");
+        // The first answer stops halfway, the second sends the rest
+        let mut answers = [
+            format!("HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n{}", "01234"),
+            format!(
+                "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 5-9/10\r\n\
+                 Content-Length: 5\r\n\r\n{}",
+                "56789"
+            ),
+        ]
+        .into_iter();
+        let (site, requests) = serve(move |_| answers.next().unwrap()).await;
+        let url = format!("{}hack.zip", site);
+        let body = b"0123456789";
+
+        let config = Config {
+            max_retries: 0,
+            page_delay_secs: 0,
+            ..Config::default()
+        };
+        let client = client(&config, &RetryLog::default()).unwrap();
+        let part = part_path(&dir.join("hack.zip"));
+        let cut_off = download_resuming(&client, &url, &part).await;
+        download_resuming(&client, &url, &part).await.unwrap();
+        let requests: Vec<_> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| r.to_lowercase())
+            .collect();
+        let contents = fs::read(&part).unwrap();
+        assert!(cut_off.is_err());
+        assert!(!requests[0].contains("range:"));
+        assert!(requests[1].contains("range: bytes=5-"), "{}", requests[1]);
+        assert_eq!(contents, body);
+    }
+}
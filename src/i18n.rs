@@ -0,0 +1,130 @@
+use crate::utils::*;
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource, FluentValue};
+use std::sync::OnceLock;
+use unic_langid::LanguageIdentifier;
+
+// Console messages and the HTML catalog in the user's language, from the
+// fluent files under `locales/`. English is built in as the fallback for
+// anything a translation doesn't have.
+
+const LOCALES: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.ftl")),
+    ("es", include_str!("../locales/es.ftl")),
+    ("pt", include_str!("../locales/pt.ftl")),
+];
+
+// The chosen language's messages, then English's
+static BUNDLES: OnceLock<Vec<FluentBundle<FluentResource>>> = OnceLock::new();
+
+fn bundle(lang: &str) -> ResultErr<FluentBundle<FluentResource>> {
+    let (_, source) = LOCALES
+        .iter()
+        .find(|(l, _)| *l == lang)
+        .ok_or_else(|| format!("no messages in {:?}", lang))?;
+    let id: LanguageIdentifier = lang.parse()?;
+    let mut bundle = FluentBundle::new_concurrent(vec![id]);
+    // The isolation marks around arguments would be copied along with the
+    // paths in messages
+    bundle.set_use_isolating(false);
+    let resource = FluentResource::try_new(source.to_string())
+        .map_err(|(_, errors)| format!("the {} messages don't parse: {:?}", lang, errors))?;
+    bundle
+        .add_resource(resource)
+        .map_err(|errors| format!("the {} messages clash: {:?}", lang, errors))?;
+    Ok(bundle)
+}
+
+// The config's language, or the system's from the usual variables. Takes
+// tags like `pt-BR` or `es_ES.UTF-8`; languages without a translation get
+// English.
+pub fn set_language(language: Option<&str>) -> ResultErr<()> {
+    let tag = language.map(|l| l.to_owned()).or_else(|| {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .find_map(|v| std::env::var(v).ok().filter(|v| !v.is_empty()))
+    });
+    let lang = tag
+        .as_deref()
+        .and_then(|t| t.split(['-', '_', '.']).next())
+        .map(|l| l.to_ascii_lowercase())
+        .unwrap_or_default();
+    let mut bundles = Vec::new();
+    if lang != "en" && LOCALES.iter().any(|(l, _)| *l == lang) {
+        bundles.push(bundle(&lang)?);
+    }
+    bundles.push(bundle("en")?);
+    BUNDLES.set(bundles).map_err(|_| "language already set")?;
+    Ok(())
+}
+
+fn bundles() -> &'static [FluentBundle<FluentResource>] {
+    BUNDLES.get_or_init(|| vec![bundle("en").expect("the English messages are built in")])
+}
+
+// The language messages are in, for the HTML catalog's `lang`
+pub fn language() -> String {
+    bundles()[0].locales[0].to_string()
+}
+
+// The message `id`, with `args` filled in
+pub fn tr(id: &str, args: &[(&str, FluentValue)]) -> String {
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        fluent_args.set(*name, value.clone());
+    }
+    for bundle in bundles() {
+        if let Some(pattern) = bundle.get_message(id).and_then(|m| m.value()) {
+            let mut errors = Vec::new();
+            return bundle
+                .format_pattern(pattern, Some(&fluent_args), &mut errors)
+                .into_owned();
+        }
+    }
+    id.to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_translation_has_every_message() {
+        // Each English message with the variables it takes, as numbers so
+        // the plural ones can pick a form too. The translations get only
+        // those, so any other variable they use shows up as an error.
+        let mut messages: Vec<(&str, FluentArgs)> = Vec::new();
+        for line in LOCALES[0].1.lines() {
+            if let Some((id, _)) = line
+                .split_once(" =")
+                .filter(|_| line.starts_with(|c: char| c.is_ascii_lowercase()))
+            {
+                messages.push((id, FluentArgs::new()));
+            }
+            if let Some((_, args)) = messages.last_mut().filter(|_| !line.starts_with('#')) {
+                for name in line.split('$').skip(1) {
+                    let end = name.find(|c: char| !c.is_ascii_alphanumeric() && c != '_');
+                    args.set(name[..end.unwrap_or(name.len())].to_owned(), 2);
+                }
+            }
+        }
+        assert!(messages.iter().any(|(id, _)| *id == "export-html"));
+        for (lang, _) in LOCALES {
+            let bundle = bundle(lang).unwrap();
+            for (id, args) in &messages {
+                let message = bundle.get_message(id);
+                assert!(message.is_some(), "{} has no {}", lang, id);
+                let mut errors = Vec::new();
+                bundle.format_pattern(message.unwrap().value().unwrap(), Some(args), &mut errors);
+                assert!(errors.is_empty(), "{} {}: {:?}", lang, id, errors);
+            }
+        }
+        let pt = bundle("pt").unwrap();
+        let mut args = FluentArgs::new();
+        args.set("count", 1);
+        args.set("path", "html/index.html");
+        let pattern = pt.get_message("export-html").unwrap().value().unwrap();
+        let text = pt.format_pattern(pattern, Some(&args), &mut Vec::new());
+        assert_eq!(text, "Um hack gravado em html/index.html");
+    }
+}
@@ -0,0 +1,347 @@
+use crate::manifest::Manifest;
+use crate::utils::*;
+use indicatif::ProgressBar;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+// These match the defaults of `ipfs add --cid-version=1`, so the CIDs we
+// record are the ones a mirror operator gets when they pin the same files
+const CHUNK_SIZE: usize = 256 * 1024;
+const MAX_LINKS: usize = 174;
+
+const CODEC_RAW: u64 = 0x55;
+const CODEC_DAG_PB: u64 = 0x70;
+const UNIXFS_FILE: u64 = 2;
+
+// Takes each block of a file with its CID
+type BlockSink<'a> = dyn FnMut(&[u8], &[u8]) -> ResultErr<()> + 'a;
+
+struct Node {
+    cid: Vec<u8>,
+    // Bytes of file content under this node
+    file_size: u64,
+    // Bytes of every block under this node, including the node itself
+    tree_size: u64,
+}
+
+fn push_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn push_bytes_field(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    push_varint(out, field << 3 | 2);
+    push_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn push_varint_field(out: &mut Vec<u8>, field: u64, n: u64) {
+    push_varint(out, field << 3);
+    push_varint(out, n);
+}
+
+fn cid_v1(codec: u64, block: &[u8]) -> Vec<u8> {
+    let mut cid = Vec::new();
+    push_varint(&mut cid, 1);
+    push_varint(&mut cid, codec);
+    // sha2-256 multihash
+    cid.push(0x12);
+    cid.push(0x20);
+    cid.extend_from_slice(&Sha256::digest(block));
+    cid
+}
+
+// Multibase base32 (lowercase, unpadded), the default text form for CIDv1
+fn cid_to_string(cid: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz234567";
+    let mut out = String::from("b");
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &byte in cid {
+        buffer = buffer << 8 | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[(buffer >> bits) as usize & 31] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(ALPHABET[(buffer << (5 - bits)) as usize & 31] as char);
+    }
+    out
+}
+
+// A UnixFS file node linking `children`, and its dag-pb block
+fn parent_node(children: &[Node]) -> (Node, Vec<u8>) {
+    let file_size = children.iter().map(|c| c.file_size).sum();
+    let mut unixfs = Vec::new();
+    push_varint_field(&mut unixfs, 1, UNIXFS_FILE);
+    push_varint_field(&mut unixfs, 3, file_size);
+    for child in children {
+        push_varint_field(&mut unixfs, 4, child.file_size);
+    }
+
+    // dag-pb puts the links ahead of the data, despite the field numbers
+    let mut block = Vec::new();
+    for child in children {
+        let mut link = Vec::new();
+        push_bytes_field(&mut link, 1, &child.cid);
+        push_bytes_field(&mut link, 2, b"");
+        push_varint_field(&mut link, 3, child.tree_size);
+        push_bytes_field(&mut block, 2, &link);
+    }
+    push_bytes_field(&mut block, 1, &unixfs);
+
+    let node = Node {
+        cid: cid_v1(CODEC_DAG_PB, &block),
+        file_size,
+        tree_size: block.len() as u64 + children.iter().map(|c| c.tree_size).sum::<u64>(),
+    };
+    (node, block)
+}
+
+// Chunks the file into its blocks, handing each to `blocks`, and returns
+// the CID of the file's root
+fn add_file(path: &Path, blocks: &mut BlockSink) -> ResultErr<Vec<u8>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut layer = Vec::new();
+    loop {
+        let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+        (&mut reader)
+            .take(CHUNK_SIZE as u64)
+            .read_to_end(&mut chunk)?;
+        // An empty file still gets a single (empty) leaf
+        if chunk.is_empty() && !layer.is_empty() {
+            break;
+        }
+        let size = chunk.len() as u64;
+        let cid = cid_v1(CODEC_RAW, &chunk);
+        blocks(&cid, &chunk)?;
+        layer.push(Node {
+            cid,
+            file_size: size,
+            tree_size: size,
+        });
+        if chunk.len() < CHUNK_SIZE {
+            break;
+        }
+    }
+    // Files that fit in one chunk are just the raw leaf. Bigger ones get the
+    // balanced layout, which comes out the same as grouping each layer
+    while layer.len() > 1 {
+        let mut parents = Vec::new();
+        for children in layer.chunks(MAX_LINKS) {
+            let (node, block) = parent_node(children);
+            blocks(&node.cid, &block)?;
+            parents.push(node);
+        }
+        layer = parents;
+    }
+    Ok(layer.swap_remove(0).cid)
+}
+
+pub fn file_cid(path: &Path) -> ResultErr<String> {
+    Ok(cid_to_string(&add_file(path, &mut |_, _| Ok(()))?))
+}
+
+// Compute the CID of every manifest file that doesn't have one yet, record it
+// in the manifest and write a `<cid> <path>` pinning list to `output`
+pub fn write_pin_list(
+    manifest: &mut Manifest,
+    root: &Path,
+    output: &Path,
+    log: &mut dyn Write,
+) -> ResultErr<()> {
+    let pb = ProgressBar::new(manifest.files().count() as u64);
+    for hack in manifest.hacks.values_mut() {
+        for file in hack.files.iter_mut() {
+            if file.cid.is_none() {
+                let path = root.join(&file.path);
+                writeln!(log, "Computing CID of {:?}", path)?;
+                file.cid = Some(file_cid(&path)?);
+            }
+            pb.inc(1);
+        }
+    }
+    pb.finish_with_message("done");
+
+    let mut pins = create_output(output)?;
+    for file in manifest.files() {
+        if let Some(cid) = &file.cid {
+            writeln!(pins, "{} {}", cid, file.path)?;
+        }
+    }
+    pins.flush()?;
+    Ok(())
+}
+
+// The head of a CBOR item: its major type and a length or value
+fn push_cbor_head(out: &mut Vec<u8>, major: u8, n: u64) {
+    let major = major << 5;
+    match n {
+        0..=23 => out.push(major | n as u8),
+        24..=0xff => out.extend([major | 24, n as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend((n as u16).to_be_bytes());
+        }
+        0x10000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend((n as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend(n.to_be_bytes());
+        }
+    }
+}
+
+// `{"roots": [...], "version": 1}` in DAG-CBOR, which orders map keys by
+// length first. CIDs are tag 42 over their bytes behind a zero byte.
+fn car_header(roots: &[Vec<u8>]) -> Vec<u8> {
+    let mut header = vec![0xa2];
+    push_cbor_head(&mut header, 3, 5);
+    header.extend_from_slice(b"roots");
+    push_cbor_head(&mut header, 4, roots.len() as u64);
+    for cid in roots {
+        header.extend([0xd8, 42]);
+        push_cbor_head(&mut header, 2, cid.len() as u64 + 1);
+        header.push(0);
+        header.extend_from_slice(cid);
+    }
+    push_cbor_head(&mut header, 3, 7);
+    header.extend_from_slice(b"version");
+    push_cbor_head(&mut header, 0, 1);
+    header
+}
+
+// CAR sections are their length as a varint, then the parts
+fn write_section(out: &mut dyn Write, parts: &[&[u8]]) -> ResultErr<()> {
+    let mut len = Vec::new();
+    push_varint(&mut len, parts.iter().map(|p| p.len() as u64).sum());
+    out.write_all(&len)?;
+    for part in parts {
+        out.write_all(part)?;
+    }
+    Ok(())
+}
+
+// Write every manifest file into a CARv1 at `output`, for `ipfs dag import`,
+// with each file's root among the CAR's roots. The CIDs are recorded in the
+// manifest as `write_pin_list` does.
+pub fn write_car(
+    manifest: &mut Manifest,
+    root: &Path,
+    output: &Path,
+    log: &mut dyn Write,
+) -> ResultErr<()> {
+    if manifest.files().next().is_none() {
+        return Err("the mirror has no files to put in a CAR".into());
+    }
+    // The header lists the roots ahead of the blocks, so the blocks wait in
+    // a scratch file until every file has been chunked
+    let scratch = output.with_extension("car.part");
+    let mut body = create_output(&scratch)?;
+    let mut written = HashSet::new();
+    let mut roots = Vec::new();
+    let pb = ProgressBar::new(manifest.files().count() as u64);
+    for hack in manifest.hacks.values_mut() {
+        for file in hack.files.iter_mut() {
+            let path = root.join(&file.path);
+            writeln!(log, "Adding {:?} to the CAR", path)?;
+            // Files and chunks that turn up more than once are written once
+            let cid = add_file(&path, &mut |cid, block| {
+                if written.insert(cid.to_vec()) {
+                    write_section(&mut body, &[cid, block])?;
+                }
+                Ok(())
+            })?;
+            file.cid = Some(cid_to_string(&cid));
+            if !roots.contains(&cid) {
+                roots.push(cid);
+            }
+            pb.inc(1);
+        }
+    }
+    pb.finish_with_message("done");
+    body.flush()?;
+    drop(body);
+
+    let mut car = create_output(output)?;
+    write_section(&mut car, &[&car_header(&roots)])?;
+    io::copy(&mut File::open(&scratch)?, &mut car)?;
+    car.flush()?;
+    fs::remove_file(&scratch)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::TestDir;
+
+    fn read_varint(car: &[u8], at: &mut usize) -> usize {
+        let mut n = 0;
+        let mut shift = 0;
+        loop {
+            let byte = car[*at];
+            *at += 1;
+            n |= ((byte & 0x7f) as usize) << shift;
+            if byte < 0x80 {
+                return n;
+            }
+            shift += 7;
+        }
+    }
+
+    #[test]
+    fn the_car_holds_every_block_of_the_mirror_once() {
+        let dir = TestDir::new("car");
+        let hack_dir = dir.join("downloads").join("0001-5-Test");
+        fs::create_dir_all(&hack_dir).unwrap();
+        // Two chunks the same, a shorter one and the node linking the three,
+        // then a chunk and that chunk again
+        let big: Vec<u8> = (0..CHUNK_SIZE * 2 + 5).map(|i| i as u8).collect();
+        fs::write(hack_dir.join("big.bin"), &big).unwrap();
+        fs::write(hack_dir.join("small.ips"), b"PATCHEOF").unwrap();
+        fs::write(hack_dir.join("small copy.ips"), b"PATCHEOF").unwrap();
+
+        let downloads = dir.join("downloads");
+        let mut manifest = Manifest::rebuild(&downloads, &mut io::sink()).unwrap();
+        let output = dir.join("mirror.car");
+        write_car(&mut manifest, &downloads, &output, &mut io::sink()).unwrap();
+        let car = fs::read(&output).unwrap();
+        assert!(!dir.join("mirror.car.part").exists());
+
+        let mut at = 0;
+        let header_len = read_varint(&car, &mut at);
+        let header = &car[at..at + header_len];
+        at += header_len;
+        let mut cids = Vec::new();
+        while at < car.len() {
+            let len = read_varint(&car, &mut at);
+            let (cid, block) = car[at..at + len].split_at(36);
+            assert_eq!(cid, cid_v1(cid[1] as u64, block));
+            cids.push(cid_to_string(cid));
+            at += len;
+        }
+
+        assert_eq!(cids.len(), 4);
+        let big_cid = manifest
+            .files()
+            .find(|f| f.path.ends_with("big.bin"))
+            .unwrap();
+        assert!(big_cid.cid.as_deref().unwrap().starts_with("bafybei"));
+        let mut roots: Vec<_> = manifest.files().map(|f| f.cid.clone().unwrap()).collect();
+        roots.dedup();
+        assert_eq!(roots.len(), 2);
+        assert!(roots.iter().all(|root| cids.contains(root)));
+        assert!(header.starts_with(b"\xa2\x65roots\x82"));
+    }
+}
@@ -0,0 +1,90 @@
+use crate::catalog::Catalog;
+use crate::outputs::Outputs;
+use crate::scope::Scope;
+use crate::utils::*;
+use sanitise_file_name::sanitise;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+#[cfg(unix)]
+fn link(target: &Path, link: &Path) -> ResultErr<()> {
+    std::os::unix::fs::symlink(target, link)?;
+    Ok(())
+}
+
+// Symlinks need a privilege or developer mode on Windows, and junctions only
+// work for directories, so ROMs fall back to hard links
+#[cfg(windows)]
+fn link(target: &Path, link: &Path) -> ResultErr<()> {
+    if std::os::windows::fs::symlink_file(target, link).is_err() {
+        fs::hard_link(target, link)?;
+    }
+    Ok(())
+}
+
+// Build `by-genre/`, `by-author/`, `by-difficulty/` and `by-scope/`
+// directories under `dest` linking to the patched ROMs, so the collection
+// can be browsed in a file manager. Returns how many links were made.
+pub fn write_links(
+    outputs: &Outputs,
+    catalog: &Catalog,
+    dest: &Path,
+    fat32_safe: bool,
+    log: &mut dyn Write,
+) -> ResultErr<usize> {
+    let axes = ["by-genre", "by-author", "by-difficulty", "by-scope"];
+    // Start over, so hacks that moved category don't linger in the old one
+    for axis in axes {
+        let dir = dest.join(axis);
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+    }
+    let mut count = 0;
+    for entry in outputs.entries.values() {
+        let Some(info) = entry.hack_id.and_then(|id| catalog.hacks.get(&id)) else {
+            writeln!(log, "No metadata for {}, not linking it", entry.output)?;
+            continue;
+        };
+        let rom = Path::new(&entry.output);
+        if !rom.exists() {
+            writeln!(log, "{} is gone, not linking it", entry.output)?;
+            continue;
+        }
+        let target = fs::canonicalize(rom)?;
+        let file_name = rom.file_name().ok_or("bad path")?.to_string_lossy();
+        // Different hacks often ship patches with the same file name
+        let mut name = sanitise(&format!("{} - {}", info.title, file_name));
+        if fat32_safe {
+            name = fat32_safe_name(&name);
+        }
+        let scope = entry
+            .bytes_changed
+            .map(|b| Scope::of(b).as_str().to_owned())
+            .unwrap_or_default();
+        for (axis, value) in axes
+            .iter()
+            .zip([&info.genre, &info.author, &info.difficulty, &scope])
+        {
+            if value.is_empty() {
+                continue;
+            }
+            let mut category = sanitise(value);
+            if fat32_safe {
+                category = fat32_safe_name(&category);
+            }
+            let dir = dest.join(axis).join(category);
+            fs::create_dir_all(&dir)?;
+            let path = dir.join(&name);
+            if path.symlink_metadata().is_ok() {
+                writeln!(log, "{:?} already links another ROM, skipping", path)?;
+                continue;
+            }
+            writeln!(log, "Linking {:?} to {:?}", path, target)?;
+            link(&target, &path)?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
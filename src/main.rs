@@ -1,67 +1,896 @@
 use clap::Parser;
-use indicatif::ProgressBar;
-use ips::Patch;
-use regex::Regex;
-use reqwest_middleware::ClientBuilder;
-use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
-use sanitise_file_name::sanitise;
-use scraper::{Html, Selector};
-use std::fs::{self, create_dir_all, File, OpenOptions};
-use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
-use std::path::PathBuf;
-use walkdir::DirEntry;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
+mod api;
+mod cache;
+mod cassette;
+mod catalog;
+mod changelog;
+mod codes;
+mod collate;
+mod config;
+mod control;
+mod counts;
+mod curation;
+mod deploy;
+mod doctor;
+mod dupes;
+mod events;
+mod export;
+mod extract;
+mod feed;
+mod filter;
+mod fsck;
+#[cfg(feature = "gui")]
+mod gui;
+mod hashdb;
+mod heartbeat;
+mod history;
+mod howto;
+mod html;
+mod http;
+mod i18n;
+mod ipfs;
+mod links;
+mod manifest;
+mod metadata;
+mod news;
+mod normalize;
+mod output;
+mod outputs;
+mod overrides;
+mod patch;
+mod paths;
+mod pins;
+mod pipeline;
+mod played;
+mod progress;
+mod prune;
+mod records;
+mod remote;
+mod reporter;
+mod rom;
+mod schema;
+mod scope;
+mod scrape;
+mod screenshots;
+mod shared;
+mod sign;
+mod smoke;
+mod snapshot;
+mod state;
+mod stats;
+mod status;
+mod summary;
+mod tags;
+mod torrent;
 mod utils;
+mod warc;
+use cassette::Cassette;
+use catalog::{Catalog, CATALOG_FILE};
+use config::{Config, CONFIG_FILE};
+use outputs::{Outputs, OUTPUTS_FILE};
+use overrides::{Overrides, OVERRIDES_FILE};
+use reporter::Reporter;
+use summary::Summary;
 use utils::*;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
 struct Args {
     #[command(subcommand)]
-    mode: RunMode,
+    mode: Option<RunMode>,
+    /// Instead of running a mode, take commands as JSON lines and answer
+    /// with JSON events, for driving the tool from another program
+    #[arg(long, value_enum, value_name = "CHANNEL", conflicts_with = "json")]
+    control: Option<control::Channel>,
+    /// Unpack archives under this directory, one subdirectory per hack,
+    /// instead of next to the archives in the downloads directory
+    #[arg(
+        long,
+        global = true,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "extracted"
+    )]
+    extract_dir: Option<String>,
+    /// Keep the names of patched ROMs and exports within FAT32's limits
+    #[arg(long, global = true)]
+    fat32_safe: bool,
+    /// Connect to a host at this address instead of looking it up, as
+    /// `host:ip` or curl's `host:port:ip`. May be repeated.
+    #[arg(long, global = true)]
+    resolve: Vec<String>,
+    /// Also archive every page and file fetched into a WARC file
+    #[arg(
+        long,
+        global = true,
+        num_args = 0..=1,
+        require_equals = true,
+        value_name = "FILE",
+        default_missing_value = warc::DEFAULT_WARC_FILE
+    )]
+    warc: Option<String>,
+    /// Save every HTTP response of this run into a cassette directory
+    #[arg(long, global = true, value_name = "DIR", conflicts_with = "replay")]
+    record: Option<String>,
+    /// Answer every HTTP request from a recorded cassette directory instead
+    /// of the network
+    #[arg(long, global = true, value_name = "DIR")]
+    replay: Option<String>,
+    /// Stream progress events as JSON lines to whatever connects to a Unix
+    /// socket created at this path
+    #[arg(long, global = true, value_name = "SOCKET")]
+    events: Option<String>,
+    /// Keep the config, state and logs in the current directory with
+    /// relative paths, so it can be moved to another machine as a whole
+    #[arg(long, global = true)]
+    portable: bool,
+    /// When to color console output
+    #[arg(long, global = true, value_enum, default_value_t)]
+    color: output::ColorChoice,
+    /// Print only a JSON document on stdout once the mode is done, with
+    /// whether it worked, its counts and the files it made. What it would
+    /// have printed goes to stderr.
+    #[arg(long, global = true)]
+    json: bool,
+    /// Take the default answer to every question instead of asking, for
+    /// running unattended
+    #[arg(long, short, global = true)]
+    yes: bool,
 }
 
-#[derive(clap::Subcommand, Debug, Clone, PartialEq, Eq)]
+#[derive(clap::Subcommand, Debug, Clone, PartialEq)]
+#[command(after_help = "Run `howto` for the order to run the modes in.")]
 enum RunMode {
-    Download,
+    /// Mirror every hack's files from the site into the downloads directory
+    ///
+    /// The first step: `unzip` and `patch` work on what this mirrors.
+    /// Interrupted runs pick up where they stopped, and hacks already
+    /// mirrored are skipped without fetching their pages.
+    #[command(
+        visible_alias = "dl",
+        after_help = "Examples:
+  metconst-tool download
+  metconst-tool download --recheck    pick up updated files too
+  metconst-tool download --jobs 4     four hacks at a time
+  metconst-tool download --id 756
+  metconst-tool download --author metroid3 --since 2020-01-01
+  metconst-tool download --warc       also archive everything fetched"
+    )]
+    Download(DownloadArgs),
+    /// Bring the mirror up to date: `download`, `unzip` and `patch` in a row
+    ///
+    /// The patch step uses the base ROM from the config file. Stops at the
+    /// first step that fails.
+    #[command(after_help = "Examples:
+  metconst-tool up
+  metconst-tool up --jobs 4 --yes     from cron")]
+    Up(DownloadArgs),
+    /// Apply every downloaded patch to the base ROM
+    ///
+    /// The last step, after `download` and `unzip`: most hacks ship their
+    /// patches inside archives. Patched ROMs go to the patched directory,
+    /// laid out like the downloads.
+    #[command(after_help = "Examples:
+  metconst-tool patch                 use the base ROM from the config
+  metconst-tool patch ~/roms/sm.sfc --fix-checksum
+  metconst-tool patch ~/roms/new.sfc --since-base ~/roms/old.sfc")]
     Patch(PatchArgs),
+    /// Download one archive or patch and patch it, without the mirror
+    ///
+    /// For trying a hack someone linked. The patched ROMs go to `adhoc` in
+    /// the patched directory, and nothing is added to the manifest.
+    #[command(after_help = "Examples:
+  metconst-tool patch-url https://example.com/hack.zip
+  metconst-tool patch-url https://example.com/hack.ips --base-rom ~/roms/sm.sfc")]
+    PatchUrl(PatchUrlArgs),
+    /// Unpack every downloaded archive
+    ///
+    /// Run after `download` and before `patch`. Archives are unpacked next
+    /// to themselves, or under the extract directory when one is set.
+    #[command(after_help = "Examples:
+  metconst-tool unzip
+  metconst-tool unzip --extract-dir   keep the downloads directory as downloaded")]
     Unzip,
+    /// List the kinds of files in the downloads, and normalize ROM images
+    /// in odd layouts
     FileTypes,
-    Metadata,
+    /// Scrape every hack's details into metadata.csv and the catalog
+    ///
+    /// Independent of the download, unzip and patch steps. Each run after
+    /// the first also writes a changelog against the last one.
+    #[command(
+        visible_alias = "md",
+        after_help = "Examples:
+  metconst-tool metadata
+  metconst-tool metadata --fast       only what the list of hacks shows
+  metconst-tool metadata --forum --screenshots"
+    )]
+    Metadata(MetadataArgs),
+    /// Collect hack announcements from the site's front page
+    News,
+    /// Show how a hack's details changed over the metadata runs
+    History {
+        /// The hack's id on the site
+        #[arg()]
+        id: u32,
+    },
+    /// List the hacks in the catalog, or only some of them
+    #[command(after_help = "Examples:
+  metconst-tool list --unplayed
+  metconst-tool list --unplayed --tag favorite")]
+    List(ListArgs),
+    /// Mark a hack as finished, with your rating and how long it took
+    #[command(after_help = "Examples:
+  metconst-tool played 756
+  metconst-tool played 756 --rating 4 --time 3h
+  metconst-tool played 756 --time 1h45m")]
+    Played(PlayedArgs),
+    /// Leaderboards from the catalog the metadata mode scraped
+    #[command(after_help = "Examples:
+  metconst-tool stats --authors
+  metconst-tool stats --authors --format csv --output authors.csv
+  metconst-tool stats --duplicates    after a patch run")]
+    Stats(StatsArgs),
+    /// Write the mirror out as a torrent, IPFS pins, a feed, link trees or
+    /// SHA256SUMS. Files are signed with the keys in the config, if any.
+    #[command(after_help = "Examples:
+  metconst-tool export --format torrent --tracker udp://tracker.example:1337
+  metconst-tool export --format links --output links
+  metconst-tool export --format sums    signed when minisign_key or gpg_key is set")]
+    Export(ExportArgs),
+    /// Print the order to run the modes in, with the configured directories
+    Howto,
+    /// Interactively create the config file
+    Init,
+    /// Check the setup and the downloads mirror for common problems
+    Doctor,
+    /// Cross-check the manifest against the downloads directory and plan
+    /// repairs
+    Fsck(FsckArgs),
+    /// Summarize what has been mirrored so far
+    Status(StatusArgs),
+    /// Run every patched ROM in the configured emulator for a few seconds to
+    /// catch the ones that crash right away
+    SmokeTest,
+    /// Copy patched ROMs onto a flashcart's SD card
+    Deploy(DeployArgs),
+    /// Remove what the retention policies in the config file no longer keep
+    Prune {
+        /// Only list what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Apply Game Genie or Pro Action Replay codes to a copy of the base ROM
+    Codes(CodesArgs),
+    /// Make an IPS patch that turns the base ROM into another ROM
+    #[command(after_help = "Examples:
+  metconst-tool diff my-hack.sfc --output my-hack.ips
+  metconst-tool diff v2.sfc --base-rom v1.sfc --output v1-to-v2.ips")]
+    Diff(DiffArgs),
+    /// Open a window to browse, download, patch and play hacks
+    ///
+    /// Only in builds with the `gui` feature. Playing runs the emulator from
+    /// `play_command` in the config, or the smoke test's.
+    #[cfg(feature = "gui")]
+    Gui,
+    /// Share and compare hashes of patched ROMs
+    #[command(subcommand)]
+    Hashdb(HashdbCommand),
+    /// Back up or restore the tool's bookkeeping, without the mirrored files
+    #[command(subcommand)]
+    State(StateCommand),
+    /// Keep your own tags and notes on hacks, to deploy or export by
+    #[command(
+        subcommand,
+        after_help = "Examples:
+  metconst-tool tag add 756 favorite \"great physics\"
+  metconst-tool tag list --tag favorite
+  metconst-tool deploy --target /media/sd --tag favorite"
+    )]
+    Tag(TagCommand),
+    /// Share your tags, ratings and plays without the mirror, or take in
+    /// someone else's
+    #[command(
+        subcommand,
+        after_help = "Examples:
+  metconst-tool curation export mine.json
+  metconst-tool curation export mine.csv     for spreadsheets
+  metconst-tool curation import alice.json   adds tags like alice/favorite
+  metconst-tool list --tag alice/played"
+    )]
+    Curation(CurationCommand),
+    /// Record the hashes of every patched ROM under a name, to see which
+    /// ones a new version of the tool or another base ROM changes
+    #[command(
+        subcommand,
+        after_help = "Examples:
+  metconst-tool snapshot create before-upgrade
+  metconst-tool patch
+  metconst-tool snapshot create after-upgrade
+  metconst-tool snapshot diff before-upgrade after-upgrade"
+    )]
+    Snapshot(SnapshotCommand),
+    /// Print the paths to one hack's files, downloading, extracting and
+    /// patching it first as needed
+    ///
+    /// Made for launchers and frontends: only the paths go to stdout, one
+    /// per line.
+    #[command(after_help = "Examples:
+  metconst-tool resolve --id 756
+  metconst-tool resolve --id 756 --ensure downloaded")]
+    Resolve(ResolveArgs),
+}
+
+impl Args {
+    // Paths given on the command line are relative to where the tool was
+    // started, before it moves to its home directory
+    fn resolve_paths(&mut self, cwd: &Path) {
+        let from_cwd = |p: &mut String| *p = paths::from_cwd(cwd, p);
+        self.record.iter_mut().for_each(from_cwd);
+        self.replay.iter_mut().for_each(from_cwd);
+        self.events.iter_mut().for_each(from_cwd);
+        self.warc.iter_mut().for_each(from_cwd);
+        self.extract_dir.iter_mut().for_each(from_cwd);
+        let Some(mode) = &mut self.mode else {
+            return;
+        };
+        match mode {
+            RunMode::Patch(pa) => {
+                pa.base_rom.iter_mut().for_each(from_cwd);
+                pa.since_base.iter_mut().for_each(from_cwd);
+            }
+            RunMode::PatchUrl(pa) => pa.base_rom.iter_mut().for_each(from_cwd),
+            RunMode::Export(ea) => ea.output.iter_mut().for_each(from_cwd),
+            RunMode::Stats(sa) => sa.output.iter_mut().for_each(from_cwd),
+            RunMode::Deploy(da) => from_cwd(&mut da.target),
+            RunMode::Codes(ca) => {
+                from_cwd(&mut ca.output);
+                ca.base_rom.iter_mut().for_each(from_cwd);
+            }
+            RunMode::Diff(da) => {
+                from_cwd(&mut da.modified);
+                from_cwd(&mut da.output);
+                da.base_rom.iter_mut().for_each(from_cwd);
+            }
+            RunMode::Hashdb(HashdbCommand::Export { file } | HashdbCommand::Import { file }) => {
+                from_cwd(file)
+            }
+            RunMode::State(StateCommand::Export { file } | StateCommand::Import { file, .. }) => {
+                from_cwd(file)
+            }
+            RunMode::Curation(
+                CurationCommand::Export { file } | CurationCommand::Import { file, .. },
+            ) => from_cwd(file),
+            _ => (),
+        }
+    }
+}
+
+impl RunMode {
+    fn name(&self) -> &'static str {
+        match self {
+            RunMode::Download(_) => "download",
+            RunMode::Up(_) => "up",
+            RunMode::Patch(_) => "patch",
+            RunMode::PatchUrl(_) => "patch-url",
+            RunMode::Unzip => "unzip",
+            RunMode::FileTypes => "file-types",
+            RunMode::Metadata(_) => "metadata",
+            RunMode::News => "news",
+            RunMode::History { .. } => "history",
+            RunMode::Stats(_) => "stats",
+            RunMode::List(_) => "list",
+            RunMode::Played(_) => "played",
+            RunMode::Export(_) => "export",
+            RunMode::Howto => "howto",
+            RunMode::Init => "init",
+            RunMode::Doctor => "doctor",
+            RunMode::Fsck(_) => "fsck",
+            RunMode::Status(_) => "status",
+            RunMode::SmokeTest => "smoke-test",
+            RunMode::Deploy(_) => "deploy",
+            RunMode::Prune { .. } => "prune",
+            RunMode::Codes(_) => "codes",
+            RunMode::Diff(_) => "diff",
+            #[cfg(feature = "gui")]
+            RunMode::Gui => "gui",
+            RunMode::Hashdb(_) => "hashdb",
+            RunMode::State(_) => "state",
+            RunMode::Tag(_) => "tag",
+            RunMode::Curation(_) => "curation",
+            RunMode::Snapshot(_) => "snapshot",
+            RunMode::Resolve(_) => "resolve",
+        }
+    }
+}
+
+#[derive(clap::Subcommand, Debug, Clone, PartialEq, Eq)]
+enum HashdbCommand {
+    /// Write the hashes of every ROM the patch mode produced
+    Export {
+        #[arg(default_value = "hashdb.json")]
+        file: String,
+    },
+    /// Compare someone else's exported hashes against the local ROMs
+    Import {
+        #[arg()]
+        file: String,
+    },
+}
+
+#[derive(clap::Subcommand, Debug, Clone, PartialEq, Eq)]
+enum TagCommand {
+    /// Tag a hack, replacing the note if it already has the tag
+    Add {
+        #[arg()]
+        id: u32,
+        #[arg()]
+        tag: String,
+        #[arg(default_value = "")]
+        note: String,
+    },
+    Remove {
+        #[arg()]
+        id: u32,
+        #[arg()]
+        tag: String,
+    },
+    /// List the tagged hacks with their tags and notes
+    List {
+        /// Only the hacks with this tag
+        #[arg(long)]
+        tag: Option<String>,
+    },
+}
+
+#[derive(clap::Subcommand, Debug, Clone, PartialEq, Eq)]
+enum CurationCommand {
+    /// Write your tags and plays, as CSV if the file ends in .csv
+    Export {
+        #[arg(default_value = "curation.json")]
+        file: String,
+    },
+    /// Add the tags and plays in someone's JSON export to your tags
+    Import {
+        #[arg()]
+        file: String,
+        /// What their tags are prefixed with, defaults to the file's name
+        #[arg(long)]
+        from: Option<String>,
+    },
+}
+
+#[derive(clap::Subcommand, Debug, Clone, PartialEq, Eq)]
+enum SnapshotCommand {
+    /// Record the hashes of the patched ROMs as they are now
+    Create {
+        #[arg()]
+        name: String,
+    },
+    /// Show which patched ROMs changed from snapshot `a` to `b`, and why
+    Diff {
+        #[arg()]
+        a: String,
+        #[arg()]
+        b: String,
+    },
+    List,
+}
+
+#[derive(clap::Subcommand, Debug, Clone, PartialEq, Eq)]
+enum StateCommand {
+    /// Write the config, manifest, catalog and caches to a .tar.zst file
+    Export {
+        #[arg(default_value = state::DEFAULT_STATE_FILE)]
+        file: String,
+    },
+    /// Restore an export, then check which mirrored files are in place
+    Import {
+        #[arg()]
+        file: String,
+        /// Replace state that's already here
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+struct ResolveArgs {
+    /// The hack's id on the site
+    #[arg(long)]
+    id: u32,
+    /// How far along the hack has to be
+    #[arg(long, value_enum, default_value_t = cache::Stage::Patched)]
+    ensure: cache::Stage,
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+struct DownloadArgs {
+    /// Fetch the pages of hacks the manifest already has every file of, to
+    /// pick up updated downloads
+    #[arg(long)]
+    recheck: bool,
+    /// Hacks to download at once. Requests still go out no closer together
+    /// than the configured page delay.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: u16,
+    /// Only this hack, may be repeated
+    #[arg(long)]
+    id: Vec<u32>,
+    /// Only hacks by an author whose name has this in it, may be repeated
+    #[arg(long)]
+    author: Vec<String>,
+    /// Only hacks of a genre with this in its name, may be repeated
+    #[arg(long)]
+    genre: Vec<String>,
+    /// Only hacks released on or after this date, as YYYY-MM-DD
+    #[arg(long, value_name = "DATE")]
+    since: Option<chrono::NaiveDate>,
+    /// Only hacks released on or before this date, as YYYY-MM-DD
+    #[arg(long, value_name = "DATE")]
+    until: Option<chrono::NaiveDate>,
+}
+
+impl DownloadArgs {
+    fn selection(&self) -> filter::Selection {
+        filter::Selection {
+            ids: self.id.clone(),
+            authors: self.author.clone(),
+            genres: self.genre.clone(),
+            since: self.since,
+            until: self.until,
+        }
+    }
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+struct MetadataArgs {
+    /// List hacks with missing or unparsable fields, and why they are missing
+    #[arg(long)]
+    audit: bool,
+    /// Also fetch each hack's forum thread for its reply count and last activity
+    #[arg(long)]
+    forum: bool,
+    /// Download each hack's screenshots and make thumbnails of them
+    #[arg(long)]
+    screenshots: bool,
+    /// Only read the title, author, date and rating off the list of hacks,
+    /// keeping the other fields from the last full run
+    #[arg(long, conflicts_with_all = ["audit", "forum", "screenshots"])]
+    fast: bool,
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+struct FsckArgs {
+    /// Carry out the repairs, downloading what's missing or damaged
+    #[arg(long)]
+    repair: bool,
+    /// Only compare sizes instead of hashing every file
+    #[arg(long)]
+    quick: bool,
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+struct StatusArgs {
+    /// Break down disk usage by phase and by hack
+    #[arg(long)]
+    disk: bool,
+    /// How many of the largest hacks to list with --disk
+    #[arg(long, default_value_t = 10)]
+    top: usize,
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+struct ListArgs {
+    /// Only hacks not marked played
+    #[arg(long)]
+    unplayed: bool,
+    /// Only hacks with this tag, may be repeated
+    #[arg(long)]
+    tag: Vec<String>,
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq)]
+struct PlayedArgs {
+    #[arg()]
+    id: u32,
+    /// Your rating, out of five
+    #[arg(long, value_parser = played::parse_rating)]
+    rating: Option<f64>,
+    /// How long it took, like 3h, 1h30m or 45m
+    #[arg(long, value_parser = played::parse_time)]
+    time: Option<u64>,
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+struct StatsArgs {
+    /// Hacks, ratings, genres and release span per author
+    #[arg(long, required_unless_present_any = ["played", "duplicates"])]
+    authors: bool,
+    /// The hacks you've marked played, with your ratings and times
+    #[arg(long, conflicts_with = "authors")]
+    played: bool,
+    /// Hacks that patch into the same or nearly the same ROM, like
+    /// reuploads and renamed versions
+    #[arg(long, conflicts_with_all = ["authors", "played"])]
+    duplicates: bool,
+    #[arg(long, value_enum, default_value_t)]
+    format: stats::StatsFormat,
+    /// Where to write the table, defaults to stdout
+    #[arg(long)]
+    output: Option<String>,
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+struct DeployArgs {
+    /// Where the SD card is mounted
+    #[arg(long)]
+    target: String,
+    #[arg(long, value_enum, default_value_t = DeployProfile::Sd2snes)]
+    profile: DeployProfile,
+    /// Only deploy this hack id, may be repeated
+    #[arg(long)]
+    hack: Vec<u32>,
+    /// Only deploy hacks with this tag, may be repeated
+    #[arg(long)]
+    tag: Vec<String>,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum DeployProfile {
+    /// FXPAK/SD2SNES, long file names
+    Sd2snes,
+    /// Everdrive, 8.3 file names
+    Everdrive,
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+struct CodesArgs {
+    /// Codes like `C2A8-DF6D` or `80D5A3EA`
+    #[arg(required = true)]
+    codes: Vec<String>,
+    #[arg(long)]
+    output: String,
+    /// Defaults to the base ROM from the config file
+    #[arg(long)]
+    base_rom: Option<String>,
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+struct DiffArgs {
+    /// The ROM the patch should make
+    #[arg()]
+    modified: String,
+    #[arg(long)]
+    output: String,
+    /// Defaults to the base ROM from the config file
+    #[arg(long)]
+    base_rom: Option<String>,
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+struct PatchUrlArgs {
+    /// An archive or a patch
+    #[arg()]
+    url: String,
+    /// Defaults to the base ROM from the config file
+    #[arg(long)]
+    base_rom: Option<String>,
+    /// Recompute the internal header checksum of the patched ROMs
+    #[arg(long)]
+    fix_checksum: bool,
 }
 
 #[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
 struct PatchArgs {
+    /// Defaults to the base ROM from the config file
     #[arg()]
-    base_rom: String,
+    base_rom: Option<String>,
+    /// Recompute the internal header checksum of every patched ROM
+    #[arg(long)]
+    fix_checksum: bool,
+    /// Also patch randomizer base patches, which are meant to be fed to a
+    /// randomizer and are left out by default
+    #[arg(long)]
+    randomizers: bool,
+    /// The base ROM the last run patched onto. Only the patches that don't
+    /// overwrite every byte where it differs from the new one are redone.
+    #[arg(long, value_name = "OLD_ROM")]
+    since_base: Option<String>,
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+struct ExportArgs {
+    #[arg(long, value_enum)]
+    format: ExportFormat,
+    /// Where to write the export, defaults to a name based on the format
+    #[arg(long)]
+    output: Option<String>,
+    /// Tracker announce URL to embed in a torrent, may be repeated
+    #[arg(long)]
+    tracker: Vec<String>,
+    /// Only link hacks with this tag, may be repeated
+    #[arg(long)]
+    tag: Vec<String>,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    /// v1/v2 hybrid .torrent of the downloads mirror
+    Torrent,
+    /// IPFS CIDs of the downloads mirror, recorded in the manifest and
+    /// listed one `<cid> <path>` line per file
+    Ipfs,
+    /// CARv1 of the downloads mirror's files, for `ipfs dag import`
+    Car,
+    /// Atom feed of the hacks most recently added to or updated in the mirror
+    Rss,
+    /// Static HTML catalog of the mirror, an index and a page per hack
+    Html,
+    /// Directories of links to the patched ROMs by genre, author and difficulty
+    Links,
+    /// SHA256SUMS of the downloads mirror, for `sha256sum -c`
+    Sums,
 }
 
 #[tokio::main]
 async fn main() -> ResultErr<()> {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    output::set_color(args.color);
+    let mut json_out = match args.json {
+        true => Some(output::stdout_to_stderr()?),
+        false => None,
+    };
+    let cwd = std::env::current_dir()?;
+    let home = paths::home(&cwd, args.portable)?;
+    args.resolve_paths(&cwd);
+    if let Some(dir) = paths::log_dir(&home, &cwd, args.portable)? {
+        set_log_dir(dir)?;
+    }
+    fs::create_dir_all(&home)?;
+    std::env::set_current_dir(&home)?;
+    let mut config = Config::load(CONFIG_FILE)?;
+    i18n::set_language(config.language.as_deref())?;
+    if args.portable {
+        config.check_portable()?;
+    }
+    if args.extract_dir.is_some() {
+        config.extract_dir = args.extract_dir;
+    }
+    if args.fat32_safe {
+        config.fat32_safe = true;
+    }
+    config.assume_yes = args.yes;
+    for spec in &args.resolve {
+        http::parse_resolve(spec)?;
+    }
+    config.resolve.extend(args.resolve);
+    if args.warc.is_some() {
+        config.warc_file = args.warc;
+    }
+    if let Some(dir) = &args.record {
+        config.cassette = Some(Cassette::open(dir, cassette::Mode::Record)?);
+    }
+    if let Some(dir) = &args.replay {
+        config.cassette = Some(Cassette::open(dir, cassette::Mode::Replay)?);
+        // Nothing to be polite to
+        config.page_delay_secs = 0;
+        config.file_delay_secs = 0;
+    }
+    if let Some(path) = &args.events {
+        config.events = Some(events::EventStream::listen(Path::new(path))?);
+    }
+    shared::apply_permissions(&config)?;
+    let overrides = Overrides::load(OVERRIDES_FILE)?;
+
+    let mode = match (args.mode, args.control) {
+        (Some(mode), None) => mode,
+        (None, Some(channel)) => return control::control(channel, config, overrides).await,
+        (Some(_), Some(_)) => return Err("--control runs instead of a mode, not with one".into()),
+        (None, None) => return Err("no mode given, see --help".into()),
+    };
+
+    let summary = Summary::default();
+    let started = std::time::Instant::now();
+    let name = mode.name();
+    // Scripts read the paths `resolve` prints and the tables `stats` does,
+    // and nothing else, and likewise the hacks `list` does. There's
+    // nothing to count in `howto`, `tag` or `played`.
+    let quiet = matches!(
+        mode,
+        RunMode::Resolve(_)
+            | RunMode::Stats(_)
+            | RunMode::List(_)
+            | RunMode::Howto
+            | RunMode::Tag(_)
+            | RunMode::Played(_)
+            | RunMode::Snapshot(_)
+    );
+    let events = config.events.clone();
+    let result = run(mode, config, overrides, &summary).await;
+    let tally = summary.tally();
+    // Kept so `status` can say where the last run's counts went
+    if tally != summary::Tally::default() {
+        summary.save_report(summary::REPORT_FILE, name)?;
+    }
+    if let Some(events) = events {
+        let skips: BTreeMap<_, _> = tally.skips.iter().map(|(s, i)| (*s, i.len())).collect();
+        events.emit(
+            "finished",
+            serde_json::json!({
+                "mode": name,
+                "ok": result.is_ok(),
+                "processed": tally.processed,
+                "skipped": tally.skipped,
+                "skips": skips,
+                "failed": tally.failed,
+                "bytes": tally.bytes,
+            }),
+        );
+    }
+    if let Some(out) = &mut json_out {
+        let error = result.as_ref().err().map(|e| e.to_string());
+        let document = summary.document(name, error, started.elapsed());
+        writeln!(out, "{}", document)?;
+    } else if !quiet {
+        print!("{}", summary.table(name, started.elapsed()));
+    }
+    result
+}
 
-    match args.mode {
-        RunMode::Download => {
-            let mut log_writer = open_log("download.txt")?;
-            download(&mut log_writer).await?;
+async fn run(
+    mode: RunMode,
+    config: Config,
+    overrides: Overrides,
+    summary: &Summary,
+) -> ResultErr<()> {
+    match mode {
+        RunMode::Download(da) => {
+            let log = Reporter::open("download.txt")?;
+            http::download(&da, &config, &overrides, summary, &log).await?;
+        }
+        RunMode::Up(da) => {
+            let patch = PatchArgs {
+                base_rom: None,
+                fix_checksum: false,
+                randomizers: false,
+                since_base: None,
+            };
+            for step in [RunMode::Download(da), RunMode::Unzip, RunMode::Patch(patch)] {
+                println!(
+                    "{}",
+                    i18n::tr("up-running", &[("mode", step.name().into())])
+                );
+                Box::pin(run(step, config.clone(), overrides.clone(), summary)).await?;
+            }
         }
         RunMode::Unzip => {
-            let mut log_writer = open_log("unzip.txt")?;
-            process_directory(
-                unarchive_in_dir,
-                "downloads",
-                is_archive_file,
-                &mut log_writer,
-            )?;
+            let summary = summary.clone();
+            run_blocking(move |cancel| {
+                let mut log_writer = Reporter::open("unzip.txt")?;
+                extract::unzip(&config, &overrides, &summary, cancel, &mut log_writer)
+            })
+            .await?;
         }
         RunMode::Patch(pa) => {
-            let mut log_writer = open_log("patch.txt")?;
-            process_directory(
-                |f, l| patch_in_dir(&pa.base_rom, f, l),
-                "downloads",
-                is_ips_file,
-                &mut log_writer,
-            )?;
+            let summary = summary.clone();
+            let config = remote::stage_loose_files(&config, &mut open_log("remote.txt")?).await?;
+            run_blocking(move |cancel| {
+                let mut log_writer = Reporter::open("patch.txt")?;
+                pipeline::patch(pa, &config, &overrides, &summary, cancel, &mut log_writer)
+            })
+            .await?;
+        }
+        RunMode::PatchUrl(pa) => {
+            let log_writer = Reporter::open("patch-url.txt")?;
+            for rom in pipeline::patch_url(pa, config, summary, log_writer).await? {
+                println!("{}", rom.to_string_lossy());
+            }
         }
         RunMode::FileTypes => {
             use std::collections::HashSet;
@@ -75,439 +904,343 @@ async fn main() -> ResultErr<()> {
                     }
                     Ok(())
                 },
-                "downloads",
+                &config.downloads_dir,
+                any_dir,
                 |_| true,
                 &mut log_writer,
             )?;
-            println!("extensions: {:?}", extensions);
+            let list = format!("{:?}", extensions);
+            println!(
+                "{}",
+                i18n::tr("filetypes-extensions", &[("list", list.into())])
+            );
+
+            // Full ROM images in odd layouts are normalized next to the
+            // patched ROMs
+            let mut roots = vec![PathBuf::from(&config.downloads_dir)];
+            roots.extend(config.extract_root().map(PathBuf::from));
+            let out_dir = Path::new(&config.patched_dir).join("normalized");
+            let unfixable = normalize::check_rom_images(&roots, &out_dir, &mut log_writer)?;
+            if !unfixable.is_empty() {
+                let args = [("count", unfixable.len().into())];
+                let header = i18n::tr("normalize-failed", &args);
+                println!("{}", output::error(header));
+                for (image, why) in unfixable {
+                    println!("  {}: {}", image.to_string_lossy(), output::error(why));
+                }
+            }
         }
-        RunMode::Metadata => {
-            let mut log_writer = open_log("metadata.txt")?;
-            metadata(&mut log_writer).await?;
+        RunMode::Metadata(ma) => {
+            let mut log_writer = Reporter::open("metadata.txt")?;
+            metadata::metadata(&ma, &config, summary, &mut log_writer).await?;
         }
-    }
-
-    Ok(())
-}
-
-fn unarchive_in_dir(entry: &DirEntry, log: &mut dyn Write) -> ResultErr<()> {
-    if is_zip_file(entry) {
-        unzip_in_dir(entry, log)?
-    } else if is_rar_file(entry) {
-        unrar_in_dir(entry, log)?
-    } else if is_7z_file(entry) {
-        un7z_in_dir(entry, log)?
-    }
-    Ok(())
-}
-
-fn un7z_in_dir(entry: &DirEntry, log: &mut dyn Write) -> ResultErr<()> {
-    writeln!(log, "7z file: {:?}", entry.path()).expect("cannot write to log");
-    if let Some(parent) = entry.path().parent() {
-        if let Some(archive_name) = entry.path().file_stem() {
-            let mut unpack_dir = PathBuf::new();
-            unpack_dir.push(parent);
-            unpack_dir.push(archive_name);
-            create_dir_all(&unpack_dir)?;
-            writeln!(log, "Creating: {:?}", unpack_dir).expect("failed to write to log");
-            sevenz_rust::decompress_file(entry.path(), unpack_dir)?;
+        RunMode::News => {
+            let mut log_writer = open_log("news.txt")?;
+            news::news(&config, &mut log_writer).await?;
         }
-    }
-    Ok(())
-}
-
-fn unrar_in_dir(entry: &DirEntry, log: &mut dyn Write) -> ResultErr<()> {
-    writeln!(log, "Rar file: {:?}", entry.path()).expect("cannot write to log");
-    let mut archive = unrar::Archive::new(entry.path()).open_for_processing()?;
-    if let Some(parent) = entry.path().parent() {
-        if let Some(archive_name) = entry.path().file_stem() {
-            let mut unpack_dir = PathBuf::new();
-            unpack_dir.push(parent);
-            unpack_dir.push(archive_name);
-            while let Some(header) = archive.read_header()? {
-                archive = if header.entry().is_file() {
-                    let mut full_file_name = PathBuf::new();
-                    full_file_name.push(unpack_dir.clone());
-                    full_file_name.push(&header.entry().filename);
-
-                    create_dir_all(full_file_name.parent().unwrap())?;
-
-                    writeln!(log, "Creating: {:?}", full_file_name)
-                        .expect("failed to write to log");
-                    header.extract_with_base(full_file_name.parent().unwrap())?
-                } else {
-                    header.skip()?
-                };
+        RunMode::History { id } => {
+            let history = history::History::load(history::HISTORY_FILE)?;
+            history::write_history(&history, id, &mut std::io::stdout())?;
+        }
+        RunMode::Stats(sa) => stats::stats(&sa)?,
+        RunMode::Played(pa) => {
+            let mut played = played::Played::load(played::PLAYED_FILE)?;
+            let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+            played.record(pa.id, &today, pa.rating, pa.time);
+            played.save(played::PLAYED_FILE)?;
+        }
+        RunMode::List(la) => catalog::list(&la, &config)?,
+        RunMode::Export(ea) => {
+            let mut log_writer = open_log("export.txt")?;
+            export::export(&ea, &config, &mut log_writer)?;
+        }
+        RunMode::Init => config::init(config)?,
+        RunMode::Howto => print!("{}", howto::workflow(&config)),
+        RunMode::Doctor => {
+            let mut log_writer = open_log("doctor.txt")?;
+            doctor::doctor(&config, &mut log_writer)?;
+        }
+        RunMode::Fsck(fa) => {
+            let mut log_writer = Reporter::open("fsck.txt")?;
+            fsck::fsck(&fa, &config, &overrides, summary, &mut log_writer).await?;
+        }
+        RunMode::Status(sa) => {
+            let mut log_writer = open_log("status.txt")?;
+            status::status(&config, sa.disk, sa.top, &mut log_writer)?;
+        }
+        RunMode::SmokeTest => {
+            let mut log_writer = open_log("smoke-test.txt")?;
+            let mut outputs = Outputs::load(OUTPUTS_FILE)?;
+            let (passed, failed) = smoke::smoke_test(&config, &mut outputs, &mut log_writer)?;
+            outputs.save(OUTPUTS_FILE)?;
+            let args = [("passed", passed.into()), ("failed", failed.into())];
+            println!("{}", i18n::tr("smoke-result", &args));
+        }
+        RunMode::Deploy(da) => {
+            let mut log_writer = open_log("deploy.txt")?;
+            let profile = match da.profile {
+                DeployProfile::Sd2snes => &deploy::SD2SNES,
+                DeployProfile::Everdrive => &deploy::EVERDRIVE,
+            };
+            let mut hacks = da.hack.clone();
+            if !da.tag.is_empty() {
+                hacks.extend(tags::Tags::load(tags::TAGS_FILE)?.select(&da.tag)?);
             }
+            deploy::deploy(
+                &Outputs::load(OUTPUTS_FILE)?,
+                &Catalog::load(CATALOG_FILE)?,
+                &hacks,
+                Path::new(&da.target),
+                profile,
+                config.sort_strip_articles,
+                &mut log_writer,
+            )?;
         }
-    }
-    Ok(())
-}
-
-fn unzip_in_dir(entry: &DirEntry, log: &mut dyn Write) -> ResultErr<()> {
-    writeln!(log, "Zip file: {:?}", entry.path()).expect("cannot write to log");
-    let zip_file = File::open(entry.path())?;
-    let zip_reader = BufReader::new(&zip_file);
-
-    let mut zip = zip::ZipArchive::new(zip_reader)?;
-
-    if let Some(parent) = entry.path().parent() {
-        if let Some(zip_name) = entry.path().file_stem() {
-            let mut unpack_dir = PathBuf::new();
-            unpack_dir.push(parent);
-            unpack_dir.push(zip_name);
-
-            writeln!(log, "creating unpack directory: {:?}", unpack_dir)
-                .expect("failed to write log");
-            create_dir_all(&unpack_dir)?;
-
-            for i in 0..zip.len() {
-                let mut file = zip.by_index(i)?;
-                if file.name().ends_with('/') {
-                    continue;
+        RunMode::Prune { dry_run } => {
+            let mut log_writer = open_log("prune.txt")?;
+            prune::prune(&config, dry_run, &mut log_writer)?;
+        }
+        RunMode::Codes(ca) => {
+            let mut log_writer = open_log("codes.txt")?;
+            let base_rom = ca
+                .base_rom
+                .or(config.base_rom.clone())
+                .ok_or("no base ROM given, pass one or set it up with `init`")?;
+            let mut codes = Vec::new();
+            for text in &ca.codes {
+                codes.push(codes::decode(text).ok_or(format!("not a cheat code: {}", text))?);
+            }
+            let mut contents = fs::read(&base_rom)?;
+            let applied = codes::apply_codes(&mut contents, &codes, &mut log_writer)?;
+            if applied == 0 {
+                return Err("none of the codes change the ROM".into());
+            }
+            fs::write(&ca.output, contents)?;
+            let args = [
+                ("applied", applied.into()),
+                ("count", codes.len().into()),
+                ("path", ca.output.into()),
+            ];
+            println!("{}", i18n::tr("codes-applied", &args));
+        }
+        RunMode::Diff(da) => {
+            let base_rom = da
+                .base_rom
+                .or(config.base_rom.clone())
+                .ok_or("no base ROM given, pass one or set it up with `init`")?;
+            let ips = patch::create_ips(&fs::read(&base_rom)?, &fs::read(&da.modified)?)?;
+            fs::write(&da.output, &ips)?;
+            let args = [("path", da.output.into()), ("size", ips.len().into())];
+            println!("{}", i18n::tr("diff-wrote", &args));
+        }
+        #[cfg(feature = "gui")]
+        RunMode::Gui => gui::gui(config, overrides)?,
+        RunMode::Hashdb(command) => {
+            let mut log_writer = open_log("hashdb.txt")?;
+            let outputs = Outputs::load(OUTPUTS_FILE)?;
+            match command {
+                HashdbCommand::Export { file } => {
+                    let count = hashdb::export(&outputs, Path::new(&file))?;
+                    let args = [("count", count.into()), ("path", file.into())];
+                    println!("{}", i18n::tr("hashdb-wrote", &args));
+                }
+                HashdbCommand::Import { file } => {
+                    hashdb::import(&outputs, Path::new(&file), &mut log_writer)?
                 }
-                let mut full_file_name = PathBuf::new();
-                full_file_name.push(unpack_dir.clone());
-                full_file_name.push(file.name());
-
-                create_dir_all(full_file_name.parent().unwrap())?;
-
-                writeln!(log, "Creating: {:?}", full_file_name).expect("failed to write to log");
-                let output = OpenOptions::new()
-                    .read(true)
-                    .write(true)
-                    .create(true)
-                    .truncate(true)
-                    .open(full_file_name)?;
-                let mut output_writer = BufWriter::new(&output);
-                std::io::copy(&mut file, &mut output_writer)?;
             }
         }
-    }
-    Ok(())
-}
-
-fn patch_in_dir(base_rom: &str, entry: &DirEntry, log: &mut dyn Write) -> ResultErr<()> {
-    let dir_path = entry.path().parent().ok_or("bad path")?;
-    let mut rom_file = PathBuf::new();
-    rom_file.push("patched");
-    rom_file.push(dir_path);
-    fs::create_dir_all(&rom_file)?;
-    rom_file.push(entry.file_name());
-    let extension = base_rom.rsplit_once('.');
-    rom_file.set_extension(extension.map(|(_, e)| e).unwrap());
-
-    writeln!(
-        log,
-        "Applying {} to create {}, in {}",
-        entry.path().to_str().unwrap_or("error"),
-        rom_file.to_str().unwrap_or("error"),
-        dir_path.to_str().unwrap_or("error"),
-    )?;
-
-    // Create a clean copy of the rom
-    writeln!(log, "Copying {:#?} to {:#?}", base_rom, &rom_file)?;
-    fs::copy(base_rom, &rom_file)?;
-    // Ensure that we can write to it
-    writeln!(log, "Setting permissions on {:#?}", &rom_file)?;
-    let mut perms = fs::metadata(&rom_file)?.permissions();
-    #[allow(clippy::permissions_set_readonly_false)]
-    perms.set_readonly(false);
-    fs::set_permissions(&rom_file, perms)?;
-
-    // Open the rom file and begin overwriting it
-    writeln!(log, "Opening {:#?} to apply patch", &rom_file)?;
-    let mut rom = OpenOptions::new().read(true).write(true).open(rom_file)?;
-    writeln!(log, "Reading patch file {:#?}", &entry.path())?;
-    let patch_contents = fs::read(entry.path())?;
-    let patch = Patch::parse(&patch_contents)?;
-
-    writeln!(log, "Applying hunks")?;
-    for hunk in patch.hunks() {
-        rom.seek(SeekFrom::Start(hunk.offset() as u64))?;
-        rom.write_all(hunk.payload())?;
-    }
-
-    if let Some(truncation) = patch.truncation() {
-        writeln!(log, "Truncating")?;
-        rom.set_len(truncation as u64)?;
-    }
-
-    Ok(())
-}
-
-async fn download(log: &mut dyn Write) -> ResultErr<()> {
-    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(10);
-    let client = ClientBuilder::new(reqwest::ClientBuilder::new().user_agent("Foo").build()?)
-        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-        .build();
-    let metconst = "https://metroidconstruction.com/";
-
-    // TODO: this will need to pull down mulitple pages once there are > 1000 hacks
-    let allhacks = format!("{}hacks.php?sort=5&dir=asc&filters%5B%5D=SM&filters%5B%5D=Unknown&filters%5B%5D=Boss+Rush&filters%5B%5D=Exploration&filters%5B%5D=Challenge&filters%5B%5D=Spoof&filters%5B%5D=Speedrun%2FRace&filters%5B%5D=Incomplete&filters%5B%5D=Quick+Play&filters%5B%5D=Improvement&filters%5B%5D=Vanilla%2B&search=&num_per_page=1000", metconst);
-
-    println!("Fetching list of hacks...");
-    let body = client.get(allhacks).send().await?.text().await?;
-    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-    let document = Html::parse_document(&body);
-    let row_selector = Selector::parse("td")?;
-    let ahref = Selector::parse("a")?;
-
-    // example: hack.php?id=756
-    let re = Regex::new(r"^hack\.php\?id=([0-9]+)$")?;
-
-    let mut hack_id = Vec::new();
-    for element in document.select(&row_selector) {
-        for e in element.select(&ahref) {
-            if let Some(href) = e.value().attr("href") {
-                for (_, [id]) in re.captures_iter(href).map(|c| c.extract()) {
-                    hack_id.push(id);
+        RunMode::Tag(command) => {
+            let mut tags = tags::Tags::load(tags::TAGS_FILE)?;
+            match command {
+                TagCommand::Add { id, tag, note } => {
+                    tags.add(id, &tag, &note)?;
+                    tags.save(tags::TAGS_FILE)?;
+                }
+                TagCommand::Remove { id, tag } => {
+                    if !tags.remove(id, &tag) {
+                        return Err(format!("hack {} isn't tagged {:?}", id, tag).into());
+                    }
+                    tags.save(tags::TAGS_FILE)?;
+                }
+                TagCommand::List { tag } => {
+                    let catalog = Catalog::load(CATALOG_FILE)?;
+                    tags::write_tags(&tags, &catalog, tag.as_deref(), &mut std::io::stdout())?;
                 }
             }
         }
-    }
-    println!(
-        "There are a total of {} hacks available. This process may take several hours.",
-        hack_id.len()
-    );
-
-    let pb = ProgressBar::new(hack_id.len() as u64);
-
-    for (idx, id) in hack_id.iter().enumerate() {
-        let hack_url = format!("{}hack.php?id={}", metconst, id);
-        let hack_page = client.get(hack_url).send().await?.text().await?;
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-        let document = Html::parse_document(&hack_page);
-        let download_link = format!(r"(^download\.php\?id={})", id);
-        let re = Regex::new(&download_link)?;
-        let meta = Selector::parse("meta")?;
-        #[allow(non_snake_case)]
-        let underboxA = Selector::parse("td.underboxA")?;
-
-        // Extract hack title
-        // In an ideal world, we would always just use the meta property
-        // but for some reason, not all hack pages have that attribute set.
-        // So when we can't find the meta tag with "og:title" we fallback to
-        // looking for the hack title on the page
-        let mut title = None;
-        for element in document.select(&meta) {
-            if element.attr("property") == Some("og:title") {
-                title = element.attr("content");
+        RunMode::Curation(command) => {
+            let mut tags = tags::Tags::load(tags::TAGS_FILE)?;
+            match command {
+                CurationCommand::Export { file } => {
+                    let played = played::Played::load(played::PLAYED_FILE)?;
+                    let catalog = Catalog::load(CATALOG_FILE)?;
+                    let count = curation::export(&tags, &played, &catalog, Path::new(&file))?;
+                    let args = [("count", count.into()), ("path", file.into())];
+                    println!("{}", i18n::tr("curation-wrote", &args));
+                }
+                CurationCommand::Import { file, from } => {
+                    let from = from.unwrap_or_else(|| curation::default_source(Path::new(&file)));
+                    let count = curation::import(&mut tags, Path::new(&file), &from)?;
+                    tags.save(tags::TAGS_FILE)?;
+                    let args = [
+                        ("count", count.into()),
+                        ("path", file.into()),
+                        ("from", from.into()),
+                    ];
+                    println!("{}", i18n::tr("curation-tagged", &args));
+                }
             }
         }
-        if title.is_none() {
-            // We just want the first underboxA on the page
-            if let Some(element) = document.select(&underboxA).next() {
-                title = element.text().next().map(|t| t.trim());
+        RunMode::Snapshot(command) => {
+            let mut snapshots = snapshot::Snapshots::load(snapshot::SNAPSHOTS_FILE)?;
+            match command {
+                SnapshotCommand::Create { name } => {
+                    let outputs = Outputs::load(OUTPUTS_FILE)?;
+                    let created =
+                        chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+                    let count = snapshots.create(&name, &created, &outputs)?;
+                    snapshots.save(snapshot::SNAPSHOTS_FILE)?;
+                    let args = [("count", count.into()), ("name", name.into())];
+                    println!("{}", i18n::tr("snapshot-recorded", &args));
+                }
+                SnapshotCommand::Diff { a, b } => {
+                    let count = snapshot::write_diff(&snapshots, &a, &b, &mut std::io::stdout())?;
+                    println!(
+                        "{}",
+                        i18n::tr("snapshot-changed", &[("count", count.into())])
+                    );
+                }
+                SnapshotCommand::List => snapshot::write_list(&snapshots, &mut std::io::stdout())?,
             }
         }
-        // No longer mutable
-        let title = title;
-
-        for element in document.select(&ahref) {
-            if let Some(href) = element.value().attr("href") {
-                //println!("href={}", href);
-                if re.is_match(href) {
-                    let redirect_url = format!("{}{}", metconst, href);
-                    let redirect_contents = client.get(redirect_url).send().await?.text().await?;
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-                    let document = Html::parse_document(&redirect_contents);
-                    for element in document.select(&meta) {
-                        if let Some(url) = element.value().attr("content") {
-                            if let Some((_, url)) = url.rsplit_once('=') {
-                                if let Some((_, file_name)) = url.rsplit_once('/') {
-                                    let dir_name;
-                                    if let Some(title) = title {
-                                        dir_name = format!(
-                                            "downloads/{}",
-                                            sanitise(&format!("{:04}-{}-{}", idx, id, title))
-                                        );
-                                    } else {
-                                        dir_name = format!("downloads/{:04}-{}", idx, id);
-                                    }
-                                    let full_file_name = format!("{}/{}", dir_name, file_name);
-                                    if std::path::Path::new(&full_file_name).exists() {
-                                        //println!("skipping {}, already downloaded", url);
-                                        writeln!(log, "skipping {}, already downloaded", url)
-                                            .expect("failed to log");
-                                    } else {
-                                        //println!("url: {}", url);
-                                        writeln!(log, "url: {}", url).expect("failed to log");
-                                        //println!("file_name: {}", file_name);
-                                        writeln!(log, "file_name: {}", file_name)
-                                            .expect("failed to log");
-                                        let file_contents =
-                                            client.get(url).send().await?.bytes().await?;
-                                        //println!("dir_name: {}", dir_name);
-                                        writeln!(log, "dir_name: {}", dir_name)
-                                            .expect("failed to log");
-                                        create_dir_all(&dir_name)?;
-                                        let mut file = File::create(full_file_name)?;
-                                        file.write_all(&file_contents)?;
-                                        tokio::time::sleep(tokio::time::Duration::from_secs(5))
-                                            .await;
-                                    }
-                                }
-                            }
-                        }
+        RunMode::State(command) => {
+            let mut log_writer = open_log("state.txt")?;
+            let home = Path::new(".");
+            match command {
+                StateCommand::Export { file } => {
+                    let count = state::export(home, Path::new(&file), &mut log_writer)?;
+                    let args = [("count", count.into()), ("path", file.into())];
+                    println!("{}", i18n::tr("state-wrote", &args));
+                }
+                StateCommand::Import { file, force } => {
+                    let count = state::import(home, Path::new(&file), force, &mut log_writer)?;
+                    let args = [("count", count.into()), ("path", file.into())];
+                    println!("{}", i18n::tr("state-restored", &args));
+                    let (present, total) = state::relink(home, &mut log_writer)?;
+                    let args = [("present", present.into()), ("count", total.into())];
+                    println!("{}", i18n::tr("state-present", &args));
+                    if present < total {
+                        println!("{}", i18n::tr("state-hint", &[]));
                     }
                 }
             }
         }
-        pb.inc(1);
+        RunMode::Resolve(ra) => {
+            let log_writer = Reporter::open("resolve.txt")?;
+            let report = |message: &str| eprintln!("{}", message);
+            for path in
+                cache::ensure(ra.id, ra.ensure, config, overrides, log_writer, &report).await?
+            {
+                println!("{}", path.to_string_lossy());
+                summary.wrote(&path);
+            }
+        }
     }
-    pb.finish_with_message("done");
 
     Ok(())
 }
 
-async fn metadata(_: &mut dyn Write) -> ResultErr<()> {
-    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(10);
-    let client = ClientBuilder::new(reqwest::ClientBuilder::new().user_agent("Foo").build()?)
-        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-        .build();
-    let metconst = "https://metroidconstruction.com/";
-
-    // TODO: this will need to pull down mulitple pages once there are > 1000 hacks
-    let allhacks = format!("{}hacks.php?sort=5&dir=asc&filters%5B%5D=SM&filters%5B%5D=Unknown&filters%5B%5D=Boss+Rush&filters%5B%5D=Exploration&filters%5B%5D=Challenge&filters%5B%5D=Spoof&filters%5B%5D=Speedrun%2FRace&filters%5B%5D=Incomplete&filters%5B%5D=Quick+Play&filters%5B%5D=Improvement&filters%5B%5D=Vanilla%2B&search=&num_per_page=1000", metconst);
-
-    println!("Fetching list of hacks...");
-    let body = client.get(allhacks).send().await?.text().await?;
-    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-    let document = Html::parse_document(&body);
-    let row_selector = Selector::parse("td")?;
-    let ahref = Selector::parse("a")?;
-
-    // example: hack.php?id=756
-    let re = Regex::new(r"^hack\.php\?id=([0-9]+)$")?;
-
-    let mut hack_id = Vec::new();
-    for element in document.select(&row_selector) {
-        for e in element.select(&ahref) {
-            if let Some(href) = e.value().attr("href") {
-                for (_, [id]) in re.captures_iter(href).map(|c| c.extract()) {
-                    hack_id.push(id);
+// Local modes are blocking work, so they run off the async runtime. Ctrl-C
+// asks them to stop, which they do as soon as the item they're on has been
+// cleaned up after; a second Ctrl-C quits straight away.
+async fn run_blocking<F, T>(work: F) -> ResultErr<T>
+where
+    F: FnOnce(&Cancel) -> ResultErr<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let cancel = Cancel::default();
+    let cancel_requested = cancel.clone();
+    let watcher = {
+        let cancel = cancel.clone();
+        tokio::spawn(async move {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                if cancel.is_cancelled() {
+                    std::process::exit(130);
                 }
+                println!("{}", i18n::tr("stopping", &[]));
+                cancel.cancel();
             }
-        }
+        })
+    };
+    // Errors don't cross threads, only their messages do
+    let result =
+        tokio::task::spawn_blocking(move || work(&cancel).map_err(|e| e.to_string())).await;
+    watcher.abort();
+    if cancel_requested.is_cancelled() {
+        return Err("stopped by Ctrl-C before finishing".into());
     }
-    println!("There are a total of {} hacks available.", hack_id.len());
-
-    let pb = ProgressBar::new(hack_id.len() as u64);
-
-    // Release date:
-    let release_date_re = Regex::new(r"<b>Release date:</b>(.*)")?;
-    // Author:
-    let author_re = Regex::new("<b>Author:</b> <a href=\".*\">(.*)</a>")?;
-    // Genre:
-    let genre_re = Regex::new("<b>Genre:</b> (.*) <")?;
-    // Difficulty:
-    let difficulty_re = Regex::new("<b>Difficulty:</b> (.*) <")?;
-    let mut csv_writer = open_log("metadata.csv")?;
-    let rating_re = Regex::new("Average Rating: ([0-9]+.[0-9]+) chozo orbs")?;
-    writeln!(
-        csv_writer,
-        "title,date,author,genre,difficulty,avg runtime,avg collection,avg rating,by pedro"
-    )?;
-    let mut pedro_aliases = vec![
-        "crimsonsunbird".to_owned(),
-        "Juan Dennys".to_owned(),
-        "pedro123".to_owned(),
-        "jailsonmendes".to_owned(),
-        "FaiskaBr".to_owned(),
-    ];
-    pedro_aliases
-        .iter_mut()
-        .for_each(|s| s.make_ascii_lowercase());
-    for id in hack_id.iter() {
-        let hack_url = format!("{}hack.php?id={}", metconst, id);
-        let hack_page = client.get(hack_url).send().await?.text().await?;
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-        let document = Html::parse_document(&hack_page);
-        let meta = Selector::parse("meta")?;
-        #[allow(non_snake_case)]
-        let underboxA = Selector::parse("td.underboxA")?;
-        #[allow(non_snake_case)]
-        let underboxD = Selector::parse(".underboxD")?;
-
-        // Extract hack title
-        // In an ideal world, we would always just use the meta property
-        // but for some reason, not all hack pages have that attribute set.
-        // So when we can't find the meta tag with "og:title" we fallback to
-        // looking for the hack title on the page
-        let mut title = None;
-        for element in document.select(&meta) {
-            if element.attr("property") == Some("og:title") {
-                title = element.attr("content");
-            }
-        }
-        if title.is_none() {
-            // We just want the first underboxA on the page
-            if let Some(element) = document.select(&underboxA).next() {
-                title = element.text().next().map(|t| t.trim());
-            }
-        }
-        // No longer mutable
-        let title = title;
-
-        let mut date: String = String::new();
-        let mut author: String = String::new();
-        let mut genre = String::new();
-        let mut difficulty = String::new();
-        for element in document.select(&underboxD) {
-            let text = element.inner_html();
-            for (_, [d]) in release_date_re.captures_iter(&text).map(|c| c.extract()) {
-                date = d.trim().to_owned();
-            }
-            for (_, [a]) in author_re.captures_iter(&text).map(|c| c.extract()) {
-                author = a.trim().to_owned();
-            }
-            for (_, [g]) in genre_re.captures_iter(&text).map(|c| c.extract()) {
-                genre = g.trim().to_owned();
-            }
-            for (_, [d]) in difficulty_re.captures_iter(&text).map(|c| c.extract()) {
-                difficulty = d.trim().to_owned();
-            }
-        }
-        let mut runtime = String::new();
-        let avg_runtime = Selector::parse("#average_runtime")?;
-        for element in document.select(&avg_runtime) {
-            runtime = element.inner_html();
-        }
-        let mut collection = String::new();
-        let avg_collection = Selector::parse("#average_completion")?;
-        for element in document.select(&avg_collection) {
-            collection = element.inner_html();
-        }
-        let mut rating = String::new();
-        let avg_rating = Selector::parse("span[title]")?;
-        for element in document.select(&avg_rating) {
-            let text = element.inner_html();
-            for (_, [d]) in rating_re.captures_iter(&text).map(|c| c.extract()) {
-                rating = d.trim().to_owned();
-            }
-        }
-        let by_pedro = if pedro_aliases.contains(&author.to_ascii_lowercase()) {
-            "Y"
-        } else {
-            "N"
+    Ok(result??)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downloads_can_be_narrowed_to_ids_authors_genres_and_dates() {
+        let args = Args::try_parse_from([
+            "metconst-tool",
+            "download",
+            "--author",
+            "METROID3",
+            "--genre",
+            "exploration",
+            "--since",
+            "2020-01-01",
+        ])
+        .unwrap();
+        let Some(RunMode::Download(da)) = args.mode else {
+            panic!("not the download mode");
+        };
+        let selection = da.selection();
+        assert!(selection.needs_page());
+        let page = |author: &str, genre: &str, date: &str| scrape::HackPage {
+            author: author.to_owned(),
+            genre: genre.to_owned(),
+            date: date.to_owned(),
+            ..scrape::HackPage::default()
+        };
+        assert!(selection.selects(&page("metroid3", "Exploration", "Mar 05, 2021")));
+        assert!(!selection.selects(&page("metroid3", "Exploration", "Mar 05, 2019")));
+        assert!(!selection.selects(&page("metroid3", "Exploration", "")));
+        assert!(!selection.selects(&page("Kejardon", "Exploration", "Mar 05, 2021")));
+        assert!(!selection.selects(&page("metroid3", "Challenge", "Mar 05, 2021")));
+
+        // Ids are known from the list, so the others are never fetched
+        let by_id = filter::Selection {
+            ids: vec![756],
+            ..filter::Selection::default()
         };
-        writeln!(
-            csv_writer,
-            "\"{}\",\"{}\",\"{}\",{},{},{},{},{},{}",
-            title.unwrap_or(""),
-            date,
-            author,
-            genre,
-            difficulty,
-            runtime,
-            collection,
-            rating,
-            by_pedro,
-        )?;
-        pb.inc(1);
+        assert!(!by_id.needs_page());
+        let filter = filter::HackFilter::default().with_selection(by_id);
+        assert!(filter.may_allow(756) && !filter.may_allow(212));
     }
-    pb.finish_with_message("done");
 
-    Ok(())
+    #[test]
+    fn up_and_the_short_aliases_parse_to_their_modes() {
+        let parse = |args: &[&str]| {
+            let args = Args::try_parse_from([&["metconst-tool"], args].concat()).unwrap();
+            (args.mode.unwrap().name(), args.yes)
+        };
+        assert_eq!(parse(&["dl", "--jobs", "2"]), ("download", false));
+        assert_eq!(parse(&["md", "--fast"]), ("metadata", false));
+        assert_eq!(parse(&["up", "--yes"]), ("up", true));
+        assert_eq!(parse(&["-y", "up", "--recheck"]), ("up", true));
+    }
+
+    #[test]
+    fn json_documents_are_not_printed_for_the_control_loop() {
+        assert!(Args::try_parse_from(["metconst-tool", "--json", "--control", "stdio"]).is_err());
+        assert!(Args::try_parse_from(["metconst-tool", "--json", "download"]).is_ok());
+    }
 }
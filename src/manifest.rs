@@ -0,0 +1,312 @@
+use crate::i18n;
+use crate::records::DownloadRecord;
+use crate::schema::{self, Migration};
+use crate::shared::Locks;
+use crate::utils::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+const MIGRATIONS: &[Migration] = &[schema::unchanged, mark_randomizers];
+
+// Bookkeeping for everything the download mode has mirrored into `downloads/`.
+// Hacks are keyed by their metroidconstruction.com id.
+#[derive(Debug, Default)]
+pub struct Manifest {
+    pub hacks: BTreeMap<u32, HackEntry>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct HackEntry {
+    pub title: Option<String>,
+    // Directory name under `downloads/`
+    pub dir: String,
+    #[serde(default)]
+    pub files: Vec<DownloadRecord>,
+    // A randomizer base patch, which the patch mode leaves out by default
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub randomizer: bool,
+}
+
+impl HackEntry {
+    // Randomizer base patches are fed to a randomizer rather than applied
+    // as they are, and their titles or file names say so
+    fn looks_like_randomizer(&self) -> bool {
+        let files = self.files.iter().map(|f| f.path.as_str());
+        self.title.as_deref().into_iter().chain(files).any(|name| {
+            name.split(|c: char| !c.is_alphanumeric()).any(|word| {
+                let word = word.to_lowercase();
+                word == "rando" || word.contains("randomiz") || word.contains("randomis")
+            })
+        })
+    }
+}
+
+// Version 2 flags the randomizer base patches mirrored before there was a
+// flag for them
+fn mark_randomizers(value: &mut Value) -> ResultErr<()> {
+    let Some(hacks) = value.get_mut("hacks").and_then(|h| h.as_object_mut()) else {
+        return Ok(());
+    };
+    for hack in hacks.values_mut() {
+        let entry: HackEntry = serde_json::from_value(hack.clone())?;
+        if entry.looks_like_randomizer() {
+            hack["randomizer"] = json!(true);
+        }
+    }
+    Ok(())
+}
+
+impl Manifest {
+    pub fn load<P: AsRef<Path>>(fname: P) -> ResultErr<Manifest> {
+        if !fname.as_ref().exists() {
+            return Ok(Manifest::default());
+        }
+        let mut value = read_json(fname)?;
+        schema::upgrade(&mut value, MIGRATIONS, MANIFEST_FILE)?;
+        Manifest::from_json(&value)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, fname: P) -> ResultErr<()> {
+        write_json(fname, &self.to_json())
+    }
+
+    // Writes only `id`'s entry over what's on disk, so runs on other machines
+    // sharing the mirror keep theirs, and picks up what they recorded
+    pub fn save_hack<P: AsRef<Path>>(&mut self, fname: P, id: u32, locks: &Locks) -> ResultErr<()> {
+        let _lock = locks.state()?;
+        let mut on_disk = Manifest::load(&fname)?;
+        match self.hacks.get(&id) {
+            Some(hack) => on_disk.hacks.insert(id, hack.clone()),
+            None => on_disk.hacks.remove(&id),
+        };
+        on_disk.save(fname)?;
+        self.hacks = on_disk.hacks;
+        Ok(())
+    }
+
+    fn to_json(&self) -> Value {
+        let mut value = json!({ "hacks": self.hacks });
+        schema::stamp(&mut value, MIGRATIONS);
+        value
+    }
+
+    fn from_json(value: &Value) -> ResultErr<Manifest> {
+        let hacks = value.get("hacks").cloned().unwrap_or_else(|| json!({}));
+        Ok(Manifest {
+            hacks: serde_json::from_value(hacks)?,
+        })
+    }
+
+    // Hash `file` (which must live under `root`) and record it against `id`,
+    // replacing any previous entry for the same path.
+    pub fn record_file(
+        &mut self,
+        root: &Path,
+        id: u32,
+        title: Option<&str>,
+        dir: &str,
+        file: &Path,
+    ) -> ResultErr<()> {
+        let metadata = fs::metadata(file)?;
+        let downloaded = chrono::DateTime::<chrono::Utc>::from(metadata.modified()?)
+            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        let path = relative_path(root, file)?;
+        let hack = self.hacks.entry(id).or_default();
+        // A file downloaded again was still reached by the same links
+        let links = hack
+            .files
+            .iter()
+            .find(|f| f.path == path)
+            .map(|f| f.links.clone())
+            .unwrap_or_default();
+        let entry = DownloadRecord {
+            path,
+            size: metadata.len(),
+            sha256: sha256_file(file)?,
+            cid: None,
+            downloaded: Some(downloaded),
+            links,
+        };
+        if let Some(title) = title {
+            hack.title = Some(title.to_owned());
+        }
+        hack.dir = dir.to_owned();
+        hack.files.retain(|f| f.path != entry.path);
+        hack.files.push(entry);
+        hack.randomizer = hack.looks_like_randomizer();
+        Ok(())
+    }
+
+    // Note the download links that led to `file`, keeping those seen before
+    pub fn record_links(
+        &mut self,
+        root: &Path,
+        id: u32,
+        file: &Path,
+        links: &[String],
+    ) -> ResultErr<()> {
+        let path = relative_path(root, file)?;
+        let record = self
+            .hacks
+            .get_mut(&id)
+            .and_then(|h| h.files.iter_mut().find(|f| f.path == path));
+        if let Some(record) = record {
+            for link in links {
+                if !record.links.contains(link) {
+                    record.links.push(link.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Forget a file that was deleted from under `root`
+    pub fn remove_path(&mut self, root: &Path, file: &Path) -> ResultErr<()> {
+        let path = relative_path(root, file)?;
+        for hack in self.hacks.values_mut() {
+            hack.files.retain(|f| f.path != path);
+        }
+        Ok(())
+    }
+
+    pub fn contains_path(&self, root: &Path, file: &Path) -> bool {
+        match relative_path(root, file) {
+            Ok(path) => self.files().any(|f| f.path == path),
+            Err(_) => false,
+        }
+    }
+
+    // Whether every file recorded for `id` is still under `root` at the size
+    // it was downloaded at, so the hack's pages needn't be fetched again
+    pub fn has_all_files(&self, root: &Path, id: u32) -> bool {
+        match self.hacks.get(&id) {
+            Some(hack) if !hack.files.is_empty() => hack.files.iter().all(|f| {
+                fs::metadata(root.join(&f.path)).is_ok_and(|m| m.is_file() && m.len() == f.size)
+            }),
+            _ => false,
+        }
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &DownloadRecord> {
+        self.hacks.values().flat_map(|h| h.files.iter())
+    }
+
+    // `sha256sum -c` input for the mirror, paths relative to its root
+    pub fn write_sums(&self, out: &mut dyn Write) -> ResultErr<()> {
+        for file in self.files() {
+            writeln!(out, "{}  {}", file.sha256, file.path)?;
+        }
+        Ok(())
+    }
+
+    // Reconstruct the manifest from an existing downloads directory. Only the
+    // files sitting directly inside each `NNNN-ID-Title` directory are
+    // downloads; anything deeper was produced by the unzip mode.
+    pub fn rebuild(root: &Path, log: &mut dyn Write) -> ResultErr<Manifest> {
+        let mut manifest = Manifest::default();
+        let mut dirs: Vec<_> = fs::read_dir(root)?
+            .filter_map(|d| d.ok())
+            .filter(|d| d.path().is_dir())
+            .collect();
+        dirs.sort_by_key(|d| d.file_name());
+        for hack_dir in dirs {
+            let dir = hack_dir.file_name().to_string_lossy().to_string();
+            let (id, title) = match parse_hack_dir(&dir) {
+                Some(parsed) => parsed,
+                None => {
+                    writeln!(log, "Not a hack directory, skipping: {}", dir)?;
+                    continue;
+                }
+            };
+            let mut files: Vec<_> = fs::read_dir(hack_dir.path())?
+                .filter_map(|f| f.ok())
+                .filter(|f| f.path().is_file())
+                .collect();
+            files.sort_by_key(|f| f.file_name());
+            for file in files {
+                writeln!(log, "Hashing {:?}", file.path())?;
+                manifest.record_file(root, id, title, &dir, &file.path())?;
+            }
+        }
+        Ok(manifest)
+    }
+
+    // Load the manifest, falling back to rebuilding it from disk for mirrors
+    // that were downloaded before the manifest existed.
+    pub fn load_or_rebuild(root: &Path, log: &mut dyn Write) -> ResultErr<Manifest> {
+        let manifest = Manifest::load(MANIFEST_FILE)?;
+        if !manifest.hacks.is_empty() {
+            return Ok(manifest);
+        }
+        let args = [("path", format!("{:?}", root).into())];
+        println!("{}", i18n::tr("manifest-rebuilding", &args));
+        let manifest = Manifest::rebuild(root, log)?;
+        manifest.save(MANIFEST_FILE)?;
+        Ok(manifest)
+    }
+}
+
+// Hack directories are named `NNNN-ID-Title`, or `NNNN-ID` when the title
+// couldn't be scraped
+pub fn parse_hack_dir(dir: &str) -> Option<(u32, Option<&str>)> {
+    let mut parts = dir.splitn(3, '-');
+    let id = parts.nth(1)?.parse().ok()?;
+    Some((id, parts.next()))
+}
+
+// The id of the hack whose directory under `root` contains `path`
+pub fn hack_id_of(root: &Path, path: &Path) -> Option<u32> {
+    let dir = path.strip_prefix(root).ok()?.components().next()?;
+    parse_hack_dir(&dir.as_os_str().to_string_lossy()).map(|(id, _)| id)
+}
+
+fn relative_path(root: &Path, file: &Path) -> ResultErr<String> {
+    let relative = file.strip_prefix(root)?;
+    let components: Vec<_> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    Ok(components.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::TestDir;
+
+    #[test]
+    fn randomizer_base_patches_are_flagged_in_the_manifest() {
+        let dir = TestDir::new("rando");
+        fs::create_dir_all(dir.join("0001-5-Varia")).unwrap();
+        fs::create_dir_all(dir.join("0002-6-Fernando")).unwrap();
+        fs::write(dir.join("0001-5-Varia/SM_Rando_base.ips"), "PATCH").unwrap();
+        fs::write(dir.join("0002-6-Fernando/fernando.ips"), "PATCH").unwrap();
+        let mut manifest = Manifest::default();
+        for (id, name, file) in [
+            (5, "0001-5-Varia", "SM_Rando_base.ips"),
+            (6, "0002-6-Fernando", "fernando.ips"),
+        ] {
+            let path = dir.join(name).join(file);
+            manifest
+                .record_file(&dir, id, Some(name), name, &path)
+                .unwrap();
+        }
+        assert!(manifest.hacks[&5].randomizer);
+        assert!(!manifest.hacks[&6].randomizer);
+
+        // Mirrors from before the flag get it when the manifest is upgraded
+        let old = dir.join("manifest.json");
+        fs::write(
+            &old,
+            r#"{"schema_version": 1, "hacks": {"7": {"title": "Super Metroid Randomizer", "dir": "0003-7-X"}}}"#,
+        )
+        .unwrap();
+        assert!(Manifest::load(&old).unwrap().hacks[&7].randomizer);
+    }
+}
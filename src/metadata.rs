@@ -0,0 +1,372 @@
+use crate::api;
+use crate::catalog::{Catalog, CATALOG_FILE};
+use crate::changelog;
+use crate::collate;
+use crate::config::Config;
+use crate::counts;
+use crate::history;
+use crate::http;
+use crate::i18n;
+use crate::news;
+use crate::outputs::{Outputs, OUTPUTS_FILE};
+use crate::records::{self, HackMetadata};
+use crate::scope;
+use crate::scrape;
+use crate::screenshots;
+use crate::sign;
+use crate::summary::Summary;
+use crate::utils::*;
+use crate::MetadataArgs;
+use indicatif::ProgressBar;
+use reqwest_middleware::ClientWithMiddleware;
+use std::io::Write;
+use std::path::Path;
+
+// The metadata mode: every hack's page is scraped into the catalog and
+// metadata.csv, or with --fast only the hack list is read over last run's
+// catalog, and the changes since then are written out as a changelog
+
+pub async fn metadata(
+    args: &MetadataArgs,
+    config: &Config,
+    summary: &Summary,
+    log: &mut dyn Write,
+) -> ResultErr<()> {
+    let retries = http::RetryLog::default();
+    let client = http::client(config, &retries)?;
+    let metconst = config.site_url.as_str();
+
+    println!("{}", i18n::tr("fetching-hack-list", &[]));
+    let list = api::hack_list(config, &client, log).await?;
+    summary.listed_from(list.source().as_str());
+    let previous = Catalog::load(CATALOG_FILE)?;
+    let today_date = chrono::Local::now().date_naive();
+    let scopes = scope::hack_scopes(&Outputs::load(OUTPUTS_FILE)?);
+    let scope_of = |id: u32| {
+        scopes
+            .get(&id)
+            .map(|s| s.as_str().to_owned())
+            .unwrap_or_default()
+    };
+    if args.fast {
+        let mut catalog = fast_catalog(&previous, &list.listings()?, log)?;
+        let args = [("count", catalog.hacks.len().into())];
+        println!("{}", i18n::tr("metadata-read", &args));
+        let mut audit = Vec::new();
+        for (id, hack) in &mut catalog.hacks {
+            // The list may show a different author, or the groups changed
+            hack.groups = config.author_flags(&hack.author);
+            hack.scope = scope_of(*id);
+            let suspect = left_out(scrape::set_aside_anomalies(hack, today_date));
+            if !suspect.is_empty() {
+                audit.push((*id, hack.title.clone(), suspect));
+            }
+        }
+        if !audit.is_empty() {
+            report_audit(&mut audit, config, log)?;
+        }
+        retries.report(log)?;
+        return finish_metadata(config, &previous, &catalog, log);
+    }
+    let hack_id = list.ids()?;
+    println!(
+        "{}",
+        i18n::tr("metadata-total", &[("count", hack_id.len().into())])
+    );
+
+    let pb = ProgressBar::new(hack_id.len() as u64);
+
+    let mut csv_writer = create_output("metadata.csv")?;
+    let parser = scrape::PageParser::for_site(metconst)?;
+    let mut counts = counts::DownloadCounts::load(counts::COUNTS_FILE)?;
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let mut audit = Vec::new();
+    let mut screenshot_cache = screenshots::ScreenshotCache::load(screenshots::SCREENSHOTS_FILE)?;
+    let columns = HackMetadata {
+        groups: config.author_flags(""),
+        ..HackMetadata::default()
+    };
+    writeln!(csv_writer, "{}", records::csv_header_like(&columns)?)?;
+    let mut catalog = Catalog::default();
+    for id in hack_id.iter() {
+        let id: u32 = id.parse()?;
+        let scraped = scrape_hack(
+            args,
+            config,
+            &client,
+            &parser,
+            &mut screenshot_cache,
+            id,
+            log,
+        )
+        .await?;
+        if let Some(downloads) = scraped.downloads {
+            counts.record(id, &today, downloads);
+        }
+        let (mut hack, mut suspect) = (scraped.hack, scraped.suspect);
+        hack.scope = scope_of(id);
+        for (field, reason) in left_out(scrape::set_aside_anomalies(&mut hack, today_date)) {
+            // A missing author is already listed
+            if !suspect.iter().any(|(f, _)| *f == field) {
+                suspect.push((field, reason));
+            }
+        }
+        if !suspect.is_empty() {
+            audit.push((id, hack.title.clone(), suspect));
+        }
+        writeln!(csv_writer, "{}", records::csv_row(&hack)?)?;
+        catalog.hacks.insert(id, hack);
+        summary.processed(scraped.page_len);
+        pb.inc(1);
+    }
+    pb.finish_with_message("done");
+
+    // Values left out are reported even without --audit
+    if args.audit || !audit.is_empty() {
+        report_audit(&mut audit, config, log)?;
+    }
+
+    counts.save(counts::COUNTS_FILE)?;
+    if args.screenshots {
+        screenshot_cache.save(screenshots::SCREENSHOTS_FILE)?;
+    }
+    retries.report(log)?;
+    finish_metadata(config, &previous, &catalog, log)
+}
+
+// What the metadata mode makes of one hack's page, along with its forum
+// thread and screenshots when asked for
+struct ScrapedHack {
+    hack: HackMetadata,
+    downloads: Option<u64>,
+    // Fields the page audit found missing or odd
+    suspect: Vec<(&'static str, String)>,
+    page_len: u64,
+}
+
+async fn scrape_hack(
+    args: &MetadataArgs,
+    config: &Config,
+    client: &ClientWithMiddleware,
+    parser: &scrape::PageParser,
+    screenshot_cache: &mut screenshots::ScreenshotCache,
+    id: u32,
+    log: &mut dyn Write,
+) -> ResultErr<ScrapedHack> {
+    let pause = |secs| tokio::time::sleep(tokio::time::Duration::from_secs(secs));
+    let hack_url = format!("{}hack.php?id={}", config.site_url, id);
+    let hack_page = http::fetch_page(client, &hack_url).await?;
+    pause(config.page_delay_secs).await;
+    let page = parser.parse(&hack_page)?;
+    let mut suspect = Vec::new();
+    if args.audit {
+        for (field, reason) in parser.audit(&hack_page, &page) {
+            suspect.push((field, reason.to_string()));
+        }
+    }
+    let mut forum = None;
+    if let (true, Some(thread)) = (args.forum, &page.forum_thread) {
+        let first_page = http::fetch_page(client, thread).await?;
+        pause(config.page_delay_secs).await;
+        let last_page = parser.forum_last_page(thread, &first_page)?;
+        if &last_page == thread {
+            forum = Some(parser.forum_stats(&first_page));
+        } else {
+            let last_page = http::fetch_page(client, &last_page).await?;
+            pause(config.page_delay_secs).await;
+            forum = Some(parser.forum_stats(&last_page));
+        }
+    }
+    let forum = forum.unwrap_or_default();
+    if args.screenshots && !page.screenshots.is_empty() {
+        let dir = Path::new(&config.screenshots_dir);
+        let fetched =
+            screenshots::fetch(client, screenshot_cache, dir, id, &page.screenshots, log).await?;
+        if fetched > 0 {
+            pause(config.file_delay_secs).await;
+        }
+    }
+    let replies = if forum.last_activity.is_some() {
+        forum.replies.to_string()
+    } else {
+        String::new()
+    };
+    Ok(ScrapedHack {
+        hack: HackMetadata {
+            groups: config.author_flags(&page.author),
+            title: page.title,
+            date: page.date,
+            author: page.author,
+            genre: page.genre,
+            difficulty: page.difficulty,
+            requires: page.requires,
+            scope: String::new(),
+            runtime: page.runtime,
+            collection: page.collection,
+            rating: page.rating,
+            forum_thread: page.forum_thread.unwrap_or_default(),
+            replies,
+            last_activity: forum.last_activity.unwrap_or_default(),
+            videos: page.videos,
+            screenshots: page.screenshots,
+        },
+        downloads: page.downloads,
+        suspect,
+        page_len: hack_page.len() as u64,
+    })
+}
+
+// A hack's fields the metadata mode couldn't find or left out, and why
+type AuditEntry = (u32, String, Vec<(&'static str, String)>);
+
+fn report_audit(audit: &mut [AuditEntry], config: &Config, log: &mut dyn Write) -> ResultErr<()> {
+    audit.sort_by(|a, b| collate::compare_titles(&a.1, &b.1, config.sort_strip_articles));
+    println!(
+        "{}",
+        i18n::tr("metadata-audit", &[("count", audit.len().into())])
+    );
+    for (id, title, missing) in audit.iter() {
+        for (field, reason) in missing {
+            println!("  {} {}: {} ({})", id, title, field, reason);
+            writeln!(
+                log,
+                "hack {} {:?} is missing {}: {}",
+                id, title, field, reason
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// Audit reasons for the values `set_aside_anomalies` cleared, quoting them
+pub fn left_out(
+    anomalies: Vec<(&'static str, scrape::Missing, String)>,
+) -> Vec<(&'static str, String)> {
+    anomalies
+        .into_iter()
+        .map(|(field, why, value)| match value.is_empty() {
+            true => (field, why.to_string()),
+            false => (field, format!("{}: {:?}", why, value)),
+        })
+        .collect()
+}
+
+// The catalog with what the list of hacks shows swapped in, and everything
+// only found on hack pages kept from the last run
+fn fast_catalog(
+    previous: &Catalog,
+    rows: &[records::HackListing],
+    log: &mut dyn Write,
+) -> ResultErr<Catalog> {
+    let mut catalog = Catalog::default();
+    for row in rows {
+        let mut entry = previous.hacks.get(&row.id).cloned().unwrap_or_default();
+        if !previous.hacks.contains_key(&row.id) {
+            writeln!(
+                log,
+                "hack {} is new, only the list fields are known",
+                row.id
+            )?;
+        }
+        for (field, value) in [
+            (&mut entry.title, &row.title),
+            (&mut entry.author, &row.author),
+            (&mut entry.date, &row.date),
+            (&mut entry.rating, &row.rating),
+        ] {
+            if !value.is_empty() {
+                *field = value.clone();
+            }
+        }
+        catalog.hacks.insert(row.id, entry);
+    }
+    Ok(catalog)
+}
+
+// Write the changelog against the last run and save the new catalog
+fn finish_metadata(
+    config: &Config,
+    previous: &Catalog,
+    catalog: &Catalog,
+    log: &mut dyn Write,
+) -> ResultErr<()> {
+    // The very first run has nothing to compare against
+    if !previous.hacks.is_empty() {
+        let news = news::News::load(news::NEWS_FILE)?;
+        let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+        let fname = format!("CHANGELOG-{}.md", date);
+        let mut changelog = create_output(&fname)?;
+        let (added, updated, removed) = changelog::write_changelog(
+            previous,
+            catalog,
+            &news,
+            &date,
+            config.sort_strip_articles,
+            &mut changelog,
+        )?;
+        changelog.flush()?;
+        let args = [
+            ("path", fname.as_str().into()),
+            ("added", added.into()),
+            ("updated", updated.into()),
+            ("removed", removed.into()),
+        ];
+        println!("{}", i18n::tr("metadata-changelog", &args));
+        writeln!(log, "Wrote {}", fname)?;
+        sign::sign_export(config, &fname, log)?;
+    }
+    catalog.save(CATALOG_FILE)?;
+    let mut history = history::History::load(history::HISTORY_FILE)?;
+    history.record_catalog(
+        &chrono::Local::now().format("%Y-%m-%d").to_string(),
+        catalog,
+    );
+    history.save(history::HISTORY_FILE)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scrape;
+
+    #[test]
+    fn implausible_values_are_left_out() {
+        let today = chrono::NaiveDate::from_ymd_opt(2026, 10, 16).unwrap();
+        let mut hack = HackMetadata {
+            title: "Hack".to_owned(),
+            date: "Dec 24, 2026".to_owned(),
+            genre: "Exploration".to_owned(),
+            rating: "7.5".to_owned(),
+            ..HackMetadata::default()
+        };
+        let found = left_out(scrape::set_aside_anomalies(&mut hack, today));
+        assert_eq!(
+            found,
+            [
+                (
+                    "rating",
+                    "left out, outside the 0 to 5 star range: \"7.5\"".to_owned()
+                ),
+                (
+                    "date",
+                    "left out, in the future: \"Dec 24, 2026\"".to_owned()
+                ),
+                (
+                    "author",
+                    "left out, no author on a finished hack".to_owned()
+                ),
+            ]
+        );
+        assert_eq!((hack.rating.as_str(), hack.date.as_str()), ("", ""));
+
+        let mut fine = HackMetadata {
+            date: "Oct 16, 2026".to_owned(),
+            genre: "Incomplete".to_owned(),
+            rating: "4.95".to_owned(),
+            ..HackMetadata::default()
+        };
+        assert!(scrape::set_aside_anomalies(&mut fine, today).is_empty());
+        assert_eq!(fine.rating, "4.95");
+    }
+}
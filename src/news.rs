@@ -0,0 +1,118 @@
+use crate::config::Config;
+use crate::http;
+use crate::i18n;
+use crate::schema::{self, Migration};
+use crate::scrape;
+use crate::utils::*;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+pub const NEWS_FILE: &str = "news.json";
+
+const MIGRATIONS: &[Migration] = &[schema::unchanged];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Announcement {
+    // Day the news mode first saw the post
+    pub seen: String,
+    pub text: String,
+}
+
+// Front page posts that mention a hack, by hack id
+#[derive(Debug, Default)]
+pub struct News {
+    pub hacks: BTreeMap<u32, Vec<Announcement>>,
+}
+
+impl News {
+    pub fn load<P: AsRef<Path>>(fname: P) -> ResultErr<News> {
+        let mut news = News::default();
+        if !fname.as_ref().exists() {
+            return Ok(news);
+        }
+        let mut value = read_json(fname)?;
+        schema::upgrade(&mut value, MIGRATIONS, NEWS_FILE)?;
+        if let Some(hacks) = value.get("hacks").and_then(|h| h.as_object()) {
+            for (id, posts) in hacks {
+                let posts = posts
+                    .as_array()
+                    .map(|a| {
+                        a.iter()
+                            .filter_map(|p| {
+                                Some(Announcement {
+                                    seen: json_str(p, "seen")?,
+                                    text: json_str(p, "text")?,
+                                })
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                news.hacks.insert(id.parse()?, posts);
+            }
+        }
+        Ok(news)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, fname: P) -> ResultErr<()> {
+        let hacks: serde_json::Map<_, _> = self
+            .hacks
+            .iter()
+            .map(|(id, posts)| {
+                let posts: Vec<Value> = posts
+                    .iter()
+                    .map(|p| json!({ "seen": p.seen, "text": p.text }))
+                    .collect();
+                (id.to_string(), json!(posts))
+            })
+            .collect();
+        let mut value = json!({ "hacks": hacks });
+        schema::stamp(&mut value, MIGRATIONS);
+        write_json(fname, &value)
+    }
+
+    // Returns false when the post was already known
+    pub fn record(&mut self, id: u32, seen: &str, text: &str) -> bool {
+        let posts = self.hacks.entry(id).or_default();
+        if posts.iter().any(|p| p.text == text) {
+            return false;
+        }
+        posts.push(Announcement {
+            seen: seen.to_owned(),
+            text: text.to_owned(),
+        });
+        true
+    }
+
+    pub fn latest(&self, id: u32) -> Option<&Announcement> {
+        self.hacks.get(&id).and_then(|p| p.last())
+    }
+}
+
+pub async fn news(config: &Config, log: &mut dyn Write) -> ResultErr<()> {
+    let retries = http::RetryLog::default();
+    let client = http::client(config, &retries)?;
+    let metconst = config.site_url.as_str();
+
+    println!("{}", i18n::tr("news-fetching", &[]));
+    let body = http::fetch_page(&client, metconst).await?;
+    let posts = scrape::parse_news(&body)?;
+
+    let mut news = News::load(NEWS_FILE)?;
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let mut new_posts = 0;
+    for post in &posts {
+        for id in &post.hack_ids {
+            if news.record(*id, &today, &post.text) {
+                writeln!(log, "hack {}: {}", id, post.text)?;
+                new_posts += 1;
+            }
+        }
+    }
+    news.save(NEWS_FILE)?;
+    retries.report(log)?;
+    let args = [("count", posts.len().into()), ("new", new_posts.into())];
+    println!("{}", i18n::tr("news-found", &args));
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use crate::i18n;
+use crate::rom;
+use crate::utils::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// Full ROM images some hacks ship instead of (or next to) a patch
+fn is_rom_image(path: &Path) -> bool {
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    matches!(ext.as_str(), "sfc" | "smc" | "swc" | "fig") || split_part(path).is_some()
+}
+
+// Super Wild Card style splits are named `NAME.1`, `NAME.2` and so on
+fn split_part(path: &Path) -> Option<u32> {
+    let ext = path.extension()?.to_str()?;
+    if ext.len() == 1 {
+        ext.parse().ok()
+    } else {
+        None
+    }
+}
+
+fn strip_header(mut data: Vec<u8>) -> Vec<u8> {
+    if rom::has_copier_header(data.len()) {
+        data.drain(..rom::COPIER_HEADER_SIZE);
+    }
+    data
+}
+
+// Found a ROM image that wasn't in the usual layout: how it was (or wasn't)
+// turned into a plain unheadered one
+enum Outcome {
+    Fine,
+    Normalized(&'static str),
+    Unfixable(String),
+}
+
+fn normalize(data: Vec<u8>) -> (Outcome, Vec<u8>) {
+    let data = strip_header(data);
+    if rom::is_interleaved(&data) {
+        return (
+            Outcome::Normalized("de-interleaved"),
+            rom::deinterleave(&data),
+        );
+    }
+    if rom::is_standard_size(data.len()) {
+        return (Outcome::Fine, data);
+    }
+    (
+        Outcome::Unfixable(format!("{} bytes isn't a ROM size", data.len())),
+        data,
+    )
+}
+
+// Look through the ROM images under `roots` for odd sizes, interleaved dumps
+// and split files, writing whatever can be fixed as plain unheadered ROMs
+// under `out_dir`. Returns the images that couldn't be fixed.
+pub fn check_rom_images(
+    roots: &[PathBuf],
+    out_dir: &Path,
+    log: &mut dyn Write,
+) -> ResultErr<Vec<(PathBuf, String)>> {
+    let mut unfixable = Vec::new();
+    for root in roots {
+        let mut images = Vec::new();
+        process_directory(
+            |f, _| {
+                images.push(f.path().to_path_buf());
+                Ok(())
+            },
+            root,
+            any_dir,
+            |f| is_rom_image(f.path()),
+            log,
+        )?;
+
+        // Splits are only useful merged, keyed by what the merged file is
+        let mut splits: BTreeMap<PathBuf, Vec<(u32, PathBuf)>> = BTreeMap::new();
+        let mut found = Vec::new();
+        for image in images {
+            match split_part(&image) {
+                Some(part) => splits
+                    .entry(image.with_extension(""))
+                    .or_default()
+                    .push((part, image)),
+                None => found.push((image.clone(), vec![image])),
+            }
+        }
+        for (merged, mut parts) in splits {
+            parts.sort();
+            found.push((merged, parts.into_iter().map(|(_, p)| p).collect()));
+        }
+
+        for (image, parts) in found {
+            let merged =
+                (parts.len() > 1 || parts[0] != image).then_some("merged from split files");
+            let mut data = Vec::new();
+            for part in &parts {
+                // Every part of a split may carry its own copier header
+                if merged.is_some() {
+                    writeln!(log, "Merging {:?} into {:?}", part, image)?;
+                    data.extend(strip_header(fs::read(part)?));
+                } else {
+                    data = fs::read(part)?;
+                }
+            }
+            let ext = image
+                .extension()
+                .map(|e| e.to_string_lossy().to_ascii_lowercase())
+                .unwrap_or_default();
+            let headered = rom::has_copier_header(data.len());
+            if (ext == "sfc" && headered) || (ext == "smc" && !headered) {
+                writeln!(
+                    log,
+                    "{:?} is {}, unusual for a .{} file",
+                    image,
+                    if headered { "headered" } else { "unheadered" },
+                    ext
+                )?;
+            }
+            let (outcome, data) = normalize(data);
+            let how = match (outcome, merged) {
+                (Outcome::Unfixable(why), _) => {
+                    writeln!(log, "Can't normalize {:?}: {}", image, why)?;
+                    unfixable.push((image, why));
+                    continue;
+                }
+                (Outcome::Fine, None) => continue,
+                (Outcome::Fine, Some(merged)) => merged.to_owned(),
+                (Outcome::Normalized(how), None) => how.to_owned(),
+                (Outcome::Normalized(how), Some(merged)) => format!("{} and {}", merged, how),
+            };
+            let relative = image.strip_prefix(root)?;
+            let out = out_dir.join(relative).with_extension("sfc");
+            fs::create_dir_all(out.parent().ok_or("bad path")?)?;
+            writeln!(log, "{:?} {}, writing {:?}", image, how, out)?;
+            let args = [
+                ("image", image.to_string_lossy().into()),
+                ("how", how.into()),
+                ("path", out.to_string_lossy().into()),
+            ];
+            println!("{}", i18n::tr("normalize-wrote", &args));
+            fs::write(&out, data)?;
+        }
+    }
+    Ok(unfixable)
+}
@@ -0,0 +1,63 @@
+use crate::utils::*;
+use console::{style, StyledObject};
+use std::fmt::Display;
+use std::fs::File;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Color output going to a terminal, unless NO_COLOR is set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+pub fn set_color(choice: ColorChoice) {
+    let enabled = match choice {
+        // console already looks at the terminal and the environment
+        ColorChoice::Auto => return,
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+    };
+    console::set_colors_enabled(enabled);
+    console::set_colors_enabled_stderr(enabled);
+}
+
+// Sends everything printed to stdout to stderr for the rest of the run,
+// handing back the real stdout for the one thing meant for it
+#[cfg(unix)]
+pub fn stdout_to_stderr() -> ResultErr<File> {
+    use std::io::Write;
+    use std::os::fd::FromRawFd;
+
+    std::io::stdout().flush()?;
+    // SAFETY: dup and dup2 only work on descriptors; the duplicate is owned
+    // by the File handed back and nothing else
+    unsafe {
+        let saved = libc::dup(libc::STDOUT_FILENO);
+        if saved < 0 || libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(File::from_raw_fd(saved))
+    }
+}
+
+#[cfg(not(unix))]
+pub fn stdout_to_stderr() -> ResultErr<File> {
+    Err("--json is only supported on Unix".into())
+}
+
+// Styles for what the modes print as they go. Use `.for_stderr()` on the
+// result for lines going to stderr.
+
+pub fn error<D: Display>(text: D) -> StyledObject<D> {
+    style(text).red()
+}
+
+pub fn skip<D: Display>(text: D) -> StyledObject<D> {
+    style(text).dim()
+}
+
+pub fn success<D: Display>(text: D) -> StyledObject<D> {
+    style(text).green()
+}
@@ -0,0 +1,221 @@
+use crate::manifest::hack_id_of;
+use crate::schema::{self, Migration};
+use crate::utils::*;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+pub const OUTPUTS_FILE: &str = "patched.json";
+
+const MIGRATIONS: &[Migration] = &[schema::unchanged];
+
+// One patched ROM and what it was made from
+#[derive(Debug, Clone)]
+pub struct OutputEntry {
+    pub hack_id: Option<u32>,
+    pub patch: String,
+    pub patch_sha256: String,
+    pub output: String,
+    pub sha256: String,
+    // Missing from entries recorded before they were tracked
+    pub base_sha256: Option<String>,
+    pub size: Option<u64>,
+    pub resized: Option<Resize>,
+    // What asar said while assembling an .asm patch
+    pub asar_output: Option<String>,
+    // "passed", or why the smoke-test mode failed the ROM
+    pub smoke_test: Option<String>,
+    // How many bytes the patch changed in the base ROM, and where, see
+    // `rom::fingerprint`
+    pub bytes_changed: Option<u64>,
+    pub fingerprint: Option<String>,
+}
+
+// How patching changed the size of the ROM, IPS can do either
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resize {
+    Expanded,
+    Truncated,
+}
+
+impl Resize {
+    fn as_str(self) -> &'static str {
+        match self {
+            Resize::Expanded => "expanded",
+            Resize::Truncated => "truncated",
+        }
+    }
+}
+
+// What the patch mode made out of one patch
+#[derive(Debug, Clone)]
+pub struct Patched {
+    pub rom: PathBuf,
+    pub base_sha256: String,
+    pub resized: Option<Resize>,
+    pub asar_output: Option<String>,
+    pub bytes_changed: u64,
+    pub fingerprint: String,
+}
+
+// How a re-patched ROM differs from the one recorded on an earlier run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputChange {
+    // The patch file itself changed, e.g. the hack got a silent update
+    PatchUpdated,
+    // Same patch, different result: a patch engine regression or a
+    // different base ROM
+    OutputChanged,
+}
+
+// Every ROM the patch mode has produced, keyed by output path
+#[derive(Debug, Default)]
+pub struct Outputs {
+    pub entries: BTreeMap<String, OutputEntry>,
+}
+
+fn path_string(path: &Path) -> String {
+    let components: Vec<_> = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    components.join("/")
+}
+
+impl Outputs {
+    pub fn load<P: AsRef<Path>>(fname: P) -> ResultErr<Outputs> {
+        let mut outputs = Outputs::default();
+        if !fname.as_ref().exists() {
+            return Ok(outputs);
+        }
+        let mut value = read_json(fname)?;
+        schema::upgrade(&mut value, MIGRATIONS, OUTPUTS_FILE)?;
+        if let Some(entries) = value.get("outputs").and_then(|o| o.as_array()) {
+            for entry in entries {
+                let entry = OutputEntry::from_json(entry)?;
+                outputs.entries.insert(entry.output.clone(), entry);
+            }
+        }
+        Ok(outputs)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, fname: P) -> ResultErr<()> {
+        let entries: Vec<_> = self.entries.values().map(|e| e.to_json()).collect();
+        let mut value = json!({ "outputs": entries });
+        schema::stamp(&mut value, MIGRATIONS);
+        write_json(fname, &value)
+    }
+
+    // Record a freshly patched ROM, reporting whether it differs from what
+    // an earlier run recorded for the same output
+    // What patch file `patch` made last time
+    pub fn for_patch(&self, patch: &Path) -> Option<&OutputEntry> {
+        let patch = path_string(patch);
+        self.entries.values().find(|e| e.patch == patch)
+    }
+
+    // Moves an output onto another base ROM that it comes out the same on
+    pub fn rebase(&mut self, output: &str, base_sha256: &str) {
+        if let Some(entry) = self.entries.get_mut(output) {
+            entry.base_sha256 = Some(base_sha256.to_owned());
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        root: &Path,
+        patch: &Path,
+        patched: &Patched,
+    ) -> ResultErr<Option<OutputChange>> {
+        let mut entry = OutputEntry {
+            hack_id: hack_id_of(root, patch),
+            patch: path_string(patch),
+            patch_sha256: sha256_file(patch)?,
+            output: path_string(&patched.rom),
+            sha256: sha256_file(&patched.rom)?,
+            base_sha256: Some(patched.base_sha256.clone()),
+            size: Some(std::fs::metadata(&patched.rom)?.len()),
+            resized: patched.resized,
+            asar_output: patched.asar_output.clone(),
+            smoke_test: None,
+            bytes_changed: Some(patched.bytes_changed),
+            fingerprint: Some(patched.fingerprint.clone()),
+        };
+        let change = match self.entries.get(&entry.output) {
+            // The same ROM still passes or fails the same smoke test
+            Some(old) if old.sha256 == entry.sha256 => {
+                entry.smoke_test = old.smoke_test.clone();
+                None
+            }
+            Some(old) if old.patch_sha256 != entry.patch_sha256 => Some(OutputChange::PatchUpdated),
+            Some(_) => Some(OutputChange::OutputChanged),
+            None => None,
+        };
+        self.entries.insert(entry.output.clone(), entry);
+        Ok(change)
+    }
+}
+
+impl OutputEntry {
+    fn to_json(&self) -> Value {
+        json!({
+            "hack_id": self.hack_id,
+            "patch": self.patch,
+            "patch_sha256": self.patch_sha256,
+            "output": self.output,
+            "sha256": self.sha256,
+            "base_sha256": self.base_sha256,
+            "size": self.size,
+            "resized": self.resized.map(|r| r.as_str()),
+            "asar_output": self.asar_output,
+            "smoke_test": self.smoke_test,
+            "bytes_changed": self.bytes_changed,
+            "fingerprint": self.fingerprint,
+        })
+    }
+
+    fn from_json(value: &Value) -> ResultErr<OutputEntry> {
+        let field = |key| json_str(value, key).ok_or(format!("patched entry without {}", key));
+        Ok(OutputEntry {
+            hack_id: json_u64(value, "hack_id").map(|id| id as u32),
+            patch: field("patch")?,
+            patch_sha256: field("patch_sha256")?,
+            output: field("output")?,
+            sha256: field("sha256")?,
+            base_sha256: json_str(value, "base_sha256"),
+            size: json_u64(value, "size"),
+            resized: match json_str(value, "resized").as_deref() {
+                Some("expanded") => Some(Resize::Expanded),
+                Some("truncated") => Some(Resize::Truncated),
+                _ => None,
+            },
+            asar_output: json_str(value, "asar_output"),
+            smoke_test: json_str(value, "smoke_test"),
+            bytes_changed: json_u64(value, "bytes_changed"),
+            fingerprint: json_str(value, "fingerprint"),
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // An entry for `output` with nothing else known about it
+    pub fn test_output_entry(output: &str) -> OutputEntry {
+        OutputEntry {
+            hack_id: None,
+            patch: String::new(),
+            patch_sha256: String::new(),
+            output: output.to_owned(),
+            sha256: String::new(),
+            base_sha256: None,
+            size: None,
+            resized: None,
+            asar_output: None,
+            smoke_test: None,
+            bytes_changed: None,
+            fingerprint: None,
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use crate::manifest::hack_id_of;
+use crate::schema::{self, Migration};
+use crate::utils::*;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const OVERRIDES_FILE: &str = "overrides.json";
+
+const MIGRATIONS: &[Migration] = &[schema::unchanged];
+
+// Hand-written special cases for individual hacks, e.g.
+// { "hacks": { "756": { "skip": true }, "12": { "password": "metroid" } } }
+#[derive(Debug, Default, Clone)]
+pub struct Override {
+    pub skip: bool,
+    // Patch against this ROM instead of the configured base ROM
+    pub base_rom: Option<String>,
+    // Whether the patch expects a copier header on the base ROM. The base ROM
+    // is used as is when unset.
+    pub headered: Option<bool>,
+    // File name of the one patch to apply when a hack ships several variants
+    pub patch: Option<String>,
+    // File name (without extension) for the patched ROM
+    pub output_name: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Overrides {
+    hacks: BTreeMap<u32, Override>,
+}
+
+impl Overrides {
+    pub fn load<P: AsRef<Path>>(fname: P) -> ResultErr<Overrides> {
+        if !fname.as_ref().exists() {
+            return Ok(Overrides::default());
+        }
+        let mut value = read_json(fname)?;
+        schema::upgrade(&mut value, MIGRATIONS, OVERRIDES_FILE)?;
+        let mut overrides = Overrides::default();
+        if let Some(hacks) = value.get("hacks").and_then(|h| h.as_object()) {
+            for (id, hack) in hacks {
+                overrides
+                    .hacks
+                    .insert(id.parse()?, Override::from_json(hack));
+            }
+        }
+        Ok(overrides)
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Override> {
+        self.hacks.get(&id)
+    }
+
+    // The override for whichever hack directory `path` is in
+    pub fn for_path(&self, root: &Path, path: &Path) -> Option<&Override> {
+        hack_id_of(root, path).and_then(|id| self.get(id))
+    }
+
+    pub fn skips(&self, id: u32) -> bool {
+        self.get(id).map(|o| o.skip).unwrap_or(false)
+    }
+}
+
+impl Override {
+    fn from_json(value: &Value) -> Override {
+        Override {
+            skip: value.get("skip").and_then(|s| s.as_bool()).unwrap_or(false),
+            base_rom: json_str(value, "base_rom"),
+            headered: value.get("headered").and_then(|h| h.as_bool()),
+            patch: json_str(value, "patch"),
+            output_name: json_str(value, "output_name"),
+            password: json_str(value, "password"),
+        }
+    }
+}
@@ -0,0 +1,837 @@
+use crate::rom;
+use crate::utils::*;
+use ips::Patch;
+use std::fs::{self, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+// Patch formats besides IPS, which the ips crate handles. BPS and UPS
+// record the size and CRC32 of the ROM they were made for, and xdelta3 the
+// checksum of what it writes, so a patch applied to the wrong base ROM
+// fails instead of making a ROM that crashes.
+
+const BPS_MAGIC: &[u8] = b"BPS1";
+const UPS_MAGIC: &[u8] = b"UPS1";
+// VCDIFF, version 0, which is what xdelta3 writes
+const VCDIFF_MAGIC: &[u8] = &[0xd6, 0xc3, 0xc4, 0x00];
+// Source, target and patch CRC32s, the same in both formats
+const FOOTER: usize = 12;
+// IPS offsets are three bytes, and a hunk can't start where they'd spell
+// the "EOF" that ends the patch
+const IPS_MAX_SIZE: usize = 0x1000000;
+const IPS_EOF: usize = 0x454f46;
+const IPS_MAX_HUNK: usize = 0xffff;
+// Runs of one byte at least this long are written as RLE hunks
+const IPS_RLE_MIN: usize = 8;
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn at_end(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> ResultErr<&'a [u8]> {
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or("the patch ends early")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    // Numbers are stored seven bits at a time, lowest first, with the top
+    // bit ending them. Every step adds one on top, so each number has only
+    // one encoding.
+    fn number(&mut self) -> ResultErr<u64> {
+        let (mut value, mut shift) = (0u64, 1u64);
+        loop {
+            let byte = self.bytes(1)?[0];
+            value = (byte as u64 & 0x7f)
+                .checked_mul(shift)
+                .and_then(|v| v.checked_add(value))
+                .ok_or("the patch has a number too big to be real")?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift
+                .checked_mul(0x80)
+                .ok_or("the patch has a number too big to be real")?;
+            value = value
+                .checked_add(shift)
+                .ok_or("the patch has a number too big to be real")?;
+        }
+    }
+
+    // VCDIFF's numbers are also seven bits a byte, but highest first, with
+    // the top bit set on every byte but the last
+    fn varint(&mut self) -> ResultErr<usize> {
+        let mut value = 0usize;
+        loop {
+            let byte = self.bytes(1)?[0];
+            value = value
+                .checked_mul(0x80)
+                .ok_or("the patch has a number too big to be real")?
+                | (byte & 0x7f) as usize;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    // Offsets relative to the last copy, with the sign in the lowest bit
+    fn offset(&mut self) -> ResultErr<i64> {
+        let number = self.number()?;
+        let magnitude = (number >> 1) as i64;
+        Ok(if number & 1 == 1 {
+            -magnitude
+        } else {
+            magnitude
+        })
+    }
+}
+
+fn crc_at(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+// The part of the patch between its magic and its footer
+fn body<'a>(patch: &'a [u8], magic: &[u8]) -> ResultErr<Reader<'a>> {
+    if patch.len() < magic.len() + FOOTER || !patch.starts_with(magic) {
+        return Err(format!("not a {} patch", String::from_utf8_lossy(&magic[..3])).into());
+    }
+    Ok(Reader {
+        data: &patch[..patch.len() - FOOTER],
+        pos: magic.len(),
+    })
+}
+
+// The size of the ROM a patch was made for, if it says
+pub fn source_size(patch: &[u8]) -> ResultErr<Option<usize>> {
+    if patch.starts_with(VCDIFF_MAGIC) {
+        return Ok(None);
+    }
+    let magic = if patch.starts_with(UPS_MAGIC) {
+        UPS_MAGIC
+    } else {
+        BPS_MAGIC
+    };
+    Ok(Some(body(patch, magic)?.number()? as usize))
+}
+
+// The ROM a BPS, UPS or xdelta3 patch makes out of `source`, given up on
+// once `deadline` passes
+pub fn apply(patch: &[u8], source: &[u8], deadline: &Deadline) -> ResultErr<Vec<u8>> {
+    if patch.starts_with(VCDIFF_MAGIC) {
+        apply_vcdiff(patch, source, deadline)
+    } else if patch.starts_with(UPS_MAGIC) {
+        apply_ups(patch, source, deadline)
+    } else {
+        apply_bps(patch, source, deadline)
+    }
+}
+
+// Checks the patch against its own CRC32, and `source` against the size
+// and CRC32 it was made for. Returns the size of the ROM it makes.
+fn check(patch: &[u8], source: &[u8], source_size: u64, target_size: u64) -> ResultErr<usize> {
+    let footer = patch.len() - FOOTER;
+    if crc32fast::hash(&patch[..footer + 8]) != crc_at(patch, footer + 8) {
+        return Err("the patch is damaged, its CRC32 doesn't match".into());
+    }
+    if source.len() as u64 != source_size {
+        return Err(format!(
+            "the patch is for a {} byte ROM, the base ROM has {}",
+            source_size,
+            source.len()
+        )
+        .into());
+    }
+    if crc32fast::hash(source) != crc_at(patch, footer) {
+        return Err("the patch was made for another base ROM, its CRC32 doesn't match".into());
+    }
+    if target_size > (rom::MAX_ROM_SIZE + rom::COPIER_HEADER_SIZE) as u64 {
+        return Err(format!(
+            "the patch makes a {} byte ROM, past the largest possible SNES ROM",
+            target_size
+        )
+        .into());
+    }
+    Ok(target_size as usize)
+}
+
+fn check_target(patch: &[u8], target: &[u8]) -> ResultErr<()> {
+    if crc32fast::hash(target) != crc_at(patch, patch.len() - FOOTER + 4) {
+        return Err("the patched ROM's CRC32 doesn't match the patch's".into());
+    }
+    Ok(())
+}
+
+fn apply_bps(patch: &[u8], source: &[u8], deadline: &Deadline) -> ResultErr<Vec<u8>> {
+    let mut reader = body(patch, BPS_MAGIC)?;
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+    let target_size = check(patch, source, source_size, target_size)?;
+    let metadata_size = reader.number()? as usize;
+    reader.bytes(metadata_size)?;
+
+    let mut target = Vec::with_capacity(target_size);
+    let (mut source_at, mut target_at) = (0i64, 0i64);
+    while !reader.at_end() {
+        deadline.check()?;
+        let action = reader.number()?;
+        let len = (action >> 2) as usize + 1;
+        if target.len() + len > target_size {
+            return Err("the patch writes past the end of the ROM".into());
+        }
+        match action & 3 {
+            // The same bytes as in the source
+            0 => {
+                let at = target.len();
+                let bytes = source
+                    .get(at..at + len)
+                    .ok_or("the patch reads past the end of the base ROM")?;
+                target.extend_from_slice(bytes);
+            }
+            // New bytes, from the patch
+            1 => target.extend_from_slice(reader.bytes(len)?),
+            // Bytes from elsewhere in the source
+            2 => {
+                source_at = source_at
+                    .checked_add(reader.offset()?)
+                    .ok_or("BPS offset out of range")?;
+                let at = usize::try_from(source_at)?;
+                let bytes = at
+                    .checked_add(len)
+                    .and_then(|end| source.get(at..end))
+                    .ok_or("the patch reads past the end of the base ROM")?;
+                target.extend_from_slice(bytes);
+                source_at = source_at
+                    .checked_add(len as i64)
+                    .ok_or("BPS offset out of range")?;
+            }
+            // Bytes already written, one at a time since the copy may
+            // overlap what it writes, like a run of one repeated byte
+            _ => {
+                target_at = target_at
+                    .checked_add(reader.offset()?)
+                    .ok_or("BPS offset out of range")?;
+                for _ in 0..len {
+                    let byte = *target
+                        .get(usize::try_from(target_at)?)
+                        .ok_or("the patch copies bytes it hasn't written yet")?;
+                    target.push(byte);
+                    target_at += 1;
+                }
+            }
+        }
+    }
+    if target.len() != target_size {
+        return Err("the patch ends before the whole ROM is written".into());
+    }
+    check_target(patch, &target)?;
+    Ok(target)
+}
+
+// UPS hunks skip ahead some bytes, then XOR the ones after with theirs up
+// to a zero byte, which stands for the byte it lands on staying as it is.
+// The ROM grows or shrinks to its new size first, zero filled.
+fn apply_ups(patch: &[u8], source: &[u8], deadline: &Deadline) -> ResultErr<Vec<u8>> {
+    let mut reader = body(patch, UPS_MAGIC)?;
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+    let target_size = check(patch, source, source_size, target_size)?;
+
+    let mut target = source.to_vec();
+    target.resize(target_size, 0);
+    let mut at = 0usize;
+    while !reader.at_end() {
+        deadline.check()?;
+        at = usize::try_from(reader.number()?)?
+            .checked_add(at)
+            .ok_or("the patch writes past the end of the ROM")?;
+        loop {
+            let xor = reader.bytes(1)?[0];
+            if xor == 0 {
+                break;
+            }
+            *target
+                .get_mut(at)
+                .ok_or("the patch writes past the end of the ROM")? ^= xor;
+            at += 1;
+        }
+        at = at.saturating_add(1);
+    }
+    check_target(patch, &target)?;
+    Ok(target)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Inst {
+    Noop,
+    // Sizes of 0 are read from the instructions instead
+    Add(usize),
+    Run(usize),
+    Copy(usize, u8),
+}
+
+// The default code table from RFC 3284, the only one xdelta3 writes: each
+// instruction byte stands for one or two instructions
+fn code_table() -> Vec<(Inst, Inst)> {
+    let mut table = vec![(Inst::Run(0), Inst::Noop)];
+    table.extend((0..18).map(|size| (Inst::Add(size), Inst::Noop)));
+    for mode in 0..9 {
+        table.push((Inst::Copy(0, mode), Inst::Noop));
+        table.extend((4..19).map(|size| (Inst::Copy(size, mode), Inst::Noop)));
+    }
+    for mode in 0..6 {
+        for add in 1..5 {
+            table.extend((4..7).map(|copy| (Inst::Add(add), Inst::Copy(copy, mode))));
+        }
+    }
+    for mode in 6..9 {
+        table.extend((1..5).map(|add| (Inst::Add(add), Inst::Copy(4, mode))));
+    }
+    table.extend((0..9).map(|mode| (Inst::Copy(4, mode), Inst::Add(1))));
+    table
+}
+
+// Copy addresses are mostly given relative to recent ones, which are kept
+// per window
+struct AddressCache {
+    near: [usize; 4],
+    next: usize,
+    same: [usize; 3 * 256],
+}
+
+impl AddressCache {
+    fn new() -> AddressCache {
+        AddressCache {
+            near: [0; 4],
+            next: 0,
+            same: [0; 3 * 256],
+        }
+    }
+
+    fn decode(&mut self, addresses: &mut Reader, here: usize, mode: u8) -> ResultErr<usize> {
+        let bad = "the patch copies from outside what it can see";
+        let addr = match mode {
+            0 => addresses.varint()?,
+            1 => here.checked_sub(addresses.varint()?).ok_or(bad)?,
+            2..=5 => self.near[mode as usize - 2]
+                .checked_add(addresses.varint()?)
+                .ok_or(bad)?,
+            _ => self.same[(mode as usize - 6) * 256 + addresses.bytes(1)?[0] as usize],
+        };
+        self.near[self.next] = addr;
+        self.next = (self.next + 1) % self.near.len();
+        self.same[addr % self.same.len()] = addr;
+        Ok(addr)
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+// The instruction's size, or the next number in the instructions when the
+// code table leaves it open
+fn inst_size(size: usize, instructions: &mut Reader) -> ResultErr<usize> {
+    match size {
+        0 => instructions.varint(),
+        size => Ok(size),
+    }
+}
+
+// VCDIFF patches are a series of windows, each writing the next stretch of
+// the ROM out of its own bytes and copies from a segment of the source, or
+// of what's been written before. Only patches xdelta3 made without
+// secondary compression can be read.
+fn apply_vcdiff(patch: &[u8], source: &[u8], deadline: &Deadline) -> ResultErr<Vec<u8>> {
+    let mut reader = Reader {
+        data: patch,
+        pos: VCDIFF_MAGIC.len(),
+    };
+    let indicator = reader.bytes(1)?[0];
+    if indicator & 0x01 != 0 {
+        return Err(
+            "the patch uses secondary compression, which can't be read; \
+                    make it again with `xdelta3 -S none`"
+                .into(),
+        );
+    }
+    if indicator & 0x02 != 0 {
+        return Err("the patch brings its own code table, which can't be read".into());
+    }
+    // xdelta3's own header, with the file names it was made from
+    if indicator & 0x04 != 0 {
+        let len = reader.varint()?;
+        reader.bytes(len)?;
+    }
+
+    let table = code_table();
+    let mut target = Vec::new();
+    while !reader.at_end() {
+        let window = reader.bytes(1)?[0];
+        let segment = if window & 0x03 != 0 {
+            let size = reader.varint()?;
+            let at = reader.varint()?;
+            let from = if window & 0x01 != 0 {
+                source
+            } else {
+                &target[..]
+            };
+            at.checked_add(size)
+                .and_then(|end| from.get(at..end))
+                .ok_or("the patch reads past the end of the base ROM")?
+                .to_vec()
+        } else {
+            Vec::new()
+        };
+        reader.varint()?;
+        let window_size = reader.varint()?;
+        let target_size = target
+            .len()
+            .checked_add(window_size)
+            .ok_or("the patch has a window too big to be real")?;
+        if target_size > rom::MAX_ROM_SIZE + rom::COPIER_HEADER_SIZE {
+            return Err("the patch makes a ROM past the largest possible SNES ROM".into());
+        }
+        if reader.bytes(1)?[0] != 0 {
+            return Err(
+                "the patch uses secondary compression, which can't be read; \
+                        make it again with `xdelta3 -S none`"
+                    .into(),
+            );
+        }
+        let data_len = reader.varint()?;
+        let instructions_len = reader.varint()?;
+        let addresses_len = reader.varint()?;
+        let checksum = match window & 0x04 {
+            0 => None,
+            _ => Some(u32::from_be_bytes(reader.bytes(4)?.try_into()?)),
+        };
+        let mut data = Reader {
+            data: reader.bytes(data_len)?,
+            pos: 0,
+        };
+        let mut instructions = Reader {
+            data: reader.bytes(instructions_len)?,
+            pos: 0,
+        };
+        let mut addresses = Reader {
+            data: reader.bytes(addresses_len)?,
+            pos: 0,
+        };
+
+        let mut written = Vec::with_capacity(window_size);
+        let mut cache = AddressCache::new();
+        while !instructions.at_end() {
+            deadline.check()?;
+            let (first, second) = table[instructions.bytes(1)?[0] as usize];
+            for inst in [first, second] {
+                let size = match inst {
+                    Inst::Noop => continue,
+                    Inst::Add(size) | Inst::Run(size) | Inst::Copy(size, _) => {
+                        inst_size(size, &mut instructions)?
+                    }
+                };
+                if written
+                    .len()
+                    .checked_add(size)
+                    .is_none_or(|end| end > window_size)
+                {
+                    return Err("the patch writes past the end of the ROM".into());
+                }
+                match inst {
+                    Inst::Add(_) => written.extend_from_slice(data.bytes(size)?),
+                    Inst::Run(_) => {
+                        let byte = data.bytes(1)?[0];
+                        written.resize(written.len() + size, byte);
+                    }
+                    Inst::Copy(_, mode) => {
+                        // Addresses past the segment are in what this
+                        // window has written, which the copy may overlap
+                        let here = segment.len() + written.len();
+                        let from = cache.decode(&mut addresses, here, mode)?;
+                        if from >= here {
+                            return Err("the patch copies bytes it hasn't written yet".into());
+                        }
+                        for at in from..from + size {
+                            let byte = match segment.get(at) {
+                                Some(&byte) => byte,
+                                None => written[at - segment.len()],
+                            };
+                            written.push(byte);
+                        }
+                    }
+                    Inst::Noop => (),
+                }
+            }
+        }
+        if written.len() != window_size {
+            return Err("the patch ends before the whole ROM is written".into());
+        }
+        if checksum.is_some_and(|sum| sum != adler32(&written)) {
+            return Err("the patched ROM's checksum doesn't match the patch's, \
+                 it was likely made for another base ROM"
+                .into());
+        }
+        target.extend(written);
+    }
+    Ok(target)
+}
+
+// BPS and UPS patches carry the size and CRC32 of the ROM they were made
+// for, which is nearly always headerless, so a header on the base ROM is
+// left out unless the patch counts it. xdelta3 patches don't say, and are
+// tried without the header first.
+pub fn apply_checked(
+    patch_file: &Path,
+    rom_file: &Path,
+    deadline: Deadline,
+    log: &mut dyn Write,
+) -> ResultErr<()> {
+    writeln!(log, "Reading patch file {:#?}", patch_file)?;
+    let patch_contents = fs::read(patch_file)?;
+    let contents = fs::read(rom_file)?;
+    let headerless = rom::without_header(&contents);
+    let mut sources = match source_size(&patch_contents)? {
+        Some(size) if size == contents.len() => vec![&contents[..]],
+        Some(_) => vec![headerless],
+        None => vec![headerless, &contents[..]],
+    };
+    sources.dedup_by_key(|s| s.len());
+    let mut result = Err("no base ROM to patch".into());
+    for source in sources {
+        writeln!(log, "Applying patch to {} bytes", source.len())?;
+        result = apply(&patch_contents, source, &deadline);
+        if result.is_ok() {
+            break;
+        }
+    }
+    match result {
+        Ok(patched) => fs::write(rom_file, patched)?,
+        Err(e) => {
+            // Don't leave the unpatched copy behind as if it were the hack
+            fs::remove_file(rom_file)?;
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+pub fn apply_ips(
+    patch_file: &Path,
+    rom_file: &Path,
+    deadline: Deadline,
+    log: &mut dyn Write,
+) -> ResultErr<()> {
+    // Open the rom file and begin overwriting it
+    writeln!(log, "Opening {:#?} to apply patch", rom_file)?;
+    let mut rom = OpenOptions::new().read(true).write(true).open(rom_file)?;
+    writeln!(log, "Reading patch file {:#?}", patch_file)?;
+    let patch_contents = fs::read(patch_file)?;
+    // The ips crate takes a truncation of 0x100 to 0x1ff bytes for one more
+    // hunk, so a patch it can't read is read again without its last 3 bytes
+    let (patch, truncation) = match Patch::parse(&patch_contents) {
+        Ok(patch) => {
+            let truncation = patch.truncation();
+            (patch, truncation)
+        }
+        Err(e) => {
+            let (hunks, size) = patch_contents.split_at(patch_contents.len().saturating_sub(3));
+            match Patch::parse(hunks) {
+                Ok(patch) if hunks.ends_with(b"EOF") && patch.truncation().is_none() => {
+                    let size = size.iter().fold(0, |n, &b| n << 8 | b as usize);
+                    (patch, Some(size))
+                }
+                _ => {
+                    // Don't leave the unpatched copy behind as if it were
+                    // the hack
+                    drop(rom);
+                    fs::remove_file(rom_file)?;
+                    return Err(e.into());
+                }
+            }
+        }
+    };
+
+    // IPS doesn't record the size of the ROM it was made for, so patches for
+    // expanded ROMs are recognized by where their last hunk ends
+    let end = patch
+        .hunks()
+        .iter()
+        .map(|h| h.offset() + h.payload().len())
+        .max()
+        .unwrap_or(0);
+    writeln!(log, "Last hunk ends at {:#x}", end)?;
+    let largest = rom::MAX_ROM_SIZE + rom::COPIER_HEADER_SIZE;
+    let too_big = if end > largest {
+        Some(format!("patch writes up to {:#x}", end))
+    } else {
+        // A truncation past the end would grow the ROM instead
+        truncation
+            .filter(|&size| size > largest)
+            .map(|size| format!("patch sizes the ROM to {:#x}", size))
+    };
+    if let Some(too_big) = too_big {
+        drop(rom);
+        fs::remove_file(rom_file)?;
+        return Err(format!("{}, past the largest possible SNES ROM", too_big).into());
+    }
+    let len = rom.metadata()?.len();
+    if end as u64 > len {
+        writeln!(
+            log,
+            "Expanding {:#?} from {:#x} to {:#x} bytes",
+            rom_file, len, end
+        )?;
+        rom.set_len(end as u64)?;
+    }
+
+    writeln!(log, "Applying hunks")?;
+    for hunk in patch.hunks() {
+        if let Err(e) = deadline.check() {
+            // Don't leave a half patched ROM behind
+            drop(rom);
+            writeln!(log, "Removing partially patched {:#?}", rom_file)?;
+            fs::remove_file(rom_file)?;
+            return Err(e);
+        }
+        rom.seek(SeekFrom::Start(hunk.offset() as u64))?;
+        rom.write_all(hunk.payload())?;
+    }
+
+    if let Some(truncation) = truncation {
+        writeln!(log, "Truncating")?;
+        rom.set_len(truncation as u64)?;
+    }
+    Ok(())
+}
+
+// An IPS patch turning `source` into `target`. A shorter target gets the
+// truncation extension the ips crate reads.
+pub fn create_ips(source: &[u8], target: &[u8]) -> ResultErr<Vec<u8>> {
+    if target.len() > IPS_MAX_SIZE {
+        return Err(format!("IPS can't describe a ROM of {:#x} bytes", target.len()).into());
+    }
+    let changed = |at: usize| source.get(at) != Some(&target[at]);
+    let run = |at: usize, end: usize| {
+        let len = target[at..end]
+            .iter()
+            .take_while(|&&b| b == target[at])
+            .count();
+        len.min(IPS_MAX_HUNK)
+    };
+    let mut patch = b"PATCH".to_vec();
+    let mut hunk = |offset: usize, len: usize, rle: Option<u8>| {
+        patch.extend_from_slice(&(offset as u32).to_be_bytes()[1..]);
+        match rle {
+            Some(byte) => {
+                patch.extend_from_slice(&[0, 0]);
+                patch.extend_from_slice(&(len as u16).to_be_bytes());
+                patch.push(byte);
+            }
+            None => {
+                patch.extend_from_slice(&(len as u16).to_be_bytes());
+                patch.extend_from_slice(&target[offset..offset + len]);
+            }
+        }
+    };
+    let mut at = 0;
+    while at < target.len() {
+        if !changed(at) {
+            at += 1;
+            continue;
+        }
+        let mut end = at;
+        while end < target.len() && changed(end) {
+            end += 1;
+        }
+        while at < end {
+            let len = run(at, end);
+            if len >= IPS_RLE_MIN && at != IPS_EOF {
+                hunk(at, len, Some(target[at]));
+                at += len;
+                continue;
+            }
+            // Plain bytes up to the next run, started a byte early when
+            // they'd start at "EOF"
+            let start = if at == IPS_EOF { at - 1 } else { at };
+            let mut stop = at + 1;
+            while stop < end && stop - start < IPS_MAX_HUNK && run(stop, end) < IPS_RLE_MIN {
+                stop += 1;
+            }
+            hunk(start, stop - start, None);
+            at = stop;
+        }
+    }
+    patch.extend_from_slice(b"EOF");
+    if target.len() < source.len() {
+        patch.extend_from_slice(&(target.len() as u32).to_be_bytes()[1..]);
+    }
+    Ok(patch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::TestDir;
+
+    #[test]
+    fn bps_patches_check_their_crcs_and_copy_both_ways() {
+        let deadline = Deadline::after(std::time::Duration::from_secs(60), &Cancel::default());
+        let source = b"ABCDEFGH";
+        let target = b"ABXDEFGHHHHA";
+        // Numbers under 0x80 are one byte with the top bit set
+        let mut bps = b"BPS1".to_vec();
+        bps.extend([0x88, 0x8c, 0x80]);
+        // Two bytes as in the source, an X, five more from the source, the
+        // last byte three times over, and the source's first byte again
+        bps.extend([0x84, 0x81, b'X', 0x90, 0x8b, 0x8e, 0x82, 0x80]);
+        bps.extend(crc32fast::hash(source).to_le_bytes());
+        bps.extend(crc32fast::hash(target).to_le_bytes());
+        bps.extend(crc32fast::hash(&bps).to_le_bytes());
+
+        assert_eq!(source_size(&bps).unwrap(), Some(8));
+        assert_eq!(apply(&bps, source, &deadline).unwrap(), target);
+        let wrong_base = apply(&bps, b"ABCDEFGI", &deadline).unwrap_err();
+        assert!(
+            wrong_base.to_string().contains("another base ROM"),
+            "{}",
+            wrong_base
+        );
+        let mut damaged = bps.clone();
+        damaged[9] = b'Y';
+        assert!(apply(&damaged, source, &deadline).is_err());
+
+        // A cancelled run stops partway instead of finishing the ROM
+        let cancel = Cancel::default();
+        cancel.cancel();
+        let cancelled = Deadline::after(std::time::Duration::from_secs(60), &cancel);
+        assert!(apply(&bps, source, &cancelled)
+            .unwrap_err()
+            .is::<Cancelled>());
+    }
+
+    // BPS numbers are seven bits a byte, lowest first, each continuation
+    // one less than it would be, and the top bit set on the last byte
+    fn bps_number(mut number: u64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let low = (number & 0x7f) as u8;
+            number >>= 7;
+            if number == 0 {
+                bytes.push(0x80 | low);
+                return bytes;
+            }
+            bytes.push(low);
+            number -= 1;
+        }
+    }
+
+    #[test]
+    fn bps_offsets_past_the_range_of_a_number_are_refused() {
+        let deadline = Deadline::after(std::time::Duration::from_secs(60), &Cancel::default());
+        let source = b"AB";
+        let mut bps = b"BPS1".to_vec();
+        bps.extend([0x82, 0x82, 0x80]);
+        // A byte from the start of the source, then one from as far past
+        // it as an offset can reach
+        bps.extend([0x82, 0x80, 0x82]);
+        bps.extend(bps_number(u64::MAX - 1));
+        bps.extend(crc32fast::hash(source).to_le_bytes());
+        bps.extend(crc32fast::hash(source).to_le_bytes());
+        bps.extend(crc32fast::hash(&bps).to_le_bytes());
+
+        let error = apply(&bps, source, &deadline).unwrap_err();
+        assert_eq!(error.to_string(), "BPS offset out of range");
+    }
+
+    #[test]
+    fn ips_patches_that_dont_parse_leave_no_rom_behind() {
+        let dir = TestDir::new("bad-ips");
+        let (patch, rom) = (dir.join("hack.ips"), dir.join("hack.sfc"));
+        fs::write(&patch, b"PATCH\x00\x00\x10\x00").unwrap();
+        fs::write(&rom, [0u8; 0x20]).unwrap();
+        let deadline = Deadline::after(std::time::Duration::from_secs(60), &Cancel::default());
+
+        assert!(apply_ips(&patch, &rom, deadline.clone(), &mut std::io::sink()).is_err());
+        assert!(!rom.exists());
+
+        // Nor do ones whose truncation would grow the ROM past any real one
+        fs::write(&patch, b"PATCHEOF\xff\xff\xff").unwrap();
+        fs::write(&rom, [0u8; 0x20]).unwrap();
+        let error = apply_ips(&patch, &rom, deadline, &mut std::io::sink()).unwrap_err();
+        assert!(error.to_string().contains("past the largest"), "{}", error);
+        assert!(!rom.exists());
+    }
+
+    #[test]
+    fn ups_patches_xor_their_hunks_into_the_resized_rom() {
+        let deadline = Deadline::after(std::time::Duration::from_secs(60), &Cancel::default());
+        let source = b"ABCDEFGH";
+        let target = b"ABXDEFGHIJ";
+        let mut ups = b"UPS1".to_vec();
+        ups.extend([0x88, 0x8a]);
+        // Skip two bytes and change C to X; then, past the byte the zero
+        // stood for, skip four and fill in the two new bytes
+        ups.extend([0x82, b'C' ^ b'X', 0x00, 0x84, b'I', b'J', 0x00]);
+        ups.extend(crc32fast::hash(source).to_le_bytes());
+        ups.extend(crc32fast::hash(target).to_le_bytes());
+        ups.extend(crc32fast::hash(&ups).to_le_bytes());
+
+        assert_eq!(source_size(&ups).unwrap(), Some(8));
+        assert_eq!(apply(&ups, source, &deadline).unwrap(), target);
+        assert!(apply(&ups, b"ABCDEFG", &deadline).is_err());
+    }
+
+    #[test]
+    fn xdelta_patches_copy_from_the_base_rom_and_what_they_wrote() {
+        let deadline = Deadline::after(std::time::Duration::from_secs(60), &Cancel::default());
+        let source = b"ABCDEFGH";
+        let target = b"ABXDEFGHHHH";
+        let adler = target.iter().fold((1u32, 0u32), |(a, b), &byte| {
+            let a = (a + byte as u32) % 65521;
+            (a, (b + a) % 65521)
+        });
+        // Two bytes from the base ROM, an X, five more from the base ROM,
+        // then the last byte written three times over
+        let instructions = [19, 2, 2, 21, 35, 3];
+        let addresses = [0, 3, 1];
+        let mut vcdiff = vec![0xd6, 0xc3, 0xc4, 0x00, 0x00];
+        // Windows read from the base ROM, and have a checksum
+        vcdiff.extend([0x05, 8, 0, 19, target.len() as u8, 0x00, 1, 6, 3]);
+        vcdiff.extend(((adler.1 << 16) | adler.0).to_be_bytes());
+        vcdiff.push(b'X');
+        vcdiff.extend(instructions);
+        vcdiff.extend(addresses);
+
+        assert_eq!(source_size(&vcdiff).unwrap(), None);
+        assert_eq!(apply(&vcdiff, source, &deadline).unwrap(), target);
+        let wrong_base = apply(&vcdiff, b"ABCDEFGI", &deadline).unwrap_err();
+        assert!(
+            wrong_base.to_string().contains("checksum"),
+            "{}",
+            wrong_base
+        );
+
+        // A second window as big as a number can be
+        let mut huge = vcdiff.clone();
+        huge.extend([0x00, 0]);
+        huge.push(0x81);
+        huge.extend([0xff; 8]);
+        huge.push(0x7f);
+        let error = apply(&huge, source, &deadline).unwrap_err();
+        assert!(error.to_string().contains("window too big"), "{}", error);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::config::{Config, CONFIG_FILE};
+use crate::manifest::MANIFEST_FILE;
+use crate::utils::*;
+use std::env;
+use std::path::{Path, PathBuf};
+
+const APP_DIR: &str = "metconst-tool";
+
+// Where state, config and the default relative directories live. Setups
+// that keep a config file or a mirror in the directory they're run from
+// carry on working there; anything else, like a scheduled task started in some
+// system directory, uses the per-user data directory. Portable runs always
+// stay where they were started.
+pub fn home(cwd: &Path, portable: bool) -> ResultErr<PathBuf> {
+    if portable {
+        return Ok(cwd.to_path_buf());
+    }
+    if let Some(dir) = env::var_os("METCONST_HOME") {
+        return absolute_override("METCONST_HOME", dir.into());
+    }
+    if is_setup(cwd) {
+        return Ok(cwd.to_path_buf());
+    }
+    data_dir().ok_or_else(|| "no home directory to keep data in, set METCONST_HOME".into())
+}
+
+// Mirrors made before there was a config file only have their downloads
+// and manifest to go by
+fn is_setup(dir: &Path) -> bool {
+    dir.join(CONFIG_FILE).exists()
+        || dir.join(MANIFEST_FILE).exists()
+        || dir.join(Config::default().downloads_dir).is_dir()
+}
+
+// Only needed when not running out of a directory with a config file in it,
+// next to which the logs have always been written
+pub fn log_dir(home: &Path, cwd: &Path, portable: bool) -> ResultErr<Option<PathBuf>> {
+    if portable {
+        return Ok(None);
+    }
+    if let Some(dir) = env::var_os("METCONST_LOG_DIR") {
+        return absolute_override("METCONST_LOG_DIR", dir.into()).map(Some);
+    }
+    if home == cwd || env::var_os("METCONST_HOME").is_some() {
+        return Ok(None);
+    }
+    Ok(platform_log_dir())
+}
+
+fn absolute_override(var: &str, dir: PathBuf) -> ResultErr<PathBuf> {
+    if !dir.is_absolute() {
+        return Err(format!("{} has to be an absolute path, not {:?}", var, dir).into());
+    }
+    Ok(dir)
+}
+
+// A path given on the command line, relative to where the tool was started
+// rather than to its home
+pub fn from_cwd(cwd: &Path, path: &str) -> String {
+    cwd.join(path).to_string_lossy().into_owned()
+}
+
+fn user_home() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+fn data_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        user_home().map(|h| h.join("Library").join("Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|d| d.is_absolute())
+            .or_else(|| user_home().map(|h| h.join(".local").join("share")))
+    };
+    base.map(|b| b.join(APP_DIR))
+}
+
+fn platform_log_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        data_dir().map(|d| d.join("logs"))
+    } else if cfg!(target_os = "macos") {
+        user_home().map(|h| h.join("Library").join("Logs").join(APP_DIR))
+    } else {
+        env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .filter(|d| d.is_absolute())
+            .or_else(|| user_home().map(|h| h.join(".local").join("state")))
+            .map(|d| d.join(APP_DIR))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::TestDir;
+    use std::fs;
+
+    #[test]
+    fn setups_with_a_config_file_keep_their_directory() {
+        let dir = TestDir::new("home");
+        fs::write(dir.join(CONFIG_FILE), "{}").unwrap();
+        let home = home(&dir, false);
+        let logs = log_dir(&dir, &dir, false);
+
+        assert_eq!(home.unwrap(), *dir);
+        assert_eq!(logs.unwrap(), None);
+        assert_eq!(
+            from_cwd(Path::new("/start"), "base.sfc"),
+            Path::new("/start").join("base.sfc").to_string_lossy()
+        );
+    }
+
+    #[test]
+    fn mirrors_from_before_the_config_file_keep_their_directory() {
+        let dir = TestDir::new("old-home");
+        let downloads = dir.join("downloads");
+        let manifest = dir.join("manifest-only");
+        fs::create_dir_all(downloads.join("0001-756-Hyper Metroid")).unwrap();
+        fs::create_dir_all(&manifest).unwrap();
+        fs::write(manifest.join(MANIFEST_FILE), "{}").unwrap();
+        let empty = dir.join("empty");
+        fs::create_dir_all(&empty).unwrap();
+        let with_downloads = home(&dir, false);
+        let with_manifest = home(&manifest, false);
+        let with_nothing = home(&empty, false);
+
+        assert_eq!(with_downloads.unwrap(), *dir);
+        assert_eq!(with_manifest.unwrap(), manifest);
+        assert_ne!(with_nothing.unwrap(), empty);
+    }
+}
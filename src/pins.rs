@@ -0,0 +1,101 @@
+use crate::schema::{self, Migration};
+use crate::utils::*;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const PINS_FILE: &str = "pins.json";
+
+const MIGRATIONS: &[Migration] = &[schema::unchanged];
+
+// The SHA-256 the archives of a hack are known to have, written by hand to
+// catch uploads that changed without anyone saying so, e.g.
+// { "hacks": { "756": "9f86d08...", "12": ["2c26b46...", "fcde2b2..."] } }
+// A hack with several archives lists all of them.
+#[derive(Debug, Default, Clone)]
+pub struct Pins {
+    hacks: BTreeMap<u32, Vec<String>>,
+}
+
+impl Pins {
+    pub fn load<P: AsRef<Path>>(fname: P) -> ResultErr<Pins> {
+        if !fname.as_ref().exists() {
+            return Ok(Pins::default());
+        }
+        let mut value = read_json(fname)?;
+        schema::upgrade(&mut value, MIGRATIONS, PINS_FILE)?;
+        let mut pins = Pins::default();
+        if let Some(hacks) = value.get("hacks").and_then(|h| h.as_object()) {
+            for (id, pinned) in hacks {
+                let hashes = match pinned {
+                    serde_json::Value::String(hash) => vec![hash.to_lowercase()],
+                    serde_json::Value::Array(hashes) => hashes
+                        .iter()
+                        .filter_map(|h| h.as_str())
+                        .map(|h| h.to_lowercase())
+                        .collect(),
+                    _ => return Err(format!("pin for hack {} isn't a hash", id).into()),
+                };
+                pins.hacks.insert(id.parse()?, hashes);
+            }
+        }
+        Ok(pins)
+    }
+
+    // Errors when an archive of a pinned hack isn't one of its pinned
+    // hashes. Other files, and hacks without pins, pass.
+    pub fn check(&self, id: u32, file_name: &str, contents: &[u8]) -> ResultErr<()> {
+        let Some(pinned) = self.hacks.get(&id) else {
+            return Ok(());
+        };
+        let is_archive = Path::new(file_name)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .is_some_and(|e| ["zip", "rar", "7z"].contains(&e.as_str()));
+        if !is_archive {
+            return Ok(());
+        }
+        let hash = to_hex(&Sha256::digest(contents));
+        if pinned.contains(&hash) {
+            return Ok(());
+        }
+        Err(format!(
+            "{} of hack {} has SHA-256 {}, which isn't pinned in {}. \
+             The upload may have been altered; check it and update the pin",
+            file_name, id, hash, PINS_FILE
+        )
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::TestDir;
+
+    #[test]
+    fn pinned_archives_must_match_their_hash() {
+        let dir = TestDir::new("pins");
+        let fname = dir.join(PINS_FILE);
+        let hash = |contents: &[u8]| {
+            use sha2::Digest;
+            to_hex(&sha2::Sha256::digest(contents))
+        };
+        let pinned = serde_json::json!({
+            "hacks": { "756": hash(b"original").to_uppercase(), "12": [hash(b"a"), hash(b"b")] }
+        });
+        write_json(&fname, &pinned).unwrap();
+        let pins = Pins::load(&fname).unwrap();
+
+        assert!(pins.check(756, "hack.zip", b"original").is_ok());
+        let altered = pins.check(756, "hack.zip", b"altered").unwrap_err();
+        assert!(
+            altered.to_string().contains(&hash(b"altered")),
+            "{}",
+            altered
+        );
+        assert!(pins.check(756, "readme.txt", b"altered").is_ok());
+        assert!(pins.check(12, "part2.7z", b"b").is_ok());
+        assert!(pins.check(5, "other.zip", b"anything").is_ok());
+    }
+}
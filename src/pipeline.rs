@@ -0,0 +1,1779 @@
+use crate::catalog::{Catalog, CATALOG_FILE};
+use crate::codes;
+use crate::config::Config;
+use crate::events;
+use crate::extract;
+use crate::filter::HackFilter;
+use crate::heartbeat;
+use crate::howto;
+use crate::http;
+use crate::i18n;
+use crate::manifest;
+use crate::manifest::{Manifest, MANIFEST_FILE};
+use crate::output;
+use crate::outputs::{OutputChange, Outputs, Patched, Resize, OUTPUTS_FILE};
+use crate::overrides::Override;
+use crate::overrides::Overrides;
+use crate::patch;
+use crate::pins::{Pins, PINS_FILE};
+use crate::progress::{HackProgress, Progress, PROGRESS_FILE};
+use crate::reporter::Reporter;
+use crate::rom;
+use crate::scrape;
+use crate::shared::Locks;
+use crate::summary::{Skip, Summary};
+use crate::utils::*;
+use crate::{PatchArgs, PatchUrlArgs};
+use indicatif::ProgressBar;
+use ips::Patch;
+use regex::Regex;
+use reqwest_middleware::ClientWithMiddleware;
+use sanitise_file_name::sanitise;
+use scraper::{Html, Selector};
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fs;
+use std::fs::create_dir_all;
+use std::io::Write;
+use std::path::Path;
+use std::path::{Component, PathBuf};
+use std::sync::Arc;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::task::{JoinSet, LocalSet};
+use walkdir::DirEntry;
+
+// The stages of the download mode, in order: the hack list is planned, each
+// hack's page resolves to the URLs of its files, the files are fetched, and
+// stored with the manifest and progress kept up to date. Fetching and
+// storing run side by side, handing work on through a channel.
+//
+// The patch mode comes after, one patch at a time: each patch in the mirror
+// is applied to its own copy of the base ROM.
+
+// A hack on the list, and whether it needs fetching at all
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListedHack {
+    // Position on the list, which is part of the hack's directory name
+    pub idx: usize,
+    pub id: u32,
+    pub skip: Option<Skip>,
+    // What the last run had worked out, if it stopped inside this hack
+    pub resume: Option<HackProgress>,
+}
+
+// A hack's title and the files its download links lead to
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedHack {
+    pub idx: usize,
+    pub id: u32,
+    pub title: Option<String>,
+    pub files: Vec<String>,
+    // The download links that led to each file; not kept for a resumed hack
+    pub links: BTreeMap<String, Vec<String>>,
+    // Files an interrupted run already stored
+    pub done: Vec<String>,
+    pub resumed: bool,
+}
+
+#[derive(Debug)]
+pub enum Resolved {
+    Skipped { idx: usize, id: u32, reason: Skip },
+    Hack(ResolvedHack),
+}
+
+#[derive(Debug)]
+pub struct FetchedFile {
+    pub id: u32,
+    pub title: Option<String>,
+    pub url: String,
+    pub hack_dir: String,
+    pub file_name: String,
+    pub links: Vec<String>,
+    // None when the file is already on disk
+    pub contents: Option<Vec<u8>>,
+}
+
+#[derive(Debug)]
+pub enum Fetched {
+    Skipped { idx: usize, id: u32, reason: Skip },
+    Started(ResolvedHack),
+    File(FetchedFile),
+    Finished { idx: usize },
+}
+
+// The most hacks the site shows on one page of its list
+pub const HACKS_PER_PAGE: usize = 1000;
+
+// One page of the hack list, counting from 1
+pub fn hack_list_url(config: &Config, page: usize) -> String {
+    format!("{}hacks.php?sort=5&dir=asc&filters%5B%5D=SM&filters%5B%5D=Unknown&filters%5B%5D=Boss+Rush&filters%5B%5D=Exploration&filters%5B%5D=Challenge&filters%5B%5D=Spoof&filters%5B%5D=Speedrun%2FRace&filters%5B%5D=Incomplete&filters%5B%5D=Quick+Play&filters%5B%5D=Improvement&filters%5B%5D=Vanilla%2B&search=&num_per_page={}&page={}", config.site_url, HACKS_PER_PAGE, page)
+}
+
+// Every page of the hack list. Pages are fetched in turn until a short one,
+// or one with no hacks the earlier pages didn't have, which is what the
+// site answers past the last page if it ignores `page=`.
+pub async fn fetch_hack_list(
+    config: &Config,
+    client: &ClientWithMiddleware,
+) -> ResultErr<Vec<String>> {
+    let mut pages = Vec::new();
+    let mut seen = HashSet::new();
+    for page in 1.. {
+        let body = http::fetch_page(client, &hack_list_url(config, page)).await?;
+        tokio::time::sleep(tokio::time::Duration::from_secs(config.page_delay_secs)).await;
+        let ids = hack_ids(&body)?;
+        let new = ids.iter().filter(|id| seen.insert(id.to_string())).count();
+        if new == 0 {
+            break;
+        }
+        pages.push(body);
+        if ids.len() < HACKS_PER_PAGE {
+            break;
+        }
+    }
+    Ok(pages)
+}
+
+// The ids on all the pages of the hack list, in list order, each once
+pub fn listed_ids(pages: &[String]) -> ResultErr<Vec<String>> {
+    let mut seen = HashSet::new();
+    let mut ids = Vec::new();
+    for page in pages {
+        ids.extend(
+            hack_ids(page)?
+                .into_iter()
+                .filter(|id| seen.insert(id.clone())),
+        );
+    }
+    Ok(ids)
+}
+
+// The ids linked from the hack list, in list order
+pub fn hack_ids(page: &str) -> ResultErr<Vec<String>> {
+    let document = Html::parse_document(page);
+    let row_selector = Selector::parse("td")?;
+    let ahref = Selector::parse("a")?;
+
+    // example: hack.php?id=756
+    let re = Regex::new(r"^hack\.php\?id=([0-9]+)$")?;
+
+    let mut hack_id = Vec::new();
+    for element in document.select(&row_selector) {
+        for e in element.select(&ahref) {
+            if let Some(href) = e.value().attr("href") {
+                for (_, [id]) in re.captures_iter(href).map(|c| c.extract()) {
+                    hack_id.push(id.to_owned());
+                }
+            }
+        }
+    }
+    Ok(hack_id)
+}
+
+// Decide up front which hacks can be skipped without fetching anything. A
+// resumed run starts where the last one stopped.
+pub fn plan(
+    ids: &[String],
+    recheck: bool,
+    config: &Config,
+    overrides: &Overrides,
+    filter: &HackFilter,
+    manifest: &Manifest,
+    progress: &Progress,
+) -> ResultErr<Vec<ListedHack>> {
+    let downloads = Path::new(&config.downloads_dir);
+    let mut listed = Vec::new();
+    for (idx, id) in ids.iter().enumerate().skip(progress.next) {
+        let id: u32 = id.parse()?;
+        let resume = progress.resume_hack(id).cloned();
+        let skip = if overrides.skips(id) {
+            Some(Skip::Quarantined)
+        } else if filter.blocks(id, None) {
+            Some(Skip::Blocklisted)
+        } else if !filter.may_allow(id) {
+            Some(Skip::FilteredOut)
+        } else if !recheck && resume.is_none() && manifest.has_all_files(downloads, id) {
+            // A hack the last run stopped in the middle of has only some of
+            // its files in the manifest
+            Some(Skip::UpToDate)
+        } else {
+            None
+        };
+        listed.push(ListedHack {
+            idx,
+            id,
+            skip,
+            resume,
+        });
+    }
+    Ok(listed)
+}
+
+// What a hack's page leads to. Pages may link to a file more than once,
+// e.g. from a button and from its name, so each file is listed once with
+// every link that led to it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HackFiles {
+    pub title: Option<String>,
+    pub files: Vec<String>,
+    pub links: BTreeMap<String, Vec<String>>,
+}
+
+// Fetch a hack's page for its title, and follow its download links to the
+// URLs of its files
+pub async fn resolve_hack_files(
+    config: &Config,
+    client: &ClientWithMiddleware,
+    id: u32,
+) -> ResultErr<HackFiles> {
+    let hack_page = fetch_hack_page(config, client, id).await?;
+    hack_files(config, client, id, &hack_page).await
+}
+
+pub async fn fetch_hack_page(
+    config: &Config,
+    client: &ClientWithMiddleware,
+    id: u32,
+) -> ResultErr<String> {
+    let hack_url = format!("{}hack.php?id={}", config.site_url, id);
+    let hack_page = http::fetch_page(client, &hack_url).await?;
+    tokio::time::sleep(tokio::time::Duration::from_secs(config.page_delay_secs)).await;
+    Ok(hack_page)
+}
+
+// The title on a hack's page, and the URLs of the files its download links
+// lead to
+pub async fn hack_files(
+    config: &Config,
+    client: &ClientWithMiddleware,
+    id: u32,
+    hack_page: &str,
+) -> ResultErr<HackFiles> {
+    let metconst = config.site_url.as_str();
+    let document = Html::parse_document(hack_page);
+    // Not the links of hacks whose id starts with this one's
+    let download_link = format!(r"^download\.php\?id={}(?:&|$)", id);
+    let re = Regex::new(&download_link)?;
+    let ahref = Selector::parse("a")?;
+    let meta = Selector::parse("meta")?;
+    #[allow(non_snake_case)]
+    let underboxA = Selector::parse("td.underboxA")?;
+
+    // Extract hack title
+    // In an ideal world, we would always just use the meta property
+    // but for some reason, not all hack pages have that attribute set.
+    // So when we can't find the meta tag with "og:title" we fallback to
+    // looking for the hack title on the page
+    let mut title = None;
+    for element in document.select(&meta) {
+        if element.attr("property") == Some("og:title") {
+            title = element.attr("content");
+        }
+    }
+    if title.is_none() {
+        // We just want the first underboxA on the page
+        if let Some(element) = document.select(&underboxA).next() {
+            title = element.text().next().map(|t| t.trim());
+        }
+    }
+    let title = title.map(|t| t.to_owned());
+
+    let mut redirects = Vec::new();
+    for element in document.select(&ahref) {
+        if let Some(href) = element.value().attr("href") {
+            //println!("href={}", href);
+            let redirect = format!("{}{}", metconst, href);
+            if re.is_match(href) && !redirects.contains(&redirect) {
+                redirects.push(redirect);
+            }
+        }
+    }
+    let mut hack = HackFiles {
+        title,
+        ..HackFiles::default()
+    };
+    for redirect_url in redirects {
+        let redirect_contents = http::fetch_page(client, &redirect_url).await?;
+        tokio::time::sleep(tokio::time::Duration::from_secs(config.page_delay_secs)).await;
+        let document = Html::parse_document(&redirect_contents);
+        for element in document.select(&meta) {
+            if let Some(url) = element.value().attr("content") {
+                if let Some((_, url)) = url.rsplit_once('=') {
+                    if !hack.files.iter().any(|f| f == url) {
+                        hack.files.push(url.to_owned());
+                    }
+                    let links = hack.links.entry(url.to_owned()).or_default();
+                    if !links.contains(&redirect_url) {
+                        links.push(redirect_url.clone());
+                    }
+                }
+            }
+        }
+    }
+    Ok(hack)
+}
+
+// The `NNNN-ID-Title` directory a hack's files are stored in, `idx` being
+// its position on the list
+pub fn hack_dir_name(config: &Config, idx: usize, id: u32, title: Option<&str>) -> String {
+    match title {
+        Some(title) => config
+            .names
+            .apply(&sanitise(&format!("{:04}-{}-{}", idx, id, title))),
+        None => format!("{:04}-{}", idx, id),
+    }
+}
+
+// What the jobs fetching hacks share
+#[derive(Clone)]
+pub struct Fetcher {
+    pub config: Config,
+    pub client: ClientWithMiddleware,
+    pub filter: Arc<HackFilter>,
+    pub locks: Arc<Locks>,
+    pub reporter: Reporter,
+}
+
+impl Fetcher {
+    // Look up the hack's files, unless it's skipped
+    async fn resolve(&self, hack: ListedHack) -> ResultErr<Resolved> {
+        // Held until the hack is stored, unless it's skipped
+        let locked = match hack.skip {
+            Some(_) => None,
+            None => self.locks.hold_hack(hack.id)?,
+        };
+        let resolved = if let Some(reason) = hack.skip {
+            Resolved::Skipped {
+                idx: hack.idx,
+                id: hack.id,
+                reason,
+            }
+        } else if let Some(holder) = locked {
+            let args = [("id", hack.id.into()), ("holder", holder.as_str().into())];
+            self.reporter.println(i18n::tr("download-locked", &args));
+            Resolved::Skipped {
+                idx: hack.idx,
+                id: hack.id,
+                reason: Skip::Locked,
+            }
+        } else if let Some(resume) = hack.resume {
+            Resolved::Hack(ResolvedHack {
+                idx: hack.idx,
+                id: hack.id,
+                title: resume.title,
+                files: resume.files,
+                links: BTreeMap::new(),
+                done: resume.done,
+                resumed: true,
+            })
+        } else {
+            let hack_page = fetch_hack_page(&self.config, &self.client, hack.id).await?;
+            // Only the page is needed to tell whether the hack was asked for
+            let selected = match self.filter.selection().needs_page() {
+                true => {
+                    let parser = scrape::PageParser::for_site(&self.config.site_url)?;
+                    self.filter.selection().selects(&parser.parse(&hack_page)?)
+                }
+                false => true,
+            };
+            let found = match selected {
+                true => hack_files(&self.config, &self.client, hack.id, &hack_page).await?,
+                false => HackFiles::default(),
+            };
+            if selected && self.filter.allows(hack.id, found.title.as_deref()) {
+                Resolved::Hack(ResolvedHack {
+                    idx: hack.idx,
+                    id: hack.id,
+                    title: found.title,
+                    files: found.files,
+                    links: found.links,
+                    done: Vec::new(),
+                    resumed: false,
+                })
+            } else {
+                let reason = if self.filter.blocks(hack.id, found.title.as_deref()) {
+                    Skip::Blocklisted
+                } else {
+                    Skip::FilteredOut
+                };
+                Resolved::Skipped {
+                    idx: hack.idx,
+                    id: hack.id,
+                    reason,
+                }
+            }
+        };
+        Ok(resolved)
+    }
+
+    // Download the files of one hack that aren't on disk yet, as what the
+    // store stage is handed for it
+    async fn fetch(&self, hack: ListedHack) -> ResultErr<Vec<Fetched>> {
+        let config = &self.config;
+        let worker = self.reporter.worker(&format!("hack {}", hack.id));
+        let hack = match self.resolve(hack).await? {
+            Resolved::Skipped { idx, id, reason } => {
+                worker.finish_and_clear();
+                return Ok(vec![Fetched::Skipped { idx, id, reason }]);
+            }
+            Resolved::Hack(hack) => hack,
+        };
+        let (idx, id, title) = (hack.idx, hack.id, hack.title.clone());
+        let files: Vec<_> = hack
+            .files
+            .iter()
+            .filter(|url| !hack.done.contains(url))
+            .cloned()
+            .collect();
+        let mut links = hack.links.clone();
+        let mut fetched = vec![Fetched::Started(hack)];
+        for url in files {
+            let Some((_, file_name)) = url.rsplit_once('/') else {
+                continue;
+            };
+            let hack_dir = hack_dir_name(config, idx, id, title.as_deref());
+            let file_name = config.names.apply(file_name);
+            let full_path = format!("{}/{}/{}", config.downloads_dir, hack_dir, file_name);
+            let contents = if Path::new(&full_path).exists() {
+                None
+            } else {
+                worker.set_message(file_name.clone());
+                // Only whole files get their own name, so one cut off
+                // is picked up where it stopped instead of taken as done
+                let part = http::part_path(Path::new(&full_path));
+                create_dir_all(part.parent().ok_or("bad path")?)?;
+                http::download_resuming(&self.client, &url, &part).await?;
+                tokio::time::sleep(tokio::time::Duration::from_secs(config.file_delay_secs)).await;
+                Some(std::fs::read(&part)?)
+            };
+            fetched.push(Fetched::File(FetchedFile {
+                id,
+                title: title.clone(),
+                links: links.remove(&url).unwrap_or_default(),
+                url,
+                hack_dir,
+                file_name,
+                contents,
+            }));
+        }
+        fetched.push(Fetched::Finished { idx });
+        worker.finish_and_clear();
+        Ok(fetched)
+    }
+}
+
+// Stage: resolve and fetch up to `jobs` hacks at once. Each hack is handed
+// on whole and in list order all the same, which the store stage and the
+// progress file count on. The stages stop quietly when the one after them
+// has gone, it has the error to report.
+pub async fn fetch(
+    fetcher: Fetcher,
+    jobs: usize,
+    listed: Vec<ListedHack>,
+    tx: Sender<Fetched>,
+) -> ResultErr<()> {
+    // Parsed pages can't move between threads, so the jobs stay on this one
+    let local = LocalSet::new();
+    local
+        .run_until(async move {
+            let mut listed = listed.into_iter();
+            let mut running = JoinSet::new();
+            // Hacks in the order they're handed on, and the ones done
+            // fetching while a hack before them isn't yet
+            let mut order = VecDeque::new();
+            let mut done = BTreeMap::new();
+            loop {
+                while running.len() + done.len() < jobs.max(1) {
+                    let Some(hack) = listed.next() else {
+                        break;
+                    };
+                    order.push_back(hack.idx);
+                    let fetcher = fetcher.clone();
+                    running.spawn_local(async move {
+                        let idx = hack.idx;
+                        (idx, fetcher.fetch(hack).await)
+                    });
+                }
+                while let Some(fetched) = order.front().and_then(|idx| done.remove(idx)) {
+                    order.pop_front();
+                    for message in fetched {
+                        if tx.send(message).await.is_err() {
+                            return Ok(());
+                        }
+                    }
+                }
+                let Some(joined) = running.join_next().await else {
+                    break;
+                };
+                let (idx, fetched) = joined?;
+                done.insert(idx, fetched?);
+            }
+            ResultErr::<()>::Ok(())
+        })
+        .await
+}
+
+// Stage: write the fetched files into the downloads directory, keeping the
+// manifest and the progress file up to date as they land
+#[allow(clippy::too_many_arguments)]
+pub async fn store(
+    config: &Config,
+    manifest: &mut Manifest,
+    progress: &mut Progress,
+    pb: &ProgressBar,
+    summary: &Summary,
+    locks: &Locks,
+    mut rx: Receiver<Fetched>,
+    log: &mut dyn Write,
+) -> ResultErr<()> {
+    let downloads = Path::new(&config.downloads_dir);
+    let pins = Pins::load(PINS_FILE)?;
+    let mut current = None;
+    let finish = |progress: &mut Progress, idx: usize| -> ResultErr<()> {
+        progress.finish_hack(idx + 1);
+        if ((idx + 1) as u64).is_multiple_of(config.download_checkpoint_every.max(1)) {
+            progress.save(PROGRESS_FILE)?;
+        }
+        pb.inc(1);
+        events::emit(
+            config,
+            "progress",
+            json!({ "done": idx + 1, "total": pb.length() }),
+        );
+        Ok(())
+    };
+    while let Some(fetched) = rx.recv().await {
+        match fetched {
+            Fetched::Skipped { idx, id, reason } => {
+                writeln!(log, "skipping hack {}, {}", id, reason.as_str())?;
+                events::emit(
+                    config,
+                    "hack_skipped",
+                    json!({ "hack": id, "reason": reason }),
+                );
+                summary.skipped(format!("hack {}", id), reason);
+                locks.release_hack(id);
+                finish(progress, idx)?;
+            }
+            Fetched::Started(hack) => {
+                if hack.resumed {
+                    writeln!(log, "resuming hack {}", hack.id)?;
+                }
+                current = Some(hack.id);
+                let fields =
+                    json!({ "hack": hack.id, "title": hack.title, "files": hack.files.len() });
+                events::emit(config, "hack_started", fields);
+                progress.start_hack(hack.id, hack.title, hack.files);
+            }
+            Fetched::File(file) => {
+                let dir_name = format!("{}/{}", config.downloads_dir, file.hack_dir);
+                let full_file_name = format!("{}/{}", dir_name, file.file_name);
+                let full_path = Path::new(&full_file_name);
+                let title = file.title.as_deref();
+                let Some(contents) = file.contents else {
+                    //println!("skipping {}, already downloaded", url);
+                    writeln!(log, "skipping {}, already downloaded", file.url)
+                        .expect("failed to log");
+                    summary.skipped(&file.url, Skip::AlreadyPresent);
+                    if !manifest.contains_path(downloads, full_path) {
+                        manifest.record_file(
+                            downloads,
+                            file.id,
+                            title,
+                            &file.hack_dir,
+                            full_path,
+                        )?;
+                    }
+                    manifest.record_links(downloads, file.id, full_path, &file.links)?;
+                    continue;
+                };
+                //println!("url: {}", url);
+                writeln!(log, "url: {}", file.url).expect("failed to log");
+                //println!("file_name: {}", file_name);
+                writeln!(log, "file_name: {}", file.file_name).expect("failed to log");
+                //println!("dir_name: {}", dir_name);
+                writeln!(log, "dir_name: {}", dir_name).expect("failed to log");
+                let part = http::part_path(full_path);
+                if let Err(e) = pins.check(file.id, &file.file_name, &contents) {
+                    // Not to be resumed either
+                    std::fs::remove_file(&part)?;
+                    return Err(e);
+                }
+                std::fs::rename(&part, full_path)?;
+                summary.processed(contents.len() as u64);
+                let fields =
+                    json!({ "hack": file.id, "file": file.file_name, "bytes": contents.len() });
+                events::emit(config, "file_stored", fields);
+                manifest.record_file(downloads, file.id, title, &file.hack_dir, full_path)?;
+                manifest.record_links(downloads, file.id, full_path, &file.links)?;
+                // The manifest has to know about the file before the
+                // progress says it's done
+                manifest.save_hack(MANIFEST_FILE, file.id, locks)?;
+                progress.file_done(&file.url);
+                progress.save(PROGRESS_FILE)?;
+            }
+            Fetched::Finished { idx } => {
+                if let Some(id) = current.take() {
+                    manifest.save_hack(MANIFEST_FILE, id, locks)?;
+                    locks.release_hack(id);
+                    events::emit(config, "hack_finished", json!({ "hack": id }));
+                }
+                finish(progress, idx)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub struct PatchSettings {
+    pub base_rom: String,
+    pub patched_dir: PathBuf,
+    pub fix_checksum: bool,
+    pub downloads_dir: PathBuf,
+    pub extract_dir: Option<PathBuf>,
+    pub timeout: std::time::Duration,
+    pub fat32_safe: bool,
+    pub asar: Option<String>,
+    pub names: NameRules,
+    pub cancel: Cancel,
+}
+
+impl PatchSettings {
+    pub fn from_config(
+        config: &Config,
+        base_rom: Option<String>,
+        fix_checksum: bool,
+        cancel: &Cancel,
+    ) -> ResultErr<PatchSettings> {
+        Ok(PatchSettings {
+            base_rom: base_rom
+                .or(config.base_rom.clone())
+                .ok_or("no base ROM given, pass one or set it up with `init`")?,
+            patched_dir: PathBuf::from(&config.patched_dir),
+            fix_checksum,
+            downloads_dir: PathBuf::from(&config.downloads_dir),
+            extract_dir: config.extract_root().map(PathBuf::from),
+            timeout: std::time::Duration::from_secs(config.item_timeout_secs),
+            fat32_safe: config.fat32_safe,
+            asar: config.asar.clone(),
+            names: config.names.clone(),
+            cancel: cancel.clone(),
+        })
+    }
+}
+
+pub fn patch_in_dir(
+    settings: &PatchSettings,
+    ov: Option<&Override>,
+    entry: &DirEntry,
+    log: &mut dyn Write,
+) -> ResultErr<Option<Patched>> {
+    let ov = ov.cloned().unwrap_or_default();
+    if let Some((_, why)) = patch_skip(settings, &ov, entry)? {
+        writeln!(log, "skipping {:?}, {}", entry.path(), why)?;
+        return Ok(None);
+    }
+    let codes = if is_txt_file(entry) {
+        let codes = codes::parse_code_list(&String::from_utf8_lossy(&fs::read(entry.path())?));
+        if codes.is_empty() {
+            return Ok(None);
+        }
+        if has_patch_siblings(entry.path())? {
+            writeln!(
+                log,
+                "skipping {:?}, codes next to a patch are extras for it",
+                entry.path()
+            )?;
+            return Ok(None);
+        }
+        Some(codes)
+    } else {
+        None
+    };
+    let base_rom = ov.base_rom.as_deref().unwrap_or(&settings.base_rom);
+    let deadline = Deadline::after(settings.timeout, &settings.cancel);
+
+    let dir_path = entry.path().parent().ok_or("bad path")?;
+    // Patches from the extract directory land where they would have if
+    // they had been extracted inside the downloads directory
+    let mirror_path = match &settings.extract_dir {
+        Some(extract_dir) if dir_path.starts_with(extract_dir) => settings
+            .downloads_dir
+            .join(dir_path.strip_prefix(extract_dir)?),
+        _ => dir_path.to_path_buf(),
+    };
+    let mut rom_file = PathBuf::new();
+    rom_file.push(&settings.patched_dir);
+    // Only the normal components, so an absolute downloads directory still
+    // ends up inside the patched directory
+    for component in mirror_path.components() {
+        if let Component::Normal(part) = component {
+            if settings.fat32_safe {
+                rom_file.push(fat32_safe_name(&part.to_string_lossy()));
+            } else {
+                rom_file.push(part);
+            }
+        }
+    }
+    fs::create_dir_all(&rom_file)?;
+    // A base ROM named without an extension still makes .sfc files
+    let extension = Path::new(base_rom)
+        .extension()
+        .map(|e| e.to_string_lossy())
+        .unwrap_or("sfc".into());
+    let file_name = match &ov.output_name {
+        Some(name) => format!("{}.{}", name, extension),
+        None => {
+            let stem = Path::new(entry.file_name()).file_stem().unwrap_or_default();
+            format!("{}.{}", stem.to_string_lossy(), extension)
+        }
+    };
+    let file_name = settings.names.apply(&file_name);
+    if settings.fat32_safe {
+        rom_file.push(fat32_safe_name(&file_name));
+    } else {
+        rom_file.push(file_name);
+    }
+
+    writeln!(
+        log,
+        "Applying {} to create {}, in {}",
+        entry.path().to_str().unwrap_or("error"),
+        rom_file.to_str().unwrap_or("error"),
+        dir_path.to_str().unwrap_or("error"),
+    )?;
+
+    // Create a clean copy of the rom
+    writeln!(log, "Copying {:#?} to {:#?}", base_rom, &rom_file)?;
+    fs::copy(base_rom, &rom_file)?;
+    // Ensure that we can write to it
+    writeln!(log, "Setting permissions on {:#?}", &rom_file)?;
+    let mut perms = fs::metadata(&rom_file)?.permissions();
+    #[allow(clippy::permissions_set_readonly_false)]
+    perms.set_readonly(false);
+    fs::set_permissions(&rom_file, perms)?;
+
+    // Add or remove the copier header when the patch was made the other way
+    if let Some(headered) = ov.headered {
+        let mut contents = fs::read(&rom_file)?;
+        let has_header = rom::has_copier_header(contents.len());
+        if headered && !has_header {
+            writeln!(log, "Adding a copier header to {:#?}", &rom_file)?;
+            contents.splice(0..0, [0u8; rom::COPIER_HEADER_SIZE]);
+            fs::write(&rom_file, contents)?;
+        } else if !headered && has_header {
+            writeln!(log, "Removing the copier header from {:#?}", &rom_file)?;
+            contents.drain(..rom::COPIER_HEADER_SIZE);
+            fs::write(&rom_file, contents)?;
+        }
+    }
+
+    let len = fs::metadata(&rom_file)?.len();
+    let asar_output = if is_asm_file(entry) {
+        Some(run_asar(settings, entry.path(), &rom_file, log)?)
+    } else if let Some(codes) = &codes {
+        let mut contents = fs::read(&rom_file)?;
+        if codes::apply_codes(&mut contents, codes, log)? == 0 {
+            fs::remove_file(&rom_file)?;
+            return Err("none of the codes change the ROM".into());
+        }
+        fs::write(&rom_file, contents)?;
+        None
+    } else if is_bps_file(entry) || is_ups_file(entry) || is_xdelta_file(entry) {
+        patch::apply_checked(entry.path(), &rom_file, deadline, log)?;
+        None
+    } else {
+        patch::apply_ips(entry.path(), &rom_file, deadline, log)?;
+        None
+    };
+    let final_len = fs::metadata(&rom_file)?.len();
+    let resized = match final_len.cmp(&len) {
+        std::cmp::Ordering::Greater => Some(Resize::Expanded),
+        std::cmp::Ordering::Less => Some(Resize::Truncated),
+        std::cmp::Ordering::Equal => None,
+    };
+
+    if settings.fix_checksum {
+        let mut contents = fs::read(&rom_file)?;
+        if rom::fix_checksum(&mut contents) {
+            writeln!(log, "Fixing the header checksum of {:#?}", &rom_file)?;
+            fs::write(&rom_file, contents)?;
+        } else {
+            writeln!(
+                log,
+                "{:#?} is too small to have a header checksum",
+                &rom_file
+            )?;
+        }
+    }
+
+    let (base, patched) = (fs::read(base_rom)?, fs::read(&rom_file)?);
+    Ok(Some(Patched {
+        rom: rom_file,
+        base_sha256: sha256_file(base_rom)?,
+        resized,
+        asar_output,
+        bytes_changed: rom::bytes_changed(&base, &patched),
+        fingerprint: rom::fingerprint(&base, &patched),
+    }))
+}
+
+// Why a patch is passed over without looking inside it, if it is
+fn patch_skip(
+    settings: &PatchSettings,
+    ov: &Override,
+    entry: &DirEntry,
+) -> ResultErr<Option<(Skip, String)>> {
+    if ov.skip {
+        return Ok(Some((
+            Skip::Quarantined,
+            "disabled in overrides".to_owned(),
+        )));
+    }
+    if let Some(variant) = &ov.patch {
+        if entry.file_name().to_string_lossy() != variant.as_str() {
+            let why = format!("overrides select {} instead", variant);
+            return Ok(Some((Skip::FilteredOut, why)));
+        }
+    }
+    if is_asm_file(entry) {
+        if settings.asar.is_none() {
+            return Ok(Some((Skip::FilteredOut, "no asar configured".to_owned())));
+        }
+        if is_asm_include(entry.path())? {
+            return Ok(Some((
+                Skip::FilteredOut,
+                "included by another patch".to_owned(),
+            )));
+        }
+    }
+    Ok(None)
+}
+
+// Whether a code list sits next to an actual patch
+fn has_patch_siblings(path: &Path) -> ResultErr<bool> {
+    let dir = path.parent().ok_or("bad path")?;
+    for sibling in fs::read_dir(dir)?.filter_map(|d| d.ok()).map(|d| d.path()) {
+        let is_patch = sibling.extension().is_some_and(|e| {
+            ["ips", "bps", "ups", "xdelta", "vcdiff", "asm"]
+                .iter()
+                .any(|p| e.eq_ignore_ascii_case(p))
+        });
+        if is_patch {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// Hacks that ship several .asm files usually have a main one pulling in the
+// rest with `incsrc`, and only that one can be assembled on its own
+fn is_asm_include(path: &Path) -> ResultErr<bool> {
+    let name = path
+        .file_name()
+        .ok_or("bad path")?
+        .to_string_lossy()
+        .to_lowercase();
+    let dir = path.parent().ok_or("bad path")?;
+    // Includes can sit in a subdirectory of the main file
+    for dir in [Some(dir), dir.parent()].into_iter().flatten() {
+        for sibling in fs::read_dir(dir)?.filter_map(|d| d.ok()).map(|d| d.path()) {
+            let is_asm = sibling
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("asm"));
+            if sibling == path || !is_asm {
+                continue;
+            }
+            let text = String::from_utf8_lossy(&fs::read(&sibling)?).to_lowercase();
+            let includes = text.lines().any(|l| {
+                let l = l.trim_start();
+                (l.starts_with("incsrc") || l.starts_with("incbin")) && l.contains(&name)
+            });
+            if includes {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+// asar assembles straight into the ROM, what's left is keeping what it said
+fn run_asar(
+    settings: &PatchSettings,
+    patch_file: &Path,
+    rom_file: &Path,
+    log: &mut dyn Write,
+) -> ResultErr<String> {
+    let asar = settings.asar.as_deref().ok_or("no asar configured")?;
+    writeln!(log, "Running {} {:?} {:?}", asar, patch_file, rom_file)?;
+    let output = std::process::Command::new(asar)
+        .arg(patch_file)
+        .arg(rom_file)
+        .output()
+        .map_err(|e| format!("cannot run {}: {}", asar, e))?;
+    let text = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    )
+    .trim()
+    .to_owned();
+    writeln!(log, "{}", text)?;
+    if !output.status.success() {
+        writeln!(log, "Removing partially patched {:#?}", rom_file)?;
+        fs::remove_file(rom_file)?;
+        let last = text.lines().last().unwrap_or_default();
+        return Err(format!("asar failed with {}: {}", output.status, last).into());
+    }
+    Ok(text)
+}
+
+// Point out the mirrored hacks whose pages say they need a different base
+// ROM than the one they're about to be patched onto, going by the catalog
+fn warn_unmet_requirements(base_rom: &str, log: &mut dyn Write) -> ResultErr<()> {
+    // Patching reports an unreadable base ROM itself
+    let Ok(base_crc) = rom::unheadered_crc32(base_rom) else {
+        return Ok(());
+    };
+    let catalog = Catalog::load(CATALOG_FILE)?;
+    let manifest = Manifest::load(MANIFEST_FILE)?;
+    for (id, hack) in &catalog.hacks {
+        if !manifest.hacks.contains_key(id) {
+            continue;
+        }
+        for why in hack
+            .requires
+            .iter()
+            .filter_map(|r| rom::unmet_requirement(r, base_crc))
+        {
+            let args = [
+                ("title", hack.title.as_str().into()),
+                ("id", (*id).into()),
+                ("why", why.into()),
+            ];
+            println!("{}", output::skip(i18n::tr("patch-unmet", &args)));
+            writeln!(
+                log,
+                "{} (id {}) {}, its patched ROM may not work",
+                hack.title, id, why
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// A switch to another base ROM, for redoing only the patches it changes
+pub struct BaseSwap {
+    pub old_sha256: String,
+    pub new_sha256: String,
+    // Where the two base ROMs differ
+    pub diff: Vec<std::ops::Range<usize>>,
+}
+
+impl BaseSwap {
+    // None when the ROMs aren't the same size, which moves everything
+    pub fn new(old_rom: &str, new_rom: &str) -> ResultErr<Option<BaseSwap>> {
+        let (old, new) = (fs::read(old_rom)?, fs::read(new_rom)?);
+        let Some(diff) = rom::differing_ranges(&old, &new) else {
+            return Ok(None);
+        };
+        Ok(Some(BaseSwap {
+            old_sha256: sha256_file(old_rom)?,
+            new_sha256: sha256_file(new_rom)?,
+            diff,
+        }))
+    }
+
+    // The ROM `patch` made on the old base ROM, when it would come out the
+    // same on the new one because the patch overwrites every byte where the
+    // two differ. Only plain IPS patches can tell, assembly and codes may
+    // read anything.
+    pub fn unaffected(
+        &self,
+        outputs: &Outputs,
+        ov: Option<&Override>,
+        patch: &DirEntry,
+    ) -> ResultErr<Option<String>> {
+        let own_base = ov.is_some_and(|o| o.base_rom.is_some() || o.headered.is_some());
+        if !is_ips_file(patch) || own_base {
+            return Ok(None);
+        }
+        let Some(entry) = outputs.for_patch(patch.path()) else {
+            return Ok(None);
+        };
+        if entry.base_sha256.as_deref() != Some(self.old_sha256.as_str())
+            || !Path::new(&entry.output).exists()
+            || entry.patch_sha256 != sha256_file(patch.path())?
+        {
+            return Ok(None);
+        }
+        let contents = fs::read(patch.path())?;
+        let ips = Patch::parse(&contents)?;
+        let writes: Vec<_> = ips
+            .hunks()
+            .iter()
+            .map(|h| h.offset()..h.offset() + h.payload().len())
+            .collect();
+        Ok(rom::covers(&writes, &self.diff).then(|| entry.output.clone()))
+    }
+}
+
+pub fn patch(
+    pa: PatchArgs,
+    config: &Config,
+    overrides: &Overrides,
+    summary: &Summary,
+    cancel: &Cancel,
+    log: &mut dyn Write,
+) -> ResultErr<()> {
+    let settings = PatchSettings::from_config(config, pa.base_rom, pa.fix_checksum, cancel)?;
+    if howto::nothing_downloaded(config) {
+        println!("{}", i18n::tr("nothing-downloaded", &[]));
+        return Ok(());
+    }
+    if howto::nothing_unzipped(&extract::ExtractSettings::from_config(config, cancel), log)? {
+        println!("{}", output::skip(i18n::tr("patch-not-unpacked", &[])));
+    }
+    warn_unmet_requirements(&settings.base_rom, log)?;
+    let swap = match &pa.since_base {
+        Some(old_rom) => {
+            let swap = BaseSwap::new(old_rom, &settings.base_rom)?;
+            match &swap {
+                Some(swap) => writeln!(log, "base ROMs differ in {} places", swap.diff.len())?,
+                None => println!("{}", i18n::tr("patch-base-size", &[])),
+            }
+            swap
+        }
+        None => None,
+    };
+    let manifest = Manifest::load(MANIFEST_FILE)?;
+    let randomizers: BTreeMap<u32, String> = match pa.randomizers {
+        true => BTreeMap::new(),
+        false => manifest
+            .hacks
+            .iter()
+            .filter(|(_, hack)| hack.randomizer)
+            .map(|(id, hack)| (*id, hack.title.clone().unwrap_or_else(|| hack.dir.clone())))
+            .collect(),
+    };
+    let mut left_out = BTreeSet::new();
+    let mut outputs = Outputs::load(OUTPUTS_FILE)?;
+    let mut changed = Vec::new();
+    let mut heartbeat = heartbeat::Heartbeat::start(
+        std::time::Duration::from_secs(config.heartbeat_secs),
+        std::time::Duration::from_secs(config.slow_item_secs),
+    );
+    let locks = Locks::new(config);
+    // Patches inside archives may have been extracted elsewhere
+    let mut roots = vec![settings.downloads_dir.clone()];
+    roots.extend(settings.extract_dir.clone());
+    for root in &roots {
+        let walked = process_directory(
+            |f, l| {
+                let _lock = match locks.path(root, f.path())? {
+                    Ok(lock) => lock,
+                    Err(holder) => {
+                        writeln!(l, "skipping {:?}, locked by {}", f.path(), holder)?;
+                        summary.skipped(f.path().display(), Skip::Locked);
+                        return Ok(());
+                    }
+                };
+                let hack_id = manifest::hack_id_of(root, f.path());
+                if let Some(id) = hack_id.filter(|id| randomizers.contains_key(id)) {
+                    writeln!(l, "skipping {:?}, a randomizer base patch", f.path())?;
+                    left_out.insert(id);
+                    summary.skipped(f.path().display(), Skip::FilteredOut);
+                    return Ok(());
+                }
+                let ov = overrides.for_path(root, f.path());
+                let skip = patch_skip(&settings, &ov.cloned().unwrap_or_default(), f)?;
+                if let Some((why, detail)) = skip {
+                    writeln!(l, "skipping {:?}, {}", f.path(), detail)?;
+                    summary.skipped(f.path().display(), why);
+                    return Ok(());
+                }
+                if let Some(swap) = &swap {
+                    if let Some(output) = swap.unaffected(&outputs, ov, f)? {
+                        writeln!(
+                            l,
+                            "skipping {:?}, it overwrites the base ROM change",
+                            f.path()
+                        )?;
+                        outputs.rebase(&output, &swap.new_sha256);
+                        summary.skipped(f.path().display(), Skip::UpToDate);
+                        return Ok(());
+                    }
+                }
+                let patched = heartbeat.track(f.path(), l, |l| {
+                    // Most text files aren't code lists, which isn't a skip
+                    let Some(patched) = patch_in_dir(&settings, ov, f, l)? else {
+                        return Ok(());
+                    };
+                    summary.processed(fs::metadata(&patched.rom).map(|m| m.len()).unwrap_or(0));
+                    summary.wrote(&patched.rom);
+                    let change = outputs.record(root, f.path(), &patched)?;
+                    if let Some(change) = change {
+                        let rom = patched.rom;
+                        writeln!(l, "{:?} differs from the last run: {:?}", rom, change)?;
+                        changed.push((rom, change));
+                    }
+                    Ok(())
+                });
+                match &patched {
+                    Err(e) if !e.is::<Cancelled>() => summary.failed(),
+                    _ => (),
+                }
+                patched
+            },
+            root,
+            any_dir,
+            |e| {
+                is_ips_file(e)
+                    || is_bps_file(e)
+                    || is_ups_file(e)
+                    || is_xdelta_file(e)
+                    || is_asm_file(e)
+                    || is_txt_file(e)
+            },
+            log,
+        );
+        if walked.is_err() {
+            // Keep track of what was patched before stopping
+            outputs.save(OUTPUTS_FILE)?;
+            heartbeat.report(log)?;
+            return walked;
+        }
+    }
+    outputs.save(OUTPUTS_FILE)?;
+    heartbeat.report(log)?;
+    if !changed.is_empty() {
+        println!(
+            "{}",
+            i18n::tr("patch-changed", &[("count", changed.len().into())])
+        );
+        for (rom, change) in changed {
+            let reason = match change {
+                OutputChange::PatchUpdated => i18n::tr("patch-updated", &[]),
+                OutputChange::OutputChanged => i18n::tr("patch-output-changed", &[]),
+            };
+            println!("  {}: {}", rom.to_string_lossy(), reason);
+        }
+    }
+    if !left_out.is_empty() {
+        let args = [("count", left_out.len().into())];
+        println!("{}", output::skip(i18n::tr("patch-randomizers", &args)));
+        for id in &left_out {
+            println!("  {} (id {})", randomizers[id], id);
+        }
+    }
+    Ok(())
+}
+
+// Fetch the file at `pa.url` into a directory of its own in the staging
+// directory, unpack and patch it there, and keep only the patched ROMs
+pub async fn patch_url(
+    pa: PatchUrlArgs,
+    config: Config,
+    summary: &Summary,
+    mut log: Reporter,
+) -> ResultErr<Vec<PathBuf>> {
+    let file_name = pa
+        .url
+        .split(['?', '#'])
+        .next()
+        .and_then(|u| u.rsplit_once('/'))
+        .map(|(_, name)| config.names.apply(name))
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| format!("{} doesn't end in a file name", pa.url))?;
+    let retries = http::RetryLog::default();
+    let client = http::client(&config, &retries)?;
+    println!(
+        "{}",
+        i18n::tr("downloading", &[("name", file_name.as_str().into())])
+    );
+    writeln!(log, "url: {}", pa.url)?;
+    let contents = client
+        .get(&pa.url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    retries.report(&mut log)?;
+
+    let work = Path::new(&config.staging_dir).join(format!("adhoc.{}", std::process::id()));
+    let downloads = work.join("downloads");
+    fs::create_dir_all(&downloads)?;
+    fs::write(downloads.join(&file_name), &contents)?;
+    let summary = summary.clone();
+    let patched = crate::run_blocking(move |cancel| {
+        let extract = extract::ExtractSettings {
+            downloads_dir: downloads.clone(),
+            extract_dir: None,
+            ..extract::ExtractSettings::from_config(&config, cancel)
+        };
+        let settings = PatchSettings {
+            patched_dir: work.join("patched"),
+            downloads_dir: downloads,
+            extract_dir: None,
+            ..PatchSettings::from_config(&config, pa.base_rom, pa.fix_checksum, cancel)?
+        };
+        let out_dir = Path::new(&config.patched_dir).join("adhoc");
+        let patched = patch_adhoc(&extract, &settings, &out_dir, &summary, &mut log);
+        fs::remove_dir_all(&work)?;
+        patched
+    })
+    .await?;
+    if patched.is_empty() {
+        return Err(format!("found nothing to patch in {}", file_name).into());
+    }
+    Ok(patched)
+}
+
+// Unpack whatever archives sit in the downloads directory of `extract`,
+// then apply every patch there and in them, moving the ROMs to `out_dir`
+pub fn patch_adhoc(
+    extract: &extract::ExtractSettings,
+    settings: &PatchSettings,
+    out_dir: &Path,
+    summary: &Summary,
+    log: &mut dyn Write,
+) -> ResultErr<Vec<PathBuf>> {
+    let downloads = &settings.downloads_dir;
+    for archive in matching_files(downloads, any_dir, is_archive_file, log)? {
+        extract::unarchive_in_dir(extract, None, &archive, log)?;
+    }
+    let patches = matching_files(
+        downloads,
+        any_dir,
+        |e| {
+            is_ips_file(e)
+                || is_bps_file(e)
+                || is_ups_file(e)
+                || is_xdelta_file(e)
+                || is_asm_file(e)
+                || is_txt_file(e)
+        },
+        log,
+    )?;
+    fs::create_dir_all(out_dir)?;
+    let mut roms = Vec::new();
+    for patch in patches {
+        if let Some((why, detail)) = patch_skip(settings, &Override::default(), &patch)? {
+            writeln!(log, "skipping {:?}, {}", patch.path(), detail)?;
+            summary.skipped(patch.path().display(), why);
+            continue;
+        }
+        let Some(patched) = patch_in_dir(settings, None, &patch, log)? else {
+            continue;
+        };
+        let rom = out_dir.join(patched.rom.file_name().ok_or("bad path")?);
+        // The staging and patched directories may be on different disks
+        fs::copy(&patched.rom, &rom)?;
+        summary.processed(fs::metadata(&rom)?.len());
+        summary.wrote(&rom);
+        roms.push(rom);
+    }
+    Ok(roms)
+}
+
+// Apply the patches of the hack in `hack_dir` like the patch mode would,
+// returning the ROMs made
+pub fn patch_hack(
+    settings: &PatchSettings,
+    overrides: &Overrides,
+    hack_dir: &str,
+    log: &mut dyn Write,
+) -> ResultErr<Vec<PathBuf>> {
+    let mut outputs = Outputs::load(OUTPUTS_FILE)?;
+    let mut roms = Vec::new();
+    let mut roots = vec![settings.downloads_dir.clone()];
+    roots.extend(settings.extract_dir.clone());
+    for root in &roots {
+        let dir = root.join(hack_dir);
+        if !dir.exists() {
+            continue;
+        }
+        process_directory(
+            |f, l| {
+                let ov = overrides.for_path(root, f.path());
+                if let Some(patched) = patch_in_dir(settings, ov, f, l)? {
+                    outputs.record(root, f.path(), &patched)?;
+                    roms.push(patched.rom);
+                }
+                Ok(())
+            },
+            &dir,
+            any_dir,
+            |e| {
+                is_ips_file(e)
+                    || is_bps_file(e)
+                    || is_ups_file(e)
+                    || is_xdelta_file(e)
+                    || is_asm_file(e)
+                    || is_txt_file(e)
+            },
+            log,
+        )?;
+    }
+    outputs.save(OUTPUTS_FILE)?;
+    if roms.is_empty() {
+        return Err(format!("no patch in {} could be applied", hack_dir).into());
+    }
+    Ok(roms)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::http::tests::{path_of, respond, serve};
+    use crate::utils::tests::TestDir;
+    use crate::{extract, filter, http, progress, rom, shared};
+    use proptest::prelude::*;
+
+    // Patching under `dir`: a base ROM at base.sfc, patches under
+    // downloads/ and the ROMs written to patched/
+    pub fn test_patch_settings(dir: &Path) -> PatchSettings {
+        PatchSettings {
+            base_rom: dir.join("base.sfc").to_string_lossy().to_string(),
+            patched_dir: dir.join("patched"),
+            fix_checksum: false,
+            downloads_dir: dir.join("downloads"),
+            extract_dir: None,
+            timeout: std::time::Duration::from_secs(60),
+            fat32_safe: false,
+            asar: None,
+            names: NameRules::default(),
+            cancel: Cancel::default(),
+        }
+    }
+
+    #[test]
+    fn patching_is_reproducible() {
+        let dir = TestDir::new("repro");
+        let hack_dir = dir.join("downloads").join("0001-5-Test");
+        fs::create_dir_all(&hack_dir).unwrap();
+        let base_rom = dir.join("base.sfc");
+        let rom: Vec<u8> = (0..0x10000u32).map(|i| (i * 7) as u8).collect();
+        fs::write(&base_rom, rom).unwrap();
+        // One plain hunk and one RLE hunk
+        fs::write(
+            hack_dir.join("test.ips"),
+            b"PATCH\x00\x00\x10\x00\x02AB\x00\x01\x00\x00\x00\x00\x40\xeaEOF",
+        )
+        .unwrap();
+
+        let mut runs = Vec::new();
+        for run in 0..2 {
+            let settings = PatchSettings {
+                patched_dir: dir.join(format!("patched{}", run)),
+                fix_checksum: true,
+                ..test_patch_settings(&dir)
+            };
+            let mut hashes = Vec::new();
+            process_directory(
+                |f, l| {
+                    if let Some(patched) = patch_in_dir(&settings, None, f, l)? {
+                        hashes.push(sha256_file(patched.rom)?);
+                    }
+                    Ok(())
+                },
+                dir.join("downloads"),
+                any_dir,
+                is_ips_file,
+                &mut std::io::sink(),
+            )
+            .unwrap();
+            runs.push(hashes);
+        }
+
+        assert_eq!(runs[0].len(), 1);
+        assert_eq!(runs[0], runs[1]);
+    }
+
+    #[test]
+    fn patches_for_expanded_roms_grow_the_output() {
+        let dir = TestDir::new("expand");
+        let hack_dir = dir.join("downloads").join("0001-5-Test");
+        fs::create_dir_all(&hack_dir).unwrap();
+        let base_rom = dir.join("base.sfc");
+        fs::write(&base_rom, vec![0u8; 0x8000]).unwrap();
+        // Writes two bytes at 0x10000, past the end of the base ROM
+        let expand = hack_dir.join("expand.ips");
+        fs::write(&expand, b"PATCH\x01\x00\x00\x00\x02ABEOF").unwrap();
+        // Writes at 0xff0000, past anything the SNES can map
+        let bogus = hack_dir.join("bogus.ips");
+        fs::write(&bogus, b"PATCH\xff\x00\x00\x00\x01AEOF").unwrap();
+
+        let settings = test_patch_settings(&dir);
+        let patch = |ips: &Path| {
+            let entry = walkdir::WalkDir::new(ips)
+                .into_iter()
+                .next()
+                .unwrap()
+                .unwrap();
+            patch_in_dir(&settings, None, &entry, &mut std::io::sink())
+        };
+        let expanded = patch(&expand).unwrap().unwrap();
+        let len = fs::metadata(&expanded.rom).unwrap().len();
+        let refused = patch(&bogus).is_err();
+        let leftover = expanded.rom.with_file_name("bogus.sfc").exists();
+
+        assert_eq!(len, 0x10002);
+        assert_eq!(expanded.resized, Some(Resize::Expanded));
+        assert!(refused);
+        assert!(!leftover);
+    }
+
+    #[test]
+    fn base_roms_without_an_extension_make_sfc_files() {
+        let dir = TestDir::new("noext");
+        let hack_dir = dir.join("downloads").join("0001-5-Test");
+        fs::create_dir_all(&hack_dir).unwrap();
+        let base_rom = dir.join("Super Metroid");
+        fs::write(&base_rom, vec![0u8; 0x8000]).unwrap();
+        let ips = hack_dir.join("hack.ips");
+        fs::write(&ips, b"PATCH\x00\x01\x00\x00\x02ABEOF").unwrap();
+
+        let settings = PatchSettings {
+            base_rom: base_rom.to_string_lossy().to_string(),
+            ..test_patch_settings(&dir)
+        };
+        let entry = walkdir::WalkDir::new(&ips)
+            .into_iter()
+            .next()
+            .unwrap()
+            .unwrap();
+        let patched = patch_in_dir(&settings, None, &entry, &mut std::io::sink());
+
+        assert_eq!(
+            patched.unwrap().unwrap().rom.file_name().unwrap(),
+            "hack.sfc"
+        );
+    }
+
+    #[test]
+    // The ranges are lists of spans, not spans to collect
+    #[allow(clippy::single_range_in_vec_init)]
+    fn base_swaps_only_redo_patches_that_leave_the_change_showing() {
+        let dir = TestDir::new("swap");
+        let hack_dir = dir.join("downloads").join("0001-5-Test");
+        fs::create_dir_all(&hack_dir).unwrap();
+        let (old_rom, new_rom) = (dir.join("old.sfc"), dir.join("new.sfc"));
+        let mut base = vec![0u8; 0x8000];
+        fs::write(&old_rom, &base).unwrap();
+        base[0x100] = 1;
+        fs::write(&new_rom, &base).unwrap();
+        // Overwrites the byte the base ROMs differ in, and leaves it alone
+        let covering = hack_dir.join("covering.ips");
+        fs::write(&covering, b"PATCH\x00\x01\x00\x00\x02ABEOF").unwrap();
+        let elsewhere = hack_dir.join("elsewhere.ips");
+        fs::write(&elsewhere, b"PATCH\x00\x02\x00\x00\x01CEOF").unwrap();
+
+        let settings = PatchSettings {
+            base_rom: old_rom.to_string_lossy().to_string(),
+            ..test_patch_settings(&dir)
+        };
+        let entry = |ips: &Path| {
+            walkdir::WalkDir::new(ips)
+                .into_iter()
+                .next()
+                .unwrap()
+                .unwrap()
+        };
+        let mut outputs = Outputs::default();
+        for ips in [&covering, &elsewhere] {
+            let patched = patch_in_dir(&settings, None, &entry(ips), &mut std::io::sink())
+                .unwrap()
+                .unwrap();
+            outputs
+                .record(&settings.downloads_dir, ips, &patched)
+                .unwrap();
+        }
+        let swap = BaseSwap::new(&settings.base_rom, &new_rom.to_string_lossy())
+            .unwrap()
+            .unwrap();
+        let unaffected = |ips: &Path| swap.unaffected(&outputs, None, &entry(ips)).unwrap();
+        let (kept, redone) = (unaffected(&covering), unaffected(&elsewhere));
+
+        assert_eq!(swap.diff, [0x100..0x101]);
+        assert!(kept.is_some_and(|output| output.ends_with("covering.sfc")));
+        assert_eq!(redone, None);
+        assert!(rom::covers(&[0..4, 4..8], &[2..6]));
+        assert!(!rom::covers(&[0..4, 5..8], &[2..6]));
+    }
+
+    #[test]
+    fn adhoc_patches_land_in_their_own_directory() {
+        let dir = TestDir::new("adhoc");
+        let downloads = dir.join("work").join("downloads");
+        fs::create_dir_all(&downloads).unwrap();
+        let base_rom = dir.join("base.sfc");
+        fs::write(&base_rom, vec![0u8; 0x8000]).unwrap();
+        fs::write(
+            downloads.join("linked.ips"),
+            b"PATCH\x00\x01\x00\x00\x02ABEOF",
+        )
+        .unwrap();
+        fs::write(downloads.join("readme.txt"), "Have fun!").unwrap();
+
+        let extract = extract::ExtractSettings {
+            downloads_dir: downloads.clone(),
+            extract_dir: None,
+            ..extract::ExtractSettings::from_config(&Config::default(), &Cancel::default())
+        };
+        let settings = PatchSettings {
+            patched_dir: dir.join("work").join("patched"),
+            downloads_dir: downloads,
+            ..test_patch_settings(&dir)
+        };
+        let out_dir = dir.join("patched").join("adhoc");
+        let summary = Summary::default();
+        let roms = patch_adhoc(
+            &extract,
+            &settings,
+            &out_dir,
+            &summary,
+            &mut std::io::sink(),
+        );
+        let patched = fs::read(out_dir.join("linked.sfc"));
+
+        assert_eq!(roms.unwrap(), [out_dir.join("linked.sfc")]);
+        assert_eq!(&patched.unwrap()[0x100..0x102], b"AB");
+        assert_eq!(summary.tally().processed, 1);
+    }
+
+    #[test]
+    fn download_plan_skips_before_fetching() {
+        let ids: Vec<String> = ["10", "20", "30", "40"].map(String::from).to_vec();
+        let config = Config {
+            blocklist: vec!["20".to_owned()],
+            ..Config::default()
+        };
+        let interrupted = progress::HackProgress {
+            id: 30,
+            title: Some("Hack 30".to_owned()),
+            files: vec!["https://example.com/30.zip".to_owned()],
+            done: Vec::new(),
+        };
+        let progress = Progress {
+            list: "list".to_owned(),
+            next: 1,
+            hack: Some(interrupted.clone()),
+        };
+        let listed = plan(
+            &ids,
+            false,
+            &config,
+            &Overrides::default(),
+            &filter::HackFilter::from_config(&config).unwrap(),
+            &Manifest::default(),
+            &progress,
+        )
+        .unwrap();
+        let plan: Vec<_> = listed
+            .iter()
+            .map(|h| (h.idx, h.id, h.skip, h.resume.clone()))
+            .collect();
+        assert_eq!(
+            plan,
+            vec![
+                (1, 20, Some(Skip::Blocklisted), None),
+                (2, 30, None, Some(interrupted)),
+                (3, 40, None, None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_fetches_hand_hacks_on_in_list_order() {
+        let config = Config::default();
+        let reasons = [Skip::UpToDate, Skip::Blocklisted, Skip::FilteredOut];
+        let listed: Vec<_> = (0..7)
+            .map(|idx| ListedHack {
+                idx,
+                id: 100 + idx as u32,
+                skip: Some(reasons[idx % reasons.len()]),
+                resume: None,
+            })
+            .collect();
+        let fetcher = Fetcher {
+            client: http::client(&config, &http::RetryLog::default()).unwrap(),
+            filter: std::sync::Arc::new(filter::HackFilter::default()),
+            locks: std::sync::Arc::new(shared::Locks::new(&config)),
+            reporter: Reporter::new(std::io::sink()),
+            config,
+        };
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let (fetched, handed_on) = tokio::join!(fetch(fetcher, 3, listed, tx), async {
+            let mut handed_on = Vec::new();
+            while let Some(Fetched::Skipped { idx, reason, .. }) = rx.recv().await {
+                handed_on.push((idx, reason));
+            }
+            handed_on
+        });
+        fetched.unwrap();
+        let expected: Vec<_> = (0..7).map(|idx| (idx, reasons[idx % 3])).collect();
+        assert_eq!(handed_on, expected);
+    }
+
+    #[tokio::test]
+    async fn the_hack_list_is_read_a_page_at_a_time() {
+        let list = |ids: std::ops::Range<usize>| {
+            let rows: String = ids
+                .map(|id| format!("<tr><td><a href=\"hack.php?id={}\">Hack</a></td></tr>", id))
+                .collect();
+            respond("200 OK", &format!("<table>{}</table>", rows))
+        };
+        // A site that pages its list, then one that ignores `page=`
+        let mut pages = [
+            list(0..1000),
+            list(1000..1003),
+            list(0..1000),
+            list(0..1000),
+        ]
+        .into_iter();
+        let (site, _) = serve(move |_| pages.next().unwrap()).await;
+
+        let config = Config {
+            site_url: site,
+            max_retries: 0,
+            page_delay_secs: 0,
+            ..Config::default()
+        };
+        let client = http::client(&config, &http::RetryLog::default()).unwrap();
+        let paged = fetch_hack_list(&config, &client).await.unwrap();
+        let unpaged = fetch_hack_list(&config, &client).await.unwrap();
+        let ids = listed_ids(&paged).unwrap();
+        assert_eq!(paged.len(), 2);
+        assert_eq!(ids.len(), 1003);
+        assert_eq!((ids[0].as_str(), ids[1002].as_str()), ("0", "1002"));
+        assert_eq!(unpaged.len(), 1);
+        assert_eq!(listed_ids(&unpaged).unwrap().len(), 1000);
+    }
+
+    #[tokio::test]
+    async fn repeated_download_links_fetch_each_file_once() {
+        let (site, requests) = serve(|request| {
+            let body = match path_of(request).as_str() {
+                "/hack.php?id=5" => {
+                    r#"<a href="download.php?id=5">Download</a>
+                    <a href="download.php?id=5">hack.zip</a>
+                    <a href="download.php?id=5&amp;mirror=1">Mirror</a>
+                    <a href="download.php?id=50">Another hack</a>"#
+                }
+                _ => {
+                    r#"<meta http-equiv="refresh" content="0; url=https://files.example/hack.zip">"#
+                }
+            };
+            respond("200 OK", body)
+        })
+        .await;
+
+        let config = Config {
+            site_url: site.clone(),
+            max_retries: 0,
+            page_delay_secs: 0,
+            ..Config::default()
+        };
+        let client = http::client(&config, &http::RetryLog::default()).unwrap();
+        let found = resolve_hack_files(&config, &client, 5).await.unwrap();
+        let paths: Vec<_> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| path_of(r))
+            .collect();
+        assert_eq!(
+            paths[1..],
+            ["/download.php?id=5", "/download.php?id=5&mirror=1"]
+        );
+        assert_eq!(found.files, ["https://files.example/hack.zip"]);
+        let links = vec![
+            format!("{}download.php?id=5", site),
+            format!("{}download.php?id=5&mirror=1", site),
+        ];
+        assert_eq!(found.links["https://files.example/hack.zip"], links);
+
+        let dir = TestDir::new("links");
+        let hack_dir = dir.join("0001-5-Test");
+        fs::create_dir_all(&hack_dir).unwrap();
+        let file = hack_dir.join("hack.zip");
+        fs::write(&file, b"zip").unwrap();
+        let mut manifest = Manifest::default();
+        manifest
+            .record_file(&dir, 5, None, "0001-5-Test", &file)
+            .unwrap();
+        manifest.record_links(&dir, 5, &file, &links).unwrap();
+        manifest.record_links(&dir, 5, &file, &links[..1]).unwrap();
+        // Downloading it again keeps what led to it
+        manifest
+            .record_file(&dir, 5, None, "0001-5-Test", &file)
+            .unwrap();
+        assert_eq!(manifest.hacks[&5].files[0].links, links);
+    }
+
+    #[derive(Debug, Clone)]
+    enum Edit {
+        Bytes(usize, Vec<u8>),
+        Fill(usize, usize, u8),
+    }
+
+    fn edits(rom_len: usize) -> impl Strategy<Value = Vec<Edit>> {
+        let bytes = (0..rom_len, proptest::collection::vec(any::<u8>(), 1..64))
+            .prop_map(|(offset, data)| Edit::Bytes(offset, data));
+        let fill = (0..rom_len, 1..256usize, any::<u8>())
+            .prop_map(|(offset, count, byte)| Edit::Fill(offset, count, byte));
+        proptest::collection::vec(prop_oneof![bytes, fill], 0..32)
+    }
+
+    // Edits are clipped to the ROM, and later ones win where they overlap
+    fn apply_edits(rom: &mut [u8], edits: &[Edit]) {
+        for edit in edits {
+            let (offset, data) = match edit {
+                Edit::Bytes(offset, data) => (*offset, data.clone()),
+                Edit::Fill(offset, count, byte) => (*offset, vec![*byte; *count]),
+            };
+            let end = (offset + data.len()).min(rom.len());
+            rom[offset..end].copy_from_slice(&data[..end - offset]);
+        }
+    }
+
+    // A ROM and a changed copy of it, which may have grown or shrunk
+    fn rom_and_target() -> impl Strategy<Value = (Vec<u8>, Vec<u8>)> {
+        let sizes = (1..4096usize, 1..8192usize);
+        (sizes.prop_flat_map(|(rom_len, target_len)| {
+            let rom = proptest::collection::vec(any::<u8>(), rom_len);
+            (rom, Just(target_len), edits(target_len))
+        }))
+        .prop_map(|(rom, target_len, edits)| {
+            let mut target = rom.clone();
+            target.resize(target_len, 0);
+            apply_edits(&mut target, &edits);
+            (rom, target)
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn made_patches_turn_the_base_rom_into_the_target((rom, target) in rom_and_target()) {
+            let dir = TestDir::new("prop");
+            let hack_dir = dir.join("downloads").join("0001-5-Test");
+            fs::create_dir_all(&hack_dir).unwrap();
+            let base_rom = dir.join("base.sfc");
+            fs::write(&base_rom, &rom).unwrap();
+            let ips = hack_dir.join("test.ips");
+            fs::write(&ips, patch::create_ips(&rom, &target).unwrap()).unwrap();
+
+            let settings = test_patch_settings(&dir);
+            let entry = walkdir::WalkDir::new(&ips).into_iter().next().unwrap().unwrap();
+            let output = patch_in_dir(&settings, None, &entry, &mut std::io::sink())
+                .unwrap()
+                .unwrap();
+            let patched = fs::read(output.rom).unwrap();
+
+            prop_assert_eq!(patched, target);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use crate::schema::{self, Migration};
+use crate::utils::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const PLAYED_FILE: &str = "played.json";
+
+const MIGRATIONS: &[Migration] = &[schema::unchanged];
+
+// When the user finished a hack, and what they made of it
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Play {
+    pub finished: String,
+    // Out of five, like the site's ratings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minutes: Option<u64>,
+}
+
+// The user's own record of the hacks they've finished, kept apart from
+// anything scraped
+#[derive(Debug, Default)]
+pub struct Played {
+    pub hacks: BTreeMap<u32, Play>,
+}
+
+impl Played {
+    pub fn load<P: AsRef<Path>>(fname: P) -> ResultErr<Played> {
+        if !fname.as_ref().exists() {
+            return Ok(Played::default());
+        }
+        let mut value = read_json(fname)?;
+        schema::upgrade(&mut value, MIGRATIONS, PLAYED_FILE)?;
+        let hacks = value.get("hacks").cloned().unwrap_or_else(|| json!({}));
+        Ok(Played {
+            hacks: serde_json::from_value(hacks)?,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, fname: P) -> ResultErr<()> {
+        let mut value = json!({ "hacks": self.hacks });
+        schema::stamp(&mut value, MIGRATIONS);
+        write_json(fname, &value)
+    }
+
+    // Marking a hack played again moves its date, and only replaces the
+    // rating and time when given
+    pub fn record(&mut self, id: u32, date: &str, rating: Option<f64>, minutes: Option<u64>) {
+        let play = self.hacks.entry(id).or_default();
+        play.finished = date.to_owned();
+        play.rating = rating.or(play.rating);
+        play.minutes = minutes.or(play.minutes);
+    }
+}
+
+pub fn parse_rating(text: &str) -> Result<f64, String> {
+    match text.parse::<f64>() {
+        Ok(rating) if (0.0..=5.0).contains(&rating) => Ok(rating),
+        _ => Err(format!("{:?} isn't a rating from 0 to 5", text)),
+    }
+}
+
+// Minutes in times like "3h", "1h30m", "45m" or "2:15"
+pub fn parse_time(text: &str) -> Result<u64, String> {
+    let invalid = || format!("{:?} isn't a time like 3h, 1h30m, 45m or 2:15", text);
+    let text = text.trim().to_lowercase();
+    if let Some((hours, minutes)) = text.split_once(':') {
+        let hours: u64 = hours.parse().map_err(|_| invalid())?;
+        let minutes: u64 = minutes.parse().map_err(|_| invalid())?;
+        return Ok(hours * 60 + minutes);
+    }
+    let (hours, rest) = match text.split_once('h') {
+        Some((hours, rest)) => (hours.parse::<u64>().map_err(|_| invalid())?, rest),
+        None => (0, text.as_str()),
+    };
+    let minutes = match rest.strip_suffix('m').unwrap_or(rest) {
+        "" if text.contains('h') => 0,
+        minutes => minutes.parse::<u64>().map_err(|_| invalid())?,
+    };
+    Ok(hours * 60 + minutes)
+}
+
+pub fn format_minutes(minutes: u64) -> String {
+    match (minutes / 60, minutes % 60) {
+        (0, m) => format!("{}m", m),
+        (h, 0) => format!("{}h", h),
+        (h, m) => format!("{}h{:02}m", h, m),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::Catalog;
+    use crate::records::HackMetadata;
+    use crate::stats;
+
+    #[test]
+    fn played_keeps_earlier_ratings_and_reads_times() {
+        assert_eq!(parse_time("3h"), Ok(180));
+        assert_eq!(parse_time("1h30m"), Ok(90));
+        assert_eq!(parse_time("45m"), Ok(45));
+        assert_eq!(parse_time("2:05"), Ok(125));
+        assert!(parse_time("soon").is_err());
+        assert!(parse_rating("5.5").is_err());
+        assert_eq!(format_minutes(125), "2h05m");
+
+        let mut played = Played::default();
+        played.record(756, "2026-10-01", Some(4.0), None);
+        played.record(756, "2026-10-16", None, Some(180));
+        played.record(12, "2026-09-01", None, None);
+        let mut catalog = Catalog::default();
+        catalog.hacks.insert(
+            756,
+            HackMetadata {
+                title: "Hyper Metroid".to_owned(),
+                rating: "4.8".to_owned(),
+                ..HackMetadata::default()
+            },
+        );
+        let rows = stats::played_stats(&played, &catalog);
+        assert_eq!(
+            rows[0],
+            stats::PlayedStats {
+                id: 756,
+                title: "Hyper Metroid".to_owned(),
+                finished: "2026-10-16".to_owned(),
+                rating: "4".to_owned(),
+                site_rating: "4.8".to_owned(),
+                time: "3h".to_owned(),
+            }
+        );
+        assert_eq!(rows[1].id, 12);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::schema::{self, Migration};
+use crate::utils::*;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+pub const PROGRESS_FILE: &str = "download-progress.json";
+
+const MIGRATIONS: &[Migration] = &[schema::unchanged];
+
+// The hack a download run was in the middle of: its title and the file URLs
+// its download pages resolved to, so resuming doesn't fetch those pages again
+#[derive(Debug, Clone, PartialEq)]
+pub struct HackProgress {
+    pub id: u32,
+    pub title: Option<String>,
+    pub files: Vec<String>,
+    pub done: Vec<String>,
+}
+
+// How far a download run got through the hack list. Only good for the same
+// list, since the position of a hack in it is part of its directory name.
+#[derive(Debug, Default, PartialEq)]
+pub struct Progress {
+    pub list: String,
+    pub next: usize,
+    pub hack: Option<HackProgress>,
+}
+
+// Fingerprint of the hack list a run works through
+pub fn list_hash(ids: &[&str]) -> String {
+    to_hex(&Sha256::digest(ids.join(",").as_bytes()))
+}
+
+impl Progress {
+    // Where to pick up for this hack list, from the top if the saved
+    // progress was for a different one
+    pub fn load<P: AsRef<Path>>(fname: P, list: &str) -> ResultErr<Progress> {
+        let fresh = Progress {
+            list: list.to_owned(),
+            ..Progress::default()
+        };
+        if !fname.as_ref().exists() {
+            return Ok(fresh);
+        }
+        let mut value = read_json(fname)?;
+        schema::upgrade(&mut value, MIGRATIONS, PROGRESS_FILE)?;
+        if json_str(&value, "list").as_deref() != Some(list) {
+            return Ok(fresh);
+        }
+        let hack = value.get("hack").and_then(|h| {
+            Some(HackProgress {
+                id: json_u64(h, "id")? as u32,
+                title: json_str(h, "title"),
+                files: json_str_list(h, "files"),
+                done: json_str_list(h, "done"),
+            })
+        });
+        Ok(Progress {
+            next: json_u64(&value, "next").unwrap_or(0) as usize,
+            hack,
+            ..fresh
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, fname: P) -> ResultErr<()> {
+        let hack = self
+            .hack
+            .as_ref()
+            .map(|h| json!({ "id": h.id, "title": h.title, "files": h.files, "done": h.done }));
+        let mut value = json!({ "list": self.list, "next": self.next, "hack": hack });
+        schema::stamp(&mut value, MIGRATIONS);
+        write_json(fname, &value)
+    }
+
+    // What was already worked out for `id`, if the run stopped inside it
+    pub fn resume_hack(&self, id: u32) -> Option<&HackProgress> {
+        self.hack.as_ref().filter(|h| h.id == id)
+    }
+
+    pub fn start_hack(&mut self, id: u32, title: Option<String>, files: Vec<String>) {
+        if self.resume_hack(id).is_none() {
+            self.hack = Some(HackProgress {
+                id,
+                title,
+                files,
+                done: Vec::new(),
+            });
+        }
+    }
+
+    pub fn file_done(&mut self, url: &str) {
+        if let Some(hack) = &mut self.hack {
+            hack.done.push(url.to_owned());
+        }
+    }
+
+    pub fn finish_hack(&mut self, next: usize) {
+        self.next = next;
+        self.hack = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::TestDir;
+
+    #[test]
+    fn download_progress_only_resumes_the_same_list() {
+        let dir = TestDir::new("progress");
+        let file = dir.join(PROGRESS_FILE);
+        let list = list_hash(&["12", "7", "300"]);
+        let mut saved = Progress::load(&file, &list).unwrap();
+        saved.finish_hack(1);
+        saved.start_hack(7, Some("Hack".to_owned()), vec!["a/x.zip".to_owned()]);
+        saved.file_done("a/x.zip");
+        saved.save(&file).unwrap();
+
+        assert_eq!(Progress::load(&file, &list).unwrap(), saved);
+        let other = list_hash(&["12", "8", "7", "300"]);
+        let fresh = Progress::load(&file, &other).unwrap();
+        assert_eq!((fresh.next, fresh.hack), (0, None));
+    }
+}
@@ -0,0 +1,206 @@
+use crate::catalog::{Catalog, CATALOG_FILE};
+use crate::config::Config;
+use crate::i18n;
+use crate::manifest::{Manifest, MANIFEST_FILE};
+use crate::status::human_size;
+use crate::utils::*;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+// Something a retention policy wants gone, and which policy
+struct Removal {
+    path: PathBuf,
+    bytes: u64,
+    reason: String,
+}
+
+fn size_of(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.metadata().ok())
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+        .sum()
+}
+
+fn is_archive_name(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .map(|e| e == "zip" || e == "rar" || e == "7z")
+        .unwrap_or(false)
+}
+
+fn sorted_dir(dir: &Path) -> ResultErr<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut entries: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|d| d.ok())
+        .map(|d| d.path())
+        .collect();
+    entries.sort();
+    Ok(entries)
+}
+
+fn old_extracted_trees(config: &Config, days: u64) -> ResultErr<Vec<Removal>> {
+    let cutoff = SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60);
+    let root = config.extract_root().unwrap_or(&config.downloads_dir);
+    let mut removals = Vec::new();
+    for hack_dir in sorted_dir(Path::new(root))? {
+        for tree in sorted_dir(&hack_dir)?.into_iter().filter(|t| t.is_dir()) {
+            if fs::metadata(&tree)?.modified()? < cutoff {
+                removals.push(Removal {
+                    bytes: size_of(&tree),
+                    path: tree,
+                    reason: i18n::tr("prune-old", &[("count", days.into())]),
+                });
+            }
+        }
+    }
+    Ok(removals)
+}
+
+// An archive a newer one in the same hack directory has replaced, along
+// with whatever was extracted from it
+fn old_archives(config: &Config) -> ResultErr<Vec<Removal>> {
+    let downloads = Path::new(&config.downloads_dir);
+    let mut removals = Vec::new();
+    for hack_dir in sorted_dir(downloads)? {
+        let mut archives = Vec::new();
+        for file in sorted_dir(&hack_dir)? {
+            if file.is_file() && is_archive_name(&file) {
+                archives.push((fs::metadata(&file)?.modified()?, file));
+            }
+        }
+        archives.sort();
+        let Some((_, latest)) = archives.pop() else {
+            continue;
+        };
+        let latest = latest.file_name().unwrap_or_default().to_string_lossy();
+        for (_, archive) in archives {
+            let reason = i18n::tr("prune-superseded", &[("name", latest.clone().into())]);
+            let stem = config
+                .names
+                .apply(&archive.file_stem().ok_or("bad path")?.to_string_lossy());
+            let extracted = match config.extract_root() {
+                Some(root) => Path::new(root)
+                    .join(hack_dir.strip_prefix(downloads)?)
+                    .join(&stem),
+                None => hack_dir.join(&stem),
+            };
+            if extracted.is_dir() {
+                removals.push(Removal {
+                    bytes: size_of(&extracted),
+                    path: extracted,
+                    reason: reason.clone(),
+                });
+            }
+            removals.push(Removal {
+                bytes: size_of(&archive),
+                path: archive,
+                reason,
+            });
+        }
+    }
+    Ok(removals)
+}
+
+fn low_rated_screenshots(config: &Config, below: f64) -> ResultErr<Vec<Removal>> {
+    let catalog = Catalog::load(CATALOG_FILE)?;
+    let mut removals = Vec::new();
+    for dir in sorted_dir(Path::new(&config.screenshots_dir))? {
+        let id: u32 = match dir.file_name().and_then(|n| n.to_str()?.parse().ok()) {
+            Some(id) => id,
+            None => continue,
+        };
+        // Unrated hacks are kept, there is nothing to judge them by
+        let rating = catalog
+            .hacks
+            .get(&id)
+            .and_then(|h| h.rating.parse::<f64>().ok());
+        if let Some(rating) = rating.filter(|r| *r < below) {
+            removals.push(Removal {
+                bytes: size_of(&dir),
+                path: dir,
+                reason: i18n::tr(
+                    "prune-low-rated",
+                    &[("rating", rating.into()), ("below", below.into())],
+                ),
+            });
+        }
+    }
+    Ok(removals)
+}
+
+// Apply the retention policies from the config, or with `dry_run` only
+// list what they would remove
+pub fn prune(config: &Config, dry_run: bool, log: &mut dyn Write) -> ResultErr<()> {
+    let mut removals = Vec::new();
+    if let Some(days) = config.prune_extracted_days {
+        removals.extend(old_extracted_trees(config, days)?);
+    }
+    if config.prune_old_archives {
+        if config.work_dir.is_some() {
+            return Err("the downloads directory is read-only while work_dir is set".into());
+        }
+        removals.extend(old_archives(config)?);
+    }
+    if let Some(below) = config.prune_screenshots_below {
+        removals.extend(low_rated_screenshots(config, below)?);
+    }
+    // A tree can be both old and extracted from a superseded archive
+    let mut seen = std::collections::HashSet::new();
+    removals.retain(|r| seen.insert(r.path.clone()));
+    if removals.is_empty() {
+        println!("{}", i18n::tr("prune-nothing", &[]));
+        return Ok(());
+    }
+
+    let downloads = Path::new(&config.downloads_dir);
+    let mut manifest = Manifest::load(MANIFEST_FILE)?;
+    let (verb, id) = match dry_run {
+        true => ("Would remove", "prune-would-remove"),
+        false => ("Removing", "prune-removing"),
+    };
+    for removal in &removals {
+        let args = [
+            ("path", format!("{:?}", removal.path).into()),
+            ("size", human_size(removal.bytes).into()),
+            ("why", removal.reason.as_str().into()),
+        ];
+        println!("{}", i18n::tr(id, &args));
+        writeln!(
+            log,
+            "{} {:?} ({} bytes): {}",
+            verb, removal.path, removal.bytes, removal.reason
+        )?;
+        if dry_run {
+            continue;
+        }
+        if removal.path.is_dir() {
+            fs::remove_dir_all(&removal.path)?;
+        } else {
+            fs::remove_file(&removal.path)?;
+            // Only archives are removed file by file, and they come out of the
+            // manifest too or `doctor` would report them as missing
+            manifest.remove_path(downloads, &removal.path)?;
+        }
+    }
+    let total = removals.iter().map(|r| r.bytes).sum();
+    if dry_run {
+        println!(
+            "{}",
+            i18n::tr("prune-would-free", &[("size", human_size(total).into())])
+        );
+    } else {
+        manifest.save(MANIFEST_FILE)?;
+        println!(
+            "{}",
+            i18n::tr("prune-freed", &[("size", human_size(total).into())])
+        );
+    }
+    Ok(())
+}
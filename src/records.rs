@@ -0,0 +1,184 @@
+use crate::utils::*;
+use serde::de::{MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+// The records the scraping and download modes produce, shared by the files
+// they're written to: the catalog and manifest JSON, metadata.csv and the
+// exports built from them
+
+// One row of the hacks list table: what it shows about a hack without
+// visiting the hack's own page
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HackListing {
+    pub id: u32,
+    pub title: String,
+    pub author: String,
+    pub date: String,
+    pub rating: String,
+}
+
+// What the metadata mode scraped for one hack, kept as the site shows it.
+// Fields are in the order metadata.csv has its columns.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HackMetadata {
+    pub title: String,
+    pub date: String,
+    pub author: String,
+    pub genre: String,
+    pub difficulty: String,
+    // What the hack page says it has to be patched onto, e.g. Project Base
+    pub requires: Vec<String>,
+    // Minor edit, halfhack or full hack, going by the patched ROMs
+    pub scope: String,
+    // Average time to finish and item collection, as the site rounds them
+    pub runtime: String,
+    pub collection: String,
+    pub rating: String,
+    // Whether the author is in each of the config's author groups, keyed
+    // `by_<group>`
+    #[serde(flatten)]
+    pub groups: BTreeMap<String, bool>,
+    // Only filled in by `metadata --forum`, and not counted as changes
+    pub forum_thread: String,
+    pub replies: String,
+    pub last_activity: String,
+    pub videos: Vec<String>,
+    pub screenshots: Vec<String>,
+}
+
+// A file the download mode mirrored
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DownloadRecord {
+    // Relative to the downloads directory and always '/' separated, so the
+    // manifest reads the same on every platform
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+    // IPFS CIDv1, only filled in once the mirror has been exported for IPFS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cid: Option<String>,
+    // When the file was written, as an RFC 3339 UTC timestamp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub downloaded: Option<String>,
+    // The links on the hack's page that led to the file, each a variant
+    // of `download.php?id=`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<String>,
+}
+
+impl HackMetadata {
+    // Human readable `field: old → new` descriptions of what changed
+    pub fn changes_from(&self, old: &HackMetadata) -> Vec<String> {
+        let fields = [
+            ("title", &old.title, &self.title),
+            ("release date", &old.date, &self.date),
+            ("author", &old.author, &self.author),
+            ("genre", &old.genre, &self.genre),
+            ("difficulty", &old.difficulty, &self.difficulty),
+            ("rating", &old.rating, &self.rating),
+        ];
+        fields
+            .iter()
+            .filter(|(_, old, new)| old != new)
+            .map(|(name, old, new)| {
+                let or_none = |s: &str| {
+                    if s.is_empty() {
+                        "none".to_owned()
+                    } else {
+                        s.to_owned()
+                    }
+                };
+                format!("{}: {} → {}", name, or_none(old), or_none(new))
+            })
+            .collect()
+    }
+}
+
+// A record's fields in the order they're declared, which a JSON object
+// doesn't keep
+struct Fields(Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for Fields {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Fields, D::Error> {
+        struct FieldsVisitor;
+        impl<'de> Visitor<'de> for FieldsVisitor {
+            type Value = Fields;
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a record")
+            }
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Fields, A::Error> {
+                let mut fields = Vec::new();
+                while let Some(field) = map.next_entry()? {
+                    fields.push(field);
+                }
+                Ok(Fields(fields))
+            }
+        }
+        deserializer.deserialize_map(FieldsVisitor)
+    }
+}
+
+fn fields<T: Serialize>(record: &T) -> ResultErr<Vec<(String, Value)>> {
+    let Fields(fields) = serde_json::from_str(&serde_json::to_string(record)?)?;
+    Ok(fields)
+}
+
+fn csv_line(cells: impl Iterator<Item = String>) -> String {
+    cells
+        .map(|cell| format!("\"{}\"", cell.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// The header line of a CSV file of `T`s, one column per field
+pub fn csv_header<T: Serialize + Default>() -> ResultErr<String> {
+    csv_header_like(&T::default())
+}
+
+// The header line for records whose columns depend on the config, taken
+// from one of them
+pub fn csv_header_like<T: Serialize>(record: &T) -> ResultErr<String> {
+    Ok(csv_line(fields(record)?.into_iter().map(|(name, _)| name)))
+}
+
+// A record as a CSV line. Lists are joined with spaces, and flags written Y/N.
+pub fn csv_row<T: Serialize>(record: &T) -> ResultErr<String> {
+    Ok(csv_line(fields(record)?.into_iter().map(|(_, value)| {
+        match value {
+            Value::String(s) => s,
+            Value::Bool(b) => (if b { "Y" } else { "N" }).to_owned(),
+            Value::Null => String::new(),
+            Value::Array(items) => items
+                .iter()
+                .map(|i| i.as_str().map(|s| s.to_owned()).unwrap_or(i.to_string()))
+                .collect::<Vec<_>>()
+                .join(" "),
+            other => other.to_string(),
+        }
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_csv_columns_follow_the_record() {
+        let hack = HackMetadata {
+            title: "Hyper \"Metroid\", Deluxe".to_owned(),
+            rating: "4.5".to_owned(),
+            groups: [("by_pedro".to_owned(), true)].into(),
+            videos: vec!["a".to_owned(), "b".to_owned()],
+            ..HackMetadata::default()
+        };
+        let header = csv_header_like(&hack).unwrap();
+        let row = csv_row(&hack).unwrap();
+        assert!(header.starts_with("\"title\",\"date\",\"author\","));
+        assert_eq!(header.split(',').count(), row.split("\",\"").count());
+        assert!(row.starts_with("\"Hyper \"\"Metroid\"\", Deluxe\",\"\","));
+        assert!(row.ends_with(",\"4.5\",\"Y\",\"\",\"\",\"\",\"a b\",\"\""));
+    }
+}
@@ -0,0 +1,110 @@
+use crate::utils::*;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+// A mode's log and progress bars, as a handle its workers each get a clone
+// of. Every handle holds on to what it's given until the end of a line, so
+// lines from different workers never run into each other in the log, and
+// the bars are drawn together rather than over one another.
+pub struct Reporter {
+    log: Arc<Mutex<Box<dyn Write + Send>>>,
+    bars: MultiProgress,
+    line: Vec<u8>,
+}
+
+impl Clone for Reporter {
+    fn clone(&self) -> Reporter {
+        Reporter {
+            log: self.log.clone(),
+            bars: self.bars.clone(),
+            line: Vec::new(),
+        }
+    }
+}
+
+impl Reporter {
+    pub fn new(log: impl Write + Send + 'static) -> Reporter {
+        Reporter {
+            log: Arc::new(Mutex::new(Box::new(log))),
+            bars: MultiProgress::new(),
+            line: Vec::new(),
+        }
+    }
+
+    // Logs to `fname` in the log directory, like `open_log`
+    pub fn open(fname: &str) -> ResultErr<Reporter> {
+        Ok(Reporter::new(open_log(fname)?))
+    }
+
+    // The bar for the run as a whole
+    pub fn bar(&self, len: u64) -> ProgressBar {
+        self.bars.add(ProgressBar::new(len))
+    }
+
+    // A spinner for one worker, with what it's on as its message. Cleared
+    // with `finish_and_clear` once the worker is done.
+    pub fn worker(&self, name: &str) -> ProgressBar {
+        let spinner = self.bars.add(ProgressBar::new_spinner());
+        if let Ok(style) = ProgressStyle::with_template("{spinner} {prefix}: {wide_msg}") {
+            spinner.set_style(style);
+        }
+        spinner.set_prefix(name.to_owned());
+        spinner
+    }
+
+    // Prints above the bars instead of through them
+    pub fn println(&self, message: impl AsRef<str>) {
+        if self.bars.is_hidden() || self.bars.println(message.as_ref()).is_err() {
+            println!("{}", message.as_ref());
+        }
+    }
+}
+
+impl Write for Reporter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        if let Some(end) = self.line.iter().rposition(|&b| b == b'\n') {
+            let rest = self.line.split_off(end + 1);
+            let lines = std::mem::replace(&mut self.line, rest);
+            self.log.lock().unwrap().write_all(&lines)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut log = self.log.lock().unwrap();
+        log.write_all(&std::mem::take(&mut self.line))?;
+        log.flush()
+    }
+}
+
+impl Drop for Reporter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::TestDir;
+    use std::fs;
+
+    #[test]
+    fn reporter_keeps_lines_from_different_workers_whole() {
+        let dir = TestDir::new("reporter");
+        let fname = dir.join("log.txt");
+        let log = Reporter::new(fs::File::create(&fname).unwrap());
+        let (mut fetch, mut store) = (log.clone(), log.clone());
+        write!(fetch, "fetching ").unwrap();
+        writeln!(store, "stored a.ips").unwrap();
+        writeln!(fetch, "b.ips").unwrap();
+        write!(store, "stored b.ips").unwrap();
+        drop((log, fetch, store));
+        assert_eq!(
+            fs::read_to_string(&fname).unwrap(),
+            "stored a.ips\nfetching b.ips\nstored b.ips"
+        );
+    }
+}
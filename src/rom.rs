@@ -0,0 +1,250 @@
+use crate::utils::*;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+// CRC32 of an unheadered Super Metroid (JU) ROM
+pub const SM_CRC32: u32 = 0xd63ed5f8;
+
+// Largest ROM any SNES memory map (ExHiROM) can address
+pub const MAX_ROM_SIZE: usize = 8 * 1024 * 1024;
+
+// Copier headers add 512 bytes in front of the actual ROM data
+pub const COPIER_HEADER_SIZE: usize = 512;
+
+pub fn has_copier_header(len: usize) -> bool {
+    len % 1024 == COPIER_HEADER_SIZE
+}
+
+// CRC32 of the ROM data with any copier header stripped off
+pub fn unheadered_crc32<P: AsRef<Path>>(path: P) -> ResultErr<u32> {
+    let contents = fs::read(path)?;
+    let data = if has_copier_header(contents.len()) {
+        &contents[COPIER_HEADER_SIZE..]
+    } else {
+        &contents[..]
+    };
+    Ok(crc32fast::hash(data))
+}
+
+pub fn without_header(data: &[u8]) -> &[u8] {
+    match has_copier_header(data.len()) {
+        true => &data[COPIER_HEADER_SIZE..],
+        false => data,
+    }
+}
+
+// How many bytes of the ROM data differ between two images, copier headers
+// aside. Whatever one has past the end of the other all counts.
+pub fn bytes_changed(base: &[u8], patched: &[u8]) -> u64 {
+    let (base, patched) = (without_header(base), without_header(patched));
+    let differing = base.iter().zip(patched).filter(|(a, b)| a != b).count();
+    (differing + base.len().abs_diff(patched.len())) as u64
+}
+
+// Which 32 KiB banks a patch changed and what they hold now, as
+// `bank:crc32` pairs. Two patches that rework the game the same way have
+// mostly the same pairs, however they got there.
+pub fn fingerprint(base: &[u8], patched: &[u8]) -> String {
+    let (base, patched) = (without_header(base), without_header(patched));
+    patched
+        .chunks(0x8000)
+        .enumerate()
+        .filter(|(bank, data)| base.get(bank * 0x8000..bank * 0x8000 + data.len()) != Some(*data))
+        .map(|(bank, data)| format!("{}:{:08x}", bank, crc32fast::hash(data)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// The byte ranges where two images of the same size differ, counting any
+// copier header like IPS offsets do. None when their sizes differ.
+pub fn differing_ranges(a: &[u8], b: &[u8]) -> Option<Vec<Range<usize>>> {
+    if a.len() != b.len() {
+        return None;
+    }
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (i, _) in a.iter().zip(b).enumerate().filter(|(_, (x, y))| x != y) {
+        match ranges.last_mut() {
+            Some(range) if range.end == i => range.end = i + 1,
+            _ => ranges.push(i..i + 1),
+        }
+    }
+    Some(ranges)
+}
+
+// Whether `writes` between them cover every byte of `ranges`
+pub fn covers(writes: &[Range<usize>], ranges: &[Range<usize>]) -> bool {
+    let mut writes = writes.to_vec();
+    writes.sort_by_key(|w| w.start);
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for write in writes {
+        match merged.last_mut() {
+            Some(last) if write.start <= last.end => last.end = last.end.max(write.end),
+            _ => merged.push(write),
+        }
+    }
+    ranges
+        .iter()
+        .all(|r| merged.iter().any(|m| m.start <= r.start && r.end <= m.end))
+}
+
+// LoROM internal header fields, relative to the start of the ROM data
+const CHECKSUM_COMPLEMENT: usize = 0x7fdc;
+const CHECKSUM: usize = 0x7fde;
+
+// Where the internal header sits for each memory map
+const LOROM_HEADER: usize = 0x7fc0;
+const HIROM_HEADER: usize = 0xffc0;
+// Offsets into the internal header
+const MAP_MODE: usize = 0x15;
+const HEADER_COMPLEMENT: usize = 0x1c;
+const HEADER_CHECKSUM: usize = 0x1e;
+
+// Cartridge ROMs are a power of two, or two of them added up like the
+// 24 Mbit of Super Metroid itself, and at least one 32 KiB bank
+pub fn is_standard_size(len: usize) -> bool {
+    let len = if has_copier_header(len) {
+        len - COPIER_HEADER_SIZE
+    } else {
+        len
+    };
+    len >= 0x8000 && len.is_multiple_of(0x8000) && len.count_ones() <= 2
+}
+
+// A plausible internal header at `at`: its checksum and complement agree
+fn header_at(data: &[u8], at: usize) -> Option<u8> {
+    let header = data.get(at..at + 0x20)?;
+    let complement = u16::from_le_bytes([header[HEADER_COMPLEMENT], header[HEADER_COMPLEMENT + 1]]);
+    let checksum = u16::from_le_bytes([header[HEADER_CHECKSUM], header[HEADER_CHECKSUM + 1]]);
+    (complement ^ checksum == 0xffff).then_some(header[MAP_MODE])
+}
+
+// Interleaved dumps (Game Doctor and friends) store each 64 KiB HiROM bank
+// as two swapped 32 KiB halves, which puts the HiROM header where a LoROM
+// one would be
+pub fn is_interleaved(data: &[u8]) -> bool {
+    data.len().is_multiple_of(0x10000)
+        && header_at(data, HIROM_HEADER).is_none()
+        && header_at(data, LOROM_HEADER).is_some_and(|mode| mode & 1 == 1)
+}
+
+pub fn deinterleave(data: &[u8]) -> Vec<u8> {
+    let banks = data.len() / 0x10000;
+    let mut out = Vec::with_capacity(data.len());
+    for bank in 0..banks {
+        let lower = (banks + bank) * 0x8000;
+        let upper = bank * 0x8000;
+        out.extend_from_slice(&data[lower..lower + 0x8000]);
+        out.extend_from_slice(&data[upper..upper + 0x8000]);
+    }
+    out
+}
+
+// Sum the way the SNES header checksum is defined: sizes that aren't a power
+// of two have their tail mirrored until it fills the next power of two
+fn mirror_sum(data: &[u8], mut mask: usize) -> u32 {
+    while mask > 0 && data.len() & mask == 0 {
+        mask >>= 1;
+    }
+    if mask == 0 {
+        return 0;
+    }
+    let mut sum = data[..mask]
+        .iter()
+        .fold(0u32, |acc, b| acc.wrapping_add(*b as u32));
+    let rest = &data[mask..];
+    if !rest.is_empty() {
+        let mut part = mirror_sum(rest, mask >> 1);
+        let mut len = rest.len();
+        while len < mask {
+            len += len;
+            part = part.wrapping_add(part);
+        }
+        sum = sum.wrapping_add(part);
+    }
+    sum
+}
+
+// Recompute the internal header checksum of a (LoROM) ROM image in place.
+// Returns false when the image is too small to have a header.
+pub fn fix_checksum(contents: &mut [u8]) -> bool {
+    let data = if has_copier_header(contents.len()) {
+        &mut contents[COPIER_HEADER_SIZE..]
+    } else {
+        contents
+    };
+    if data.len() < CHECKSUM + 2 {
+        return false;
+    }
+    data[CHECKSUM_COMPLEMENT..CHECKSUM_COMPLEMENT + 2].copy_from_slice(&[0xff, 0xff]);
+    data[CHECKSUM..CHECKSUM + 2].copy_from_slice(&[0x00, 0x00]);
+    let checksum = mirror_sum(data, 0x800000) as u16;
+    data[CHECKSUM_COMPLEMENT..CHECKSUM_COMPLEMENT + 2]
+        .copy_from_slice(&(checksum ^ 0xffff).to_le_bytes());
+    data[CHECKSUM..CHECKSUM + 2].copy_from_slice(&checksum.to_le_bytes());
+    true
+}
+
+// Why a base ROM with this CRC32 doesn't suit a hack whose page says it
+// requires `requirement`. Only vanilla (JU) ROMs can be told apart, so
+// anything else gets the benefit of the doubt.
+pub fn unmet_requirement(requirement: &str, base_crc: u32) -> Option<&'static str> {
+    let vanilla = base_crc == SM_CRC32;
+    match requirement {
+        "Project Base" if vanilla => Some("needs Project Base, but the base ROM is vanilla"),
+        "PAL" if vanilla => Some("needs the PAL release, but the base ROM is JU"),
+        "JU" if !vanilla => Some("needs a vanilla JU ROM, but the base ROM isn't one"),
+        _ => None,
+    }
+}
+
+pub fn check_base_rom<P: AsRef<Path>>(path: P) -> ResultErr<()> {
+    let crc = unheadered_crc32(&path)?;
+    if crc != SM_CRC32 {
+        return Err(format!(
+            "{} is not an unmodified Super Metroid (JU) ROM, CRC32 is {:08x} but should be {:08x}",
+            path.as_ref().to_string_lossy(),
+            crc,
+            SM_CRC32
+        )
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interleaved_hirom_images_are_restored() {
+        // 1 MiB HiROM image with a valid internal header
+        let mut hirom: Vec<u8> = (0..0x100000u32).map(|i| (i * 7 % 251) as u8).collect();
+        hirom[0xffd5] = 0x21;
+        hirom[0xffdc..0xffe0].copy_from_slice(&[0xcb, 0xed, 0x34, 0x12]);
+        assert!(!is_interleaved(&hirom));
+        assert!(is_standard_size(hirom.len() + COPIER_HEADER_SIZE));
+
+        let banks = hirom.len() / 0x10000;
+        let mut interleaved = vec![0; hirom.len()];
+        for (bank, data) in hirom.chunks(0x10000).enumerate() {
+            let lower = (banks + bank) * 0x8000;
+            let upper = bank * 0x8000;
+            interleaved[lower..lower + 0x8000].copy_from_slice(&data[..0x8000]);
+            interleaved[upper..upper + 0x8000].copy_from_slice(&data[0x8000..]);
+        }
+        assert!(is_interleaved(&interleaved));
+        assert_eq!(deinterleave(&interleaved), hirom);
+    }
+
+    #[test]
+    fn base_rom_requirements_only_flag_what_the_crc_rules_out() {
+        let vanilla = SM_CRC32;
+        let other = 0x1234_5678;
+        assert!(unmet_requirement("Project Base", vanilla).is_some());
+        assert!(unmet_requirement("Project Base", other).is_none());
+        assert!(unmet_requirement("JU", vanilla).is_none());
+        assert!(unmet_requirement("JU", other).is_some());
+        assert!(unmet_requirement("PAL", vanilla).is_some());
+    }
+}
@@ -0,0 +1,45 @@
+use crate::utils::*;
+use serde_json::{json, Value};
+
+const VERSION_KEY: &str = "schema_version";
+
+// Upgrades a document from version `n` to `n + 1`, where `n` is its index in
+// the migration list. Files written before versioning existed count as 0.
+pub type Migration = fn(&mut Value) -> ResultErr<()>;
+
+pub fn version(value: &Value) -> u64 {
+    json_u64(value, VERSION_KEY).unwrap_or(0)
+}
+
+pub fn current_version(migrations: &[Migration]) -> u64 {
+    migrations.len() as u64
+}
+
+// Stamp a freshly serialized document with the current version
+pub fn stamp(value: &mut Value, migrations: &[Migration]) {
+    value[VERSION_KEY] = json!(current_version(migrations));
+}
+
+// Bring `value` up to the current version, refusing documents written by a
+// newer release rather than misreading them
+pub fn upgrade(value: &mut Value, migrations: &[Migration], what: &str) -> ResultErr<()> {
+    let found = version(value);
+    let current = current_version(migrations);
+    if found > current {
+        return Err(format!(
+            "{} has schema version {}, but this release only understands up to {}; upgrade metconst-tool",
+            what, found, current
+        )
+        .into());
+    }
+    for migration in &migrations[found as usize..] {
+        migration(value)?;
+    }
+    stamp(value, migrations);
+    Ok(())
+}
+
+// For the first versioned release, where the layout itself didn't change
+pub fn unchanged(_: &mut Value) -> ResultErr<()> {
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use crate::outputs::Outputs;
+use std::collections::BTreeMap;
+
+// How much of the game a hack reworks, going by how many bytes its patch
+// changes in the base ROM. A room or two is a minor edit, a fair part of
+// the map a halfhack, and anything past that a full hack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    MinorEdit,
+    Halfhack,
+    FullHack,
+}
+
+// One LoROM bank, and a sixth of the 3 MiB game
+const HALFHACK_BYTES: u64 = 32 * 1024;
+const FULL_HACK_BYTES: u64 = 512 * 1024;
+
+impl Scope {
+    pub fn of(bytes_changed: u64) -> Scope {
+        match bytes_changed {
+            b if b < HALFHACK_BYTES => Scope::MinorEdit,
+            b if b < FULL_HACK_BYTES => Scope::Halfhack,
+            _ => Scope::FullHack,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::MinorEdit => "minor edit",
+            Scope::Halfhack => "halfhack",
+            Scope::FullHack => "full hack",
+        }
+    }
+}
+
+// Each patched hack's scope, by the biggest of its patches so that an
+// optional extra patch doesn't make a full hack look small
+pub fn hack_scopes(outputs: &Outputs) -> BTreeMap<u32, Scope> {
+    let mut scopes: BTreeMap<u32, Scope> = BTreeMap::new();
+    for entry in outputs.entries.values() {
+        let (Some(id), Some(bytes)) = (entry.hack_id, entry.bytes_changed) else {
+            continue;
+        };
+        let scope = Scope::of(bytes);
+        scopes
+            .entry(id)
+            .and_modify(|s| *s = (*s).max(scope))
+            .or_insert(scope);
+    }
+    scopes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outputs::tests::test_output_entry;
+    use crate::outputs::OutputEntry;
+    use crate::rom;
+
+    #[test]
+    fn hacks_are_scoped_by_their_biggest_patch() {
+        let base = vec![0u8; 0x300000];
+        let mut patched = base.clone();
+        patched[..100].fill(1);
+        // A copier header on one side only isn't a change
+        let mut headered = vec![0u8; rom::COPIER_HEADER_SIZE];
+        headered.extend_from_slice(&patched);
+        assert_eq!(rom::bytes_changed(&base, &headered), 100);
+        patched.resize(0x400000, 0xff);
+        assert_eq!(rom::bytes_changed(&base, &patched), 100 + 0x100000);
+
+        let mut outputs = Outputs::default();
+        for (output, id, bytes) in [
+            ("a.sfc", 1, 100),
+            ("b.sfc", 1, 600_000),
+            ("c.sfc", 2, 40_000),
+        ] {
+            let entry = OutputEntry {
+                hack_id: Some(id),
+                bytes_changed: Some(bytes),
+                ..test_output_entry(output)
+            };
+            outputs.entries.insert(output.to_owned(), entry);
+        }
+        let scopes = hack_scopes(&outputs);
+        assert_eq!(scopes[&1], Scope::FullHack);
+        assert_eq!(scopes[&2].as_str(), "halfhack");
+    }
+}
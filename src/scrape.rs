@@ -0,0 +1,520 @@
+use crate::records::{HackListing, HackMetadata};
+use crate::utils::*;
+use regex::Regex;
+use scraper::{Html, Selector};
+
+pub const SITE_URL: &str = "https://metroidconstruction.com/";
+
+// Everything the metadata mode reads off a single hack page
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HackPage {
+    pub title: String,
+    pub date: String,
+    pub author: String,
+    pub genre: String,
+    pub difficulty: String,
+    // What the page says the hack has to be patched onto
+    pub requires: Vec<String>,
+    pub runtime: String,
+    pub collection: String,
+    pub rating: String,
+    // Not every hack page shows a download counter
+    pub downloads: Option<u64>,
+    pub forum_thread: Option<String>,
+    // Gameplay videos linked or embedded on the page, as watch links
+    pub videos: Vec<String>,
+    // Absolute URLs of the hack's screenshots
+    pub screenshots: Vec<String>,
+}
+
+// Activity on a hack's forum thread, as of its last page
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForumStats {
+    pub replies: u64,
+    pub last_activity: Option<String>,
+}
+
+// A front page post and the hacks it links to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewsPost {
+    pub text: String,
+    pub hack_ids: Vec<u32>,
+}
+
+// Posts on the front page are content boxes like the ones on hack pages;
+// only the ones linking to a hack are kept
+pub fn parse_news(page: &str) -> ResultErr<Vec<NewsPost>> {
+    let document = Html::parse_document(page);
+    #[allow(non_snake_case)]
+    let underboxD = Selector::parse(".underboxD")?;
+    let ahref = Selector::parse("a")?;
+    // example: hack.php?id=756
+    let re = Regex::new(r"hack\.php\?id=([0-9]+)")?;
+
+    let mut posts = Vec::new();
+    for element in document.select(&underboxD) {
+        let mut hack_ids = Vec::new();
+        for e in element.select(&ahref) {
+            if let Some(href) = e.value().attr("href") {
+                for (_, [id]) in re.captures_iter(href).map(|c| c.extract()) {
+                    let id = id.parse()?;
+                    if !hack_ids.contains(&id) {
+                        hack_ids.push(id);
+                    }
+                }
+            }
+        }
+        if hack_ids.is_empty() {
+            continue;
+        }
+        let text = clean_text(&element.text().collect::<String>());
+        posts.push(NewsPost { text, hack_ids });
+    }
+    Ok(posts)
+}
+
+// Scraped text as it reads on the page: tags dropped, entities decoded and
+// runs of whitespace made one space. Some fields are cut out of the page's
+// HTML, and some authors type markup into their titles.
+pub fn clean_text(raw: &str) -> String {
+    let text = Html::parse_fragment(raw)
+        .root_element()
+        .text()
+        .collect::<String>();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn cell_text(element: scraper::ElementRef) -> String {
+    clean_text(&element.text().collect::<String>())
+}
+
+// The columns of the list table are told apart by their headers rather than
+// their position, which has changed with the site's sort options
+pub fn parse_hack_list(page: &str) -> ResultErr<Vec<HackListing>> {
+    let document = Html::parse_document(page);
+    let tr = Selector::parse("tr")?;
+    let th = Selector::parse("th")?;
+    let td = Selector::parse("td")?;
+    let ahref = Selector::parse("a")?;
+    let titled = Selector::parse("[title]")?;
+    // example: hack.php?id=756
+    let re = Regex::new(r"^hack\.php\?id=([0-9]+)$")?;
+    let rating_re = Regex::new(r"[0-9]+\.[0-9]+")?;
+
+    let mut columns = Vec::new();
+    let mut rows = Vec::new();
+    for row in document.select(&tr) {
+        let headers: Vec<_> = row
+            .select(&th)
+            .map(|h| cell_text(h).to_lowercase())
+            .collect();
+        if !headers.is_empty() {
+            columns = headers;
+            continue;
+        }
+        let cells: Vec<_> = row.select(&td).collect();
+        let link = cells.iter().flat_map(|c| c.select(&ahref)).find_map(|a| {
+            let id = re.captures(a.value().attr("href")?)?[1].parse().ok()?;
+            Some((id, cell_text(a)))
+        });
+        let Some((id, title)) = link else {
+            continue;
+        };
+        let mut entry = HackListing {
+            id,
+            title,
+            ..HackListing::default()
+        };
+        for (header, cell) in columns.iter().zip(&cells) {
+            if header.contains("author") {
+                entry.author = cell_text(*cell);
+            } else if header.contains("date") || header.contains("released") {
+                entry.date = cell_text(*cell);
+            } else if header.contains("rating") {
+                // Ratings are shown as orbs, with the number in a tooltip
+                let mut text = cell_text(*cell);
+                for e in cell.select(&titled) {
+                    text.push(' ');
+                    text.push_str(e.value().attr("title").unwrap_or(""));
+                }
+                if let Some(m) = rating_re.find(&text) {
+                    entry.rating = m.as_str().to_owned();
+                }
+            }
+        }
+        rows.push(entry);
+    }
+    Ok(rows)
+}
+
+// Why a field came out empty
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Missing {
+    // The label is on the page, so the parser failed to pick the value out
+    SelectorMiss,
+    // The site doesn't have the field for this hack
+    Absent,
+    UnparsableDate,
+    // Scraped, but not something the site can really have
+    Implausible(&'static str),
+}
+
+impl std::fmt::Display for Missing {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Missing::SelectorMiss => write!(f, "selector miss"),
+            Missing::Absent => write!(f, "absent from the page"),
+            Missing::UnparsableDate => write!(f, "unparsable date"),
+            Missing::Implausible(why) => write!(f, "left out, {}", why),
+        }
+    }
+}
+
+// Release dates as the site has written them over the years
+const DATE_FORMATS: &[&str] = &["%b %d, %Y", "%B %d, %Y", "%Y-%m-%d", "%m/%d/%Y"];
+
+pub fn parse_date(date: &str) -> Option<chrono::NaiveDate> {
+    DATE_FORMATS
+        .iter()
+        .find_map(|f| chrono::NaiveDate::parse_from_str(date, f).ok())
+}
+
+// Clear the values a hack can't really have, e.g. a rating past the five
+// stars the site gives out, so they don't end up in metadata.csv. Returns
+// what was wrong and the bad values, for the audit report.
+pub fn set_aside_anomalies(
+    hack: &mut HackMetadata,
+    today: chrono::NaiveDate,
+) -> Vec<(&'static str, Missing, String)> {
+    let mut found = Vec::new();
+    let rating = hack
+        .rating
+        .split_whitespace()
+        .next()
+        .map(|r| r.parse::<f64>());
+    if let Some(Ok(rating)) = rating {
+        if !(0.0..=5.0).contains(&rating) {
+            let why = Missing::Implausible("outside the 0 to 5 star range");
+            found.push(("rating", why, std::mem::take(&mut hack.rating)));
+        }
+    }
+    if parse_date(&hack.date).is_some_and(|d| d > today) {
+        let why = Missing::Implausible("in the future");
+        found.push(("date", why, std::mem::take(&mut hack.date)));
+    }
+    // Only hacks still in the works are sometimes posted anonymously
+    let finished = !hack.genre.is_empty() && !hack.genre.eq_ignore_ascii_case("incomplete");
+    if finished && hack.author.trim().is_empty() {
+        let why = Missing::Implausible("no author on a finished hack");
+        found.push(("author", why, String::new()));
+    }
+    found
+}
+
+pub struct PageParser {
+    // Relative links on the pages are resolved against this
+    site_url: String,
+    release_date_re: Regex,
+    author_re: Regex,
+    genre_re: Regex,
+    difficulty_re: Regex,
+    rating_re: Regex,
+    downloads_re: Regex,
+    // example: https://forum.metroidconstruction.com/index.php/topic,4520.0.html
+    topic_re: Regex,
+    reply_re: Regex,
+    posted_re: Regex,
+    youtube_re: Regex,
+    requires_re: Regex,
+}
+
+// The words hack pages use for what a hack has to be patched onto, and the
+// requirement they stand for
+const REQUIREMENTS: &[(&str, &str)] = &[
+    ("project base", "Project Base"),
+    ("pal", "PAL"),
+    ("european", "PAL"),
+    ("ntsc", "JU"),
+    ("ju", "JU"),
+];
+
+impl PageParser {
+    // A parser for pages from a copy of the site at `site_url`
+    pub fn for_site(site_url: &str) -> ResultErr<PageParser> {
+        Ok(PageParser {
+            site_url: site_url.to_owned(),
+            // Release date:
+            release_date_re: Regex::new(r"<b>Release date:</b>(.*)")?,
+            // Author:
+            author_re: Regex::new("<b>Author:</b> <a href=\".*\">(.*)</a>")?,
+            // Genre:
+            genre_re: Regex::new("<b>Genre:</b> (.*) <")?,
+            // Difficulty:
+            difficulty_re: Regex::new("<b>Difficulty:</b> (.*) <")?,
+            rating_re: Regex::new("Average Rating: ([0-9]+.[0-9]+) chozo orbs")?,
+            // Downloads:
+            downloads_re: Regex::new("<b>Downloads:</b> ([0-9,]+)")?,
+            topic_re: Regex::new(
+                r"^(https?://forum\.metroidconstruction\.com/.*topic,([0-9]+))\.([0-9]+)\.html",
+            )?,
+            // « Reply #12 on: March 03, 2019, 10:12:01 PM »
+            reply_re: Regex::new(r"Reply #([0-9]+) on:</strong>\s*([^«»<]+)")?,
+            // « on: March 03, 2019, 10:12:01 PM », for the opening post
+            posted_re: Regex::new(r"« <strong>on:</strong>\s*([^«»<]+)")?,
+            // youtube.com/watch?v=ID, youtu.be/ID and youtube.com/embed/ID
+            youtube_re: Regex::new(
+                r"^(?:https?:)?//(?:www\.|m\.)?(?:youtube\.com/(?:watch\?(?:.*&)?v=|embed/)|youtu\.be/)([A-Za-z0-9_-]{11})",
+            )?,
+            // "Requires Project Base", "apply it to a PAL ROM", up to the
+            // end of the sentence
+            requires_re: Regex::new(
+                r"(?i)\b(?:requires?|needs?|made for|apply (?:it |this |the patch )?(?:on)?to)\b([^.!?\n]*)",
+            )?,
+        })
+    }
+
+    // The requirements stated anywhere in the page's text, each once
+    pub fn requirements(&self, text: &str) -> Vec<String> {
+        let mut found = Vec::new();
+        for c in self.requires_re.captures_iter(text) {
+            let words: Vec<_> = c[1]
+                .split(|ch: char| !ch.is_alphanumeric())
+                .map(|w| w.to_lowercase())
+                .collect();
+            let phrase = format!(" {} ", words.join(" "));
+            for (word, requirement) in REQUIREMENTS {
+                if phrase.contains(&format!(" {} ", word)) && !found.contains(requirement) {
+                    found.push(requirement);
+                }
+            }
+        }
+        found.into_iter().map(|r| r.to_string()).collect()
+    }
+
+    pub fn parse(&self, hack_page: &str) -> ResultErr<HackPage> {
+        let document = Html::parse_document(hack_page);
+        let meta = Selector::parse("meta")?;
+        #[allow(non_snake_case)]
+        let underboxA = Selector::parse("td.underboxA")?;
+        #[allow(non_snake_case)]
+        let underboxD = Selector::parse(".underboxD")?;
+
+        // Extract hack title
+        // In an ideal world, we would always just use the meta property
+        // but for some reason, not all hack pages have that attribute set.
+        // So when we can't find the meta tag with "og:title" we fallback to
+        // looking for the hack title on the page
+        let mut title = None;
+        for element in document.select(&meta) {
+            if element.attr("property") == Some("og:title") {
+                title = element.attr("content");
+            }
+        }
+        if title.is_none() {
+            // We just want the first underboxA on the page
+            if let Some(element) = document.select(&underboxA).next() {
+                title = element.text().next().map(|t| t.trim());
+            }
+        }
+
+        let mut page = HackPage {
+            title: title.unwrap_or("").to_owned(),
+            ..HackPage::default()
+        };
+        for element in document.select(&underboxD) {
+            let text = element.inner_html();
+            for (_, [d]) in self
+                .release_date_re
+                .captures_iter(&text)
+                .map(|c| c.extract())
+            {
+                page.date = d.trim().to_owned();
+            }
+            for (_, [a]) in self.author_re.captures_iter(&text).map(|c| c.extract()) {
+                page.author = a.trim().to_owned();
+            }
+            for (_, [g]) in self.genre_re.captures_iter(&text).map(|c| c.extract()) {
+                page.genre = g.trim().to_owned();
+            }
+            for (_, [d]) in self.difficulty_re.captures_iter(&text).map(|c| c.extract()) {
+                page.difficulty = d.trim().to_owned();
+            }
+            for (_, [d]) in self.downloads_re.captures_iter(&text).map(|c| c.extract()) {
+                page.downloads = d.replace(',', "").parse().ok();
+            }
+        }
+        let body = document.root_element().text().collect::<Vec<_>>().join(" ");
+        page.requires = self.requirements(&body);
+        let avg_runtime = Selector::parse("#average_runtime")?;
+        for element in document.select(&avg_runtime) {
+            page.runtime = element.inner_html();
+        }
+        let avg_collection = Selector::parse("#average_completion")?;
+        for element in document.select(&avg_collection) {
+            page.collection = element.inner_html();
+        }
+        let ahref = Selector::parse("a")?;
+        for element in document.select(&ahref) {
+            if let Some(href) = element.value().attr("href") {
+                if page.forum_thread.is_none() && self.topic_re.is_match(href) {
+                    page.forum_thread = Some(href.to_owned());
+                }
+            }
+        }
+        let iframe = Selector::parse("iframe")?;
+        let links = document
+            .select(&ahref)
+            .filter_map(|e| e.value().attr("href"))
+            .chain(
+                document
+                    .select(&iframe)
+                    .filter_map(|e| e.value().attr("src")),
+            );
+        for link in links {
+            if let Some(c) = self.youtube_re.captures(link) {
+                let video = format!("https://www.youtube.com/watch?v={}", &c[1]);
+                if !page.videos.contains(&video) {
+                    page.videos.push(video);
+                }
+            }
+        }
+        let img = Selector::parse("img")?;
+        for element in document.select(&img) {
+            let Some(src) = element.value().attr("src") else {
+                continue;
+            };
+            if !src.to_ascii_lowercase().contains("screenshot") {
+                continue;
+            }
+            let url = if src.starts_with("http://") || src.starts_with("https://") {
+                src.to_owned()
+            } else {
+                format!("{}{}", self.site_url, src.trim_start_matches('/'))
+            };
+            if !page.screenshots.contains(&url) {
+                page.screenshots.push(url);
+            }
+        }
+        let avg_rating = Selector::parse("span[title]")?;
+        for element in document.select(&avg_rating) {
+            let text = element.inner_html();
+            for (_, [d]) in self.rating_re.captures_iter(&text).map(|c| c.extract()) {
+                page.rating = d.trim().to_owned();
+            }
+        }
+        for field in [
+            &mut page.title,
+            &mut page.date,
+            &mut page.author,
+            &mut page.genre,
+            &mut page.difficulty,
+            &mut page.runtime,
+            &mut page.collection,
+            &mut page.rating,
+        ] {
+            *field = clean_text(field);
+        }
+        Ok(page)
+    }
+
+    // The thread's last page, found from the page links on its first page
+    pub fn forum_last_page(&self, thread_url: &str, first_page: &str) -> ResultErr<String> {
+        let (base, topic) = match self.topic_re.captures(thread_url) {
+            Some(c) => (c[1].to_owned(), c[2].to_owned()),
+            None => return Err(format!("not a forum thread: {}", thread_url).into()),
+        };
+        let document = Html::parse_document(first_page);
+        let ahref = Selector::parse("a")?;
+        let mut last = 0u64;
+        for element in document.select(&ahref) {
+            let Some(href) = element.value().attr("href") else {
+                continue;
+            };
+            if let Some(c) = self.topic_re.captures(href) {
+                if c[2] == topic {
+                    last = last.max(c[3].parse()?);
+                }
+            }
+        }
+        Ok(format!("{}.{}.html", base, last))
+    }
+
+    pub fn forum_stats(&self, last_page: &str) -> ForumStats {
+        let mut stats = ForumStats::default();
+        for (_, [posted]) in self.posted_re.captures_iter(last_page).map(|c| c.extract()) {
+            stats.last_activity = Some(posted.trim().to_owned());
+        }
+        for (_, [reply, posted]) in self.reply_re.captures_iter(last_page).map(|c| c.extract()) {
+            stats.replies = stats.replies.max(reply.parse().unwrap_or(0));
+            stats.last_activity = Some(posted.trim().to_owned());
+        }
+        stats
+    }
+
+    // List the fields of an already parsed page that came out empty, and
+    // whether the page had them at all
+    pub fn audit(&self, hack_page: &str, page: &HackPage) -> Vec<(&'static str, Missing)> {
+        let reason = |labels: &[&str]| {
+            if labels.iter().any(|l| hack_page.contains(l)) {
+                Missing::SelectorMiss
+            } else {
+                Missing::Absent
+            }
+        };
+        let mut missing = Vec::new();
+        if page.title.is_empty() {
+            missing.push(("title", reason(&["og:title", "underboxA"])));
+        }
+        if page.date.is_empty() {
+            missing.push(("date", reason(&["Release date:"])));
+        } else if parse_date(&page.date).is_none() {
+            missing.push(("date", Missing::UnparsableDate));
+        }
+        if page.author.is_empty() {
+            missing.push(("author", reason(&["Author:"])));
+        }
+        if page.genre.is_empty() {
+            missing.push(("genre", reason(&["Genre:"])));
+        }
+        if page.difficulty.is_empty() {
+            missing.push(("difficulty", reason(&["Difficulty:"])));
+        }
+        if page.rating.is_empty() {
+            missing.push(("rating", reason(&["Average Rating"])));
+        }
+        missing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scraped_text_has_its_entities_decoded_and_tags_stripped() {
+        assert_eq!(clean_text("Tom &amp; Jerry"), "Tom & Jerry");
+        assert_eq!(
+            clean_text(" <i>Super</i>\n  Metroid&nbsp;Redesign "),
+            "Super Metroid Redesign"
+        );
+        assert_eq!(clean_text("&lt;3 &#x2605;&#9733;"), "<3 \u{2605}\u{2605}");
+
+        let parser = PageParser::for_site(SITE_URL).unwrap();
+        let page = parser
+            .parse(
+                r#"<meta property="og:title" content="Ice &amp; Fire <b>Deluxe</b>">
+                <div class="underboxD"><b>Author:</b> <a href="profile.php?id=1">Foo &amp; Bar</a>
+                <b>Genre:</b> <i>Exploration</i> <br></div>"#,
+            )
+            .unwrap();
+        assert_eq!(page.title, "Ice & Fire Deluxe");
+        assert_eq!(page.author, "Foo & Bar");
+        assert_eq!(page.genre, "Exploration");
+        let rows = parse_hack_list(
+            r#"<table><tr><td><a href="hack.php?id=1">Chozo &lt;b&gt;Quest&lt;/b&gt;</a></td></tr>
+            </table>"#,
+        )
+        .unwrap();
+        assert_eq!(rows[0].title, "Chozo Quest");
+    }
+}
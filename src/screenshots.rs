@@ -0,0 +1,219 @@
+use crate::schema::{self, Migration};
+use crate::utils::*;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use reqwest_middleware::ClientWithMiddleware;
+use sanitise_file_name::sanitise;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub const SCREENSHOTS_FILE: &str = "screenshots.json";
+
+const MIGRATIONS: &[Migration] = &[schema::unchanged];
+
+// Longest side of a thumbnail, in pixels
+const THUMBNAIL_SIZE: u32 = 160;
+
+// What was last downloaded from a screenshot URL, and how to ask the site
+// whether it changed since
+#[derive(Debug, Clone, Default)]
+pub struct CachedImage {
+    pub sha256: String,
+    pub file: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+// Screenshots are stored once under `<screenshots_dir>/cache`, named by
+// content hash, and linked into each hack's directory, so the same image
+// used by several hacks or versions is only downloaded and kept once
+#[derive(Debug, Default)]
+pub struct ScreenshotCache {
+    pub urls: BTreeMap<String, CachedImage>,
+}
+
+impl ScreenshotCache {
+    pub fn load<P: AsRef<Path>>(fname: P) -> ResultErr<ScreenshotCache> {
+        let mut cache = ScreenshotCache::default();
+        if !fname.as_ref().exists() {
+            return Ok(cache);
+        }
+        let mut value = read_json(fname)?;
+        schema::upgrade(&mut value, MIGRATIONS, SCREENSHOTS_FILE)?;
+        if let Some(urls) = value.get("urls").and_then(|u| u.as_object()) {
+            for (url, image) in urls {
+                let image = CachedImage {
+                    sha256: json_str(image, "sha256").unwrap_or_default(),
+                    file: json_str(image, "file").unwrap_or_default(),
+                    etag: json_str(image, "etag"),
+                    last_modified: json_str(image, "last_modified"),
+                };
+                cache.urls.insert(url.clone(), image);
+            }
+        }
+        Ok(cache)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, fname: P) -> ResultErr<()> {
+        let urls: serde_json::Map<_, _> = self
+            .urls
+            .iter()
+            .map(|(url, image)| {
+                let image = json!({
+                    "sha256": image.sha256,
+                    "file": image.file,
+                    "etag": image.etag,
+                    "last_modified": image.last_modified,
+                });
+                (url.clone(), image)
+            })
+            .collect();
+        let mut value = json!({ "urls": urls });
+        schema::stamp(&mut value, MIGRATIONS);
+        write_json(fname, &value)
+    }
+}
+
+// Where `fetch` links a hack's screenshot from `url`
+pub fn screenshot_path(dir: &Path, hack_id: u32, url: &str) -> PathBuf {
+    let name = sanitise(url.rsplit('/').next().unwrap_or(url));
+    dir.join(hack_id.to_string()).join(name)
+}
+
+pub fn thumbnail_path(screenshot: &Path) -> ResultErr<PathBuf> {
+    let dir = screenshot.parent().ok_or("bad path")?.join("thumbs");
+    let stem = screenshot.file_stem().ok_or("bad path")?;
+    Ok(dir.join(stem).with_extension("png"))
+}
+
+pub fn make_thumbnail(screenshot: &Path, log: &mut dyn Write) -> ResultErr<PathBuf> {
+    let thumb = thumbnail_path(screenshot)?;
+    if thumb.exists() {
+        return Ok(thumb);
+    }
+    fs::create_dir_all(thumb.parent().ok_or("bad path")?)?;
+    writeln!(log, "Creating thumbnail {:?}", thumb)?;
+    let image = image::open(screenshot)?;
+    image
+        .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        .save_with_format(&thumb, image::ImageFormat::Png)?;
+    Ok(thumb)
+}
+
+// Hard links keep the per-hack directories free, fall back to a copy on
+// filesystems without them
+fn link_or_copy(src: &Path, dst: &Path) -> ResultErr<()> {
+    if dst.exists() {
+        fs::remove_file(dst)?;
+    }
+    if fs::hard_link(src, dst).is_err() {
+        fs::copy(src, dst)?;
+    }
+    Ok(())
+}
+
+// Make sure a hack's screenshots are in the cache, asking the site only for
+// images that are new or changed, then link them and their thumbnails into
+// `dir/<id>/`. Returns how many images were actually downloaded.
+pub async fn fetch(
+    client: &ClientWithMiddleware,
+    cache: &mut ScreenshotCache,
+    dir: &Path,
+    hack_id: u32,
+    urls: &[String],
+    log: &mut dyn Write,
+) -> ResultErr<usize> {
+    let cache_dir = dir.join("cache");
+    let hack_dir = dir.join(hack_id.to_string());
+    fs::create_dir_all(&cache_dir)?;
+    let mut fetched = 0;
+    for url in urls {
+        let known = cache
+            .urls
+            .get(url)
+            .filter(|c| cache_dir.join(&c.file).exists())
+            .cloned();
+        let mut request = client.get(url);
+        if let Some(known) = &known {
+            if let Some(etag) = &known.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &known.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let response = request.send().await?;
+        let image = match known {
+            Some(known) if response.status() == StatusCode::NOT_MODIFIED => {
+                writeln!(log, "Screenshot {} is unchanged", url)?;
+                known
+            }
+            _ => {
+                let response = response.error_for_status()?;
+                let header = |name| {
+                    response
+                        .headers()
+                        .get(name)
+                        .and_then(|v| v.to_str().ok())
+                        .map(|v| v.to_owned())
+                };
+                let etag = header(ETAG);
+                let last_modified = header(LAST_MODIFIED);
+                let bytes = response.bytes().await?;
+                fetched += 1;
+                let sha256 = to_hex(&Sha256::digest(&bytes));
+                let extension = Path::new(url.rsplit('/').next().unwrap_or(url))
+                    .extension()
+                    .map(|e| sanitise(&e.to_string_lossy().to_ascii_lowercase()))
+                    .unwrap_or_else(|| "img".to_owned());
+                let file = format!("{}.{}", sha256, extension);
+                let cached = cache_dir.join(&file);
+                if cached.exists() {
+                    writeln!(log, "Screenshot {} is already cached as {}", url, file)?;
+                } else {
+                    writeln!(log, "Caching screenshot {} as {}", url, file)?;
+                    fs::write(&cached, &bytes)?;
+                }
+                CachedImage {
+                    sha256,
+                    file,
+                    etag,
+                    last_modified,
+                }
+            }
+        };
+        let cached = cache_dir.join(&image.file);
+        let thumb = make_thumbnail(&cached, log)?;
+
+        let path = screenshot_path(dir, hack_id, url);
+        fs::create_dir_all(hack_dir.join("thumbs"))?;
+        link_or_copy(&cached, &path)?;
+        link_or_copy(&thumb, &thumbnail_path(&path)?)?;
+        cache.urls.insert(url.clone(), image);
+    }
+    Ok(fetched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::TestDir;
+
+    #[test]
+    fn thumbnails_keep_the_aspect_ratio() {
+        let dir = TestDir::new("thumb");
+        // SNES output resolution
+        let screenshot = dir.join("shot.png");
+        image::RgbImage::new(256, 224).save(&screenshot).unwrap();
+
+        let thumb = make_thumbnail(&screenshot, &mut std::io::sink()).unwrap();
+        let size = image::image_dimensions(&thumb).unwrap();
+
+        assert_eq!(thumb, dir.join("thumbs").join("shot.png"));
+        assert_eq!(size, (160, 140));
+    }
+}
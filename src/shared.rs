@@ -0,0 +1,298 @@
+use crate::config::Config;
+use crate::manifest::hack_id_of;
+use crate::utils::*;
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+// Support for a mirror several users work on at once, e.g. over NFS or
+// Samba. Runs lock each hack they write into with a lock file under
+// `<downloads>/.locks`, created exclusively so only one machine gets it,
+// and hacks locked by someone else are skipped until the next run. Held
+// locks are touched every so often, so that only the locks of runs that
+// died go stale and get taken over.
+
+const LOCKS_DIR: &str = ".locks";
+
+// Removes its lock file once dropped
+#[derive(Debug)]
+pub struct Lock {
+    path: PathBuf,
+    // Dropping it stops the thread keeping the lock file fresh
+    _refresh: Sender<()>,
+}
+
+impl Lock {
+    fn new(path: PathBuf, stale_after: Duration) -> Lock {
+        let (refresh, stop) = mpsc::channel::<()>();
+        let every = (stale_after / 4).max(Duration::from_millis(50));
+        let touched = path.clone();
+        std::thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(every) {
+                // Not created again if it's gone, whoever removed it has it
+                if let Ok(file) = OpenOptions::new().write(true).open(&touched) {
+                    let _ = file.set_modified(SystemTime::now());
+                }
+            }
+        });
+        Lock {
+            path,
+            _refresh: refresh,
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[derive(Debug)]
+pub struct Locks {
+    dir: PathBuf,
+    stale_after: Duration,
+    // Hacks locked by one stage of the download mode and released by another
+    held: Mutex<BTreeMap<u32, Lock>>,
+}
+
+impl Locks {
+    pub fn new(config: &Config) -> Locks {
+        Locks {
+            // The downloads directory is read-only while work_dir is set
+            dir: Path::new(config.work_dir.as_ref().unwrap_or(&config.downloads_dir))
+                .join(LOCKS_DIR),
+            stale_after: Duration::from_secs(config.lock_stale_secs),
+            held: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    // Who holds the hack's lock, if it's not this run
+    pub fn hack(&self, id: u32) -> ResultErr<Result<Lock, String>> {
+        self.try_lock(&format!("hack-{}", id))
+    }
+
+    // Locks the hack whose directory under `root` holds `path`. Files outside
+    // hack directories don't need one.
+    pub fn path(&self, root: &Path, path: &Path) -> ResultErr<Result<Option<Lock>, String>> {
+        match hack_id_of(root, path) {
+            Some(id) => Ok(self.hack(id)?.map(Some)),
+            None => Ok(Ok(None)),
+        }
+    }
+
+    // Like `hack`, but the lock stays held until released
+    pub fn hold_hack(&self, id: u32) -> ResultErr<Option<String>> {
+        match self.hack(id)? {
+            Ok(lock) => {
+                self.held.lock().unwrap().insert(id, lock);
+                Ok(None)
+            }
+            Err(holder) => Ok(Some(holder)),
+        }
+    }
+
+    pub fn release_hack(&self, id: u32) {
+        self.held.lock().unwrap().remove(&id);
+    }
+
+    // For read-modify-write of the shared state files, which only takes a
+    // moment, so it's waited for
+    pub fn state(&self) -> ResultErr<Lock> {
+        loop {
+            match self.try_lock("state")? {
+                Ok(lock) => return Ok(lock),
+                Err(_) => std::thread::sleep(Duration::from_millis(100)),
+            }
+        }
+    }
+
+    fn try_lock(&self, name: &str) -> ResultErr<Result<Lock, String>> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("{}.lock", name));
+        let mut took_over = false;
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    writeln!(file, "{}", owner())?;
+                    return Ok(Ok(Lock::new(path, self.stale_after)));
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let holder = fs::read_to_string(&path).unwrap_or_default();
+                    // Left behind by a run that died
+                    if !took_over && self.is_stale(&path) {
+                        self.take_over(name, &path)?;
+                        took_over = true;
+                        continue;
+                    }
+                    return Ok(Err(holder.trim().to_owned()));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn is_stale(&self, path: &Path) -> bool {
+        fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|m| m.elapsed().ok())
+            .is_some_and(|age| age > self.stale_after)
+    }
+
+    // Moves a stale lock out of the way. Only one of the machines trying
+    // to at once gets to rename it; a lock that turns out to be fresh, made
+    // by a machine that got there first, is linked back where it was.
+    fn take_over(&self, name: &str, path: &Path) -> ResultErr<()> {
+        let moved = self.dir.join(format!(
+            "{}.lock.stale-{}-{}",
+            name,
+            hostname(),
+            std::process::id()
+        ));
+        match fs::rename(path, &moved) {
+            Ok(()) => (),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+        if !self.is_stale(&moved) {
+            let _ = fs::hard_link(&moved, path);
+        }
+        fs::remove_file(&moved)?;
+        Ok(())
+    }
+}
+
+fn owner() -> String {
+    format!(
+        "{} pid {} since {}",
+        hostname(),
+        std::process::id(),
+        chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+    )
+}
+
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    // SAFETY: the buffer outlives the call and its length is passed along
+    let ok = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } == 0;
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    match ok {
+        true => String::from_utf8_lossy(&buf[..len]).into_owned(),
+        false => "unknown host".to_owned(),
+    }
+}
+
+#[cfg(not(unix))]
+fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_else(|_| "unknown host".to_owned())
+}
+
+// Applies the configured umask, and hands the mirror's directories to the
+// configured group. They're made setgid, so everything created under them
+// later belongs to the group too.
+#[cfg(unix)]
+pub fn apply_permissions(config: &Config) -> ResultErr<()> {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    if let Some(umask) = &config.umask {
+        let mask = u32::from_str_radix(umask, 8).map_err(|_| format!("bad umask {:?}", umask))?;
+        // SAFETY: umask only swaps the process's file mode mask
+        unsafe { libc::umask(mask as libc::mode_t) };
+    }
+    let Some(group) = &config.group else {
+        return Ok(());
+    };
+    let gid = group_id(group)?;
+    let mut dirs = vec![
+        ".",
+        &config.downloads_dir,
+        &config.patched_dir,
+        &config.screenshots_dir,
+        &config.staging_dir,
+    ];
+    dirs.extend(config.extract_root());
+    for dir in dirs {
+        fs::create_dir_all(dir)?;
+        if fs::metadata(dir)?.gid() != gid {
+            std::os::unix::fs::chown(dir, None, Some(gid))?;
+        }
+        let mut permissions = fs::metadata(dir)?.permissions();
+        permissions.set_mode(permissions.mode() | 0o2070);
+        fs::set_permissions(dir, permissions)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn apply_permissions(config: &Config) -> ResultErr<()> {
+    if config.umask.is_some() || config.group.is_some() {
+        return Err("umask and group are only supported on Unix".into());
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn group_id(group: &str) -> ResultErr<u32> {
+    if let Ok(gid) = group.parse() {
+        return Ok(gid);
+    }
+    let name = std::ffi::CString::new(group)?;
+    // SAFETY: getgrnam returns null or a pointer to a static entry, which is
+    // read straight away
+    let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+    if entry.is_null() {
+        return Err(format!("no group named {:?}", group).into());
+    }
+    Ok(unsafe { (*entry).gr_gid })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::TestDir;
+
+    fn locks(dir: &Path, stale_after: Duration) -> Locks {
+        Locks {
+            dir: dir.to_path_buf(),
+            stale_after,
+            held: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    #[test]
+    fn locks_are_taken_over_only_once_stale() {
+        let dir = TestDir::new("locks");
+        let stale_after = Duration::from_millis(200);
+        let (mine, theirs) = (locks(&dir, stale_after), locks(&dir, stale_after));
+
+        // Held, and kept fresh while held past when it would go stale
+        let held = mine.hack(1).unwrap().unwrap();
+        std::thread::sleep(stale_after * 3);
+        let holder = theirs.hack(1).unwrap().unwrap_err();
+        assert!(holder.contains(&format!("pid {}", std::process::id())));
+        drop(held);
+        assert!(theirs.hack(1).unwrap().is_ok());
+
+        // Left behind by a run that died a moment ago
+        let path = dir.join("hack-2.lock");
+        fs::write(&path, "elsewhere pid 1\n").unwrap();
+        assert_eq!(theirs.hack(2).unwrap().unwrap_err(), "elsewhere pid 1");
+
+        // Left behind long enough ago to have gone stale
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(60))
+            .unwrap();
+        let taken = theirs.hack(2).unwrap().unwrap();
+        assert!(!fs::read_to_string(&path).unwrap().contains("elsewhere"));
+        drop(taken);
+        assert!(!path.exists());
+        let left: Vec<_> = fs::read_dir(&*dir).unwrap().collect();
+        assert!(left.is_empty());
+    }
+}
@@ -0,0 +1,119 @@
+use crate::config::Config;
+use crate::i18n;
+use crate::utils::*;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Detached signatures for what a public mirror publishes, so whoever
+// downloads its exports can check they came from the mirror. minisign
+// writes `<file>.minisig` and gpg `<file>.asc`, with both made when both
+// keys are configured. The tools may ask for the key's password.
+pub fn commands(config: &Config, file: &Path) -> Vec<(Command, PathBuf)> {
+    let mut commands = Vec::new();
+    if let Some(key) = &config.minisign_key {
+        let signature = with_suffix(file, ".minisig");
+        let mut command = Command::new("minisign");
+        command
+            .arg("-S")
+            .arg("-s")
+            .arg(key)
+            .arg("-m")
+            .arg(file)
+            .arg("-x")
+            .arg(&signature);
+        commands.push((command, signature));
+    }
+    if let Some(key) = &config.gpg_key {
+        let signature = with_suffix(file, ".asc");
+        let mut command = Command::new("gpg");
+        command
+            .args(["--yes", "--armor", "--detach-sign", "--local-user"])
+            .arg(key)
+            .arg("--output")
+            .arg(&signature)
+            .arg(file);
+        commands.push((command, signature));
+    }
+    commands
+}
+
+fn with_suffix(file: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(file.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// Signs `file` with every configured key, returning the signatures written.
+// Nothing is signed when no key is configured.
+pub fn sign(config: &Config, file: &Path, log: &mut dyn Write) -> ResultErr<Vec<PathBuf>> {
+    let mut signatures = Vec::new();
+    for (mut command, signature) in commands(config, file) {
+        let tool = command.get_program().to_string_lossy().to_string();
+        writeln!(log, "Signing {:?} with {}", file, tool)?;
+        let status = command
+            .status()
+            .map_err(|e| format!("cannot run {}: {}", tool, e))?;
+        if !status.success() {
+            return Err(format!("{} failed to sign {:?} with {}", tool, file, status).into());
+        }
+        signatures.push(signature);
+    }
+    Ok(signatures)
+}
+
+pub fn sign_export(config: &Config, output: &str, log: &mut dyn Write) -> ResultErr<()> {
+    for signature in sign(config, Path::new(output), log)? {
+        let args = [("path", signature.to_string_lossy().into())];
+        println!("{}", i18n::tr("sign-signed", &args));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::Manifest;
+    use crate::utils::tests::TestDir;
+    use std::fs;
+
+    #[test]
+    fn exports_are_summed_and_signed_with_each_configured_key() {
+        let dir = TestDir::new("sums");
+        let hack_dir = dir.join("0001-5-Test");
+        fs::create_dir_all(&hack_dir).unwrap();
+        fs::write(hack_dir.join("hack.zip"), "abc").unwrap();
+        let mut manifest = Manifest::default();
+        manifest
+            .record_file(&dir, 5, None, "0001-5-Test", &hack_dir.join("hack.zip"))
+            .unwrap();
+        let mut sums = Vec::new();
+        manifest.write_sums(&mut sums).unwrap();
+        assert_eq!(
+            String::from_utf8(sums).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  0001-5-Test/hack.zip\n"
+        );
+
+        let file = Path::new("SHA256SUMS");
+        assert!(commands(&Config::default(), file).is_empty());
+        let config = Config {
+            minisign_key: Some("mirror.key".to_owned()),
+            gpg_key: Some("mirror@example.org".to_owned()),
+            ..Config::default()
+        };
+        let commands = commands(&config, file);
+        let signatures: Vec<_> = commands.iter().map(|(_, s)| s.clone()).collect();
+        assert_eq!(
+            signatures,
+            [
+                PathBuf::from("SHA256SUMS.minisig"),
+                PathBuf::from("SHA256SUMS.asc")
+            ]
+        );
+        let (gpg, _) = &commands[1];
+        let args: Vec<_> = gpg.get_args().collect();
+        assert!(args.contains(&std::ffi::OsStr::new("mirror@example.org")));
+        assert_eq!(args.last(), Some(&file.as_os_str()));
+    }
+}
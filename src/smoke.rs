@@ -0,0 +1,86 @@
+use crate::config::{Config, CONFIG_FILE};
+use crate::i18n;
+use crate::output;
+use crate::outputs::Outputs;
+use crate::utils::*;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+// The emulator command line for `rom`
+pub fn command_args(command: &[String], rom: &str) -> Vec<String> {
+    command.iter().map(|a| a.replace("{rom}", rom)).collect()
+}
+
+// Run one ROM in the configured emulator. Still running when time is up
+// counts as a pass, so does a clean exit from an exit-on-frame script.
+fn run_rom(command: &[String], rom: &str, timeout: Duration) -> ResultErr<Result<(), String>> {
+    let args = command_args(command, rom);
+    let mut child = Command::new(&args[0])
+        .args(&args[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("cannot run {}: {}", args[0], e))?;
+    let deadline = Deadline::after(timeout, &Cancel::default());
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(if status.success() {
+                Ok(())
+            } else {
+                Err(format!("emulator exited early with {}", status))
+            });
+        }
+        if deadline.passed() {
+            child.kill()?;
+            child.wait()?;
+            return Ok(Ok(()));
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+// Smoke test every patched ROM, recording the results in the patched ROM
+// report. Returns how many passed and failed.
+pub fn smoke_test(
+    config: &Config,
+    outputs: &mut Outputs,
+    log: &mut dyn Write,
+) -> ResultErr<(usize, usize)> {
+    if config.smoke_test_command.is_empty() {
+        return Err(format!(
+            "no emulator configured, set smoke_test_command in {}",
+            CONFIG_FILE
+        )
+        .into());
+    }
+    let timeout = Duration::from_secs(config.smoke_test_secs);
+    let (mut passed, mut failed) = (0, 0);
+    for entry in outputs.entries.values_mut() {
+        if !Path::new(&entry.output).exists() {
+            writeln!(log, "{} is gone, skipping", entry.output)?;
+            continue;
+        }
+        writeln!(log, "Running {}", entry.output)?;
+        match run_rom(&config.smoke_test_command, &entry.output, timeout)? {
+            Ok(()) => {
+                writeln!(log, "{} passed", entry.output)?;
+                entry.smoke_test = Some("passed".to_owned());
+                passed += 1;
+            }
+            Err(why) => {
+                writeln!(log, "{} failed: {}", entry.output, why)?;
+                let args = [
+                    ("path", entry.output.as_str().into()),
+                    ("why", output::error(&why).to_string().into()),
+                ];
+                println!("{}", i18n::tr("smoke-failed", &args));
+                entry.smoke_test = Some(why);
+                failed += 1;
+            }
+        }
+    }
+    Ok((passed, failed))
+}
@@ -0,0 +1,193 @@
+use crate::outputs::Outputs;
+use crate::schema::{self, Migration};
+use crate::utils::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::Path;
+
+pub const SNAPSHOTS_FILE: &str = "snapshots.json";
+
+const MIGRATIONS: &[Migration] = &[schema::unchanged];
+
+// A patched ROM as it was when the snapshot was taken
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shot {
+    pub sha256: String,
+    pub patch_sha256: String,
+    #[serde(default)]
+    pub base_sha256: Option<String>,
+}
+
+// The whole patched set at one point, to compare the next release of the
+// tool or another base ROM against
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub created: String,
+    // The tool version that made the ROMs
+    pub version: String,
+    pub outputs: BTreeMap<String, Shot>,
+}
+
+#[derive(Debug, Default)]
+pub struct Snapshots {
+    pub snapshots: BTreeMap<String, Snapshot>,
+}
+
+impl Snapshots {
+    pub fn load<P: AsRef<Path>>(fname: P) -> ResultErr<Snapshots> {
+        if !fname.as_ref().exists() {
+            return Ok(Snapshots::default());
+        }
+        let mut value = read_json(fname)?;
+        schema::upgrade(&mut value, MIGRATIONS, SNAPSHOTS_FILE)?;
+        let snapshots = value.get("snapshots").cloned().unwrap_or_else(|| json!({}));
+        Ok(Snapshots {
+            snapshots: serde_json::from_value(snapshots)?,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, fname: P) -> ResultErr<()> {
+        let mut value = json!({ "snapshots": self.snapshots });
+        schema::stamp(&mut value, MIGRATIONS);
+        write_json(fname, &value)
+    }
+
+    // Names are never reused, so a diff always means what it meant before
+    pub fn create(&mut self, name: &str, created: &str, outputs: &Outputs) -> ResultErr<usize> {
+        if self.snapshots.contains_key(name) {
+            return Err(format!("there already is a snapshot called {:?}", name).into());
+        }
+        let shots: BTreeMap<_, _> = outputs
+            .entries
+            .values()
+            .map(|e| {
+                let shot = Shot {
+                    sha256: e.sha256.clone(),
+                    patch_sha256: e.patch_sha256.clone(),
+                    base_sha256: e.base_sha256.clone(),
+                };
+                (e.output.clone(), shot)
+            })
+            .collect();
+        let count = shots.len();
+        let snapshot = Snapshot {
+            created: created.to_owned(),
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            outputs: shots,
+        };
+        self.snapshots.insert(name.to_owned(), snapshot);
+        Ok(count)
+    }
+
+    fn get(&self, name: &str) -> ResultErr<&Snapshot> {
+        self.snapshots
+            .get(name)
+            .ok_or_else(|| format!("no snapshot called {:?}, see `snapshot list`", name).into())
+    }
+}
+
+pub fn write_list(snapshots: &Snapshots, out: &mut dyn Write) -> ResultErr<()> {
+    for (name, snapshot) in &snapshots.snapshots {
+        writeln!(
+            out,
+            "{}  {}, version {}, {} ROMs",
+            name,
+            snapshot.created,
+            snapshot.version,
+            snapshot.outputs.len()
+        )?;
+    }
+    Ok(())
+}
+
+// Every patched ROM that differs between snapshots `a` and `b`, with what
+// changed under it. Returns how many did.
+pub fn write_diff(
+    snapshots: &Snapshots,
+    a: &str,
+    b: &str,
+    out: &mut dyn Write,
+) -> ResultErr<usize> {
+    let (old, new) = (snapshots.get(a)?, snapshots.get(b)?);
+    writeln!(
+        out,
+        "{} ({}, version {}) to {} ({}, version {})",
+        a, old.created, old.version, b, new.created, new.version
+    )?;
+    let paths: BTreeSet<_> = old.outputs.keys().chain(new.outputs.keys()).collect();
+    let mut count = 0;
+    for path in paths {
+        let why = match (old.outputs.get(path), new.outputs.get(path)) {
+            (Some(o), Some(n)) if o.sha256 == n.sha256 => continue,
+            (Some(o), Some(n)) if o.patch_sha256 != n.patch_sha256 => "the patch was updated",
+            (Some(o), Some(n)) if o.base_sha256 != n.base_sha256 => "the base ROM changed",
+            (Some(_), Some(_)) => "same patch and base ROM, the patching changed",
+            (Some(_), None) => "gone",
+            (None, Some(_)) => "new",
+            (None, None) => continue,
+        };
+        writeln!(out, "  {}: {}", path, why)?;
+        count += 1;
+    }
+    if old.version != new.version && count == 0 {
+        writeln!(out, "  Every patched ROM came out the same")?;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outputs::tests::test_output_entry;
+    use crate::outputs::OutputEntry;
+
+    #[test]
+    fn snapshot_diffs_say_what_changed_under_each_rom() {
+        let entry = |output: &str, sha256: &str, patch_sha256: &str, base: &str| OutputEntry {
+            patch_sha256: patch_sha256.to_owned(),
+            sha256: sha256.to_owned(),
+            base_sha256: Some(base.to_owned()),
+            ..test_output_entry(output)
+        };
+        let outputs = |entries: Vec<OutputEntry>| Outputs {
+            entries: entries.into_iter().map(|e| (e.output.clone(), e)).collect(),
+        };
+        let mut snapshots = Snapshots::default();
+        let before = outputs(vec![
+            entry("a.sfc", "1", "p", "base"),
+            entry("b.sfc", "2", "q", "base"),
+            entry("c.sfc", "3", "r", "base"),
+            entry("d.sfc", "4", "s", "base"),
+        ]);
+        assert_eq!(
+            snapshots.create("before", "2026-10-01", &before).unwrap(),
+            4
+        );
+        assert!(snapshots.create("before", "2026-10-02", &before).is_err());
+        let after = outputs(vec![
+            entry("a.sfc", "1", "p", "base"),
+            entry("b.sfc", "5", "q2", "base"),
+            entry("c.sfc", "6", "r", "base"),
+            entry("e.sfc", "7", "t", "base"),
+        ]);
+        snapshots.create("after", "2026-10-16", &after).unwrap();
+
+        let mut out = Vec::new();
+        let count = write_diff(&snapshots, "before", "after", &mut out).unwrap();
+        assert_eq!(count, 4);
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().skip(1).collect();
+        assert_eq!(
+            lines,
+            [
+                "  b.sfc: the patch was updated",
+                "  c.sfc: same patch and base ROM, the patching changed",
+                "  d.sfc: gone",
+                "  e.sfc: new",
+            ]
+        );
+        assert!(write_diff(&snapshots, "before", "later", &mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+use crate::catalog::CATALOG_FILE;
+use crate::config::{Config, CONFIG_FILE};
+use crate::counts::COUNTS_FILE;
+use crate::http::RETRIES_FILE;
+use crate::manifest::{Manifest, MANIFEST_FILE};
+use crate::news::NEWS_FILE;
+use crate::outputs::OUTPUTS_FILE;
+use crate::overrides::OVERRIDES_FILE;
+use crate::pins::PINS_FILE;
+use crate::played::PLAYED_FILE;
+use crate::progress::PROGRESS_FILE;
+use crate::screenshots::SCREENSHOTS_FILE;
+use crate::snapshot::SNAPSHOTS_FILE;
+use crate::tags::TAGS_FILE;
+use crate::utils::*;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+
+pub const DEFAULT_STATE_FILE: &str = "state.tar.zst";
+
+// The tool's bookkeeping, without the downloads, extracted files and
+// patched ROMs it describes, which are far bigger and can be synced or
+// rebuilt separately
+const STATE_FILES: &[&str] = &[
+    CONFIG_FILE,
+    MANIFEST_FILE,
+    CATALOG_FILE,
+    OUTPUTS_FILE,
+    OVERRIDES_FILE,
+    PINS_FILE,
+    COUNTS_FILE,
+    NEWS_FILE,
+    SCREENSHOTS_FILE,
+    TAGS_FILE,
+    PLAYED_FILE,
+    SNAPSHOTS_FILE,
+    RETRIES_FILE,
+    PROGRESS_FILE,
+];
+
+const BLOCK: usize = 512;
+
+// Writes the state files found in `dir` to a zstd compressed tar file
+pub fn export(dir: &Path, fname: &Path, log: &mut dyn Write) -> ResultErr<usize> {
+    let mut out = zstd::Encoder::new(File::create(fname)?, 0)?;
+    let mut count = 0;
+    for name in STATE_FILES {
+        let path = dir.join(name);
+        if !path.exists() {
+            continue;
+        }
+        let mtime = fs::metadata(&path)?
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        write_entry(&mut out, name, &fs::read(&path)?, mtime)?;
+        writeln!(log, "exported {}", name)?;
+        count += 1;
+    }
+    // A tar file ends with two empty blocks
+    out.write_all(&[0; 2 * BLOCK])?;
+    out.finish()?;
+    Ok(count)
+}
+
+// Unpacks a state export into `dir`. Existing state is only replaced when
+// forced, and nothing is written unless the whole export is readable.
+pub fn import(dir: &Path, fname: &Path, force: bool, log: &mut dyn Write) -> ResultErr<usize> {
+    let entries = read_entries(zstd::Decoder::new(File::open(fname)?)?)?;
+    if let Some((name, _)) = entries
+        .iter()
+        .find(|(n, _)| !STATE_FILES.contains(&n.as_str()))
+    {
+        return Err(format!("{:?} has {:?}, which isn't a state file", fname, name).into());
+    }
+    if !force {
+        let existing: Vec<_> = entries
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .filter(|name| dir.join(name).exists())
+            .collect();
+        if !existing.is_empty() {
+            return Err(format!(
+                "{} already exist here, pass --force to replace them",
+                existing.join(", ")
+            )
+            .into());
+        }
+    }
+    for (name, contents) in &entries {
+        fs::write(dir.join(name), contents)?;
+        writeln!(log, "imported {}", name)?;
+    }
+    Ok(entries.len())
+}
+
+// How many of the files the imported manifest lists are in place under the
+// configured downloads directory, out of how many
+pub fn relink(dir: &Path, log: &mut dyn Write) -> ResultErr<(usize, usize)> {
+    let config = Config::load(dir.join(CONFIG_FILE))?;
+    let manifest = Manifest::load(dir.join(MANIFEST_FILE))?;
+    let downloads = dir.join(&config.downloads_dir);
+    let mut present = 0;
+    let mut total = 0;
+    for file in manifest.files() {
+        total += 1;
+        let path = downloads.join(&file.path);
+        match fs::metadata(&path) {
+            Ok(m) if m.len() == file.size => present += 1,
+            Ok(_) => writeln!(log, "{:?} doesn't match the manifest", path)?,
+            Err(_) => writeln!(log, "{:?} is missing", path)?,
+        }
+    }
+    Ok((present, total))
+}
+
+fn write_entry(out: &mut dyn Write, name: &str, contents: &[u8], mtime: u64) -> ResultErr<()> {
+    let mut header = [0u8; BLOCK];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    octal(&mut header[100..108], 0o644);
+    octal(&mut header[108..116], 0);
+    octal(&mut header[116..124], 0);
+    octal(&mut header[124..136], contents.len() as u64);
+    octal(&mut header[136..148], mtime);
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    let checksum = checksum(&header);
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+    out.write_all(&header)?;
+    out.write_all(contents)?;
+    out.write_all(&vec![0; padding(contents.len())])?;
+    Ok(())
+}
+
+fn read_entries(mut input: impl Read) -> ResultErr<Vec<(String, Vec<u8>)>> {
+    let mut entries = Vec::new();
+    loop {
+        let mut header = [0u8; BLOCK];
+        input.read_exact(&mut header)?;
+        if header.iter().all(|&b| b == 0) {
+            return Ok(entries);
+        }
+        if parse_octal(&header[148..156])? != checksum(&header) {
+            return Err("corrupt tar header".into());
+        }
+        let name_len = header[..100].iter().position(|&b| b == 0).unwrap_or(100);
+        let name = String::from_utf8(header[..name_len].to_vec())?;
+        let size = parse_octal(&header[124..136])? as usize;
+        let mut contents = vec![0; size + padding(size)];
+        input.read_exact(&mut contents)?;
+        contents.truncate(size);
+        // Only plain files are ever written
+        if matches!(header[156], b'0' | 0) {
+            entries.push((name, contents));
+        }
+    }
+}
+
+// Header checksums are taken with the checksum field itself as spaces
+fn checksum(header: &[u8; BLOCK]) -> u64 {
+    let sum: u64 = header.iter().map(|&b| b as u64).sum();
+    sum - header[148..156].iter().map(|&b| b as u64).sum::<u64>() + 8 * b' ' as u64
+}
+
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}\0", value, width = field.len() - 1);
+    field.copy_from_slice(digits.as_bytes());
+}
+
+fn parse_octal(field: &[u8]) -> ResultErr<u64> {
+    let text = String::from_utf8_lossy(field);
+    let digits = text.trim_matches(|c: char| c == '\0' || c == ' ');
+    Ok(u64::from_str_radix(digits, 8)?)
+}
+
+fn padding(len: usize) -> usize {
+    (BLOCK - len % BLOCK) % BLOCK
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::TestDir;
+
+    #[test]
+    fn state_exports_restore_elsewhere_without_clobbering() {
+        let dir = TestDir::new("state");
+        let (from, to) = (dir.join("from"), dir.join("to"));
+        fs::create_dir_all(&from).unwrap();
+        fs::create_dir_all(&to).unwrap();
+        fs::write(from.join(CATALOG_FILE), "{\"hacks\": {}}").unwrap();
+        // Big enough to need more than one tar block
+        fs::write(from.join(OVERRIDES_FILE), "x".repeat(1000)).unwrap();
+        fs::write(from.join("unrelated.txt"), "not state").unwrap();
+        let archive = dir.join("state.tar.zst");
+        let log = &mut std::io::sink();
+
+        let exported = export(&from, &archive, log).unwrap();
+        let imported = import(&to, &archive, false, log).unwrap();
+        let again = import(&to, &archive, false, log);
+        let catalog = fs::read_to_string(to.join(CATALOG_FILE)).unwrap();
+        let overrides = fs::read_to_string(to.join(OVERRIDES_FILE)).unwrap();
+        let unrelated = to.join("unrelated.txt").exists();
+
+        assert_eq!((exported, imported), (2, 2));
+        assert!(again.is_err());
+        assert_eq!(catalog, "{\"hacks\": {}}");
+        assert_eq!(overrides, "x".repeat(1000));
+        assert!(!unrelated);
+    }
+}
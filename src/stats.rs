@@ -0,0 +1,371 @@
+use crate::catalog::Catalog;
+use crate::catalog::CATALOG_FILE;
+use crate::dupes;
+use crate::dupes::{Cluster, Likeness};
+use crate::i18n;
+use crate::outputs::{Outputs, OUTPUTS_FILE};
+use crate::played;
+use crate::played::{format_minutes, Played};
+use crate::records;
+use crate::scrape::parse_date;
+use crate::utils::*;
+use crate::StatsArgs;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StatsFormat {
+    /// A Markdown table, for posting or pasting into notes
+    #[default]
+    Markdown,
+    Csv,
+}
+
+// One line of the author leaderboard. Fields are in column order.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct AuthorStats {
+    pub author: String,
+    pub hacks: usize,
+    // How many of the hacks have a rating at all
+    pub rated: usize,
+    pub mean_rating: String,
+    // Genres can have spaces, so they're joined with commas
+    pub genres: String,
+    pub first_release: String,
+    pub last_release: String,
+}
+
+// Per-author aggregates over the catalog, most prolific first. The site
+// only shows each hack's average rating, not how many votes went into it,
+// so every rated hack weighs the same in the mean.
+pub fn author_stats(catalog: &Catalog) -> Vec<AuthorStats> {
+    #[derive(Default)]
+    struct Tally {
+        author: String,
+        hacks: usize,
+        ratings: Vec<f64>,
+        genres: BTreeSet<String>,
+        dates: Vec<chrono::NaiveDate>,
+    }
+    // The same author is sometimes capitalized differently from hack to hack
+    let mut tallies: BTreeMap<String, Tally> = BTreeMap::new();
+    for hack in catalog.hacks.values() {
+        let author = hack.author.trim();
+        if author.is_empty() {
+            continue;
+        }
+        let tally = tallies.entry(author.to_lowercase()).or_default();
+        if tally.author.is_empty() {
+            tally.author = author.to_owned();
+        }
+        tally.hacks += 1;
+        if let Ok(rating) = hack.rating.trim().parse() {
+            tally.ratings.push(rating);
+        }
+        if !hack.genre.is_empty() {
+            tally.genres.insert(hack.genre.clone());
+        }
+        tally.dates.extend(parse_date(&hack.date));
+    }
+
+    let mut stats: Vec<_> = tallies
+        .into_values()
+        .map(|t| AuthorStats {
+            author: t.author,
+            hacks: t.hacks,
+            rated: t.ratings.len(),
+            mean_rating: match t.ratings.is_empty() {
+                true => String::new(),
+                false => format!(
+                    "{:.2}",
+                    t.ratings.iter().sum::<f64>() / t.ratings.len() as f64
+                ),
+            },
+            genres: t.genres.into_iter().collect::<Vec<_>>().join(", "),
+            first_release: t
+                .dates
+                .iter()
+                .min()
+                .map(|d| d.to_string())
+                .unwrap_or_default(),
+            last_release: t
+                .dates
+                .iter()
+                .max()
+                .map(|d| d.to_string())
+                .unwrap_or_default(),
+        })
+        .collect();
+    // Ties go to the better rated, then alphabetical
+    stats.sort_by(|a, b| {
+        b.hacks
+            .cmp(&a.hacks)
+            .then(b.mean_rating.cmp(&a.mean_rating))
+            .then(a.author.to_lowercase().cmp(&b.author.to_lowercase()))
+    });
+    stats
+}
+
+pub fn write_author_stats(
+    stats: &[AuthorStats],
+    format: StatsFormat,
+    out: &mut dyn Write,
+) -> ResultErr<()> {
+    match format {
+        StatsFormat::Csv => {
+            writeln!(out, "{}", records::csv_header::<AuthorStats>()?)?;
+            for author in stats {
+                writeln!(out, "{}", records::csv_row(author)?)?;
+            }
+        }
+        StatsFormat::Markdown => {
+            writeln!(
+                out,
+                "| Author | Hacks | Rated | Mean rating | Genres | First release | Last release |"
+            )?;
+            writeln!(out, "|---|---:|---:|---:|---|---|---|")?;
+            for a in stats {
+                writeln!(
+                    out,
+                    "| {} | {} | {} | {} | {} | {} | {} |",
+                    a.author.replace('|', "\\|"),
+                    a.hacks,
+                    a.rated,
+                    a.mean_rating,
+                    a.genres,
+                    a.first_release,
+                    a.last_release
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// One line of the table of hacks the user has played, most recent first
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct PlayedStats {
+    pub id: u32,
+    pub title: String,
+    pub finished: String,
+    pub rating: String,
+    pub site_rating: String,
+    pub time: String,
+}
+
+pub fn played_stats(played: &Played, catalog: &Catalog) -> Vec<PlayedStats> {
+    let mut rows: Vec<_> = played
+        .hacks
+        .iter()
+        .map(|(id, play)| {
+            let hack = catalog.hacks.get(id);
+            PlayedStats {
+                id: *id,
+                title: hack.map(|h| h.title.clone()).unwrap_or_default(),
+                finished: play.finished.clone(),
+                rating: play.rating.map(|r| r.to_string()).unwrap_or_default(),
+                site_rating: hack.map(|h| h.rating.clone()).unwrap_or_default(),
+                time: play.minutes.map(format_minutes).unwrap_or_default(),
+            }
+        })
+        .collect();
+    rows.sort_by(|a, b| b.finished.cmp(&a.finished).then(a.id.cmp(&b.id)));
+    rows
+}
+
+pub fn write_played_stats(
+    rows: &[PlayedStats],
+    played: &Played,
+    catalog: &Catalog,
+    format: StatsFormat,
+    out: &mut dyn Write,
+) -> ResultErr<()> {
+    match format {
+        StatsFormat::Csv => {
+            writeln!(out, "{}", records::csv_header::<PlayedStats>()?)?;
+            for row in rows {
+                writeln!(out, "{}", records::csv_row(row)?)?;
+            }
+        }
+        StatsFormat::Markdown => {
+            writeln!(
+                out,
+                "| Id | Title | Finished | Rating | Site rating | Time |"
+            )?;
+            writeln!(out, "|---:|---|---|---:|---:|---:|")?;
+            for r in rows {
+                writeln!(
+                    out,
+                    "| {} | {} | {} | {} | {} | {} |",
+                    r.id,
+                    r.title.replace('|', "\\|"),
+                    r.finished,
+                    r.rating,
+                    r.site_rating,
+                    r.time
+                )?;
+            }
+            let minutes: u64 = played.hacks.values().filter_map(|p| p.minutes).sum();
+            writeln!(out)?;
+            writeln!(
+                out,
+                "{} of {} hacks played, {} in all",
+                played.hacks.len(),
+                catalog.hacks.len(),
+                format_minutes(minutes)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// One hack of a cluster of duplicates, with the cluster it's in
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct DuplicateRow {
+    pub cluster: usize,
+    pub id: u32,
+    pub title: String,
+    pub likeness: String,
+}
+
+pub fn duplicate_rows(clusters: &[Cluster], catalog: &Catalog) -> Vec<DuplicateRow> {
+    let mut rows = Vec::new();
+    for (n, cluster) in clusters.iter().enumerate() {
+        let likeness = match cluster.likeness {
+            Likeness::Identical => "identical".to_owned(),
+            Likeness::Near(share) => format!("{:.0}% alike", share * 100.0),
+        };
+        for id in &cluster.hacks {
+            rows.push(DuplicateRow {
+                cluster: n + 1,
+                id: *id,
+                title: catalog
+                    .hacks
+                    .get(id)
+                    .map(|h| h.title.clone())
+                    .unwrap_or_default(),
+                likeness: likeness.clone(),
+            });
+        }
+    }
+    rows
+}
+
+pub fn write_duplicates(
+    rows: &[DuplicateRow],
+    format: StatsFormat,
+    out: &mut dyn Write,
+) -> ResultErr<()> {
+    match format {
+        StatsFormat::Csv => {
+            writeln!(out, "{}", records::csv_header::<DuplicateRow>()?)?;
+            for row in rows {
+                writeln!(out, "{}", records::csv_row(row)?)?;
+            }
+        }
+        StatsFormat::Markdown => {
+            writeln!(out, "| Cluster | Id | Title | Likeness |")?;
+            writeln!(out, "|---:|---:|---|---|")?;
+            for r in rows {
+                writeln!(
+                    out,
+                    "| {} | {} | {} | {} |",
+                    r.cluster,
+                    r.id,
+                    r.title.replace('|', "\\|"),
+                    r.likeness
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn stats(args: &StatsArgs) -> ResultErr<()> {
+    let catalog = Catalog::load(CATALOG_FILE)?;
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(output) => Box::new(create_output(output)?),
+        None => Box::new(std::io::stdout()),
+    };
+    let rows = if args.duplicates {
+        let clusters = dupes::clusters(&Outputs::load(OUTPUTS_FILE)?);
+        let rows = duplicate_rows(&clusters, &catalog);
+        write_duplicates(&rows, args.format, &mut out)?;
+        rows.len()
+    } else if args.played {
+        let played = played::Played::load(played::PLAYED_FILE)?;
+        if played.hacks.is_empty() {
+            return Err("no hack is marked played yet, see `played --help`".into());
+        }
+        let rows = played_stats(&played, &catalog);
+        write_played_stats(&rows, &played, &catalog, args.format, &mut out)?;
+        rows.len()
+    } else {
+        if catalog.hacks.is_empty() {
+            return Err("the catalog is empty, run `metadata` first".into());
+        }
+        let authors = author_stats(&catalog);
+        write_author_stats(&authors, args.format, &mut out)?;
+        authors.len()
+    };
+    out.flush()?;
+    if let Some(output) = &args.output {
+        let args = [("count", rows.into()), ("path", output.as_str().into())];
+        eprintln!("{}", i18n::tr("stats-wrote", &args));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::records::HackMetadata;
+
+    #[test]
+    fn author_stats_merge_capitalizations_and_average_ratings() {
+        let mut catalog = Catalog::default();
+        let hack = |author: &str, genre: &str, date: &str, rating: &str| HackMetadata {
+            author: author.to_owned(),
+            genre: genre.to_owned(),
+            date: date.to_owned(),
+            rating: rating.to_owned(),
+            ..HackMetadata::default()
+        };
+        catalog
+            .hacks
+            .insert(1, hack("Oi27", "Exploration", "Mar 3, 2019", "4.5"));
+        catalog
+            .hacks
+            .insert(2, hack("oi27", "Boss Rush", "Jan 9, 2016", "4.0"));
+        catalog
+            .hacks
+            .insert(3, hack("Oi27", "Exploration", "Jul 1, 2021", ""));
+        catalog.hacks.insert(
+            4,
+            hack("Digital Mantra", "Improvement", "Feb 2, 2020", "4.9"),
+        );
+        catalog.hacks.insert(5, hack("", "Incomplete", "", ""));
+        let authors = author_stats(&catalog);
+        assert_eq!(
+            authors[0],
+            AuthorStats {
+                author: "Oi27".to_owned(),
+                hacks: 3,
+                rated: 2,
+                mean_rating: "4.25".to_owned(),
+                genres: "Boss Rush, Exploration".to_owned(),
+                first_release: "2016-01-09".to_owned(),
+                last_release: "2021-07-01".to_owned(),
+            }
+        );
+        assert_eq!(authors.len(), 2);
+
+        let mut csv = Vec::new();
+        write_author_stats(&authors[1..], StatsFormat::Csv, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap().lines().nth(1),
+            Some(r#""Digital Mantra","1","1","4.90","Improvement","2020-02-02","2020-02-02""#)
+        );
+    }
+}
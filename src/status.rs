@@ -0,0 +1,227 @@
+use crate::config::Config;
+use crate::i18n;
+use crate::manifest::{hack_id_of, Manifest, MANIFEST_FILE};
+use crate::summary::{self, Report, REPORT_FILE};
+use crate::utils::*;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::Metadata;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+const PHASES: [&str; 5] = ["archives", "extracted", "patched", "screenshots", "cache"];
+const ARCHIVES: usize = 0;
+const EXTRACTED: usize = 1;
+const PATCHED: usize = 2;
+const SCREENSHOTS: usize = 3;
+const CACHE: usize = 4;
+
+#[derive(Default)]
+struct DiskUsage {
+    phases: [u64; 5],
+    hacks: BTreeMap<u32, [u64; 5]>,
+    seen: HashSet<(u64, u64)>,
+}
+
+impl DiskUsage {
+    fn add(&mut self, phase: usize, hack: Option<u32>, meta: &Metadata) {
+        if let Some(key) = file_key(meta) {
+            if !self.seen.insert(key) {
+                return;
+            }
+        }
+        self.phases[phase] += meta.len();
+        if let Some(id) = hack {
+            self.hacks.entry(id).or_default()[phase] += meta.len();
+        }
+    }
+}
+
+// Screenshots are hard linked out of the cache, so only the first link of
+// a file is counted
+#[cfg(unix)]
+fn file_key(meta: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    (meta.nlink() > 1).then(|| (meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_key(_meta: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+pub fn human_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < units.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+// How the disk usage names a phase, e.g. `status-archives`
+fn phase_name(phase: &str) -> String {
+    i18n::tr(&format!("status-{}", phase), &[])
+}
+
+fn walk<F>(root: &Path, skip: Option<&Path>, log: &mut dyn Write, mut each: F) -> ResultErr<()>
+where
+    F: FnMut(&walkdir::DirEntry, &Metadata),
+{
+    if !root.is_dir() {
+        return Ok(());
+    }
+    process_directory(
+        |f, _| {
+            each(f, &f.metadata()?);
+            Ok(())
+        },
+        root,
+        |e| Some(e.path()) != skip,
+        |_| true,
+        log,
+    )
+}
+
+// What the last run of each mode got through, with the skips broken down
+// by why, since they're most of what doesn't add up
+pub fn last_runs(reports: &BTreeMap<String, Report>) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (mode, report) in reports {
+        let tally = &report.tally;
+        let args = [
+            ("mode", mode.as_str().into()),
+            ("finished", report.finished.as_str().into()),
+            ("processed", tally.processed.into()),
+            ("skipped", tally.skipped.into()),
+            ("failed", tally.failed.into()),
+        ];
+        lines.push(i18n::tr("status-last-run", &args));
+        if !tally.skips.is_empty() {
+            let reasons: Vec<_> = tally
+                .skips
+                .iter()
+                .map(|(why, items)| format!("{} {}", items.len(), why.message()))
+                .collect();
+            let args = [("reasons", reasons.join(", ").into())];
+            lines.push(format!("  {}", i18n::tr("status-skipped", &args)));
+        }
+    }
+    lines
+}
+
+// Summarize the downloads mirror, and with `disk` where its space goes
+pub fn status(config: &Config, disk: bool, top: usize, log: &mut dyn Write) -> ResultErr<()> {
+    let manifest = Manifest::load(MANIFEST_FILE)?;
+    let files: Vec<_> = manifest.files().collect();
+    let args = [
+        ("count", manifest.hacks.len().into()),
+        ("files", files.len().into()),
+        (
+            "size",
+            human_size(files.iter().map(|f| f.size).sum()).into(),
+        ),
+    ];
+    println!("{}", i18n::tr("status-mirror", &args));
+    for line in last_runs(&summary::load_reports(REPORT_FILE)?) {
+        println!("{}", line);
+    }
+    if !disk {
+        return Ok(());
+    }
+
+    let mut usage = DiskUsage::default();
+    let downloads = PathBuf::from(&config.downloads_dir);
+    let screenshots = PathBuf::from(&config.screenshots_dir);
+    let screenshot_cache = screenshots.join("cache");
+    // Archives sit directly in the hack directories, anything deeper was
+    // extracted next to them
+    walk(&downloads, None, log, |f, meta| {
+        let phase = if f.depth() > 2 { EXTRACTED } else { ARCHIVES };
+        usage.add(phase, hack_id_of(&downloads, f.path()), meta);
+    })?;
+    if let Some(extract_root) = config.extract_root().map(PathBuf::from) {
+        walk(&extract_root, None, log, |f, meta| {
+            usage.add(EXTRACTED, hack_id_of(&extract_root, f.path()), meta);
+        })?;
+    }
+    // The patch mode mirrors the downloads layout under the patched directory
+    let mut patched = PathBuf::from(&config.patched_dir);
+    patched.extend(
+        downloads
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_))),
+    );
+    walk(Path::new(&config.patched_dir), None, log, |f, meta| {
+        usage.add(PATCHED, hack_id_of(&patched, f.path()), meta);
+    })?;
+    // Walked before the cache, so screenshots still linked to a hack count
+    // towards it and the cache only shows what isn't
+    walk(&screenshots, Some(&screenshot_cache), log, |f, meta| {
+        let id = f
+            .path()
+            .strip_prefix(&screenshots)
+            .ok()
+            .and_then(|p| p.components().next())
+            .and_then(|c| c.as_os_str().to_string_lossy().parse().ok());
+        usage.add(SCREENSHOTS, id, meta);
+    })?;
+    for dir in [screenshot_cache, PathBuf::from(&config.staging_dir)] {
+        walk(&dir, None, log, |_, meta| usage.add(CACHE, None, meta))?;
+    }
+
+    println!("{}", i18n::tr("status-disk", &[]));
+    for (phase, bytes) in PHASES.iter().zip(usage.phases) {
+        println!("  {:<12} {:>10}", phase_name(phase), human_size(bytes));
+        writeln!(log, "{}: {} bytes", phase, bytes)?;
+    }
+    println!(
+        "  {:<12} {:>10}",
+        i18n::tr("status-total", &[]),
+        human_size(usage.phases.iter().sum())
+    );
+
+    let mut hacks: Vec<_> = usage
+        .hacks
+        .iter()
+        .map(|(id, phases)| (*id, phases.iter().sum::<u64>(), phases))
+        .collect();
+    for (id, total, _) in &hacks {
+        writeln!(log, "hack {}: {} bytes", id, total)?;
+    }
+    hacks.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    hacks.truncate(top);
+    if hacks.is_empty() {
+        return Ok(());
+    }
+    println!(
+        "{}",
+        i18n::tr("status-largest", &[("count", hacks.len().into())])
+    );
+    for (id, total, phases) in hacks {
+        let title = manifest
+            .hacks
+            .get(&id)
+            .and_then(|h| h.title.clone())
+            .unwrap_or_else(|| i18n::tr("html-hack", &[("id", id.into())]));
+        let breakdown: Vec<_> = PHASES
+            .iter()
+            .zip(phases)
+            .filter(|(_, bytes)| **bytes > 0)
+            .map(|(phase, bytes)| format!("{} {}", phase_name(phase), human_size(*bytes)))
+            .collect();
+        println!(
+            "  {:>10}  {} (id {}): {}",
+            human_size(total),
+            title,
+            id,
+            breakdown.join(", ")
+        );
+    }
+    Ok(())
+}
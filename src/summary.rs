@@ -0,0 +1,289 @@
+use crate::i18n;
+use crate::output;
+use crate::schema::{self, Migration};
+use crate::status::human_size;
+use crate::utils::*;
+use console::{style, StyledObject};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub const REPORT_FILE: &str = "report.json";
+
+const MIGRATIONS: &[Migration] = &[schema::unchanged];
+
+type Style = fn(String) -> StyledObject<String>;
+
+// Why an item was passed over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Skip {
+    // The file is already on disk
+    AlreadyPresent,
+    // The hack has every one of its files already
+    UpToDate,
+    // On the config's blocklist
+    Blocklisted,
+    // Left out by the allowlist or a mode's own choice, like randomizers
+    FilteredOut,
+    // Set aside in the overrides as broken
+    Quarantined,
+    // Another run is working on it
+    Locked,
+}
+
+impl Skip {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Skip::AlreadyPresent => "already present",
+            Skip::UpToDate => "up to date",
+            Skip::Blocklisted => "blocklisted",
+            Skip::FilteredOut => "filtered out",
+            Skip::Quarantined => "quarantined",
+            Skip::Locked => "locked",
+        }
+    }
+
+    // How the run's summary table says it
+    pub fn message(self) -> String {
+        let id = match self {
+            Skip::AlreadyPresent => "skip-already-present",
+            Skip::UpToDate => "skip-up-to-date",
+            Skip::Blocklisted => "skip-blocklisted",
+            Skip::FilteredOut => "skip-filtered-out",
+            Skip::Quarantined => "skip-quarantined",
+            Skip::Locked => "skip-locked",
+        };
+        i18n::tr(id, &[])
+    }
+}
+
+// What a run got through, for the table printed when it ends
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tally {
+    pub processed: u64,
+    pub skipped: u64,
+    pub failed: u64,
+    pub bytes: u64,
+    // The items behind `skipped`, by why
+    #[serde(default)]
+    pub skips: BTreeMap<Skip, Vec<String>>,
+}
+
+// The last run of a mode, kept for `status`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Report {
+    pub finished: String,
+    #[serde(flatten)]
+    pub tally: Tally,
+    // Whether the hack list came from the site's API or was scraped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hack_list: Option<String>,
+}
+
+// Shared so modes running off the async runtime can count as well
+#[derive(Debug, Clone, Default)]
+pub struct Summary {
+    tally: Arc<Mutex<Tally>>,
+    // The files a run made, for `--json`
+    paths: Arc<Mutex<Vec<String>>>,
+    hack_list: Arc<Mutex<Option<String>>>,
+}
+
+impl Summary {
+    pub fn processed(&self, bytes: u64) {
+        let mut tally = self.tally.lock().unwrap();
+        tally.processed += 1;
+        tally.bytes += bytes;
+    }
+
+    pub fn skipped(&self, item: impl ToString, why: Skip) {
+        let mut tally = self.tally.lock().unwrap();
+        tally.skipped += 1;
+        tally.skips.entry(why).or_default().push(item.to_string());
+    }
+
+    pub fn failed(&self) {
+        self.tally.lock().unwrap().failed += 1;
+    }
+
+    pub fn wrote(&self, path: &Path) {
+        let path = path.to_string_lossy().to_string();
+        self.paths.lock().unwrap().push(path);
+    }
+
+    pub fn listed_from(&self, source: &str) {
+        *self.hack_list.lock().unwrap() = Some(source.to_owned());
+    }
+
+    pub fn tally(&self) -> Tally {
+        self.tally.lock().unwrap().clone()
+    }
+
+    // How the run went as one JSON document, for scripts
+    pub fn document(&self, mode: &str, error: Option<String>, elapsed: Duration) -> Value {
+        let tally = self.tally();
+        let skips: BTreeMap<_, _> = tally.skips.iter().map(|(s, i)| (*s, i.len())).collect();
+        json!({
+            "mode": mode,
+            "ok": error.is_none(),
+            "error": error,
+            "processed": tally.processed,
+            "skipped": tally.skipped,
+            "skips": skips,
+            "failed": tally.failed,
+            "bytes": tally.bytes,
+            "elapsed_secs": elapsed.as_secs_f64(),
+            "paths": *self.paths.lock().unwrap(),
+            "hack_list": *self.hack_list.lock().unwrap(),
+        })
+    }
+
+    // Modes that don't count items only get their elapsed time
+    pub fn table(&self, mode: &str, elapsed: Duration) -> String {
+        let tally = self.tally();
+        let secs = elapsed.as_secs();
+        let elapsed = format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+        let mut rows: Vec<(&str, String, Style)> = Vec::new();
+        if tally != Tally::default() {
+            rows.push((
+                "summary-processed",
+                tally.processed.to_string(),
+                output::success,
+            ));
+            rows.push(("summary-skipped", tally.skipped.to_string(), output::skip));
+            rows.push(("summary-failed", tally.failed.to_string(), output::error));
+            rows.push(("summary-bytes", human_size(tally.bytes), plain));
+        }
+        rows.push(("summary-elapsed", elapsed, plain));
+        let names: Vec<_> = rows.iter().map(|(id, _, _)| i18n::tr(id, &[])).collect();
+        // Translations may not fit the column the English names do
+        let width = names
+            .iter()
+            .map(|n| n.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max(10);
+        let mut table = format!("{}\n", i18n::tr("summary-title", &[("mode", mode.into())]));
+        for ((id, value, styled), name) in rows.into_iter().zip(names) {
+            let row = format!("  {:<width$} {:>12}", name, value);
+            // Zero counts are nothing to draw attention to
+            if value == "0" {
+                table.push_str(&format!("{}\n", row));
+            } else {
+                table.push_str(&format!("{}\n", styled(row)));
+            }
+            if id == "summary-skipped" {
+                for (why, items) in &tally.skips {
+                    table.push_str(&format!("    {:>5} {}\n", items.len(), why.message()));
+                }
+            }
+        }
+        table
+    }
+
+    // Keeps this run's counts as the last of `mode`'s, replacing the one
+    // before
+    pub fn save_report<P: AsRef<Path>>(&self, fname: P, mode: &str) -> ResultErr<()> {
+        let mut reports = load_reports(&fname)?;
+        let report = Report {
+            finished: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            tally: self.tally(),
+            hack_list: self.hack_list.lock().unwrap().clone(),
+        };
+        reports.insert(mode.to_owned(), report);
+        let mut value = json!({ "modes": reports });
+        schema::stamp(&mut value, MIGRATIONS);
+        write_json(fname, &value)
+    }
+}
+
+pub fn load_reports<P: AsRef<Path>>(fname: P) -> ResultErr<BTreeMap<String, Report>> {
+    if !fname.as_ref().exists() {
+        return Ok(BTreeMap::new());
+    }
+    let mut value = read_json(fname)?;
+    schema::upgrade(&mut value, MIGRATIONS, REPORT_FILE)?;
+    let modes = value.get("modes").cloned().unwrap_or_else(|| json!({}));
+    Ok(serde_json::from_value(modes)?)
+}
+
+fn plain(text: String) -> StyledObject<String> {
+    style(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status;
+    use crate::utils::tests::TestDir;
+
+    #[test]
+    fn summaries_only_show_counts_when_something_was_counted() {
+        console::set_colors_enabled(false);
+        let summary = Summary::default();
+        let elapsed = std::time::Duration::from_secs(3725);
+        assert_eq!(
+            summary.table("doctor", elapsed),
+            "doctor summary\n  elapsed         1:02:05\n"
+        );
+        summary.processed(2048);
+        summary.processed(1024);
+        summary.skipped("a.zip", Skip::AlreadyPresent);
+        summary.failed();
+        let table = summary.table("unzip", elapsed);
+        assert!(table.contains("  processed             2\n"), "{}", table);
+        assert!(table.contains("  skipped               1\n"), "{}", table);
+        assert!(table.contains("  failed                1\n"), "{}", table);
+        assert!(table.contains(&status::human_size(3072)), "{}", table);
+    }
+
+    #[test]
+    fn skip_reasons_are_reported_for_status() {
+        console::set_colors_enabled(false);
+        let summary = Summary::default();
+        summary.processed(1024);
+        summary.skipped("hack 20", Skip::Blocklisted);
+        summary.skipped("a.ips", Skip::Locked);
+        summary.skipped("b.ips", Skip::Locked);
+        let table = summary.table("patch", std::time::Duration::from_secs(1));
+        assert!(
+            table.contains("  skipped               3\n        1 blocklisted\n        2 locked\n"),
+            "{}",
+            table
+        );
+
+        let dir = TestDir::new("report");
+        let fname = dir.join(REPORT_FILE);
+        summary.save_report(&fname, "patch").unwrap();
+        let reports = load_reports(&fname).unwrap();
+        assert_eq!(
+            reports["patch"].tally.skips[&Skip::Locked],
+            ["a.ips", "b.ips"]
+        );
+        let lines = status::last_runs(&reports);
+        assert_eq!(lines[1], "  skipped: 1 blocklisted, 2 locked");
+    }
+
+    #[test]
+    fn json_documents_carry_the_outcome_counts_and_paths() {
+        let summary = Summary::default();
+        summary.processed(1024);
+        summary.skipped("b.ips", Skip::Locked);
+        summary.wrote(Path::new("patched/a.sfc"));
+        let elapsed = std::time::Duration::from_millis(1500);
+        let document = summary.document("patch", None, elapsed);
+        assert_eq!(document["ok"], true);
+        assert_eq!(document["error"], serde_json::Value::Null);
+        assert_eq!(document["skips"]["locked"], 1);
+        assert_eq!(document["paths"], serde_json::json!(["patched/a.sfc"]));
+        assert_eq!(document["elapsed_secs"], 1.5);
+
+        let failed = summary.document("patch", Some("no base ROM".to_owned()), elapsed);
+        assert_eq!(failed["ok"], false);
+        assert_eq!(failed["error"], "no base ROM");
+    }
+}
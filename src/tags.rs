@@ -0,0 +1,139 @@
+use crate::catalog::Catalog;
+use crate::schema::{self, Migration};
+use crate::utils::*;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::Path;
+
+pub const TAGS_FILE: &str = "tags.json";
+
+const MIGRATIONS: &[Migration] = &[schema::unchanged];
+
+// The user's own tags on hacks, each with an optional note. Never touched by
+// scraping, so they survive every metadata run.
+#[derive(Debug, Default)]
+pub struct Tags {
+    pub hacks: BTreeMap<u32, BTreeMap<String, String>>,
+}
+
+// Tags compare regardless of case and surrounding spaces
+fn normalize(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+impl Tags {
+    pub fn load<P: AsRef<Path>>(fname: P) -> ResultErr<Tags> {
+        if !fname.as_ref().exists() {
+            return Ok(Tags::default());
+        }
+        let mut value = read_json(fname)?;
+        schema::upgrade(&mut value, MIGRATIONS, TAGS_FILE)?;
+        let hacks = value.get("hacks").cloned().unwrap_or_else(|| json!({}));
+        Ok(Tags {
+            hacks: serde_json::from_value(hacks)?,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, fname: P) -> ResultErr<()> {
+        let mut value = json!({ "hacks": self.hacks });
+        schema::stamp(&mut value, MIGRATIONS);
+        write_json(fname, &value)
+    }
+
+    // Tagging a hack again replaces the note
+    pub fn add(&mut self, id: u32, tag: &str, note: &str) -> ResultErr<()> {
+        let tag = normalize(tag);
+        if tag.is_empty() {
+            return Err("tags can't be empty".into());
+        }
+        self.hacks
+            .entry(id)
+            .or_default()
+            .insert(tag, note.to_owned());
+        Ok(())
+    }
+
+    // Whether the hack had the tag
+    pub fn remove(&mut self, id: u32, tag: &str) -> bool {
+        let Some(tags) = self.hacks.get_mut(&id) else {
+            return false;
+        };
+        let removed = tags.remove(&normalize(tag)).is_some();
+        if tags.is_empty() {
+            self.hacks.remove(&id);
+        }
+        removed
+    }
+
+    // The hacks with any of `tags`, for the modes that take `--tag`. A tag
+    // on no hack at all is most likely a typo.
+    pub fn select(&self, tags: &[String]) -> ResultErr<BTreeSet<u32>> {
+        let mut ids = BTreeSet::new();
+        for tag in tags {
+            let tag = normalize(tag);
+            let tagged: Vec<_> = self
+                .hacks
+                .iter()
+                .filter(|(_, t)| t.contains_key(&tag))
+                .map(|(id, _)| *id)
+                .collect();
+            if tagged.is_empty() {
+                return Err(format!("no hack is tagged {:?}, see `tag list`", tag).into());
+            }
+            ids.extend(tagged);
+        }
+        Ok(ids)
+    }
+}
+
+// Every tagged hack with its tags and notes, or only those with `tag`
+pub fn write_tags(
+    tags: &Tags,
+    catalog: &Catalog,
+    tag: Option<&str>,
+    out: &mut dyn Write,
+) -> ResultErr<()> {
+    let tag = tag.map(normalize);
+    for (id, hack_tags) in &tags.hacks {
+        if tag.as_ref().is_some_and(|t| !hack_tags.contains_key(t)) {
+            continue;
+        }
+        let title = catalog.hacks.get(id).map(|h| h.title.as_str());
+        writeln!(out, "{} (id {})", title.unwrap_or("unknown title"), id)?;
+        for (name, note) in hack_tags {
+            if note.is_empty() {
+                writeln!(out, "  {}", name)?;
+            } else {
+                writeln!(out, "  {}: {}", name, note)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_select_hacks_regardless_of_case() {
+        let mut tags = Tags::default();
+        tags.add(756, "Favorite", "great physics").unwrap();
+        tags.add(12, " favorite ", "").unwrap();
+        tags.add(12, "speedrun", "").unwrap();
+        assert!(tags.add(5, " ", "").is_err());
+        let selected = tags.select(&["FAVORITE".to_owned()]).unwrap();
+        assert_eq!(selected.into_iter().collect::<Vec<_>>(), [12, 756]);
+        assert!(tags.select(&["favourite".to_owned()]).is_err());
+
+        assert!(tags.remove(12, "Speedrun"));
+        assert!(!tags.remove(12, "speedrun"));
+        let mut out = Vec::new();
+        write_tags(&tags, &Catalog::default(), Some("favorite"), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "unknown title (id 12)\n  favorite\nunknown title (id 756)\n  favorite: great physics\n"
+        );
+    }
+}
@@ -0,0 +1,346 @@
+use crate::manifest::Manifest;
+use crate::utils::*;
+use indicatif::ProgressBar;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+
+// BEP 52 fixes the merkle leaf size at 16KiB
+const BLOCK_SIZE: usize = 16 * 1024;
+const PIECE_LENGTH: usize = 1024 * 1024;
+const TORRENT_NAME: &str = "metconst-mirror";
+
+enum Bencode {
+    Int(i64),
+    Bytes(Vec<u8>),
+    List(Vec<Bencode>),
+    Dict(BTreeMap<Vec<u8>, Bencode>),
+}
+
+impl Bencode {
+    fn str(s: &str) -> Bencode {
+        Bencode::Bytes(s.as_bytes().to_vec())
+    }
+
+    fn dict<const N: usize>(entries: [(&str, Bencode); N]) -> Bencode {
+        Bencode::Dict(
+            entries
+                .into_iter()
+                .map(|(k, v)| (k.as_bytes().to_vec(), v))
+                .collect(),
+        )
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Bencode::Int(i) => out.extend_from_slice(format!("i{}e", i).as_bytes()),
+            Bencode::Bytes(b) => {
+                out.extend_from_slice(format!("{}:", b.len()).as_bytes());
+                out.extend_from_slice(b);
+            }
+            Bencode::List(l) => {
+                out.push(b'l');
+                l.iter().for_each(|v| v.encode(out));
+                out.push(b'e');
+            }
+            Bencode::Dict(d) => {
+                // BTreeMap keeps the keys in the raw byte order bencode requires
+                out.push(b'd');
+                for (k, v) in d {
+                    Bencode::Bytes(k.clone()).encode(out);
+                    v.encode(out);
+                }
+                out.push(b'e');
+            }
+        }
+    }
+}
+
+struct FileHashes {
+    // v1: sha1 of every piece, the last one zero padded when requested
+    pieces: Vec<u8>,
+    // v2: merkle root over the 16KiB blocks, None for empty files
+    pieces_root: Option<[u8; 32]>,
+    // v2: the merkle layer whose nodes each cover exactly one piece
+    piece_layer: Vec<u8>,
+}
+
+// Fill `buf` as far as possible, only returning a short count at EOF
+fn read_block(reader: &mut dyn Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+fn hash_file(path: &Path, pad_last_piece: bool) -> ResultErr<FileHashes> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buf = vec![0u8; BLOCK_SIZE];
+    let mut leaves = Vec::new();
+    let mut pieces = Vec::new();
+    let mut piece_hasher = Sha1::new();
+    let mut in_piece = 0;
+    let mut size = 0;
+    loop {
+        let n = read_block(&mut reader, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        size += n;
+        leaves.push(Into::<[u8; 32]>::into(Sha256::digest(&buf[..n])));
+        piece_hasher.update(&buf[..n]);
+        in_piece += n;
+        if in_piece == PIECE_LENGTH {
+            pieces.extend_from_slice(&piece_hasher.finalize_reset());
+            in_piece = 0;
+        }
+    }
+    if in_piece > 0 {
+        // Hybrid torrents put a padding file after every file but the last,
+        // and the zeros of that padding file are part of the v1 piece
+        if pad_last_piece {
+            piece_hasher.update(vec![0u8; PIECE_LENGTH - in_piece]);
+        }
+        pieces.extend_from_slice(&piece_hasher.finalize());
+    }
+
+    if leaves.is_empty() {
+        return Ok(FileHashes {
+            pieces,
+            pieces_root: None,
+            piece_layer: Vec::new(),
+        });
+    }
+
+    let num_pieces = size.div_ceil(PIECE_LENGTH);
+    let blocks_per_piece = PIECE_LENGTH / BLOCK_SIZE;
+    let mut layer = leaves;
+    layer.resize(layer.len().next_power_of_two(), [0u8; 32]);
+    let mut width = 1;
+    let mut piece_layer = Vec::new();
+    loop {
+        // Files no bigger than a piece don't carry a piece layer
+        if width == blocks_per_piece && size > PIECE_LENGTH {
+            piece_layer = layer[..num_pieces].concat();
+        }
+        if layer.len() == 1 {
+            break;
+        }
+        layer = layer
+            .chunks(2)
+            .map(|pair| {
+                let mut hasher = Sha256::new();
+                hasher.update(pair[0]);
+                hasher.update(pair[1]);
+                hasher.finalize().into()
+            })
+            .collect();
+        width *= 2;
+    }
+    Ok(FileHashes {
+        pieces,
+        pieces_root: Some(layer[0]),
+        piece_layer,
+    })
+}
+
+fn insert_file_tree(tree: &mut BTreeMap<Vec<u8>, Bencode>, components: &[&str], leaf: Bencode) {
+    let key = components[0].as_bytes().to_vec();
+    if components.len() == 1 {
+        tree.insert(key, Bencode::dict([("", leaf)]));
+        return;
+    }
+    let subtree = tree
+        .entry(key)
+        .or_insert_with(|| Bencode::Dict(BTreeMap::new()));
+    if let Bencode::Dict(subtree) = subtree {
+        insert_file_tree(subtree, &components[1..], leaf);
+    }
+}
+
+// Build a v1/v2 hybrid torrent of every file in the manifest and write it to
+// `output`. Returns the magnet link for the torrent.
+pub fn write_torrent(
+    manifest: &Manifest,
+    root: &Path,
+    output: &Path,
+    trackers: &[String],
+    log: &mut dyn Write,
+) -> ResultErr<String> {
+    // The v1 file list has to follow the same order as the v2 file tree
+    let mut files: Vec<_> = manifest.files().collect();
+    files.sort_by(|a, b| {
+        let a: Vec<_> = a.path.split('/').collect();
+        let b: Vec<_> = b.path.split('/').collect();
+        a.cmp(&b)
+    });
+
+    // Hybrid torrents pad every file up to a piece boundary but the last
+    // one with any data, as nothing past that needs aligning
+    let last_data = files.iter().rposition(|f| f.size > 0);
+
+    let pb = ProgressBar::new(files.len() as u64);
+    let mut v1_files = Vec::new();
+    let mut file_tree = BTreeMap::new();
+    let mut pieces = Vec::new();
+    let mut piece_layers = BTreeMap::new();
+    for (idx, entry) in files.iter().enumerate() {
+        let path = root.join(&entry.path);
+        let size = fs::metadata(&path)?.len();
+        if size != entry.size {
+            return Err(
+                format!("{} changed since it was added to the manifest", entry.path).into(),
+            );
+        }
+        writeln!(log, "Hashing {:?}", path)?;
+        let padded = last_data.is_some_and(|last| idx < last);
+        let hashes = hash_file(&path, padded)?;
+        pieces.extend_from_slice(&hashes.pieces);
+
+        let components: Vec<_> = entry.path.split('/').collect();
+        v1_files.push(Bencode::dict([
+            ("length", Bencode::Int(size as i64)),
+            (
+                "path",
+                Bencode::List(components.iter().map(|c| Bencode::str(c)).collect()),
+            ),
+        ]));
+        let padding = (PIECE_LENGTH - size as usize % PIECE_LENGTH) % PIECE_LENGTH;
+        if padded && padding > 0 {
+            v1_files.push(Bencode::dict([
+                ("attr", Bencode::str("p")),
+                ("length", Bencode::Int(padding as i64)),
+                (
+                    "path",
+                    Bencode::List(vec![
+                        Bencode::str(".pad"),
+                        Bencode::str(&padding.to_string()),
+                    ]),
+                ),
+            ]));
+        }
+
+        let leaf = match hashes.pieces_root {
+            Some(root) => {
+                if !hashes.piece_layer.is_empty() {
+                    piece_layers.insert(root.to_vec(), Bencode::Bytes(hashes.piece_layer));
+                }
+                Bencode::dict([
+                    ("length", Bencode::Int(size as i64)),
+                    ("pieces root", Bencode::Bytes(root.to_vec())),
+                ])
+            }
+            None => Bencode::dict([("length", Bencode::Int(0))]),
+        };
+        insert_file_tree(&mut file_tree, &components, leaf);
+        pb.inc(1);
+    }
+    pb.finish_with_message("done");
+
+    let info = Bencode::dict([
+        ("file tree", Bencode::Dict(file_tree)),
+        ("files", Bencode::List(v1_files)),
+        ("meta version", Bencode::Int(2)),
+        ("name", Bencode::str(TORRENT_NAME)),
+        ("piece length", Bencode::Int(PIECE_LENGTH as i64)),
+        ("pieces", Bencode::Bytes(pieces)),
+    ]);
+    let mut info_bytes = Vec::new();
+    info.encode(&mut info_bytes);
+
+    let mut torrent = BTreeMap::new();
+    torrent.insert(b"created by".to_vec(), Bencode::str("metconst-tool"));
+    torrent.insert(b"info".to_vec(), info);
+    torrent.insert(b"piece layers".to_vec(), Bencode::Dict(piece_layers));
+    if let Some(first) = trackers.first() {
+        torrent.insert(b"announce".to_vec(), Bencode::str(first));
+        torrent.insert(
+            b"announce-list".to_vec(),
+            Bencode::List(
+                trackers
+                    .iter()
+                    .map(|t| Bencode::List(vec![Bencode::str(t)]))
+                    .collect(),
+            ),
+        );
+    }
+    let mut torrent_bytes = Vec::new();
+    Bencode::Dict(torrent).encode(&mut torrent_bytes);
+    fs::write(output, torrent_bytes)?;
+
+    let mut magnet = format!(
+        "magnet:?xt=urn:btih:{}&xt=urn:btmh:1220{}&dn={}",
+        to_hex(&Sha1::digest(&info_bytes)),
+        to_hex(&Sha256::digest(&info_bytes)),
+        TORRENT_NAME
+    );
+    for tracker in trackers {
+        magnet.push_str("&tr=");
+        magnet.push_str(&url_encode(tracker));
+    }
+    writeln!(log, "Wrote {:?}: {}", output, magnet)?;
+    Ok(magnet)
+}
+
+fn url_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::TestDir;
+
+    #[test]
+    fn torrents_match_known_hashes_and_pad_only_between_data() {
+        let dir = TestDir::new("torrent");
+        let hack_dir = dir.join("0001-5-Test");
+        fs::create_dir_all(&hack_dir).unwrap();
+        let mut manifest = Manifest::default();
+        let big: Vec<u8> = (0..40000).map(|i| (i % 251) as u8).collect();
+        for (name, contents) in [
+            ("a.ips", &b"hello"[..]),
+            ("b.ips", &big),
+            ("readme.txt", b""),
+        ] {
+            fs::write(hack_dir.join(name), contents).unwrap();
+            manifest
+                .record_file(&dir, 5, None, "0001-5-Test", &hack_dir.join(name))
+                .unwrap();
+        }
+        let output = dir.join("mirror.torrent");
+        let magnet = write_torrent(&manifest, &dir, &output, &[], &mut std::io::sink()).unwrap();
+        let torrent = fs::read(&output).unwrap();
+
+        // Worked out separately from BEP 47 and 52: a.ips is padded to the
+        // end of its piece, b.ips isn't as only an empty file follows it
+        assert!(magnet.starts_with("magnet:?xt=urn:btih:3c185482768595d02508734cf5e303242e817c3e&"));
+        let roots = [
+            // One block, so the root is its hash
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+            // Three blocks and a zero one
+            "ab671631a9fa97a1fdac651fff6c68773b9acf0735b9c7f6ecdd54cbf1bf5dc2",
+        ];
+        for root in roots {
+            let mut field = b"11:pieces root32:".to_vec();
+            field.extend((0..32).map(|i| u8::from_str_radix(&root[i * 2..i * 2 + 2], 16).unwrap()));
+            assert!(torrent.windows(field.len()).any(|w| w == field), "{}", root);
+        }
+        let pads = torrent.windows(4).filter(|w| w == b".pad").count();
+        assert_eq!(pads, 1);
+    }
+}
@@ -1,45 +1,338 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
 use std::fs::{File, OpenOptions};
-use std::io::{BufWriter, Write};
-use std::path::Path;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 use walkdir::{DirEntry, WalkDir};
 
 pub type ResultErr<T> = Result<T, Box<dyn std::error::Error>>;
 
+// For files a mode produces, which stay in the working directory wherever
+// the logs go
+pub fn create_output(path: impl AsRef<Path>) -> ResultErr<BufWriter<File>> {
+    Ok(BufWriter::new(File::create(path)?))
+}
+
+// Where open_log puts logs, when not in the working directory
+static LOG_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+pub fn set_log_dir(dir: PathBuf) -> ResultErr<()> {
+    std::fs::create_dir_all(&dir)?;
+    LOG_DIR.set(dir).map_err(|_| "log directory already set")?;
+    Ok(())
+}
+
+pub fn log_dir() -> Option<&'static Path> {
+    LOG_DIR.get().map(|d| d.as_path())
+}
+
 pub fn open_log(fname: &str) -> ResultErr<BufWriter<File>> {
+    let path = match LOG_DIR.get() {
+        Some(dir) => dir.join(fname),
+        None => PathBuf::from(fname),
+    };
     let log = OpenOptions::new()
         .read(true)
         .write(true)
         .create(true)
         .truncate(true)
-        .open(fname)?;
+        .open(path)?;
     Ok(BufWriter::new(log))
 }
 
-pub fn is_zip_file(entry: &DirEntry) -> bool {
-    entry.file_type().is_dir()
-        || entry
+// Set when a run is asked to stop, e.g. by Ctrl-C. Work checks it between
+// items, and through its deadline while working on one.
+#[derive(Debug, Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+// The error work gives up with once cancelled
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+impl Cancel {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn check(&self) -> ResultErr<()> {
+        if self.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        Ok(())
+    }
+}
+
+// Work on a single item checks this regularly and gives up once it passes,
+// or once the run is cancelled
+#[derive(Debug, Clone)]
+pub struct Deadline {
+    at: Instant,
+    limit: Duration,
+    cancel: Cancel,
+}
+
+impl Deadline {
+    pub fn after(limit: Duration, cancel: &Cancel) -> Deadline {
+        Deadline {
+            at: Instant::now() + limit,
+            limit,
+            cancel: cancel.clone(),
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        Instant::now() >= self.at || self.cancel.is_cancelled()
+    }
+
+    pub fn check(&self) -> ResultErr<()> {
+        self.cancel.check()?;
+        if self.passed() {
+            return Err(format!("timed out after {}s", self.limit.as_secs()).into());
+        }
+        Ok(())
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn sha256_file<P: AsRef<Path>>(fname: P) -> ResultErr<String> {
+    let mut reader = BufReader::new(File::open(fname)?);
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut reader, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+// Characters FAT32 doesn't allow in names
+const FAT_RESERVED: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+// Longest name `fat32_safe_name` produces, which keeps whole paths well
+// under the 260 characters Windows tools still choke on
+pub const FAT32_MAX_NAME: usize = 64;
+
+// Replace whatever a FAT32 filesystem (or an old flashcart menu reading
+// one) can't show with '_'
+pub fn fat32_chars(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if !c.is_ascii() || c.is_ascii_control() || FAT_RESERVED.contains(&c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+// Cut `cleaned` down to `max` characters, keeping its extension. The cut
+// name gets a hash of `original` so two names sharing a long prefix don't
+// end up the same.
+fn shorten(cleaned: &str, original: &str, max: usize) -> String {
+    let (stem, ext) = match cleaned.rsplit_once('.') {
+        Some((stem, ext))
+            if !stem.is_empty()
+                && ext.len() <= 4
+                && ext.chars().all(|c| c.is_ascii_alphanumeric()) =>
+        {
+            (stem, format!(".{}", ext))
+        }
+        _ => (cleaned, String::new()),
+    };
+    let stem = stem.trim_end_matches(['.', ' ']);
+    if stem.chars().count() + ext.len() <= max {
+        return format!("{}{}", stem, ext);
+    }
+    let hash = to_hex(&Sha256::digest(original.as_bytes()));
+    let keep = max.saturating_sub(ext.len() + 5);
+    let short: String = stem.chars().take(keep).collect();
+    let short = short.trim_end_matches(['.', ' ']);
+    format!("{}~{}{}", short, &hash[..4], ext)
+}
+
+// A FAT32 safe version of a file or directory name. Long names are cut from
+// the end, so a leading `NNNN-ID-` keeps the hack id.
+pub fn fat32_safe_name(name: &str) -> String {
+    shorten(&fat32_chars(name), name, FAT32_MAX_NAME)
+}
+
+// Letters NFKD doesn't split into a base letter and accents
+fn transliterate_char(c: char) -> Option<&'static str> {
+    Some(match c {
+        'ß' => "ss",
+        'æ' => "ae",
+        'Æ' => "AE",
+        'œ' => "oe",
+        'Œ' => "OE",
+        'ø' => "o",
+        'Ø' => "O",
+        'ł' => "l",
+        'Ł' => "L",
+        'đ' | 'ð' => "d",
+        'Đ' | 'Ð' => "D",
+        'þ' => "th",
+        'Þ' => "TH",
+        '‘' | '’' | '‚' | '′' => "'",
+        '“' | '”' | '„' | '″' => "\"",
+        '–' | '—' | '‐' | '−' => "-",
+        '×' => "x",
+        _ => return None,
+    })
+}
+
+// ASCII spelling of `name`, accents dropped, with '_' for whatever has none
+pub fn transliterate(name: &str) -> String {
+    let mut out = String::new();
+    for c in name.nfkd() {
+        if c.is_ascii() {
+            out.push(c);
+        } else if is_combining_mark(c) {
+            continue;
+        } else {
+            out.push_str(transliterate_char(c).unwrap_or("_"));
+        }
+    }
+    out
+}
+
+// How names taken from the site and from archives are turned into file
+// names, on top of removing what no filesystem allows. Everything off keeps
+// the names as they are.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NameRules {
+    #[serde(rename = "name_transliterate")]
+    pub transliterate: bool,
+    #[serde(rename = "name_underscores")]
+    pub underscores: bool,
+    #[serde(rename = "name_lowercase")]
+    pub lowercase: bool,
+    #[serde(rename = "name_max_len")]
+    pub max_len: Option<usize>,
+}
+
+impl NameRules {
+    pub fn apply(&self, name: &str) -> String {
+        let mut cleaned = if self.transliterate {
+            transliterate(name)
+        } else {
+            name.to_owned()
+        };
+        if self.underscores {
+            cleaned = cleaned.replace(' ', "_");
+        }
+        if self.lowercase {
+            cleaned = cleaned.to_lowercase();
+        }
+        match self.max_len {
+            Some(max) => shorten(&cleaned, name, max),
+            None => cleaned,
+        }
+    }
+}
+
+pub fn json_str(value: &Value, key: &str) -> Option<String> {
+    value
+        .get(key)
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_owned())
+}
+
+pub fn json_u64(value: &Value, key: &str) -> Option<u64> {
+    value.get(key).and_then(|v| v.as_u64())
+}
+
+pub fn json_str_list(value: &Value, key: &str) -> Vec<String> {
+    value
+        .get(key)
+        .and_then(|v| v.as_array())
+        .map(|a| {
+            a.iter()
+                .filter_map(|v| v.as_str().map(|s| s.to_owned()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn read_json<P: AsRef<Path>>(fname: P) -> ResultErr<Value> {
+    let reader = BufReader::new(File::open(fname)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+// Write to a temporary file first so an interrupted run never leaves a
+// truncated file behind
+pub fn write_json<P: AsRef<Path>>(fname: P, value: &Value) -> ResultErr<()> {
+    let fname = fname.as_ref();
+    let mut tmp = fname.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    serde_json::to_writer_pretty(&mut writer, value)?;
+    writer.flush()?;
+    drop(writer);
+    std::fs::rename(tmp, fname)?;
+    Ok(())
+}
+
+// Names inside downloaded archives are untrusted, so only accept relative
+// paths that stay inside the extraction directory
+pub fn archive_entry_path(dest: &Path, name: &str, names: &NameRules) -> ResultErr<PathBuf> {
+    let name = name.replace('\\', "/");
+    let mut path = dest.to_path_buf();
+    let mut parts = 0;
+    for component in Path::new(&name).components() {
+        match component {
+            Component::Normal(part) => {
+                path.push(names.apply(&part.to_string_lossy()));
+                parts += 1;
+            }
+            Component::CurDir => (),
+            _ => return Err(format!("unsafe path in archive: {:?}", name).into()),
+        }
+    }
+    if parts == 0 {
+        return Err(format!("empty path in archive: {:?}", name).into());
+    }
+    Ok(path)
+}
+
+// Whether a file's name ends in `ext`, e.g. ".ZIP", ignoring case
+fn has_extension(entry: &DirEntry, ext: &str) -> bool {
+    entry.file_type().is_file()
+        && entry
             .file_name()
             .to_str()
-            .map(|s| s.to_ascii_uppercase().ends_with(".ZIP"))
+            .map(|s| s.to_ascii_uppercase().ends_with(ext))
             .unwrap_or(false)
 }
 
+pub fn is_zip_file(entry: &DirEntry) -> bool {
+    has_extension(entry, ".ZIP")
+}
+
 pub fn is_rar_file(entry: &DirEntry) -> bool {
-    entry.file_type().is_dir()
-        || entry
-            .file_name()
-            .to_str()
-            .map(|s| s.to_ascii_uppercase().ends_with(".RAR"))
-            .unwrap_or(false)
+    has_extension(entry, ".RAR")
 }
 
 pub fn is_7z_file(entry: &DirEntry) -> bool {
-    entry.file_type().is_dir()
-        || entry
-            .file_name()
-            .to_str()
-            .map(|s| s.to_ascii_uppercase().ends_with(".7Z"))
-            .unwrap_or(false)
+    has_extension(entry, ".7Z")
 }
 
 pub fn is_archive_file(entry: &DirEntry) -> bool {
@@ -47,26 +340,55 @@ pub fn is_archive_file(entry: &DirEntry) -> bool {
 }
 
 pub fn is_ips_file(entry: &DirEntry) -> bool {
-    entry.file_type().is_dir()
-        || entry
-            .file_name()
-            .to_str()
-            .map(|s| s.to_ascii_uppercase().ends_with(".IPS"))
-            .unwrap_or(false)
+    has_extension(entry, ".IPS")
 }
 
-pub fn process_directory<Action, Filter, Dir>(
-    mut action: Action,
+pub fn is_bps_file(entry: &DirEntry) -> bool {
+    has_extension(entry, ".BPS")
+}
+
+pub fn is_ups_file(entry: &DirEntry) -> bool {
+    has_extension(entry, ".UPS")
+}
+
+// xdelta3 patches, which some tools name .vcdiff
+pub fn is_xdelta_file(entry: &DirEntry) -> bool {
+    has_extension(entry, ".XDELTA") || has_extension(entry, ".VCDIFF")
+}
+
+pub fn is_asm_file(entry: &DirEntry) -> bool {
+    has_extension(entry, ".ASM")
+}
+
+pub fn is_txt_file(entry: &DirEntry) -> bool {
+    has_extension(entry, ".TXT")
+}
+
+// For walks that look in every directory
+pub fn any_dir(_: &DirEntry) -> bool {
+    true
+}
+
+// The files under `start_dir` that `select` picks, without looking in the
+// directories `descend` turns down
+pub fn matching_files<Descend, Select, Dir>(
     start_dir: Dir,
-    filter: Filter,
+    mut descend: Descend,
+    mut select: Select,
     log: &mut dyn Write,
-) -> ResultErr<()>
+) -> ResultErr<Vec<DirEntry>>
 where
-    Filter: FnMut(&DirEntry) -> bool,
-    Action: FnMut(&DirEntry, &mut dyn Write) -> ResultErr<()>,
+    Descend: FnMut(&DirEntry) -> bool,
+    Select: FnMut(&DirEntry) -> bool,
     Dir: AsRef<Path>,
 {
-    for entry in WalkDir::new(start_dir).into_iter().filter_entry(filter) {
+    // Sorted so every run visits (and logs) files in the same order
+    let walker = WalkDir::new(start_dir).sort_by_file_name();
+    let mut files = Vec::new();
+    for entry in walker
+        .into_iter()
+        .filter_entry(|e| !e.file_type().is_dir() || descend(e))
+    {
         let entry = match entry {
             Ok(e) => e,
             Err(e) => {
@@ -75,26 +397,164 @@ where
                 continue;
             }
         };
+        if entry.file_type().is_file() && select(&entry) {
+            files.push(entry);
+        }
+    }
+    Ok(files)
+}
+
+pub fn process_directory<Action, Descend, Select, Dir>(
+    mut action: Action,
+    start_dir: Dir,
+    descend: Descend,
+    select: Select,
+    log: &mut dyn Write,
+) -> ResultErr<()>
+where
+    Descend: FnMut(&DirEntry) -> bool,
+    Select: FnMut(&DirEntry) -> bool,
+    Action: FnMut(&DirEntry, &mut dyn Write) -> ResultErr<()>,
+    Dir: AsRef<Path>,
+{
+    for entry in matching_files(start_dir, descend, select, log)? {
         //println!("{:?}", entry.path());
-        if entry.file_type().is_file() {
-            let result = action(&entry, log);
-            match result {
-                Ok(()) => (),
-                Err(e) => {
-                    eprintln!(
-                        "Hit an error on {}, but continuing: {}",
-                        entry.path().to_string_lossy(),
-                        e
-                    );
-                    writeln!(
-                        log,
-                        "Hit an error on {}, but continuing: {}",
-                        entry.path().to_string_lossy(),
-                        e
-                    )?;
-                }
+        let result = action(&entry, log);
+        match result {
+            Ok(()) => (),
+            // Nothing else gets done once the run is cancelled
+            Err(e) if e.is::<Cancelled>() => return Err(e),
+            Err(e) => {
+                eprintln!(
+                    "Hit an error on {}, but continuing: {}",
+                    entry.path().to_string_lossy(),
+                    e
+                );
+                writeln!(
+                    log,
+                    "Hit an error on {}, but continuing: {}",
+                    entry.path().to_string_lossy(),
+                    e
+                )?;
             }
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::fs;
+    use std::ops::Deref;
+
+    // A fresh directory for one test, named after it, and removed again
+    // once the test is done with it whether it passed or not
+    pub struct TestDir(PathBuf);
+
+    impl TestDir {
+        pub fn new(name: &str) -> TestDir {
+            let dir =
+                std::env::temp_dir().join(format!("metconst-{}-{}", name, std::process::id()));
+            if dir.exists() {
+                fs::remove_dir_all(&dir).unwrap();
+            }
+            fs::create_dir_all(&dir).unwrap();
+            TestDir(dir)
+        }
+    }
+
+    impl Deref for TestDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl AsRef<Path> for TestDir {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn fat32_names_keep_the_hack_id_and_stay_distinct() {
+        let title = "Super Metroid: Redesign ".repeat(4);
+        let first = fat32_safe_name(&format!("0042-1234-{} v1.sfc", title));
+        let second = fat32_safe_name(&format!("0042-1234-{} v2.sfc", title));
+        assert!(first.starts_with("0042-1234-Super Metroid_ Redesign"));
+        assert!(first.ends_with(".sfc"));
+        assert_eq!(first.len(), FAT32_MAX_NAME);
+        assert_ne!(first, second);
+        assert_eq!(fat32_safe_name("Hack?.ips. "), "Hack_.ips");
+    }
+
+    #[test]
+    fn name_rules_apply_in_order() {
+        let rules = NameRules {
+            transliterate: true,
+            underscores: true,
+            lowercase: true,
+            max_len: Some(24),
+        };
+        assert_eq!(rules.apply("Ænima’s Métroid.ips"), "aenima's_metroid.ips");
+        let long = rules.apply("Super Metroid: Straße der Sterne.ips");
+        assert_eq!(long.chars().count(), 24);
+        assert!(long.starts_with("super_metroid:_~") && long.ends_with(".ips"));
+        assert_eq!(
+            NameRules::default().apply("Métroid 漢.ips"),
+            "Métroid 漢.ips"
+        );
+    }
+
+    #[test]
+    fn walks_pick_files_and_prune_directories_separately() {
+        let dir = TestDir::new("walk");
+        fs::create_dir_all(dir.join("0001-1-Hack/v1.ips")).unwrap();
+        fs::create_dir_all(dir.join("staging")).unwrap();
+        fs::write(dir.join("0001-1-Hack/v1.ips/readme.txt"), "").unwrap();
+        fs::write(dir.join("0001-1-Hack/hack.IPS"), "").unwrap();
+        fs::write(dir.join("staging/half.ips"), "").unwrap();
+        let files = matching_files(
+            &dir,
+            |e| e.file_name() != "staging",
+            is_ips_file,
+            &mut std::io::sink(),
+        )
+        .unwrap();
+        let names: Vec<_> = files.iter().map(|e| e.file_name().to_owned()).collect();
+        assert_eq!(names, ["hack.IPS"]);
+    }
+
+    #[test]
+    fn cancelled_walks_stop_instead_of_skipping_ahead() {
+        let dir = TestDir::new("cancel");
+        for name in ["a.ips", "b.ips", "c.ips"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let cancel = Cancel::default();
+        let mut done = Vec::new();
+        let walked = process_directory(
+            |f, _| {
+                // The deadline notices too, for work that's already started
+                Deadline::after(std::time::Duration::from_secs(60), &cancel).check()?;
+                done.push(f.file_name().to_owned());
+                cancel.cancel();
+                Ok(())
+            },
+            &dir,
+            any_dir,
+            is_ips_file,
+            &mut std::io::sink(),
+        );
+        assert!(walked.unwrap_err().is::<Cancelled>());
+        assert_eq!(done, ["a.ips"]);
+    }
+}
@@ -0,0 +1,252 @@
+use crate::utils::*;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use reqwest::header::{HeaderMap, HOST, TRANSFER_ENCODING};
+use reqwest::{Request, Response, Version};
+use reqwest_middleware::{Middleware, Next};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use task_local_extensions::Extensions;
+
+// Where fetched pages and files are archived when `--warc` has no file name
+pub const DEFAULT_WARC_FILE: &str = "mirror.warc.gz";
+
+// Appends every request the site answers, and the answer, to a WARC file as a
+// request and a response record. A name ending in `.gz` gzips each record on
+// its own, the way archive tools expect.
+#[derive(Debug, Clone)]
+pub struct WarcWriter {
+    file: Arc<Mutex<File>>,
+    gzip: bool,
+    records: Arc<Mutex<u64>>,
+}
+
+fn version(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_09 => "HTTP/0.9",
+        Version::HTTP_10 => "HTTP/1.0",
+        Version::HTTP_2 => "HTTP/2",
+        Version::HTTP_3 => "HTTP/3",
+        _ => "HTTP/1.1",
+    }
+}
+
+// RFC 4648 base32, which WARC digests are written in
+pub fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut out = String::new();
+    for chunk in bytes.chunks(5) {
+        let mut buf = [0u8; 5];
+        buf[..chunk.len()].copy_from_slice(chunk);
+        let bits = buf.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+        let chars = (chunk.len() * 8).div_ceil(5);
+        for i in 0..8 {
+            if i < chars {
+                out.push(ALPHABET[((bits >> (35 - i * 5)) & 31) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn sha1_digest(bytes: &[u8]) -> String {
+    format!("sha1:{}", base32(&Sha1::digest(bytes)))
+}
+
+fn write_headers(block: &mut Vec<u8>, headers: &HeaderMap) {
+    for (name, value) in headers {
+        // The body is stored without the chunking it may have come in
+        if name == TRANSFER_ENCODING {
+            continue;
+        }
+        block.extend_from_slice(name.as_str().as_bytes());
+        block.extend_from_slice(b": ");
+        block.extend_from_slice(value.as_bytes());
+        block.extend_from_slice(b"\r\n");
+    }
+    block.extend_from_slice(b"\r\n");
+}
+
+fn request_block(req: &Request) -> Vec<u8> {
+    let url = req.url();
+    let mut target = url.path().to_owned();
+    if let Some(query) = url.query() {
+        target = format!("{}?{}", target, query);
+    }
+    let mut block =
+        format!("{} {} {}\r\n", req.method(), target, version(req.version())).into_bytes();
+    if !req.headers().contains_key(HOST) {
+        if let Some(host) = url.host_str() {
+            let host = match url.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host.to_owned(),
+            };
+            block.extend_from_slice(format!("Host: {}\r\n", host).as_bytes());
+        }
+    }
+    write_headers(&mut block, req.headers());
+    block
+}
+
+fn response_block(response: &Response, body: &[u8]) -> Vec<u8> {
+    let status = response.status();
+    let mut block = format!(
+        "{} {} {}\r\n",
+        version(response.version()),
+        status.as_str(),
+        status.canonical_reason().unwrap_or("")
+    )
+    .into_bytes();
+    write_headers(&mut block, response.headers());
+    block.extend_from_slice(body);
+    block
+}
+
+impl WarcWriter {
+    // Opens the archive for appending, starting it with a warcinfo record
+    // when it's new
+    pub fn open(fname: &str) -> ResultErr<WarcWriter> {
+        let file = OpenOptions::new().create(true).append(true).open(fname)?;
+        let writer = WarcWriter {
+            gzip: fname.ends_with(".gz"),
+            records: Arc::new(Mutex::new(0)),
+            file: Arc::new(Mutex::new(file)),
+        };
+        if writer.file.lock().unwrap().metadata()?.len() == 0 {
+            let info = format!(
+                "software: metconst-tool/{}\r\nformat: WARC File Format 1.1\r\n",
+                env!("CARGO_PKG_VERSION")
+            );
+            let id = writer.record_id(fname);
+            writer.write_record(
+                &[
+                    ("WARC-Type", "warcinfo"),
+                    ("WARC-Record-ID", &id),
+                    ("WARC-Filename", fname),
+                    ("Content-Type", "application/warc-fields"),
+                ],
+                info.as_bytes(),
+            )?;
+        }
+        Ok(writer)
+    }
+
+    // A urn:uuid that is unique to this record
+    fn record_id(&self, what: &str) -> String {
+        let mut records = self.records.lock().unwrap();
+        *records += 1;
+        let seed = format!(
+            "{} {} {} {}",
+            what,
+            std::process::id(),
+            *records,
+            chrono::Utc::now().timestamp_nanos_opt().unwrap_or(0)
+        );
+        let hex = to_hex(&Sha256::digest(seed.as_bytes()));
+        format!(
+            "<urn:uuid:{}-{}-4{}-a{}-{}>",
+            &hex[..8],
+            &hex[8..12],
+            &hex[13..16],
+            &hex[17..20],
+            &hex[20..32]
+        )
+    }
+
+    fn write_record(&self, fields: &[(&str, &str)], block: &[u8]) -> ResultErr<()> {
+        let date = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string();
+        let mut record = b"WARC/1.1\r\n".to_vec();
+        let length = block.len().to_string();
+        let block_digest = sha1_digest(block);
+        let mut fields = fields.to_vec();
+        fields.push(("WARC-Date", &date));
+        fields.push(("WARC-Block-Digest", &block_digest));
+        fields.push(("Content-Length", &length));
+        for (name, value) in fields {
+            record.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+        }
+        record.extend_from_slice(b"\r\n");
+        record.extend_from_slice(block);
+        record.extend_from_slice(b"\r\n\r\n");
+
+        let mut file = self.file.lock().unwrap();
+        if self.gzip {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&record)?;
+            file.write_all(&encoder.finish()?)?;
+        } else {
+            file.write_all(&record)?;
+        }
+        Ok(())
+    }
+
+    fn archive(
+        &self,
+        request: &[u8],
+        url: &str,
+        response: &Response,
+        body: &[u8],
+    ) -> ResultErr<()> {
+        let response_id = self.record_id(url);
+        let request_id = self.record_id(url);
+        let payload_digest = sha1_digest(body);
+        self.write_record(
+            &[
+                ("WARC-Type", "response"),
+                ("WARC-Record-ID", &response_id),
+                ("WARC-Target-URI", url),
+                ("WARC-Payload-Digest", &payload_digest),
+                ("Content-Type", "application/http;msgtype=response"),
+            ],
+            &response_block(response, body),
+        )?;
+        self.write_record(
+            &[
+                ("WARC-Type", "request"),
+                ("WARC-Record-ID", &request_id),
+                ("WARC-Target-URI", url),
+                ("WARC-Concurrent-To", &response_id),
+                ("Content-Type", "application/http;msgtype=request"),
+            ],
+            request,
+        )
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for WarcWriter {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let url = req.url().to_string();
+        let request = request_block(&req);
+        let response = next.run(req, extensions).await?;
+        let (response, body) = crate::http::buffered(response).await?;
+        self.archive(&request, &url, &response, &body)
+            .map_err(|e| {
+                reqwest_middleware::Error::middleware(std::io::Error::other(e.to_string()))
+            })?;
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warc_digests_are_rfc_4648_base32() {
+        assert_eq!(base32(b""), "");
+        assert_eq!(base32(b"f"), "MY======");
+        assert_eq!(base32(b"foob"), "MZXW6YQ=");
+        assert_eq!(base32(b"foobar"), "MZXW6YTBOI======");
+    }
+}
@@ -0,0 +1,144 @@
+// Golden-file tests for the hack page parser: every fixture in
+// tests/fixtures/hack-pages has the fields it should produce saved next to
+// it. Run with UPDATE_GOLDEN=1 to rewrite them after an intended change.
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+
+#[allow(dead_code)]
+#[path = "../src/records.rs"]
+mod records;
+#[allow(dead_code)]
+#[path = "../src/scrape.rs"]
+mod scrape;
+#[allow(dead_code)]
+#[path = "../src/utils.rs"]
+mod utils;
+
+fn page_json(page: &scrape::HackPage, missing: &[(&str, scrape::Missing)]) -> Value {
+    let missing: Vec<_> = missing
+        .iter()
+        .map(|(field, reason)| format!("{}: {}", field, reason))
+        .collect();
+    json!({
+        "title": page.title,
+        "date": page.date,
+        "author": page.author,
+        "genre": page.genre,
+        "difficulty": page.difficulty,
+        "requires": page.requires,
+        "runtime": page.runtime,
+        "collection": page.collection,
+        "rating": page.rating,
+        "downloads": page.downloads,
+        "forum_thread": page.forum_thread,
+        "videos": page.videos,
+        "screenshots": page.screenshots,
+        "missing": missing,
+    })
+}
+
+#[test]
+fn hack_pages_match_golden_files() {
+    let parser = scrape::PageParser::for_site(scrape::SITE_URL).unwrap();
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/hack-pages");
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut pages: Vec<_> = fs::read_dir(&fixtures)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().map(|e| e == "html").unwrap_or(false))
+        .collect();
+    pages.sort();
+    assert!(!pages.is_empty(), "no fixtures in {:?}", fixtures);
+
+    for html in pages {
+        let golden = html.with_extension("json");
+        let text = fs::read_to_string(&html).unwrap();
+        let page = parser.parse(&text).unwrap();
+        let actual = page_json(&page, &parser.audit(&text, &page));
+        if update {
+            let text = serde_json::to_string_pretty(&actual).unwrap();
+            fs::write(&golden, text + "\n").unwrap();
+            continue;
+        }
+        let expected: Value = serde_json::from_str(
+            &fs::read_to_string(&golden)
+                .unwrap_or_else(|_| panic!("missing {:?}, run with UPDATE_GOLDEN=1", golden)),
+        )
+        .unwrap();
+        assert_eq!(actual, expected, "{:?} no longer parses the same", html);
+    }
+}
+
+#[test]
+fn news_posts_match_golden_file() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/news");
+    let html = fs::read_to_string(fixtures.join("front-page.html")).unwrap();
+    let posts: Vec<Value> = scrape::parse_news(&html)
+        .unwrap()
+        .iter()
+        .map(|p| json!({ "text": p.text, "hack_ids": p.hack_ids }))
+        .collect();
+    let actual = json!(posts);
+    let golden = fixtures.join("front-page.json");
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        let text = serde_json::to_string_pretty(&actual).unwrap();
+        fs::write(&golden, text + "\n").unwrap();
+        return;
+    }
+    let expected: Value = serde_json::from_str(
+        &fs::read_to_string(&golden)
+            .unwrap_or_else(|_| panic!("missing {:?}, run with UPDATE_GOLDEN=1", golden)),
+    )
+    .unwrap();
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn hack_list_rows_match_golden_file() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/hack-list");
+    let html = fs::read_to_string(fixtures.join("list-page.html")).unwrap();
+    let actual = serde_json::to_value(scrape::parse_hack_list(&html).unwrap()).unwrap();
+    let golden = fixtures.join("list-page.json");
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        let text = serde_json::to_string_pretty(&actual).unwrap();
+        fs::write(&golden, text + "\n").unwrap();
+        return;
+    }
+    let expected: Value = serde_json::from_str(
+        &fs::read_to_string(&golden)
+            .unwrap_or_else(|_| panic!("missing {:?}, run with UPDATE_GOLDEN=1", golden)),
+    )
+    .unwrap();
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn forum_thread_stats() {
+    let parser = scrape::PageParser::for_site(scrape::SITE_URL).unwrap();
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/forum");
+    let thread = "https://forum.metroidconstruction.com/index.php/topic,4520.0.html";
+    let first_page = fs::read_to_string(fixtures.join("topic-first-page.html")).unwrap();
+    let last_page = fs::read_to_string(fixtures.join("topic-last-page.html")).unwrap();
+
+    assert_eq!(
+        parser.forum_last_page(thread, &first_page).unwrap(),
+        "https://forum.metroidconstruction.com/index.php/topic,4520.30.html"
+    );
+    // A thread with a single page is its own last page
+    assert_eq!(parser.forum_last_page(thread, &last_page).unwrap(), thread);
+
+    let stats = parser.forum_stats(&first_page);
+    assert_eq!(stats.replies, 1);
+    assert_eq!(
+        stats.last_activity.as_deref(),
+        Some("February 02, 2018, 01:03:10 AM")
+    );
+    let stats = parser.forum_stats(&last_page);
+    assert_eq!(stats.replies, 31);
+    assert_eq!(
+        stats.last_activity.as_deref(),
+        Some("March 03, 2023, 10:12:01 PM")
+    );
+}